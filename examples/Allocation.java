@@ -0,0 +1,73 @@
+// Allocates far more than the default heap holds while keeping a list, a tree and a
+// cycle alive, so it only finishes if the collector frees and moves objects correctly.
+// Build it with a small heap, e.g. `npjava build Allocation.class --heap-size 1m`.
+public class Allocation {
+    static class Node {
+        int value;
+        long wide;
+        Node next;
+        Node other;
+        Node(int value, Node next) { this.value = value; this.wide = value * 3L; this.next = next; }
+    }
+
+    static class Tree {
+        Tree left, right;
+        int depth;
+    }
+
+    static Tree tree(int depth) {
+        Tree t = new Tree();
+        t.depth = depth;
+        if (depth > 0) {
+            t.left = tree(depth - 1);
+            t.right = tree(depth - 1);
+        }
+        return t;
+    }
+
+    static int check(Tree t) {
+        if (t.left == null) return 1;
+        return 1 + check(t.left) + check(t.right);
+    }
+
+    static Node garbage(int n, Node keep) {
+        Node list = null;
+        for (int i = 0; i < n; i++) {
+            list = new Node(i, list);
+            list.other = keep;
+        }
+        return list;
+    }
+
+    static int sum(Node n, int expectedLength) {
+        int count = 0;
+        while (n != null) {
+            if (n.wide != n.value * 3L) return -1;
+            count++;
+            n = n.next;
+        }
+        return count == expectedLength ? 0 : -2;
+    }
+
+    public static void main(String[] args) {
+        // A live list kept across many collections
+        Node live = null;
+        for (int i = 0; i < 1000; i++) live = new Node(i, live);
+        Tree longLived = tree(12);
+        // Cycles
+        Node a = new Node(1, null);
+        Node b = new Node(2, a);
+        a.next = b;
+        for (int round = 0; round < 2000; round++) {
+            Node g = garbage(1000, live);
+            if (sum(g.next, 999) != 0) { System.out.println("bad garbage"); return; }
+            if (g.other != live) { System.out.println("bad keep"); return; }
+            Tree t = tree(4);
+            if (check(t) != 31) { System.out.println("bad tree"); return; }
+        }
+        if (sum(live, 1000) != 0) { System.out.println("bad live"); return; }
+        if (check(longLived) != 8191) { System.out.println("bad long lived"); return; }
+        if (a.next.next != a || b.next.next != b || a.next.value != 2) { System.out.println("bad cycle"); return; }
+        System.out.println("ok");
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use crate::bytecode::classpath::ClassPath;
+use crate::codegen;
+use crate::codegen::layout::HEADER_SIZE;
+use crate::codegen::syntax::Syntax;
+use crate::codegen::target::OsAbi;
+use crate::codegen::x86_64::NullChecks;
+use crate::codegen::{elf, encoder, Assembly};
+
+// `npjava build` compiles a class to assembly, then assembles and links it with the runtime:
+//
+// Foo.class -> foo.S, foo.runtime.S -> foo.o, foo.runtime.o -> foo
+//
+// Both steps are done by our own encoder and ELF writer unless external tools are asked
+// for. The built-in linker works from the assembly, so it needs the built-in assembler.
+// The sources are written in NASM syntax, or in AT&T syntax for the GNU assembler.
+
+// The name that selects our own assembler or linker
+pub const BUILTIN: &str = "builtin";
+
+/// The tools, found on PATH unless given as paths.
+#[derive(Debug)]
+pub struct Toolchain {
+    // Anything taking NASM's `-f <format> -o <object> <source>`, like nasm or yasm, or
+    // the GNU assembler as
+    pub assembler: String,
+    // ld, or a C compiler driver like cc, gcc or clang
+    pub linker: String,
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Self {
+            assembler: BUILTIN.to_string(),
+            linker: BUILTIN.to_string(),
+        }
+    }
+}
+
+impl Toolchain {
+    // as, or a cross assembler like x86_64-linux-gnu-as
+    fn assembles_att(&self) -> bool {
+        let name = tool_name(&self.assembler);
+        name == "as" || name == "gas" || name.ends_with("-as")
+    }
+
+    /// The syntax the sources are written in.
+    pub fn syntax(&self) -> Syntax {
+        if self.assembles_att() { Syntax::Att } else { Syntax::Nasm }
+    }
+
+    fn links_with_c_driver(&self) -> bool {
+        let name = tool_name(&self.linker);
+        ["cc", "gcc", "clang"].iter().any(|driver| name == *driver || name.ends_with(&format!("-{}", driver)))
+    }
+}
+
+// The file name of a tool given as a path
+fn tool_name(tool: &str) -> &str {
+    Path::new(tool).file_name().and_then(|name| name.to_str()).unwrap_or(tool)
+}
+
+/// Runs a tool, its output goes into the error when it fails.
+fn run(command: &mut Command) -> Result<(), String> {
+    let description = format!("{:?}", command).replace('"', "");
+
+    let output = command
+        .output()
+        .map_err(|e| format!("Could not run {}: {}", command.get_program().to_string_lossy(), e))?;
+    if !output.status.success() {
+        let mut diagnostics = String::from_utf8_lossy(&output.stderr).into_owned();
+        diagnostics.push_str(&String::from_utf8_lossy(&output.stdout));
+        return Err(format!("{} failed ({}):\n{}", description, output.status, diagnostics.trim_end()));
+    }
+
+    Ok(())
+}
+
+fn write(path: &str, contents: &[u8]) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("Could not write {}: {}", path, e))
+}
+
+fn assemble(toolchain: &Toolchain, abi: &dyn OsAbi, program: &Assembly, source: &str, object: &str) -> Result<(), String> {
+    if toolchain.assembler == BUILTIN {
+        let assembled = encoder::assemble(&[program]).map_err(|e| format!("{}: {}", source, e))?;
+        return write(object, &elf::write_relocatable(&assembled));
+    }
+
+    let mut command = Command::new(&toolchain.assembler);
+    if !toolchain.assembles_att() {
+        command.args(["-f", abi.object_format()]);
+    }
+    run(command.args(["-o", object, source]))
+}
+
+// Links the program and the runtime straight from their assembly
+fn link_builtin(abi: &dyn OsAbi, sources: &[&Assembly], output: &str) -> Result<(), String> {
+    let assembled = encoder::assemble(sources)?;
+    write(output, &elf::write_executable(&assembled, abi.entry_symbol())?)?;
+
+    let mut permissions = fs::metadata(output).map_err(|e| format!("Could not stat {}: {}", output, e))?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(output, permissions).map_err(|e| format!("Could not make {} executable: {}", output, e))
+}
+
+fn link(toolchain: &Toolchain, abi: &dyn OsAbi, objects: &[&str], output: &str) -> Result<(), String> {
+    let mut command = Command::new(&toolchain.linker);
+    if toolchain.links_with_c_driver() {
+        command.args(abi.c_driver_flags());
+    } else {
+        command.args(["-e", abi.entry_symbol()]);
+    }
+    command.arg("-o").arg(output).args(objects);
+
+    run(&mut command)
+}
+
+/// Parses a heap size in bytes, with an optional k, m or g suffix like `-Xmx`.
+pub fn parse_heap_size(text: &str) -> Result<usize, String> {
+    let (digits, unit) = match text.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&text[..index], 1 << 10),
+        Some((index, 'm' | 'M')) => (&text[..index], 1 << 20),
+        Some((index, 'g' | 'G')) => (&text[..index], 1 << 30),
+        _ => (text, 1),
+    };
+
+    let size = digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|value| value.checked_mul(unit))
+        .ok_or_else(|| format!("Invalid heap size: {}", text))?;
+    // Each half has to hold at least an instance of Object
+    if size < 2 * HEADER_SIZE {
+        return Err(format!("The heap size {} is too small", text));
+    }
+
+    Ok(size)
+}
+
+/// Compiles `class_path` and the classes it uses into the executable `output`, leaving
+/// the assembly and objects next to it. The program gets a heap of `heap_size` bytes.
+pub fn build(class_path: &str, output: &str, toolchain: &Toolchain, abi: &dyn OsAbi, heap_size: usize, null_checks: NullChecks) -> Result<(), String> {
+    if null_checks == NullChecks::Signal && abi.signal_abi().is_none() {
+        return Err(format!("The runtime can't catch SIGSEGV on {}, use explicit null checks", abi.name()));
+    }
+
+    let classes = ClassPath::load(class_path)?;
+    let program = codegen::x86_64::codegen(&classes, abi, null_checks)?;
+
+    let program_source = format!("{}.S", output);
+    let runtime_source = format!("{}.runtime.S", output);
+    let program_object = format!("{}.o", output);
+    let runtime_object = format!("{}.runtime.o", output);
+
+    let runtime = codegen::runtime::generate(abi, heap_size);
+
+    let builtin = toolchain.assembler == BUILTIN || toolchain.linker == BUILTIN;
+    if builtin && abi.object_format() != "elf64" {
+        return Err(format!("The built-in assembler and linker only write ELF, use external tools for {}", abi.name()));
+    }
+    if toolchain.linker == BUILTIN && toolchain.assembler != BUILTIN {
+        return Err("The built-in linker needs the built-in assembler".to_string());
+    }
+
+    let syntax = toolchain.syntax();
+    write(&program_source, syntax.print(&program.code).as_bytes())?;
+    write(&runtime_source, syntax.print(&runtime.code).as_bytes())?;
+    if toolchain.linker == BUILTIN {
+        return link_builtin(abi, &[&program, &runtime], output);
+    }
+
+    assemble(toolchain, abi, &program, &program_source, &program_object)?;
+    assemble(toolchain, abi, &runtime, &runtime_source, &runtime_object)?;
+    link(toolchain, abi, &[&program_object, &runtime_object], output)
+}
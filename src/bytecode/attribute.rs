@@ -15,6 +15,13 @@ pub struct ExceptionTableEntry {
     pub catch_type: u16,
 }
 
+// https://docs.oracle.com/javase/specs/jvms/se17/html/jvms-4.html#jvms-4.7.23
+#[derive(Debug)]
+pub struct BootstrapMethod {
+    pub bootstrap_method_ref: u16,
+    pub bootstrap_arguments: Vec<u16>,
+}
+
 #[derive(Debug, Default)]
 pub struct CodeAttribute {
     pub name_index: u16,
@@ -29,69 +36,624 @@ pub struct CodeAttribute {
     pub attributes: Vec<Attribute>,
 }
 
-const LDC: u8 = 18;
-const ALOAD_0: u8 = 42;
-const INVOKE_VIRTUAL: u8 = 182;
-const INVOKE_SPECIAL: u8 = 183;
-const RETURN: u8 = 177;
-const GET_STATIC: u8 = 178;
+// https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-6.html#jvms-6.5
+pub(crate) const NOP: u8 = 0;
+pub(crate) const ACONST_NULL: u8 = 1;
+pub(crate) const ICONST_M1: u8 = 2;
+pub(crate) const ICONST_5: u8 = 8;
+pub(crate) const LCONST_0: u8 = 9;
+pub(crate) const LCONST_1: u8 = 10;
+pub(crate) const FCONST_0: u8 = 11;
+pub(crate) const FCONST_2: u8 = 13;
+pub(crate) const DCONST_0: u8 = 14;
+pub(crate) const DCONST_1: u8 = 15;
+pub(crate) const BIPUSH: u8 = 16;
+pub(crate) const SIPUSH: u8 = 17;
+pub(crate) const LDC: u8 = 18;
+pub(crate) const LDC_W: u8 = 19;
+pub(crate) const LDC2_W: u8 = 20;
+pub(crate) const ILOAD: u8 = 21;
+pub(crate) const LLOAD: u8 = 22;
+pub(crate) const FLOAD: u8 = 23;
+pub(crate) const DLOAD: u8 = 24;
+pub(crate) const ALOAD: u8 = 25;
+pub(crate) const ILOAD_0: u8 = 26;
+pub(crate) const LLOAD_0: u8 = 30;
+pub(crate) const FLOAD_0: u8 = 34;
+pub(crate) const DLOAD_0: u8 = 38;
+pub(crate) const ALOAD_0: u8 = 42;
+pub(crate) const ALOAD_3: u8 = 45;
+pub(crate) const IALOAD: u8 = 46;
+pub(crate) const LALOAD: u8 = 47;
+pub(crate) const FALOAD: u8 = 48;
+pub(crate) const DALOAD: u8 = 49;
+pub(crate) const AALOAD: u8 = 50;
+pub(crate) const BALOAD: u8 = 51;
+pub(crate) const CALOAD: u8 = 52;
+pub(crate) const SALOAD: u8 = 53;
+pub(crate) const ISTORE: u8 = 54;
+pub(crate) const LSTORE: u8 = 55;
+pub(crate) const FSTORE: u8 = 56;
+pub(crate) const DSTORE: u8 = 57;
+pub(crate) const ASTORE: u8 = 58;
+pub(crate) const ISTORE_0: u8 = 59;
+pub(crate) const LSTORE_0: u8 = 63;
+pub(crate) const FSTORE_0: u8 = 67;
+pub(crate) const DSTORE_0: u8 = 71;
+pub(crate) const ASTORE_0: u8 = 75;
+pub(crate) const ASTORE_3: u8 = 78;
+pub(crate) const IASTORE: u8 = 79;
+pub(crate) const LASTORE: u8 = 80;
+pub(crate) const FASTORE: u8 = 81;
+pub(crate) const DASTORE: u8 = 82;
+pub(crate) const AASTORE: u8 = 83;
+pub(crate) const BASTORE: u8 = 84;
+pub(crate) const CASTORE: u8 = 85;
+pub(crate) const SASTORE: u8 = 86;
+pub(crate) const POP: u8 = 87;
+pub(crate) const POP2: u8 = 88;
+pub(crate) const DUP: u8 = 89;
+pub(crate) const DUP_X1: u8 = 90;
+pub(crate) const DUP_X2: u8 = 91;
+pub(crate) const DUP2: u8 = 92;
+pub(crate) const DUP2_X1: u8 = 93;
+pub(crate) const DUP2_X2: u8 = 94;
+pub(crate) const SWAP: u8 = 95;
+pub(crate) const IADD: u8 = 96;
+pub(crate) const LADD: u8 = 97;
+pub(crate) const FADD: u8 = 98;
+pub(crate) const DADD: u8 = 99;
+pub(crate) const ISUB: u8 = 100;
+pub(crate) const LSUB: u8 = 101;
+pub(crate) const FSUB: u8 = 102;
+pub(crate) const DSUB: u8 = 103;
+pub(crate) const IMUL: u8 = 104;
+pub(crate) const LMUL: u8 = 105;
+pub(crate) const FMUL: u8 = 106;
+pub(crate) const DMUL: u8 = 107;
+pub(crate) const IDIV: u8 = 108;
+pub(crate) const LDIV: u8 = 109;
+pub(crate) const FDIV: u8 = 110;
+pub(crate) const DDIV: u8 = 111;
+pub(crate) const IREM: u8 = 112;
+pub(crate) const LREM: u8 = 113;
+pub(crate) const FREM: u8 = 114;
+pub(crate) const DREM: u8 = 115;
+pub(crate) const INEG: u8 = 116;
+pub(crate) const LNEG: u8 = 117;
+pub(crate) const FNEG: u8 = 118;
+pub(crate) const DNEG: u8 = 119;
+pub(crate) const ISHL: u8 = 120;
+pub(crate) const LSHL: u8 = 121;
+pub(crate) const ISHR: u8 = 122;
+pub(crate) const LSHR: u8 = 123;
+pub(crate) const IUSHR: u8 = 124;
+pub(crate) const LUSHR: u8 = 125;
+pub(crate) const IAND: u8 = 126;
+pub(crate) const LAND: u8 = 127;
+pub(crate) const IOR: u8 = 128;
+pub(crate) const LOR: u8 = 129;
+pub(crate) const IXOR: u8 = 130;
+pub(crate) const LXOR: u8 = 131;
+pub(crate) const IINC: u8 = 132;
+pub(crate) const I2L: u8 = 133;
+pub(crate) const I2F: u8 = 134;
+pub(crate) const I2D: u8 = 135;
+pub(crate) const L2I: u8 = 136;
+pub(crate) const L2F: u8 = 137;
+pub(crate) const L2D: u8 = 138;
+pub(crate) const F2I: u8 = 139;
+pub(crate) const F2L: u8 = 140;
+pub(crate) const F2D: u8 = 141;
+pub(crate) const D2I: u8 = 142;
+pub(crate) const D2L: u8 = 143;
+pub(crate) const D2F: u8 = 144;
+pub(crate) const I2B: u8 = 145;
+pub(crate) const I2C: u8 = 146;
+pub(crate) const I2S: u8 = 147;
+pub(crate) const LCMP: u8 = 148;
+pub(crate) const FCMPL: u8 = 149;
+pub(crate) const FCMPG: u8 = 150;
+pub(crate) const DCMPL: u8 = 151;
+pub(crate) const DCMPG: u8 = 152;
+pub(crate) const IFEQ: u8 = 153;
+pub(crate) const IFNE: u8 = 154;
+pub(crate) const IFLT: u8 = 155;
+pub(crate) const IFGE: u8 = 156;
+pub(crate) const IFGT: u8 = 157;
+pub(crate) const IFLE: u8 = 158;
+pub(crate) const IF_ICMPEQ: u8 = 159;
+pub(crate) const IF_ICMPNE: u8 = 160;
+pub(crate) const IF_ICMPLT: u8 = 161;
+pub(crate) const IF_ICMPGE: u8 = 162;
+pub(crate) const IF_ICMPGT: u8 = 163;
+pub(crate) const IF_ICMPLE: u8 = 164;
+pub(crate) const IF_ACMPEQ: u8 = 165;
+pub(crate) const IF_ACMPNE: u8 = 166;
+pub(crate) const GOTO: u8 = 167;
+pub(crate) const JSR: u8 = 168;
+pub(crate) const RET: u8 = 169;
+pub(crate) const TABLESWITCH: u8 = 170;
+pub(crate) const LOOKUPSWITCH: u8 = 171;
+pub(crate) const IRETURN: u8 = 172;
+pub(crate) const LRETURN: u8 = 173;
+pub(crate) const FRETURN: u8 = 174;
+pub(crate) const DRETURN: u8 = 175;
+pub(crate) const ARETURN: u8 = 176;
+pub(crate) const RETURN: u8 = 177;
+pub(crate) const GET_STATIC: u8 = 178;
+pub(crate) const PUT_STATIC: u8 = 179;
+pub(crate) const GET_FIELD: u8 = 180;
+pub(crate) const PUT_FIELD: u8 = 181;
+pub(crate) const INVOKE_VIRTUAL: u8 = 182;
+pub(crate) const INVOKE_SPECIAL: u8 = 183;
+pub(crate) const INVOKE_STATIC: u8 = 184;
+pub(crate) const INVOKE_INTERFACE: u8 = 185;
+pub(crate) const INVOKE_DYNAMIC: u8 = 186;
+pub(crate) const NEW: u8 = 187;
+pub(crate) const NEWARRAY: u8 = 188;
+pub(crate) const ANEWARRAY: u8 = 189;
+pub(crate) const ARRAYLENGTH: u8 = 190;
+pub(crate) const ATHROW: u8 = 191;
+pub(crate) const CHECKCAST: u8 = 192;
+pub(crate) const INSTANCEOF: u8 = 193;
+pub(crate) const MONITORENTER: u8 = 194;
+pub(crate) const MONITOREXIT: u8 = 195;
+pub(crate) const WIDE: u8 = 196;
+pub(crate) const MULTIANEWARRAY: u8 = 197;
+pub(crate) const IFNULL: u8 = 198;
+pub(crate) const IFNONNULL: u8 = 199;
+pub(crate) const GOTO_W: u8 = 200;
+pub(crate) const JSR_W: u8 = 201;
 
 impl CodeAttribute {
     pub fn into_code_instructions(&self) -> Result<Vec<CodeInstruction>, String> {
+        Ok(self
+            .into_code_instructions_with_offsets()?
+            .into_iter()
+            .map(|(_, instruction)| instruction)
+            .collect())
+    }
+
+    /// Decodes the code array, pairing every instruction with the offset of its opcode.
+    /// Branch targets are resolved to absolute offsets.
+    pub fn into_code_instructions_with_offsets(&self) -> Result<Vec<(u32, CodeInstruction)>, String> {
         let mut code_instructions = Vec::new();
         let mut offset = 0;
 
         while offset < self.code_length as usize {
+            let pc = offset;
             let opcode = BigEndianByteOrder::read_u8(&self.code, offset)?;
             offset += 1;
 
-            // https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-6.html#jvms-6.5
-            match opcode {
-                LDC => {
-                    let index = BigEndianByteOrder::read_u8(&self.code, offset)?;
-                    offset += 1;
-                    code_instructions.push(CodeInstruction::Ldc(index));
+            let read_u8 = |offset: &mut usize| -> Result<u8, String> {
+                let value = BigEndianByteOrder::read_u8(&self.code, *offset)?;
+                *offset += 1;
+                Ok(value)
+            };
+            let read_u16 = |offset: &mut usize| -> Result<u16, String> {
+                let value = BigEndianByteOrder::read_u16(&self.code, *offset)?;
+                *offset += 2;
+                Ok(value)
+            };
+            let read_u32 = |offset: &mut usize| -> Result<u32, String> {
+                let value = BigEndianByteOrder::read_u32(&self.code, *offset)?;
+                *offset += 4;
+                Ok(value)
+            };
+            let branch = |offset: &mut usize| -> Result<u32, String> {
+                Ok((pc as i64 + read_u16(offset)? as i16 as i64) as u32)
+            };
+            let branch_w = |offset: &mut usize| -> Result<u32, String> {
+                Ok((pc as i64 + read_u32(offset)? as i32 as i64) as u32)
+            };
+
+            let instruction = match opcode {
+                NOP => CodeInstruction::Nop,
+                ACONST_NULL => CodeInstruction::AconstNull,
+                ICONST_M1..=ICONST_5 => CodeInstruction::Iconst(opcode as i32 - ICONST_M1 as i32 - 1),
+                LCONST_0..=LCONST_1 => CodeInstruction::Lconst((opcode - LCONST_0) as i64),
+                FCONST_0..=FCONST_2 => CodeInstruction::Fconst((opcode - FCONST_0) as f32),
+                DCONST_0..=DCONST_1 => CodeInstruction::Dconst((opcode - DCONST_0) as f64),
+                BIPUSH => CodeInstruction::Bipush(read_u8(&mut offset)? as i8),
+                SIPUSH => CodeInstruction::Sipush(read_u16(&mut offset)? as i16),
+                LDC => CodeInstruction::Ldc(read_u8(&mut offset)?),
+                LDC_W => CodeInstruction::LdcW(read_u16(&mut offset)?),
+                LDC2_W => CodeInstruction::Ldc2W(read_u16(&mut offset)?),
+                ILOAD => CodeInstruction::Iload(read_u8(&mut offset)? as u16),
+                LLOAD => CodeInstruction::Lload(read_u8(&mut offset)? as u16),
+                FLOAD => CodeInstruction::Fload(read_u8(&mut offset)? as u16),
+                DLOAD => CodeInstruction::Dload(read_u8(&mut offset)? as u16),
+                ALOAD => CodeInstruction::Aload(read_u8(&mut offset)? as u16),
+                ILOAD_0..=ALOAD_3 => {
+                    let index = ((opcode - ILOAD_0) % 4) as u16;
+                    match opcode {
+                        ILOAD_0..LLOAD_0 => CodeInstruction::Iload(index),
+                        LLOAD_0..FLOAD_0 => CodeInstruction::Lload(index),
+                        FLOAD_0..DLOAD_0 => CodeInstruction::Fload(index),
+                        DLOAD_0..ALOAD_0 => CodeInstruction::Dload(index),
+                        _ => CodeInstruction::Aload(index),
+                    }
                 },
-                ALOAD_0 => {
-                    code_instructions.push(CodeInstruction::Aload0);
+                IALOAD => CodeInstruction::Iaload,
+                LALOAD => CodeInstruction::Laload,
+                FALOAD => CodeInstruction::Faload,
+                DALOAD => CodeInstruction::Daload,
+                AALOAD => CodeInstruction::Aaload,
+                BALOAD => CodeInstruction::Baload,
+                CALOAD => CodeInstruction::Caload,
+                SALOAD => CodeInstruction::Saload,
+                ISTORE => CodeInstruction::Istore(read_u8(&mut offset)? as u16),
+                LSTORE => CodeInstruction::Lstore(read_u8(&mut offset)? as u16),
+                FSTORE => CodeInstruction::Fstore(read_u8(&mut offset)? as u16),
+                DSTORE => CodeInstruction::Dstore(read_u8(&mut offset)? as u16),
+                ASTORE => CodeInstruction::Astore(read_u8(&mut offset)? as u16),
+                ISTORE_0..=ASTORE_3 => {
+                    let index = ((opcode - ISTORE_0) % 4) as u16;
+                    match opcode {
+                        ISTORE_0..LSTORE_0 => CodeInstruction::Istore(index),
+                        LSTORE_0..FSTORE_0 => CodeInstruction::Lstore(index),
+                        FSTORE_0..DSTORE_0 => CodeInstruction::Fstore(index),
+                        DSTORE_0..ASTORE_0 => CodeInstruction::Dstore(index),
+                        _ => CodeInstruction::Astore(index),
+                    }
                 },
-                INVOKE_VIRTUAL => {
-                    let index = BigEndianByteOrder::read_u16(&self.code, offset)?;
-                    offset += 2;
-                    code_instructions.push(CodeInstruction::InvokeVirtual(index));
+                IASTORE => CodeInstruction::Iastore,
+                LASTORE => CodeInstruction::Lastore,
+                FASTORE => CodeInstruction::Fastore,
+                DASTORE => CodeInstruction::Dastore,
+                AASTORE => CodeInstruction::Aastore,
+                BASTORE => CodeInstruction::Bastore,
+                CASTORE => CodeInstruction::Castore,
+                SASTORE => CodeInstruction::Sastore,
+                POP => CodeInstruction::Pop,
+                POP2 => CodeInstruction::Pop2,
+                DUP => CodeInstruction::Dup,
+                DUP_X1 => CodeInstruction::DupX1,
+                DUP_X2 => CodeInstruction::DupX2,
+                DUP2 => CodeInstruction::Dup2,
+                DUP2_X1 => CodeInstruction::Dup2X1,
+                DUP2_X2 => CodeInstruction::Dup2X2,
+                SWAP => CodeInstruction::Swap,
+                IADD => CodeInstruction::Iadd,
+                LADD => CodeInstruction::Ladd,
+                FADD => CodeInstruction::Fadd,
+                DADD => CodeInstruction::Dadd,
+                ISUB => CodeInstruction::Isub,
+                LSUB => CodeInstruction::Lsub,
+                FSUB => CodeInstruction::Fsub,
+                DSUB => CodeInstruction::Dsub,
+                IMUL => CodeInstruction::Imul,
+                LMUL => CodeInstruction::Lmul,
+                FMUL => CodeInstruction::Fmul,
+                DMUL => CodeInstruction::Dmul,
+                IDIV => CodeInstruction::Idiv,
+                LDIV => CodeInstruction::Ldiv,
+                FDIV => CodeInstruction::Fdiv,
+                DDIV => CodeInstruction::Ddiv,
+                IREM => CodeInstruction::Irem,
+                LREM => CodeInstruction::Lrem,
+                FREM => CodeInstruction::Frem,
+                DREM => CodeInstruction::Drem,
+                INEG => CodeInstruction::Ineg,
+                LNEG => CodeInstruction::Lneg,
+                FNEG => CodeInstruction::Fneg,
+                DNEG => CodeInstruction::Dneg,
+                ISHL => CodeInstruction::Ishl,
+                LSHL => CodeInstruction::Lshl,
+                ISHR => CodeInstruction::Ishr,
+                LSHR => CodeInstruction::Lshr,
+                IUSHR => CodeInstruction::Iushr,
+                LUSHR => CodeInstruction::Lushr,
+                IAND => CodeInstruction::Iand,
+                LAND => CodeInstruction::Land,
+                IOR => CodeInstruction::Ior,
+                LOR => CodeInstruction::Lor,
+                IXOR => CodeInstruction::Ixor,
+                LXOR => CodeInstruction::Lxor,
+                IINC => {
+                    let index = read_u8(&mut offset)? as u16;
+                    let value = read_u8(&mut offset)? as i8 as i16;
+                    CodeInstruction::Iinc(index, value)
                 },
-                INVOKE_SPECIAL => {
-                    let index = BigEndianByteOrder::read_u16(&self.code, offset)?;
-                    offset += 2;
-                    code_instructions.push(CodeInstruction::InvokeSpecial(index));
+                I2L => CodeInstruction::I2l,
+                I2F => CodeInstruction::I2f,
+                I2D => CodeInstruction::I2d,
+                L2I => CodeInstruction::L2i,
+                L2F => CodeInstruction::L2f,
+                L2D => CodeInstruction::L2d,
+                F2I => CodeInstruction::F2i,
+                F2L => CodeInstruction::F2l,
+                F2D => CodeInstruction::F2d,
+                D2I => CodeInstruction::D2i,
+                D2L => CodeInstruction::D2l,
+                D2F => CodeInstruction::D2f,
+                I2B => CodeInstruction::I2b,
+                I2C => CodeInstruction::I2c,
+                I2S => CodeInstruction::I2s,
+                LCMP => CodeInstruction::Lcmp,
+                FCMPL => CodeInstruction::Fcmpl,
+                FCMPG => CodeInstruction::Fcmpg,
+                DCMPL => CodeInstruction::Dcmpl,
+                DCMPG => CodeInstruction::Dcmpg,
+                IFEQ => CodeInstruction::Ifeq(branch(&mut offset)?),
+                IFNE => CodeInstruction::Ifne(branch(&mut offset)?),
+                IFLT => CodeInstruction::Iflt(branch(&mut offset)?),
+                IFGE => CodeInstruction::Ifge(branch(&mut offset)?),
+                IFGT => CodeInstruction::Ifgt(branch(&mut offset)?),
+                IFLE => CodeInstruction::Ifle(branch(&mut offset)?),
+                IF_ICMPEQ => CodeInstruction::IfIcmpeq(branch(&mut offset)?),
+                IF_ICMPNE => CodeInstruction::IfIcmpne(branch(&mut offset)?),
+                IF_ICMPLT => CodeInstruction::IfIcmplt(branch(&mut offset)?),
+                IF_ICMPGE => CodeInstruction::IfIcmpge(branch(&mut offset)?),
+                IF_ICMPGT => CodeInstruction::IfIcmpgt(branch(&mut offset)?),
+                IF_ICMPLE => CodeInstruction::IfIcmple(branch(&mut offset)?),
+                IF_ACMPEQ => CodeInstruction::IfAcmpeq(branch(&mut offset)?),
+                IF_ACMPNE => CodeInstruction::IfAcmpne(branch(&mut offset)?),
+                GOTO => CodeInstruction::Goto(branch(&mut offset)?),
+                JSR => CodeInstruction::Jsr(branch(&mut offset)?),
+                RET => CodeInstruction::Ret(read_u8(&mut offset)? as u16),
+                TABLESWITCH => {
+                    // The operands are aligned to a multiple of four bytes from the start of the code
+                    offset = (offset + 3) & !3;
+                    let default = branch_w(&mut offset)?;
+                    let low = read_u32(&mut offset)? as i32;
+                    let high = read_u32(&mut offset)? as i32;
+                    if low > high {
+                        return Err(format!("Invalid tableswitch at {}: low {} > high {}", pc, low, high));
+                    }
+
+                    let mut offsets = Vec::with_capacity((high as i64 - low as i64 + 1) as usize);
+                    for _ in low..=high {
+                        offsets.push(branch_w(&mut offset)?);
+                    }
+                    CodeInstruction::TableSwitch { default, low, high, offsets }
+                },
+                LOOKUPSWITCH => {
+                    offset = (offset + 3) & !3;
+                    let default = branch_w(&mut offset)?;
+                    let npairs = read_u32(&mut offset)? as i32;
+                    if npairs < 0 {
+                        return Err(format!("Invalid lookupswitch at {}: {} pairs", pc, npairs));
+                    }
+
+                    let mut pairs = Vec::with_capacity(npairs as usize);
+                    for _ in 0..npairs {
+                        let key = read_u32(&mut offset)? as i32;
+                        pairs.push((key, branch_w(&mut offset)?));
+                    }
+                    CodeInstruction::LookupSwitch { default, pairs }
                 },
-                RETURN => {
-                    code_instructions.push(CodeInstruction::Return);
+                IRETURN => CodeInstruction::Ireturn,
+                LRETURN => CodeInstruction::Lreturn,
+                FRETURN => CodeInstruction::Freturn,
+                DRETURN => CodeInstruction::Dreturn,
+                ARETURN => CodeInstruction::Areturn,
+                RETURN => CodeInstruction::Return,
+                GET_STATIC => CodeInstruction::GetStatic(read_u16(&mut offset)?),
+                PUT_STATIC => CodeInstruction::PutStatic(read_u16(&mut offset)?),
+                GET_FIELD => CodeInstruction::GetField(read_u16(&mut offset)?),
+                PUT_FIELD => CodeInstruction::PutField(read_u16(&mut offset)?),
+                INVOKE_VIRTUAL => CodeInstruction::InvokeVirtual(read_u16(&mut offset)?),
+                INVOKE_SPECIAL => CodeInstruction::InvokeSpecial(read_u16(&mut offset)?),
+                INVOKE_STATIC => CodeInstruction::InvokeStatic(read_u16(&mut offset)?),
+                INVOKE_INTERFACE => {
+                    let index = read_u16(&mut offset)?;
+                    let count = read_u8(&mut offset)?;
+                    // The last byte is always zero
+                    offset += 1;
+                    CodeInstruction::InvokeInterface(index, count)
                 },
-                GET_STATIC => {
-                    let index = BigEndianByteOrder::read_u16(&self.code, offset)?;
+                INVOKE_DYNAMIC => {
+                    let index = read_u16(&mut offset)?;
+                    // Followed by two zero bytes
                     offset += 2;
-                    code_instructions.push(CodeInstruction::GetStatic(index));
+                    CodeInstruction::InvokeDynamic(index)
                 },
-                _ => todo!("Implement parsing a code instruction, opcode: {}", opcode),
-            }
+                NEW => CodeInstruction::New(read_u16(&mut offset)?),
+                NEWARRAY => CodeInstruction::NewArray(read_u8(&mut offset)?),
+                ANEWARRAY => CodeInstruction::ANewArray(read_u16(&mut offset)?),
+                ARRAYLENGTH => CodeInstruction::ArrayLength,
+                ATHROW => CodeInstruction::AThrow,
+                CHECKCAST => CodeInstruction::CheckCast(read_u16(&mut offset)?),
+                INSTANCEOF => CodeInstruction::InstanceOf(read_u16(&mut offset)?),
+                MONITORENTER => CodeInstruction::MonitorEnter,
+                MONITOREXIT => CodeInstruction::MonitorExit,
+                WIDE => {
+                    let opcode = read_u8(&mut offset)?;
+                    let index = read_u16(&mut offset)?;
+                    match opcode {
+                        ILOAD => CodeInstruction::Iload(index),
+                        LLOAD => CodeInstruction::Lload(index),
+                        FLOAD => CodeInstruction::Fload(index),
+                        DLOAD => CodeInstruction::Dload(index),
+                        ALOAD => CodeInstruction::Aload(index),
+                        ISTORE => CodeInstruction::Istore(index),
+                        LSTORE => CodeInstruction::Lstore(index),
+                        FSTORE => CodeInstruction::Fstore(index),
+                        DSTORE => CodeInstruction::Dstore(index),
+                        ASTORE => CodeInstruction::Astore(index),
+                        RET => CodeInstruction::Ret(index),
+                        IINC => CodeInstruction::Iinc(index, read_u16(&mut offset)? as i16),
+                        _ => return Err(format!("Invalid opcode {} after wide at {}", opcode, pc)),
+                    }
+                },
+                MULTIANEWARRAY => {
+                    let index = read_u16(&mut offset)?;
+                    let dimensions = read_u8(&mut offset)?;
+                    CodeInstruction::MultiANewArray(index, dimensions)
+                },
+                IFNULL => CodeInstruction::IfNull(branch(&mut offset)?),
+                IFNONNULL => CodeInstruction::IfNonNull(branch(&mut offset)?),
+                // The wide forms only differ in the width of the offset
+                GOTO_W => CodeInstruction::Goto(branch_w(&mut offset)?),
+                JSR_W => CodeInstruction::Jsr(branch_w(&mut offset)?),
+                _ => return Err(format!("Invalid opcode {} at {}", opcode, pc)),
+            };
+
+            code_instructions.push((pc as u32, instruction));
         }
 
         Ok(code_instructions)
     }
 }
 
-#[derive(Debug)]
+/// A decoded JVM instruction. The `_<n>` shorthand opcodes are folded into their general
+/// form (`aload_0` becomes `Aload(0)`) and branch targets hold absolute code offsets.
+#[derive(Debug, Clone)]
 pub enum CodeInstruction {
+    Nop,
+    AconstNull,
+    Iconst(i32),
+    Lconst(i64),
+    Fconst(f32),
+    Dconst(f64),
+    Bipush(i8),
+    Sipush(i16),
     Ldc(u8),
-    Aload0,
+    LdcW(u16),
+    Ldc2W(u16),
+    Iload(u16),
+    Lload(u16),
+    Fload(u16),
+    Dload(u16),
+    Aload(u16),
+    Iaload,
+    Laload,
+    Faload,
+    Daload,
+    Aaload,
+    Baload,
+    Caload,
+    Saload,
+    Istore(u16),
+    Lstore(u16),
+    Fstore(u16),
+    Dstore(u16),
+    Astore(u16),
+    Iastore,
+    Lastore,
+    Fastore,
+    Dastore,
+    Aastore,
+    Bastore,
+    Castore,
+    Sastore,
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+    Iadd,
+    Ladd,
+    Fadd,
+    Dadd,
+    Isub,
+    Lsub,
+    Fsub,
+    Dsub,
+    Imul,
+    Lmul,
+    Fmul,
+    Dmul,
+    Idiv,
+    Ldiv,
+    Fdiv,
+    Ddiv,
+    Irem,
+    Lrem,
+    Frem,
+    Drem,
+    Ineg,
+    Lneg,
+    Fneg,
+    Dneg,
+    Ishl,
+    Lshl,
+    Ishr,
+    Lshr,
+    Iushr,
+    Lushr,
+    Iand,
+    Land,
+    Ior,
+    Lor,
+    Ixor,
+    Lxor,
+    Iinc(u16, i16),
+    I2l,
+    I2f,
+    I2d,
+    L2i,
+    L2f,
+    L2d,
+    F2i,
+    F2l,
+    F2d,
+    D2i,
+    D2l,
+    D2f,
+    I2b,
+    I2c,
+    I2s,
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+    Ifeq(u32),
+    Ifne(u32),
+    Iflt(u32),
+    Ifge(u32),
+    Ifgt(u32),
+    Ifle(u32),
+    IfIcmpeq(u32),
+    IfIcmpne(u32),
+    IfIcmplt(u32),
+    IfIcmpge(u32),
+    IfIcmpgt(u32),
+    IfIcmple(u32),
+    IfAcmpeq(u32),
+    IfAcmpne(u32),
+    Goto(u32),
+    Jsr(u32),
+    Ret(u16),
+    TableSwitch { default: u32, low: i32, high: i32, offsets: Vec<u32> },
+    LookupSwitch { default: u32, pairs: Vec<(i32, u32)> },
+    Ireturn,
+    Lreturn,
+    Freturn,
+    Dreturn,
+    Areturn,
+    Return,
+    GetStatic(u16),
+    PutStatic(u16),
+    GetField(u16),
+    PutField(u16),
     InvokeVirtual(u16),
     InvokeSpecial(u16),
-    GetStatic(u16),
-    Return,
+    InvokeStatic(u16),
+    InvokeInterface(u16, u8),
+    InvokeDynamic(u16),
+    New(u16),
+    NewArray(u8),
+    ANewArray(u16),
+    ArrayLength,
+    AThrow,
+    CheckCast(u16),
+    InstanceOf(u16),
+    MonitorEnter,
+    MonitorExit,
+    MultiANewArray(u16, u8),
+    IfNull(u32),
+    IfNonNull(u32),
 }
 
-pub fn parse_attribute(bytecode: &Vec<u8>, mut offset: usize) -> Result<(Attribute, usize), String> {
+pub fn parse_attribute(bytecode: &[u8], mut offset: usize) -> Result<(Attribute, usize), String> {
     let name_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
@@ -156,4 +718,28 @@ impl Attribute {
 
         Ok(code_attribute)
     }
+    pub fn into_bootstrap_methods(&self) -> Result<Vec<BootstrapMethod>, String> {
+        let mut offset = 0;
+
+        let num_bootstrap_methods = BigEndianByteOrder::read_u16(&self.info, offset)?;
+        offset += 2;
+
+        let mut bootstrap_methods = Vec::with_capacity(num_bootstrap_methods as usize);
+        for _ in 0..num_bootstrap_methods {
+            let bootstrap_method_ref = BigEndianByteOrder::read_u16(&self.info, offset)?;
+            offset += 2;
+            let num_bootstrap_arguments = BigEndianByteOrder::read_u16(&self.info, offset)?;
+            offset += 2;
+
+            let mut bootstrap_arguments = Vec::with_capacity(num_bootstrap_arguments as usize);
+            for _ in 0..num_bootstrap_arguments {
+                bootstrap_arguments.push(BigEndianByteOrder::read_u16(&self.info, offset)?);
+                offset += 2;
+            }
+
+            bootstrap_methods.push(BootstrapMethod { bootstrap_method_ref, bootstrap_arguments });
+        }
+
+        Ok(bootstrap_methods)
+    }
 }
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::bytecode::constantpool::ConstantPoolEntry;
+use crate::bytecode::field::Field;
+use crate::bytecode::method::Method;
+use crate::bytecode::{self, lambda, ParsedBytecode, ACC_ABSTRACT};
+
+// A program is its main class and every class it refers to, directly or through other
+// classes, whose class file sits next to it. Classes are looked up by binary name from
+// the root of the package tree, so `a/b/C` is `<root>/a/b/C.class`. The JDK classes are
+// not on disk and the runtime provides what we support of them. The classes of lambdas
+// are made up when the class with them is loaded.
+
+#[derive(Debug, Default)]
+pub struct ClassPath {
+    pub main: String,
+    pub classes: BTreeMap<String, ParsedBytecode>,
+}
+
+impl ClassPath {
+    /// Loads the class at `path` and the classes it needs.
+    pub fn load(path: &str) -> Result<ClassPath, String> {
+        let main_class = bytecode::from_file(path)?;
+        let main = main_class.class_name()?;
+
+        // The root is a level above the class file for each package the class is in
+        let mut root = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        for _ in main.split('/').skip(1) {
+            root = root.parent().map(Path::to_path_buf).unwrap_or_default();
+        }
+
+        let mut classes = BTreeMap::new();
+        let mut pending = Vec::new();
+        add_class(&mut classes, &mut pending, main_class)?;
+        while let Some(name) = pending.pop() {
+            if classes.contains_key(&name) {
+                continue;
+            }
+            let Some(path) = class_file(&root, &name) else {
+                continue;
+            };
+
+            let class = bytecode::from_file(&path.to_string_lossy()).map_err(|e| format!("{}: {}", path.display(), e))?;
+            if class.class_name()? != name {
+                return Err(format!("{} does not hold {}", path.display(), name));
+            }
+            add_class(&mut classes, &mut pending, class)?;
+        }
+
+        Ok(ClassPath { main, classes })
+    }
+
+    pub fn main_class(&self) -> &ParsedBytecode {
+        &self.classes[&self.main]
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ParsedBytecode> {
+        self.classes.get(name)
+    }
+
+    /// Finds the method a reference to `class_name.name descriptor` means, looking in the
+    /// superclasses when the class doesn't declare it and then in the interfaces for a
+    /// default method. Returns the declaring class.
+    pub fn resolve_method(&self, class_name: &str, name: &str, descriptor: &str) -> Result<Option<(&ParsedBytecode, &Method)>, String> {
+        let mut interfaces = Vec::new();
+        let mut current = self.find(class_name);
+        while let Some(class) = current {
+            if let Some(method) = class.find_method(name, descriptor)? {
+                return Ok(Some((class, method)));
+            }
+            interfaces.extend(class.interface_names()?);
+            current = match class.super_class_name()? {
+                Some(super_class) => self.find(&super_class),
+                None => None,
+            };
+        }
+
+        // A default method is preferred over an abstract declaration
+        let mut declared = None;
+        for interface in self.superinterfaces(interfaces)? {
+            let Some(class) = self.find(&interface) else {
+                continue;
+            };
+            match class.find_method(name, descriptor)? {
+                Some(method) if method.access_flags & ACC_ABSTRACT == 0 => return Ok(Some((class, method))),
+                Some(method) => declared = declared.or(Some((class, method))),
+                None => {},
+            }
+        }
+
+        Ok(declared)
+    }
+
+    /// Finds the field a reference to `class_name.name descriptor` means, looking in the
+    /// class, then in the interfaces it extends or implements and then in its superclass.
+    /// Returns the declaring class.
+    pub fn resolve_field(&self, class_name: &str, name: &str, descriptor: &str) -> Result<Option<(&ParsedBytecode, &Field)>, String> {
+        let Some(class) = self.find(class_name) else {
+            return Ok(None);
+        };
+        if let Some(field) = class.find_field(name, descriptor)? {
+            return Ok(Some((class, field)));
+        }
+        for interface in class.interface_names()? {
+            if let Some(resolved) = self.resolve_field(&interface, name, descriptor)? {
+                return Ok(Some(resolved));
+            }
+        }
+        match class.super_class_name()? {
+            Some(super_class) => self.resolve_field(&super_class, name, descriptor),
+            None => Ok(None),
+        }
+    }
+
+    /// The interfaces in `interfaces` and every interface they extend, each once and
+    /// an interface before the ones it extends. Interfaces of the JDK are included but
+    /// what they extend is not known.
+    pub fn superinterfaces(&self, interfaces: Vec<String>) -> Result<Vec<String>, String> {
+        let mut all: Vec<String> = Vec::new();
+        let mut pending = interfaces;
+        pending.reverse();
+        while let Some(interface) = pending.pop() {
+            if all.contains(&interface) {
+                continue;
+            }
+            if let Some(class) = self.find(&interface) {
+                pending.extend(class.interface_names()?.into_iter().rev());
+            }
+            all.push(interface);
+        }
+
+        Ok(all)
+    }
+}
+
+// Adds a class and the classes made up for its lambdas, and queues the classes they refer to
+fn add_class(classes: &mut BTreeMap<String, ParsedBytecode>, pending: &mut Vec<String>, class: ParsedBytecode) -> Result<(), String> {
+    for lambda in lambda::lambda_classes(&class)? {
+        pending.extend(referenced_classes(&lambda));
+        classes.insert(lambda.class_name()?, lambda);
+    }
+    pending.extend(referenced_classes(&class));
+    classes.insert(class.class_name()?, class);
+    Ok(())
+}
+
+// The classes named in a constant pool, array classes aside
+fn referenced_classes(class: &ParsedBytecode) -> Vec<String> {
+    class
+        .constant_pool
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            ConstantPoolEntry::ClassInfo(info) => class.constant_pool.find_utf8_constant_pool_entry(info.name_index).ok(),
+            _ => None,
+        })
+        .map(|name| name.bytes)
+        .filter(|name| !name.starts_with('['))
+        .collect()
+}
+
+fn class_file(root: &Path, name: &str) -> Option<PathBuf> {
+    let path = root.join(format!("{}.class", name));
+    path.is_file().then_some(path)
+}
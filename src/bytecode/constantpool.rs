@@ -16,7 +16,9 @@ pub enum ConstantPoolEntry {
     Utf8(Utf8ConstantPoolEntry),
     MethodHandle(MethodHandleConstantPoolEntry),
     MethodType(MethodTypeConstantPoolEntry),
-    InvokeDynamic(InvokeDynamicConstantPoolEntry)
+    InvokeDynamic(InvokeDynamicConstantPoolEntry),
+    // Long and Double entries take up two slots, the second one can't be referenced
+    Unusable,
 }
 
 #[derive(Debug, Clone)]
@@ -89,7 +91,11 @@ pub struct NameAndTypeConstantPoolEntry {
 pub struct Utf8ConstantPoolEntry {
     pub tag: u8,
     pub length: u16,
+    // The text with any unpaired surrogates replaced by U+FFFD, as names and descriptors
+    // are read
     pub bytes: String,
+    // The UTF-16 code units exactly, as string constants are read
+    pub units: Vec<u16>,
 }
 
 #[derive(Debug, Clone)]
@@ -112,14 +118,54 @@ pub struct InvokeDynamicConstantPoolEntry {
     pub name_and_type_index: u16,
 }
 
-const CONSTANT_UTF8: u8 = 1;
-const CONSTANT_CLASS_INFO: u8 = 7;
-const CONSTANT_STRING: u8 = 8;
-const CONSTANT_FIELD_REF: u8 = 9;
-const CONSTANT_METHOD_REF: u8 = 10;
-const CONSTANT_NAME_AND_TYPE: u8 = 12;
+pub(crate) const CONSTANT_UTF8: u8 = 1;
+pub(crate) const CONSTANT_INTEGER: u8 = 3;
+pub(crate) const CONSTANT_FLOAT: u8 = 4;
+pub(crate) const CONSTANT_LONG: u8 = 5;
+pub(crate) const CONSTANT_DOUBLE: u8 = 6;
+pub(crate) const CONSTANT_CLASS_INFO: u8 = 7;
+pub(crate) const CONSTANT_STRING: u8 = 8;
+pub(crate) const CONSTANT_FIELD_REF: u8 = 9;
+pub(crate) const CONSTANT_METHOD_REF: u8 = 10;
+pub(crate) const CONSTANT_INTERFACE_METHOD_REF: u8 = 11;
+pub(crate) const CONSTANT_NAME_AND_TYPE: u8 = 12;
+pub(crate) const CONSTANT_METHOD_HANDLE: u8 = 15;
+pub(crate) const CONSTANT_METHOD_TYPE: u8 = 16;
+pub(crate) const CONSTANT_INVOKE_DYNAMIC: u8 = 18;
+
+impl LongConstantPoolEntry {
+    pub fn value(&self) -> i64 {
+        (((self.high_bytes as u64) << 32) | self.low_bytes as u64) as i64
+    }
+}
+
+impl DoubleConstantPoolEntry {
+    pub fn value(&self) -> f64 {
+        f64::from_bits(((self.high_bytes as u64) << 32) | self.low_bytes as u64)
+    }
+}
+
+impl IntegerConstantPoolEntry {
+    pub fn value(&self) -> i32 {
+        self.bytes as i32
+    }
+}
+
+impl FloatConstantPoolEntry {
+    pub fn value(&self) -> f32 {
+        f32::from_bits(self.bytes)
+    }
+}
 
-pub fn parse_class_info_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+/// A Fieldref, Methodref or InterfaceMethodref with its class and name and type resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MemberRef {
+    pub class_name: String,
+    pub name: String,
+    pub descriptor: String,
+}
+
+pub fn parse_class_info_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let name_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
@@ -129,7 +175,7 @@ pub fn parse_class_info_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usiz
     }), offset))
 }
 
-pub fn parse_method_ref_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+pub fn parse_method_ref_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let class_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
     let name_and_type_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
@@ -142,7 +188,7 @@ pub fn parse_method_ref_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usiz
     }), offset))
 }
 
-pub fn parse_name_and_type_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+pub fn parse_name_and_type_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let name_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
     let descriptor_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
@@ -155,21 +201,69 @@ pub fn parse_name_and_type_constant_pool_entry(bytecode: &Vec<u8>, mut offset: u
     }), offset))
 }
 
-pub fn parse_utf8_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+// Class files use modified UTF-8: NUL is two bytes and characters outside the BMP are
+// surrogate pairs of three bytes each. It decodes to UTF-16 code units, unpaired
+// surrogates included, as Java strings can hold them.
+// https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.4.7
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<Vec<u16>, String> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let continuation = |at: usize| match bytes.get(at) {
+            Some(byte) if byte & 0xc0 == 0x80 => Ok((byte & 0x3f) as u16),
+            _ => Err(format!("Invalid modified UTF-8 at byte {}", at)),
+        };
+        let byte = bytes[i] as u16;
+        if byte & 0x80 == 0 && byte != 0 {
+            units.push(byte);
+            i += 1;
+        } else if byte & 0xe0 == 0xc0 {
+            units.push((byte & 0x1f) << 6 | continuation(i + 1)?);
+            i += 2;
+        } else if byte & 0xf0 == 0xe0 {
+            units.push((byte & 0x0f) << 12 | continuation(i + 1)? << 6 | continuation(i + 2)?);
+            i += 3;
+        } else {
+            return Err(format!("Invalid modified UTF-8 at byte {}", i));
+        }
+    }
+
+    Ok(units)
+}
+
+/// Encodes a string the way class files store it, which `decode_modified_utf8` reads
+/// back as its UTF-16 code units.
+pub fn encode_modified_utf8(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for unit in text.encode_utf16() {
+        match unit {
+            0x0001..=0x007f => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07ff => bytes.extend([0xc0 | (unit >> 6) as u8, 0x80 | (unit & 0x3f) as u8]),
+            _ => bytes.extend([0xe0 | (unit >> 12) as u8, 0x80 | (unit >> 6 & 0x3f) as u8, 0x80 | (unit & 0x3f) as u8]),
+        }
+    }
+    bytes
+}
+
+pub fn parse_utf8_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let length = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
-    let bytes = String::from_utf8(bytecode[offset..offset + length as usize].to_vec()).map_err(|e| e.to_string())?;
+    let encoded = bytecode
+        .get(offset..offset + length as usize)
+        .ok_or_else(|| format!("Utf8 constant at {} is past the end of the class file", offset))?;
+    let units = decode_modified_utf8(encoded)?;
     offset += length as usize;
 
     Ok((ConstantPoolEntry::Utf8(Utf8ConstantPoolEntry {
         tag: CONSTANT_UTF8,
         length,
-        bytes,
+        bytes: String::from_utf16_lossy(&units),
+        units,
     }), offset))
 }
 
-pub fn parse_field_ref_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+pub fn parse_field_ref_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let class_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
     let name_and_type_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
@@ -182,7 +276,7 @@ pub fn parse_field_ref_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize
     }), offset))
 }
 
-pub fn parse_string_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+pub fn parse_string_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let string_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
@@ -192,6 +286,101 @@ pub fn parse_string_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -
     }), offset))
 }
 
+pub fn parse_interface_method_ref_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let class_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+    let name_and_type_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    Ok((ConstantPoolEntry::InterfaceMethodref(InterfaceMethodrefConstantPoolEntry {
+        tag: CONSTANT_INTERFACE_METHOD_REF,
+        class_index,
+        name_and_type_index,
+    }), offset))
+}
+
+pub fn parse_integer_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+
+    Ok((ConstantPoolEntry::Integer(IntegerConstantPoolEntry {
+        tag: CONSTANT_INTEGER,
+        bytes,
+    }), offset))
+}
+
+pub fn parse_float_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+
+    Ok((ConstantPoolEntry::Float(FloatConstantPoolEntry {
+        tag: CONSTANT_FLOAT,
+        bytes,
+    }), offset))
+}
+
+pub fn parse_long_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let high_bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+    let low_bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+
+    Ok((ConstantPoolEntry::Long(LongConstantPoolEntry {
+        tag: CONSTANT_LONG,
+        low_bytes,
+        high_bytes,
+    }), offset))
+}
+
+pub fn parse_double_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let high_bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+    let low_bytes = BigEndianByteOrder::read_u32(bytecode, offset)?;
+    offset += 4;
+
+    Ok((ConstantPoolEntry::Double(DoubleConstantPoolEntry {
+        tag: CONSTANT_DOUBLE,
+        low_bytes,
+        high_bytes,
+    }), offset))
+}
+
+pub fn parse_method_handle_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let reference_kind = BigEndianByteOrder::read_u8(bytecode, offset)?;
+    offset += 1;
+    let reference_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    Ok((ConstantPoolEntry::MethodHandle(MethodHandleConstantPoolEntry {
+        tag: CONSTANT_METHOD_HANDLE,
+        reference_kind,
+        reference_index,
+    }), offset))
+}
+
+pub fn parse_method_type_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let descriptor_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    Ok((ConstantPoolEntry::MethodType(MethodTypeConstantPoolEntry {
+        tag: CONSTANT_METHOD_TYPE,
+        descriptor_index,
+    }), offset))
+}
+
+pub fn parse_invoke_dynamic_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+    let bootstrap_method_attr_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+    let name_and_type_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    Ok((ConstantPoolEntry::InvokeDynamic(InvokeDynamicConstantPoolEntry {
+        tag: CONSTANT_INVOKE_DYNAMIC,
+        bootstrap_method_attr_index,
+        name_and_type_index,
+    }), offset))
+}
+
 #[derive(Debug, Default)]
 pub struct ConstantPool {
     // TODO: Maybe use a BTreeMap instead of a Vec?
@@ -199,15 +388,17 @@ pub struct ConstantPool {
 }
 
 impl ConstantPool {
-    pub fn find_utf8_constant_pool_entry(&self, index: u16) -> Result<Utf8ConstantPoolEntry, String> {
+    pub fn get(&self, index: u16) -> Result<&ConstantPoolEntry, String> {
         // Constant pool is 1-indexed, so we need to subtract 1 from the index
-        let entry_index = (index as usize) - 1;
-        
-        if entry_index >= self.entries.len() {
+        if index == 0 || index as usize > self.entries.len() {
             return Err(format!("Constant pool index out of bounds: {}", index));
         }
-        
-        if let ConstantPoolEntry::Utf8(entry) = &self.entries[entry_index] {
+
+        Ok(&self.entries[index as usize - 1])
+    }
+
+    pub fn find_utf8_constant_pool_entry(&self, index: u16) -> Result<Utf8ConstantPoolEntry, String> {
+        if let ConstantPoolEntry::Utf8(entry) = self.get(index)? {
             Ok(entry.clone())
         } else {
             Err(format!("Constant pool entry at index {} is not a UTF8 entry", index))
@@ -215,44 +406,93 @@ impl ConstantPool {
     }
 
     pub fn find_string_constant_pool_entry(&self, index: u16) -> Result<StringConstantPoolEntry, String> {
-        // Constant pool is 1-indexed, so we need to subtract 1 from the index
-        let entry_index = (index as usize) - 1;
-        
-        if entry_index >= self.entries.len() {
-            return Err(format!("Constant pool index out of bounds: {}", index));
+        if let ConstantPoolEntry::String(entry) = self.get(index)? {
+            Ok(entry.clone())
+        } else {
+            Err(format!("Constant pool entry at index {} is not a string entry", index))
         }
+    }
 
-        if let ConstantPoolEntry::String(entry) = &self.entries[entry_index] {
+    pub fn find_class_info_constant_pool_entry(&self, index: u16) -> Result<ClassInfoConstantPoolEntry, String> {
+        if let ConstantPoolEntry::ClassInfo(entry) = self.get(index)? {
             Ok(entry.clone())
         } else {
-            Err(format!("Constant pool entry at index {} is not a string entry", index))
+            Err(format!("Constant pool entry at index {} is not a class entry", index))
+        }
+    }
+
+    pub fn find_name_and_type_constant_pool_entry(&self, index: u16) -> Result<NameAndTypeConstantPoolEntry, String> {
+        if let ConstantPoolEntry::NameAndType(entry) = self.get(index)? {
+            Ok(entry.clone())
+        } else {
+            Err(format!("Constant pool entry at index {} is not a name and type entry", index))
+        }
+    }
+
+    pub fn find_method_handle_constant_pool_entry(&self, index: u16) -> Result<MethodHandleConstantPoolEntry, String> {
+        if let ConstantPoolEntry::MethodHandle(entry) = self.get(index)? {
+            Ok(entry.clone())
+        } else {
+            Err(format!("Constant pool entry at index {} is not a method handle entry", index))
+        }
+    }
+
+    pub fn find_invoke_dynamic_constant_pool_entry(&self, index: u16) -> Result<InvokeDynamicConstantPoolEntry, String> {
+        if let ConstantPoolEntry::InvokeDynamic(entry) = self.get(index)? {
+            Ok(entry.clone())
+        } else {
+            Err(format!("Constant pool entry at index {} is not an invoke dynamic entry", index))
         }
     }
+
+    /// Returns the binary name (`java/lang/Object`) of the class at `index`.
+    pub fn find_class_name(&self, index: u16) -> Result<String, String> {
+        let class_info = self.find_class_info_constant_pool_entry(index)?;
+        Ok(self.find_utf8_constant_pool_entry(class_info.name_index)?.bytes)
+    }
+
+    /// Returns the name and descriptor of the NameAndType entry at `index`.
+    pub fn find_name_and_type(&self, index: u16) -> Result<(String, String), String> {
+        let name_and_type = self.find_name_and_type_constant_pool_entry(index)?;
+        let name = self.find_utf8_constant_pool_entry(name_and_type.name_index)?.bytes;
+        let descriptor = self.find_utf8_constant_pool_entry(name_and_type.descriptor_index)?.bytes;
+        Ok((name, descriptor))
+    }
+
+    /// Resolves a Fieldref, Methodref or InterfaceMethodref entry.
+    pub fn find_member_ref(&self, index: u16) -> Result<MemberRef, String> {
+        let (class_index, name_and_type_index) = match self.get(index)? {
+            ConstantPoolEntry::Fieldref(entry) => (entry.class_index, entry.name_and_type_index),
+            ConstantPoolEntry::Methodref(entry) => (entry.class_index, entry.name_and_type_index),
+            ConstantPoolEntry::InterfaceMethodref(entry) => (entry.class_index, entry.name_and_type_index),
+            _ => return Err(format!("Constant pool entry at index {} is not a member reference", index)),
+        };
+
+        let class_name = self.find_class_name(class_index)?;
+        let (name, descriptor) = self.find_name_and_type(name_and_type_index)?;
+        Ok(MemberRef { class_name, name, descriptor })
+    }
 }
 
-pub fn parse_constant_pool_entry(bytecode: &Vec<u8>, mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
+pub fn parse_constant_pool_entry(bytecode: &[u8], mut offset: usize) -> Result<(ConstantPoolEntry, usize), String> {
     let tag = BigEndianByteOrder::read_u8(bytecode, offset)?;
     offset += 1;
 
     match tag {
-        CONSTANT_CLASS_INFO => {
-            return parse_class_info_constant_pool_entry(bytecode, offset);
-        },
-        CONSTANT_METHOD_REF => {
-            return parse_method_ref_constant_pool_entry(bytecode, offset);
-        },
-        CONSTANT_NAME_AND_TYPE => {
-            return parse_name_and_type_constant_pool_entry(bytecode, offset);
-        },
-        CONSTANT_UTF8 => {
-            return parse_utf8_constant_pool_entry(bytecode, offset);
-        },
-        CONSTANT_FIELD_REF => {
-            return parse_field_ref_constant_pool_entry(bytecode, offset);
-        },
-        CONSTANT_STRING => {
-            return parse_string_constant_pool_entry(bytecode, offset);
-        },
-        _ => todo!("Implement parsing a constant pool entry, tag: {}", tag),
+        CONSTANT_CLASS_INFO => parse_class_info_constant_pool_entry(bytecode, offset),
+        CONSTANT_METHOD_REF => parse_method_ref_constant_pool_entry(bytecode, offset),
+        CONSTANT_NAME_AND_TYPE => parse_name_and_type_constant_pool_entry(bytecode, offset),
+        CONSTANT_UTF8 => parse_utf8_constant_pool_entry(bytecode, offset),
+        CONSTANT_FIELD_REF => parse_field_ref_constant_pool_entry(bytecode, offset),
+        CONSTANT_STRING => parse_string_constant_pool_entry(bytecode, offset),
+        CONSTANT_INTERFACE_METHOD_REF => parse_interface_method_ref_constant_pool_entry(bytecode, offset),
+        CONSTANT_INTEGER => parse_integer_constant_pool_entry(bytecode, offset),
+        CONSTANT_FLOAT => parse_float_constant_pool_entry(bytecode, offset),
+        CONSTANT_LONG => parse_long_constant_pool_entry(bytecode, offset),
+        CONSTANT_DOUBLE => parse_double_constant_pool_entry(bytecode, offset),
+        CONSTANT_METHOD_HANDLE => parse_method_handle_constant_pool_entry(bytecode, offset),
+        CONSTANT_METHOD_TYPE => parse_method_type_constant_pool_entry(bytecode, offset),
+        CONSTANT_INVOKE_DYNAMIC => parse_invoke_dynamic_constant_pool_entry(bytecode, offset),
+        _ => Err(format!("Invalid constant pool tag: {}", tag)),
     }
 }
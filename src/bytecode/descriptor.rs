@@ -0,0 +1,124 @@
+// https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.3
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    // None for void methods
+    pub return_type: Option<FieldType>,
+}
+
+impl FieldType {
+    pub fn is_reference(&self) -> bool {
+        matches!(self, FieldType::Object(_) | FieldType::Array(_))
+    }
+
+    /// Longs and doubles take up two local variable and operand stack slots.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, FieldType::Long | FieldType::Double)
+    }
+}
+
+impl std::fmt::Display for FieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldType::Byte => write!(f, "B"),
+            FieldType::Char => write!(f, "C"),
+            FieldType::Double => write!(f, "D"),
+            FieldType::Float => write!(f, "F"),
+            FieldType::Int => write!(f, "I"),
+            FieldType::Long => write!(f, "J"),
+            FieldType::Short => write!(f, "S"),
+            FieldType::Boolean => write!(f, "Z"),
+            FieldType::Object(class_name) => write!(f, "L{};", class_name),
+            FieldType::Array(component) => write!(f, "[{}", component),
+        }
+    }
+}
+
+impl MethodDescriptor {
+    /// The number of local variable slots taken by the parameters, not counting `this`.
+    pub fn parameter_slots(&self) -> usize {
+        self.parameters.iter().map(|p| if p.is_wide() { 2 } else { 1 }).sum()
+    }
+}
+
+fn parse_field_type(descriptor: &str, offset: usize) -> Result<(FieldType, usize), String> {
+    let bytes = descriptor.as_bytes();
+    if offset >= bytes.len() {
+        return Err(format!("Unexpected end of descriptor: {}", descriptor));
+    }
+
+    let field_type = match bytes[offset] {
+        b'B' => FieldType::Byte,
+        b'C' => FieldType::Char,
+        b'D' => FieldType::Double,
+        b'F' => FieldType::Float,
+        b'I' => FieldType::Int,
+        b'J' => FieldType::Long,
+        b'S' => FieldType::Short,
+        b'Z' => FieldType::Boolean,
+        b'L' => {
+            let end = descriptor[offset..]
+                .find(';')
+                .ok_or_else(|| format!("Unterminated class name in descriptor: {}", descriptor))?;
+            let class_name = descriptor[offset + 1..offset + end].to_string();
+            return Ok((FieldType::Object(class_name), offset + end + 1));
+        },
+        b'[' => {
+            let (component, offset) = parse_field_type(descriptor, offset + 1)?;
+            return Ok((FieldType::Array(Box::new(component)), offset));
+        },
+        c => return Err(format!("Invalid character '{}' in descriptor: {}", c as char, descriptor)),
+    };
+
+    Ok((field_type, offset + 1))
+}
+
+pub fn parse_field_descriptor(descriptor: &str) -> Result<FieldType, String> {
+    let (field_type, offset) = parse_field_type(descriptor, 0)?;
+    if offset != descriptor.len() {
+        return Err(format!("Trailing characters in field descriptor: {}", descriptor));
+    }
+
+    Ok(field_type)
+}
+
+pub fn parse_method_descriptor(descriptor: &str) -> Result<MethodDescriptor, String> {
+    if !descriptor.starts_with('(') {
+        return Err(format!("Method descriptor must start with '(': {}", descriptor));
+    }
+
+    let mut parameters = Vec::new();
+    let mut offset = 1;
+    while descriptor.as_bytes().get(offset) != Some(&b')') {
+        let (parameter, parameter_offset) = parse_field_type(descriptor, offset)?;
+        parameters.push(parameter);
+        offset = parameter_offset;
+    }
+    offset += 1;
+
+    let return_type = if &descriptor[offset..] == "V" {
+        None
+    } else {
+        let (return_type, return_offset) = parse_field_type(descriptor, offset)?;
+        if return_offset != descriptor.len() {
+            return Err(format!("Trailing characters in method descriptor: {}", descriptor));
+        }
+        Some(return_type)
+    };
+
+    Ok(MethodDescriptor { parameters, return_type })
+}
@@ -1,20 +1,20 @@
 pub trait ByteOrder {
-    fn read_u8(bytecode: &Vec<u8>, offset: usize) -> Result<u8, String>;
-    fn read_u16(bytecode: &Vec<u8>, offset: usize) -> Result<u16, String>;
-    fn read_u32(bytecode: &Vec<u8>, offset: usize) -> Result<u32, String>;
+    fn read_u8(bytecode: &[u8], offset: usize) -> Result<u8, String>;
+    fn read_u16(bytecode: &[u8], offset: usize) -> Result<u16, String>;
+    fn read_u32(bytecode: &[u8], offset: usize) -> Result<u32, String>;
 }
 
 pub(crate) struct BigEndianByteOrder;
 
 impl ByteOrder for BigEndianByteOrder {
-    fn read_u8(bytecode: &Vec<u8>, offset: usize) -> Result<u8, String> {
+    fn read_u8(bytecode: &[u8], offset: usize) -> Result<u8, String> {
         if offset + 1 > bytecode.len() {
             return Err(format!("Offset out of bounds: {}", offset));
         }
         Ok(bytecode[offset])
     }
 
-    fn read_u16(bytecode: &Vec<u8>, offset: usize) -> Result<u16, String> {
+    fn read_u16(bytecode: &[u8], offset: usize) -> Result<u16, String> {
         if offset + 2 > bytecode.len() {
             return Err(format!("Offset out of bounds: {}", offset));
         }
@@ -26,7 +26,7 @@ impl ByteOrder for BigEndianByteOrder {
         Ok(val)
     }
 
-    fn read_u32(bytecode: &Vec<u8>, offset: usize) -> Result<u32, String> {
+    fn read_u32(bytecode: &[u8], offset: usize) -> Result<u32, String> {
         if offset + 4 > bytecode.len() {
             return Err(format!("Offset out of bounds: {}", offset));
         }
@@ -0,0 +1,41 @@
+use crate::bytecode::endianness::{BigEndianByteOrder, ByteOrder};
+use crate::bytecode::attribute::{self, Attribute};
+
+#[derive(Debug)]
+pub struct Field {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes_count: u16,
+    pub attributes: Vec<Attribute>,
+}
+
+pub fn parse_field(bytecode: &[u8], mut offset: usize) -> Result<(Field, usize), String> {
+    let access_flags = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    let name_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    let descriptor_index = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    let attributes_count = BigEndianByteOrder::read_u16(bytecode, offset)?;
+    offset += 2;
+
+    let mut attrs = Vec::with_capacity(attributes_count as usize);
+
+    for _ in 0..attributes_count {
+        let (attribute, attribute_offset) = attribute::parse_attribute(bytecode, offset)?;
+        attrs.push(attribute);
+        offset = attribute_offset;
+    }
+
+    Ok((Field {
+        access_flags,
+        name_index,
+        descriptor_index,
+        attributes_count,
+        attributes: attrs,
+    }, offset))
+}
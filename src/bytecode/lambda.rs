@@ -0,0 +1,404 @@
+use crate::bytecode::attribute::{
+    ALOAD, ALOAD_0, ARETURN, CHECKCAST, DLOAD, DRETURN, DUP, F2D, FLOAD, FRETURN, GET_FIELD, I2D, I2F, I2L, ILOAD, INVOKE_INTERFACE, INVOKE_SPECIAL, INVOKE_STATIC,
+    INVOKE_VIRTUAL, IRETURN, L2D, L2F, LLOAD, LRETURN, NEW, POP, POP2, PUT_FIELD, RETURN, WIDE,
+};
+use crate::bytecode::constantpool::{ConstantPool, ConstantPoolEntry};
+use crate::bytecode::descriptor::{parse_method_descriptor, FieldType, MethodDescriptor};
+use crate::bytecode::writer::{ClassWriter, Code};
+use crate::bytecode::{parse_bytecode, CallSite, ParsedBytecode, ACC_BRIDGE, ACC_FINAL, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC, ACC_SYNTHETIC};
+
+// Lambdas and method references are invokedynamic call sites whose bootstrap method is
+// LambdaMetafactory's. Rather than spinning a class when the call site is linked, like
+// the JVM does, each gets a class made up when the program is loaded:
+//
+//   final class Main$$Lambda$<index> implements <functional interface> {
+//       private final <captured> arg$1, arg$2, ...;
+//       private Main$$Lambda$<index>(<captured>) { ... }
+//       private static <functional interface> get$Lambda(<captured>) { ... }
+//       public <result> <method>(<parameters>) { return <implementation>(arg$1, ..., <parameters>); }
+//   }
+//
+// The call site calls get$Lambda with the values it captures. The interface method
+// converts its arguments to the implementation method's parameter types, boxing,
+// unboxing and widening them as the metafactory would, and its result back.
+
+const LAMBDA_METAFACTORY_CLASS: &str = "java/lang/invoke/LambdaMetafactory";
+
+/// The static method of a lambda's class that creates an instance capturing its arguments.
+pub const LAMBDA_FACTORY: &str = "get$Lambda";
+
+// https://docs.oracle.com/javase/specs/jvms/se17/html/jvms-4.html#jvms-4.4.8
+const REF_INVOKE_VIRTUAL: u8 = 5;
+const REF_INVOKE_STATIC: u8 = 6;
+const REF_INVOKE_SPECIAL: u8 = 7;
+const REF_NEW_INVOKE_SPECIAL: u8 = 8;
+const REF_INVOKE_INTERFACE: u8 = 9;
+
+// The flags of altMetafactory
+const FLAG_SERIALIZABLE: i32 = 1;
+const FLAG_MARKERS: i32 = 2;
+const FLAG_BRIDGES: i32 = 4;
+
+/// Whether a call site's bootstrap method is one of LambdaMetafactory's.
+pub fn is_lambda_call_site(call_site: &CallSite) -> bool {
+    let bootstrap = &call_site.bootstrap_method;
+    bootstrap.class_name == LAMBDA_METAFACTORY_CLASS && (bootstrap.name == "metafactory" || bootstrap.name == "altMetafactory")
+}
+
+/// The class made up for the lambda call site at constant pool `index` of `class_name`.
+pub fn lambda_class_name(class_name: &str, index: u16) -> String {
+    format!("{}$$Lambda${}", class_name, index)
+}
+
+/// The classes of the lambdas and method references of a class.
+pub fn lambda_classes(class: &ParsedBytecode) -> Result<Vec<ParsedBytecode>, String> {
+    let class_name = class.class_name()?;
+    let mut lambdas = Vec::new();
+    for (position, entry) in class.constant_pool.entries.iter().enumerate() {
+        if !matches!(entry, ConstantPoolEntry::InvokeDynamic(_)) {
+            continue;
+        }
+
+        let index = position as u16 + 1;
+        let call_site = class.call_site(index)?;
+        if is_lambda_call_site(&call_site) {
+            let bytes = lambda_class(&class.constant_pool, &lambda_class_name(&class_name, index), &call_site)
+                .map_err(|e| format!("Lambda at constant {} of {}: {}", index, class_name, e))?;
+            lambdas.push(parse_bytecode(&bytes)?);
+        }
+    }
+
+    Ok(lambdas)
+}
+
+// The method a lambda's class forwards to
+struct Implementation {
+    kind: u8,
+    class_name: String,
+    name: String,
+    descriptor: String,
+    interface: bool,
+}
+
+impl Implementation {
+    // The types of the values the implementation is called with, the receiver first
+    fn parameters(&self) -> Result<Vec<FieldType>, String> {
+        let mut parameters = match self.kind {
+            REF_INVOKE_VIRTUAL | REF_INVOKE_SPECIAL | REF_INVOKE_INTERFACE => vec![FieldType::Object(self.class_name.clone())],
+            _ => Vec::new(),
+        };
+        parameters.extend(parse_method_descriptor(&self.descriptor)?.parameters);
+        Ok(parameters)
+    }
+
+    // What calling the implementation leaves on the stack, a constructor's new object
+    fn result(&self) -> Result<Option<FieldType>, String> {
+        match self.kind {
+            REF_NEW_INVOKE_SPECIAL => Ok(Some(FieldType::Object(self.class_name.clone()))),
+            _ => Ok(parse_method_descriptor(&self.descriptor)?.return_type),
+        }
+    }
+}
+
+// Writes the class file of a lambda's class
+fn lambda_class(constant_pool: &ConstantPool, class_name: &str, call_site: &CallSite) -> Result<Vec<u8>, String> {
+    let arguments = &call_site.bootstrap_arguments;
+    if arguments.len() < 3 {
+        return Err(format!("{} takes at least 3 static arguments", call_site.bootstrap_method.name));
+    }
+    let interface_method_type = method_type(constant_pool, arguments[0])?;
+    let instantiated_type = parse_method_descriptor(&method_type(constant_pool, arguments[2])?)?;
+
+    let handle = constant_pool.find_method_handle_constant_pool_entry(arguments[1])?;
+    let target = constant_pool.find_member_ref(handle.reference_index)?;
+    let implementation = Implementation {
+        kind: handle.reference_kind,
+        class_name: target.class_name,
+        name: target.name,
+        descriptor: target.descriptor,
+        interface: matches!(constant_pool.get(handle.reference_index)?, ConstantPoolEntry::InterfaceMethodref(_)),
+    };
+    if !(REF_INVOKE_VIRTUAL..=REF_INVOKE_INTERFACE).contains(&implementation.kind) {
+        return Err(format!("Method handle kind {} can't implement a lambda", implementation.kind));
+    }
+
+    let factory = parse_method_descriptor(&call_site.descriptor)?;
+    let Some(FieldType::Object(interface)) = &factory.return_type else {
+        return Err(format!("Lambda call site {} doesn't return an interface", call_site.descriptor));
+    };
+    let captured = factory.parameters.clone();
+
+    // altMetafactory's flags say which of the marker interfaces and bridge method types
+    // follow
+    let mut interfaces = vec![interface.clone()];
+    let mut method_types = vec![interface_method_type];
+    if call_site.bootstrap_method.name == "altMetafactory" {
+        let flags = int_argument(constant_pool, arguments, 3)?;
+        let mut next = 4;
+        if flags & FLAG_MARKERS != 0 {
+            let count = int_argument(constant_pool, arguments, next)? as usize;
+            for index in arguments.iter().skip(next + 1).take(count) {
+                interfaces.push(constant_pool.find_class_name(*index)?);
+            }
+            next += 1 + count;
+        }
+        if flags & FLAG_SERIALIZABLE != 0 {
+            interfaces.push("java/io/Serializable".to_string());
+        }
+        if flags & FLAG_BRIDGES != 0 {
+            let count = int_argument(constant_pool, arguments, next)? as usize;
+            for index in arguments.iter().skip(next + 1).take(count) {
+                method_types.push(method_type(constant_pool, *index)?);
+            }
+        }
+    }
+
+    let mut writer = ClassWriter::new();
+    for (index, ty) in captured.iter().enumerate() {
+        writer.field(ACC_PRIVATE | ACC_FINAL, &captured_field(index), &ty.to_string());
+    }
+    let constructor = MethodDescriptor { parameters: captured.clone(), return_type: None };
+    let constructor_descriptor = descriptor_string(&constructor);
+    let code = constructor_code(&mut writer, class_name, &captured);
+    writer.method(ACC_PRIVATE, "<init>", &constructor_descriptor, &code);
+    let code = factory_code(&mut writer, class_name, &captured, &constructor_descriptor);
+    writer.method(ACC_PRIVATE | ACC_STATIC, LAMBDA_FACTORY, &call_site.descriptor, &code);
+
+    for (number, descriptor) in method_types.iter().enumerate() {
+        if method_types[..number].contains(descriptor) {
+            continue;
+        }
+        let access_flags = if number == 0 { ACC_PUBLIC } else { ACC_PUBLIC | ACC_BRIDGE | ACC_SYNTHETIC };
+        let erased = parse_method_descriptor(descriptor)?;
+        let code = forwarding_code(&mut writer, class_name, &captured, &erased, &instantiated_type, &implementation)?;
+        writer.method(access_flags, &call_site.name, descriptor, &code);
+    }
+
+    Ok(writer.finish(ACC_FINAL | ACC_SYNTHETIC, class_name, "java/lang/Object", &interfaces))
+}
+
+fn method_type(constant_pool: &ConstantPool, index: u16) -> Result<String, String> {
+    match constant_pool.get(index)? {
+        ConstantPoolEntry::MethodType(entry) => Ok(constant_pool.find_utf8_constant_pool_entry(entry.descriptor_index)?.bytes),
+        _ => Err(format!("Constant pool entry at index {} is not a method type entry", index)),
+    }
+}
+
+fn int_argument(constant_pool: &ConstantPool, arguments: &[u16], position: usize) -> Result<i32, String> {
+    let index = arguments.get(position).ok_or("altMetafactory has too few static arguments")?;
+    match constant_pool.get(*index)? {
+        ConstantPoolEntry::Integer(entry) => Ok(entry.value()),
+        _ => Err(format!("Constant pool entry at index {} is not an integer entry", index)),
+    }
+}
+
+fn captured_field(index: usize) -> String {
+    format!("arg${}", index + 1)
+}
+
+fn descriptor_string(descriptor: &MethodDescriptor) -> String {
+    let parameters: String = descriptor.parameters.iter().map(|parameter| parameter.to_string()).collect();
+    match &descriptor.return_type {
+        Some(return_type) => format!("({}){}", parameters, return_type),
+        None => format!("({})V", parameters),
+    }
+}
+
+fn slots(ty: &FieldType) -> u16 {
+    if ty.is_wide() { 2 } else { 1 }
+}
+
+// Stores the captured values in the fields
+fn constructor_code(writer: &mut ClassWriter, class_name: &str, captured: &[FieldType]) -> Code {
+    let mut code = Code { max_stack: 3, max_locals: 1 + captured.iter().map(slots).sum::<u16>(), ..Code::default() };
+    code.op(ALOAD_0);
+    code.op_u16(INVOKE_SPECIAL, writer.method_ref("java/lang/Object", "<init>", "()V", false));
+    let mut local = 1;
+    for (index, ty) in captured.iter().enumerate() {
+        code.op(ALOAD_0);
+        emit_load(&mut code, ty, local);
+        code.op_u16(PUT_FIELD, writer.field_ref(class_name, &captured_field(index), &ty.to_string()));
+        local += slots(ty);
+    }
+    code.op(RETURN);
+    code
+}
+
+fn factory_code(writer: &mut ClassWriter, class_name: &str, captured: &[FieldType], constructor_descriptor: &str) -> Code {
+    let captured_slots = captured.iter().map(slots).sum::<u16>();
+    let mut code = Code { max_stack: 2 + captured_slots, max_locals: captured_slots, ..Code::default() };
+    code.op_u16(NEW, writer.class(class_name));
+    code.op(DUP);
+    let mut local = 0;
+    for ty in captured {
+        emit_load(&mut code, ty, local);
+        local += slots(ty);
+    }
+    code.op_u16(INVOKE_SPECIAL, writer.method_ref(class_name, "<init>", constructor_descriptor, false));
+    code.op(ARETURN);
+    code
+}
+
+// Calls the implementation with the captured values and the arguments of the
+// interface method, `erased` being the method's type and `instantiated` what the
+// arguments are known to be
+fn forwarding_code(
+    writer: &mut ClassWriter,
+    class_name: &str,
+    captured: &[FieldType],
+    erased: &MethodDescriptor,
+    instantiated: &MethodDescriptor,
+    implementation: &Implementation,
+) -> Result<Code, String> {
+    let parameters = implementation.parameters()?;
+    if parameters.len() != captured.len() + erased.parameters.len() || erased.parameters.len() != instantiated.parameters.len() {
+        return Err(format!("{}.{}{} doesn't fit the lambda's type", implementation.class_name, implementation.name, implementation.descriptor));
+    }
+
+    let mut code = Code {
+        max_stack: 4 + 2 * parameters.len() as u16,
+        max_locals: 1 + erased.parameters.iter().map(slots).sum::<u16>(),
+        ..Code::default()
+    };
+    if implementation.kind == REF_NEW_INVOKE_SPECIAL {
+        code.op_u16(NEW, writer.class(&implementation.class_name));
+        code.op(DUP);
+    }
+    for (index, ty) in captured.iter().enumerate() {
+        code.op(ALOAD_0);
+        code.op_u16(GET_FIELD, writer.field_ref(class_name, &captured_field(index), &ty.to_string()));
+    }
+    let mut local = 1;
+    for ((ty, known), parameter) in erased.parameters.iter().zip(&instantiated.parameters).zip(&parameters[captured.len()..]) {
+        emit_load(&mut code, ty, local);
+        local += slots(ty);
+        if ty.is_reference() && known.is_reference() && known != ty {
+            code.op_u16(CHECKCAST, writer.class(&class_operand(known)));
+        }
+        emit_conversion(&mut code, writer, known, parameter);
+    }
+
+    let interface = implementation.interface;
+    let method = writer.method_ref(&implementation.class_name, &implementation.name, &implementation.descriptor, interface);
+    match implementation.kind {
+        REF_INVOKE_VIRTUAL => code.op_u16(INVOKE_VIRTUAL, method),
+        REF_INVOKE_STATIC => code.op_u16(INVOKE_STATIC, method),
+        REF_INVOKE_SPECIAL | REF_NEW_INVOKE_SPECIAL => code.op_u16(INVOKE_SPECIAL, method),
+        _ => {
+            code.op_u16(INVOKE_INTERFACE, method);
+            code.bytes.extend([parameters.iter().map(slots).sum::<u16>() as u8, 0]);
+        },
+    }
+
+    let result = implementation.result()?;
+    match (&result, &erased.return_type) {
+        (Some(result), None) => code.op(if result.is_wide() { POP2 } else { POP }),
+        (Some(result), Some(return_type)) => emit_conversion(&mut code, writer, result, return_type),
+        (None, Some(_)) => return Err(format!("{}.{}{} returns nothing", implementation.class_name, implementation.name, implementation.descriptor)),
+        (None, None) => {},
+    }
+    code.op(match &erased.return_type {
+        None => RETURN,
+        Some(FieldType::Long) => LRETURN,
+        Some(FieldType::Float) => FRETURN,
+        Some(FieldType::Double) => DRETURN,
+        Some(ty) if ty.is_reference() => ARETURN,
+        Some(_) => IRETURN,
+    });
+
+    Ok(code)
+}
+
+fn emit_load(code: &mut Code, ty: &FieldType, local: u16) {
+    let opcode = match ty {
+        FieldType::Long => LLOAD,
+        FieldType::Float => FLOAD,
+        FieldType::Double => DLOAD,
+        ty if ty.is_reference() => ALOAD,
+        _ => ILOAD,
+    };
+    match u8::try_from(local) {
+        Ok(local) => code.op_u8(opcode, local),
+        Err(_) => {
+            code.op(WIDE);
+            code.op_u16(opcode, local);
+        },
+    }
+}
+
+// What checkcast names a type by, array classes by their descriptor
+fn class_operand(ty: &FieldType) -> String {
+    match ty {
+        FieldType::Object(class_name) => class_name.clone(),
+        ty => ty.to_string(),
+    }
+}
+
+// The box of a primitive type and the method that unboxes it
+fn box_class(ty: &FieldType) -> Option<(&'static str, &'static str)> {
+    match ty {
+        FieldType::Boolean => Some(("java/lang/Boolean", "booleanValue")),
+        FieldType::Byte => Some(("java/lang/Byte", "byteValue")),
+        FieldType::Char => Some(("java/lang/Character", "charValue")),
+        FieldType::Short => Some(("java/lang/Short", "shortValue")),
+        FieldType::Int => Some(("java/lang/Integer", "intValue")),
+        FieldType::Long => Some(("java/lang/Long", "longValue")),
+        FieldType::Float => Some(("java/lang/Float", "floatValue")),
+        FieldType::Double => Some(("java/lang/Double", "doubleValue")),
+        FieldType::Object(_) | FieldType::Array(_) => None,
+    }
+}
+
+// The primitive type a box holds
+fn unboxed(ty: &FieldType) -> Option<FieldType> {
+    let primitives = [
+        FieldType::Boolean,
+        FieldType::Byte,
+        FieldType::Char,
+        FieldType::Short,
+        FieldType::Int,
+        FieldType::Long,
+        FieldType::Float,
+        FieldType::Double,
+    ];
+    let FieldType::Object(class_name) = ty else {
+        return None;
+    };
+    primitives.into_iter().find(|primitive| box_class(primitive).is_some_and(|(box_name, _)| box_name == class_name))
+}
+
+// Converts the value on top of the stack from one type to another the way the
+// metafactory adapts a lambda's arguments and result. References need no conversion.
+fn emit_conversion(code: &mut Code, writer: &mut ClassWriter, from: &FieldType, to: &FieldType) {
+    match (box_class(from), box_class(to)) {
+        (None, None) => {},
+        // Boxing
+        (Some((box_name, _)), None) => {
+            let descriptor = format!("({})L{};", from, box_name);
+            code.op_u16(INVOKE_STATIC, writer.method_ref(box_name, "valueOf", &descriptor, false));
+        },
+        // Unboxing, into the box's type and then widened
+        (None, Some(_)) => {
+            let primitive = unboxed(from).unwrap_or_else(|| to.clone());
+            let (box_name, unbox) = box_class(&primitive).expect("primitive types have boxes");
+            code.op_u16(CHECKCAST, writer.class(box_name));
+            code.op_u16(INVOKE_VIRTUAL, writer.method_ref(box_name, unbox, &format!("(){}", primitive), false));
+            emit_widening(code, &primitive, to);
+        },
+        (Some(_), Some(_)) => emit_widening(code, from, to),
+    }
+}
+
+fn emit_widening(code: &mut Code, from: &FieldType, to: &FieldType) {
+    let opcode = match (from, to) {
+        (FieldType::Long, FieldType::Float) => L2F,
+        (FieldType::Long, FieldType::Double) => L2D,
+        (FieldType::Float, FieldType::Double) => F2D,
+        (FieldType::Long | FieldType::Float | FieldType::Double, _) => return,
+        (_, FieldType::Long) => I2L,
+        (_, FieldType::Float) => I2F,
+        (_, FieldType::Double) => I2D,
+        _ => return,
+    };
+    code.op(opcode);
+}
@@ -10,7 +10,7 @@ pub struct Method {
     pub attributes: Vec<Attribute>,
 }
 
-pub fn parse_method(bytecode: &Vec<u8>, mut offset: usize) -> Result<(Method, usize), String> {
+pub fn parse_method(bytecode: &[u8], mut offset: usize) -> Result<(Method, usize), String> {
     let access_flags = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
@@ -23,8 +23,7 @@ pub fn parse_method(bytecode: &Vec<u8>, mut offset: usize) -> Result<(Method, us
     let attributes_count = BigEndianByteOrder::read_u16(bytecode, offset)?;
     offset += 2;
 
-    let mut attrs = Vec::new();
-    attrs.reserve(attributes_count as usize);
+    let mut attrs = Vec::with_capacity(attributes_count as usize);
 
     for _ in 0..attributes_count {
         let (attribute, attribute_offset) = attribute::parse_attribute(bytecode, offset)?;
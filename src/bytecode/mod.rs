@@ -1,14 +1,43 @@
+pub mod classpath;
 pub mod constantpool;
 pub mod method;
+pub mod field;
 pub mod attribute;
+pub mod descriptor;
 pub mod endianness;
+pub mod lambda;
+pub mod writer;
 
 use std::{fs::File, io::Read};
-use crate::bytecode::attribute::Attribute;
+use crate::bytecode::attribute::{Attribute, BootstrapMethod, CodeAttribute};
 use crate::bytecode::endianness::{BigEndianByteOrder, ByteOrder};
-use crate::bytecode::constantpool::ConstantPool;
+use crate::bytecode::constantpool::{ConstantPool, ConstantPoolEntry, MemberRef};
+use crate::bytecode::field::Field;
 use crate::bytecode::method::Method;
 
+// https://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.6-200-A.1
+pub const ACC_PUBLIC: u16 = 0x0001;
+pub const ACC_PRIVATE: u16 = 0x0002;
+pub const ACC_STATIC: u16 = 0x0008;
+pub const ACC_FINAL: u16 = 0x0010;
+pub const ACC_SYNCHRONIZED: u16 = 0x0020;
+pub const ACC_BRIDGE: u16 = 0x0040;
+pub const ACC_NATIVE: u16 = 0x0100;
+pub const ACC_INTERFACE: u16 = 0x0200;
+pub const ACC_ABSTRACT: u16 = 0x0400;
+pub const ACC_SYNTHETIC: u16 = 0x1000;
+pub const ACC_ENUM: u16 = 0x4000;
+
+/// An invokedynamic call site with its bootstrap method resolved.
+#[derive(Debug)]
+pub struct CallSite {
+    pub name: String,
+    pub descriptor: String,
+    pub bootstrap_method: MemberRef,
+    // The constant pool indexes of the static arguments the bootstrap method is passed
+    pub bootstrap_arguments: Vec<u16>,
+}
+
 #[derive(Debug, Default)]
 pub struct ParsedBytecode {
     pub minor_version: u16,
@@ -21,6 +50,7 @@ pub struct ParsedBytecode {
     pub interfaces_count: u16,
     pub interfaces: Vec<u16>,
     pub fields_count: u16,
+    pub fields: Vec<Field>,
     pub methods_count: u16,
     pub methods: Vec<Method>,
     pub attributes_count: u16,
@@ -34,10 +64,123 @@ pub fn from_file(path: &str) -> Result<ParsedBytecode, String> {
     // TODO: Use a buffer, no need to read the whole bytecode
     file.read_to_end(&mut output).map_err(|e| e.to_string())?;
 
-    return parse_bytecode(&output);
+    parse_bytecode(&output)
 }
 
-pub fn parse_bytecode(bytecode: &Vec<u8>) -> Result<ParsedBytecode, String> {
+impl ParsedBytecode {
+    /// The binary name of this class, e.g. `java/lang/Object`.
+    pub fn class_name(&self) -> Result<String, String> {
+        self.constant_pool.find_class_name(self.this_class)
+    }
+
+    /// The binary name of the superclass, None for `java/lang/Object`.
+    pub fn super_class_name(&self) -> Result<Option<String>, String> {
+        if self.super_class == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(self.constant_pool.find_class_name(self.super_class)?))
+    }
+
+    /// The binary names of the interfaces this class implements or this interface extends.
+    pub fn interface_names(&self) -> Result<Vec<String>, String> {
+        self.interfaces.iter().map(|&index| self.constant_pool.find_class_name(index)).collect()
+    }
+
+    pub fn method_name(&self, method: &Method) -> Result<String, String> {
+        Ok(self.constant_pool.find_utf8_constant_pool_entry(method.name_index)?.bytes)
+    }
+
+    pub fn method_descriptor(&self, method: &Method) -> Result<String, String> {
+        Ok(self.constant_pool.find_utf8_constant_pool_entry(method.descriptor_index)?.bytes)
+    }
+
+    pub fn field_name(&self, field: &Field) -> Result<String, String> {
+        Ok(self.constant_pool.find_utf8_constant_pool_entry(field.name_index)?.bytes)
+    }
+
+    pub fn field_descriptor(&self, field: &Field) -> Result<String, String> {
+        Ok(self.constant_pool.find_utf8_constant_pool_entry(field.descriptor_index)?.bytes)
+    }
+
+    pub fn find_method(&self, name: &str, descriptor: &str) -> Result<Option<&Method>, String> {
+        for method in &self.methods {
+            if self.method_name(method)? == name && self.method_descriptor(method)? == descriptor {
+                return Ok(Some(method));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn find_field(&self, name: &str, descriptor: &str) -> Result<Option<&Field>, String> {
+        for field in &self.fields {
+            if self.field_name(field)? == name && self.field_descriptor(field)? == descriptor {
+                return Ok(Some(field));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the constant pool index of a field's ConstantValue attribute, the value a
+    /// static field has before the class's initializer runs.
+    pub fn constant_value(&self, field: &Field) -> Result<Option<u16>, String> {
+        for attribute in &field.attributes {
+            let name = self.constant_pool.find_utf8_constant_pool_entry(attribute.name_index)?;
+            if name.bytes == "ConstantValue" {
+                return Ok(Some(BigEndianByteOrder::read_u16(&attribute.info, 0)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the Code attribute of a method, None for abstract and native methods.
+    pub fn code_attribute(&self, method: &Method) -> Result<Option<CodeAttribute>, String> {
+        for attribute in &method.attributes {
+            let name = self.constant_pool.find_utf8_constant_pool_entry(attribute.name_index)?;
+            if name.bytes == "Code" {
+                return Ok(Some(attribute.into_code_attribute()?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the entries of the class's BootstrapMethods attribute, which invokedynamic call sites index into.
+    pub fn bootstrap_methods(&self) -> Result<Vec<BootstrapMethod>, String> {
+        for attribute in &self.attributes {
+            let name = self.constant_pool.find_utf8_constant_pool_entry(attribute.name_index)?;
+            if name.bytes == "BootstrapMethods" {
+                return attribute.into_bootstrap_methods();
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Resolves the InvokeDynamic entry at `index`.
+    pub fn call_site(&self, index: u16) -> Result<CallSite, String> {
+        let entry = self.constant_pool.find_invoke_dynamic_constant_pool_entry(index)?;
+        let (name, descriptor) = self.constant_pool.find_name_and_type(entry.name_and_type_index)?;
+        let mut bootstrap_methods = self.bootstrap_methods()?;
+        if entry.bootstrap_method_attr_index as usize >= bootstrap_methods.len() {
+            return Err(format!("Bootstrap method index out of bounds: {}", entry.bootstrap_method_attr_index));
+        }
+        let bootstrap = bootstrap_methods.swap_remove(entry.bootstrap_method_attr_index as usize);
+        let handle = self.constant_pool.find_method_handle_constant_pool_entry(bootstrap.bootstrap_method_ref)?;
+
+        Ok(CallSite {
+            name,
+            descriptor,
+            bootstrap_method: self.constant_pool.find_member_ref(handle.reference_index)?,
+            bootstrap_arguments: bootstrap.bootstrap_arguments,
+        })
+    }
+}
+
+pub fn parse_bytecode(bytecode: &[u8]) -> Result<ParsedBytecode, String> {
     let mut parsed_bytecode = ParsedBytecode::default();
     let mut offset = 0;
     let magic = BigEndianByteOrder::read_u32(bytecode, offset)?;
@@ -89,21 +232,25 @@ pub fn parse_bytecode(bytecode: &Vec<u8>) -> Result<ParsedBytecode, String> {
     Ok(parsed_bytecode)
 }
 
-fn parse_constant_pool(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut offset: usize) -> Result<usize, String> {
+fn parse_constant_pool(parsed_bytecode: &mut ParsedBytecode, bytecode: &[u8], mut offset: usize) -> Result<usize, String> {
     // For some dumb reason, the constant pool count is 1 indexed.
-    parsed_bytecode.constant_pool.entries.reserve(parsed_bytecode.constant_pool_count as usize - 1);
+    let count = parsed_bytecode.constant_pool_count as usize - 1;
+    parsed_bytecode.constant_pool.entries.reserve(count);
 
-    for _ in 1..parsed_bytecode.constant_pool_count {
+    while parsed_bytecode.constant_pool.entries.len() < count {
         let (entry, entry_offset) = constantpool::parse_constant_pool_entry(bytecode, offset)?;
-        println!("Parsed constant pool entry: {:?}", entry);
+        let is_wide = matches!(entry, ConstantPoolEntry::Long(_) | ConstantPoolEntry::Double(_));
         parsed_bytecode.constant_pool.entries.push(entry);
+        if is_wide {
+            parsed_bytecode.constant_pool.entries.push(ConstantPoolEntry::Unusable);
+        }
         offset = entry_offset;
     }
 
-    return Ok(offset);
+    Ok(offset)
 }
 
-fn parse_interfaces(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut offset: usize) -> Result<usize, String> {
+fn parse_interfaces(parsed_bytecode: &mut ParsedBytecode, bytecode: &[u8], mut offset: usize) -> Result<usize, String> {
     parsed_bytecode.interfaces.reserve(parsed_bytecode.interfaces_count as usize);
     for _ in 0..parsed_bytecode.interfaces_count {
         let interface = BigEndianByteOrder::read_u16(bytecode, offset)?;
@@ -111,18 +258,21 @@ fn parse_interfaces(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mu
         offset += 2;
     }
 
-    return Ok(offset);
+    Ok(offset)
 }
 
-fn parse_fields(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut offset: usize) -> Result<usize, String> {
-    if parsed_bytecode.fields_count == 0 {
-        return Ok(offset);
+fn parse_fields(parsed_bytecode: &mut ParsedBytecode, bytecode: &[u8], mut offset: usize) -> Result<usize, String> {
+    parsed_bytecode.fields.reserve(parsed_bytecode.fields_count as usize);
+    for _ in 0..parsed_bytecode.fields_count {
+        let (field, field_offset) = field::parse_field(bytecode, offset)?;
+        parsed_bytecode.fields.push(field);
+        offset = field_offset;
     }
 
-    todo!("Implement parsing fields");
+    Ok(offset)
 }
 
-fn parse_methods(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut offset: usize) -> Result<usize, String> {
+fn parse_methods(parsed_bytecode: &mut ParsedBytecode, bytecode: &[u8], mut offset: usize) -> Result<usize, String> {
     if parsed_bytecode.methods_count == 0 {
         return Ok(offset);
     }
@@ -134,10 +284,10 @@ fn parse_methods(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut o
         offset = method_offset;
     }
 
-    return Ok(offset);
+    Ok(offset)
 }
 
-fn parse_attributes(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mut offset: usize) -> Result<usize, String> {
+fn parse_attributes(parsed_bytecode: &mut ParsedBytecode, bytecode: &[u8], mut offset: usize) -> Result<usize, String> {
     if parsed_bytecode.attributes_count == 0 {
         return Ok(offset);
     }
@@ -149,7 +299,7 @@ fn parse_attributes(parsed_bytecode: &mut ParsedBytecode, bytecode: &Vec<u8>, mu
         offset = attribute_offset;
     }
 
-    return Ok(offset);
+    Ok(offset)
 }
 
 pub fn print_bytecode_methods(parsed_bytecode: &ParsedBytecode) -> Result<(), String> {
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::bytecode::constantpool::{
+    encode_modified_utf8, CONSTANT_CLASS_INFO, CONSTANT_FIELD_REF, CONSTANT_INTERFACE_METHOD_REF, CONSTANT_METHOD_REF, CONSTANT_NAME_AND_TYPE, CONSTANT_UTF8,
+};
+
+// Writes the class files of the classes the compiler makes up itself, which are parsed
+// back like the ones read from disk. Only what those classes need is supported: no
+// attributes besides Code.
+
+// https://docs.oracle.com/javase/specs/jvms/se17/html/jvms-4.html#jvms-4.1
+const MAGIC: u32 = 0xCAFEBABE;
+const MAJOR_VERSION: u16 = 52;
+
+/// The bytecode of a method and the frame it needs.
+#[derive(Debug, Default)]
+pub struct Code {
+    pub bytes: Vec<u8>,
+    pub max_stack: u16,
+    pub max_locals: u16,
+    // The exception handlers as (start pc, end pc, handler pc, catch type), the catch
+    // type being a class constant or 0 to catch everything
+    pub exception_table: Vec<[u16; 4]>,
+}
+
+impl Code {
+    pub fn op(&mut self, opcode: u8) {
+        self.bytes.push(opcode);
+    }
+
+    pub fn op_u8(&mut self, opcode: u8, operand: u8) {
+        self.bytes.extend([opcode, operand]);
+    }
+
+    pub fn op_u16(&mut self, opcode: u8, operand: u16) {
+        self.bytes.push(opcode);
+        self.bytes.extend(operand.to_be_bytes());
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClassWriter {
+    constant_pool: Vec<u8>,
+    // The index of every constant so far by its encoding, each is added once
+    constants: HashMap<Vec<u8>, u16>,
+    fields: Vec<u8>,
+    field_count: u16,
+    methods: Vec<u8>,
+    method_count: u16,
+}
+
+impl ClassWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn constant(&mut self, entry: Vec<u8>) -> u16 {
+        if let Some(index) = self.constants.get(&entry) {
+            return *index;
+        }
+
+        let index = self.constants.len() as u16 + 1;
+        self.constant_pool.extend(&entry);
+        self.constants.insert(entry, index);
+        index
+    }
+
+    pub fn utf8(&mut self, text: &str) -> u16 {
+        let bytes = encode_modified_utf8(text);
+        let mut entry = vec![CONSTANT_UTF8];
+        entry.extend((bytes.len() as u16).to_be_bytes());
+        entry.extend(bytes);
+        self.constant(entry)
+    }
+
+    pub fn class(&mut self, name: &str) -> u16 {
+        let name_index = self.utf8(name);
+        self.constant(tagged(CONSTANT_CLASS_INFO, &[name_index]))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        self.constant(tagged(CONSTANT_NAME_AND_TYPE, &[name_index, descriptor_index]))
+    }
+
+    pub fn field_ref(&mut self, class_name: &str, name: &str, descriptor: &str) -> u16 {
+        self.member_ref(CONSTANT_FIELD_REF, class_name, name, descriptor)
+    }
+
+    /// A Methodref, or an InterfaceMethodref when the method's class is an interface.
+    pub fn method_ref(&mut self, class_name: &str, name: &str, descriptor: &str, interface: bool) -> u16 {
+        let tag = if interface { CONSTANT_INTERFACE_METHOD_REF } else { CONSTANT_METHOD_REF };
+        self.member_ref(tag, class_name, name, descriptor)
+    }
+
+    fn member_ref(&mut self, tag: u8, class_name: &str, name: &str, descriptor: &str) -> u16 {
+        let class_index = self.class(class_name);
+        let name_and_type_index = self.name_and_type(name, descriptor);
+        self.constant(tagged(tag, &[class_index, name_and_type_index]))
+    }
+
+    pub fn field(&mut self, access_flags: u16, name: &str, descriptor: &str) {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        for value in [access_flags, name_index, descriptor_index, 0] {
+            self.fields.extend(value.to_be_bytes());
+        }
+        self.field_count += 1;
+    }
+
+    pub fn method(&mut self, access_flags: u16, name: &str, descriptor: &str, code: &Code) {
+        let name_index = self.utf8(name);
+        let descriptor_index = self.utf8(descriptor);
+        let code_index = self.utf8("Code");
+        for value in [access_flags, name_index, descriptor_index, 1, code_index] {
+            self.methods.extend(value.to_be_bytes());
+        }
+
+        // max_stack, max_locals, the code, the exception table and an empty attribute table
+        let length = 2 + 2 + 4 + code.bytes.len() + 2 + 8 * code.exception_table.len() + 2;
+        self.methods.extend((length as u32).to_be_bytes());
+        self.methods.extend(code.max_stack.to_be_bytes());
+        self.methods.extend(code.max_locals.to_be_bytes());
+        self.methods.extend((code.bytes.len() as u32).to_be_bytes());
+        self.methods.extend(&code.bytes);
+        self.methods.extend((code.exception_table.len() as u16).to_be_bytes());
+        for value in code.exception_table.iter().flatten() {
+            self.methods.extend(value.to_be_bytes());
+        }
+        self.methods.extend(0u16.to_be_bytes());
+        self.method_count += 1;
+    }
+
+    /// The class file of a class with the fields and methods added so far.
+    pub fn finish(mut self, access_flags: u16, class_name: &str, super_class: &str, interfaces: &[String]) -> Vec<u8> {
+        let this_class = self.class(class_name);
+        let super_class = self.class(super_class);
+        let interfaces: Vec<u16> = interfaces.iter().map(|interface| self.class(interface)).collect();
+
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC.to_be_bytes());
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend(MAJOR_VERSION.to_be_bytes());
+        bytes.extend((self.constants.len() as u16 + 1).to_be_bytes());
+        bytes.extend(&self.constant_pool);
+        for value in [access_flags, this_class, super_class, interfaces.len() as u16] {
+            bytes.extend(value.to_be_bytes());
+        }
+        for interface in interfaces {
+            bytes.extend(interface.to_be_bytes());
+        }
+        bytes.extend(self.field_count.to_be_bytes());
+        bytes.extend(&self.fields);
+        bytes.extend(self.method_count.to_be_bytes());
+        bytes.extend(&self.methods);
+        // No class attributes
+        bytes.extend(0u16.to_be_bytes());
+        bytes
+    }
+}
+
+fn tagged(tag: u8, values: &[u16]) -> Vec<u8> {
+    let mut entry = vec![tag];
+    for value in values {
+        entry.extend(value.to_be_bytes());
+    }
+    entry
+}
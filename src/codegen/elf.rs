@@ -0,0 +1,459 @@
+use crate::codegen::encoder::{Object, RelocationKind, SectionKind, SECTIONS};
+
+// Writes assembled objects as ELF64 files for x86_64, either relocatable objects for a
+// linker or static executables that need nothing else.
+//
+// https://refspecs.linuxfoundation.org/elf/gabi4+/contents.html
+// https://gitlab.com/x86-psABIs/x86-64-ABI
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 62;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+const HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const SYMBOL_SIZE: usize = 24;
+const RELA_SIZE: usize = 24;
+
+// Executables are loaded at the traditional non-PIE address
+const BASE_ADDRESS: u64 = 0x400000;
+const PAGE_SIZE: u64 = 0x1000;
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn pad_to(bytes: &mut Vec<u8>, alignment: usize) {
+    while !bytes.len().is_multiple_of(alignment) {
+        bytes.push(0);
+    }
+}
+
+fn relocation_type(kind: RelocationKind) -> u32 {
+    match kind {
+        RelocationKind::Absolute64 => 1,
+        RelocationKind::Relative32 => 2,
+        RelocationKind::Plt32 => 4,
+        RelocationKind::Absolute32S => 11,
+    }
+}
+
+fn section_flags(section: SectionKind) -> u64 {
+    match section {
+        SectionKind::Text => SHF_ALLOC | SHF_EXECINSTR,
+        SectionKind::Data => SHF_ALLOC | SHF_WRITE,
+        SectionKind::ReadOnlyData => SHF_ALLOC,
+    }
+}
+
+fn section_alignment(section: SectionKind) -> u64 {
+    match section {
+        SectionKind::Text => 16,
+        _ => 8,
+    }
+}
+
+fn write_header(bytes: &mut Vec<u8>, kind: u16, entry: u64, program_headers: u16, section_headers_offset: u64, section_headers: u16, names_index: u16) {
+    // Magic, 64-bit, little endian, version 1, System V ABI
+    bytes.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    bytes.extend_from_slice(&[0; 8]);
+    push_u16(bytes, kind);
+    push_u16(bytes, EM_X86_64);
+    push_u32(bytes, 1);
+    push_u64(bytes, entry);
+    push_u64(bytes, if program_headers > 0 { HEADER_SIZE as u64 } else { 0 });
+    push_u64(bytes, section_headers_offset);
+    push_u32(bytes, 0);
+    push_u16(bytes, HEADER_SIZE as u16);
+    push_u16(bytes, if program_headers > 0 { PROGRAM_HEADER_SIZE as u16 } else { 0 });
+    push_u16(bytes, program_headers);
+    push_u16(bytes, if section_headers > 0 { SECTION_HEADER_SIZE as u16 } else { 0 });
+    push_u16(bytes, section_headers);
+    push_u16(bytes, names_index);
+}
+
+#[derive(Default)]
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    alignment: u64,
+    entry_size: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        push_u32(bytes, self.name);
+        push_u32(bytes, self.kind);
+        push_u64(bytes, self.flags);
+        // Not loaded, so no address
+        push_u64(bytes, 0);
+        push_u64(bytes, self.offset);
+        push_u64(bytes, self.size);
+        push_u32(bytes, self.link);
+        push_u32(bytes, self.info);
+        push_u64(bytes, self.alignment);
+        push_u64(bytes, self.entry_size);
+    }
+}
+
+#[derive(Default)]
+struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        // Index 0 is the empty string
+        Self { bytes: vec![0] }
+    }
+
+    fn add(&mut self, string: &str) -> u32 {
+        let index = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(string.as_bytes());
+        self.bytes.push(0);
+        index
+    }
+}
+
+fn write_symbol(bytes: &mut Vec<u8>, name: u32, binding: u8, kind: u8, section_index: u16, value: u64) {
+    push_u32(bytes, name);
+    bytes.push(binding << 4 | kind);
+    bytes.push(0);
+    push_u16(bytes, section_index);
+    push_u64(bytes, value);
+    push_u64(bytes, 0);
+}
+
+/// Writes a relocatable object. The sections are laid out as:
+///
+/// null, .text, .data, .rodata, .rela.text, .rela.data, .rela.rodata, .symtab, .strtab, .shstrtab
+pub fn write_relocatable(object: &Object) -> Vec<u8> {
+    let section_index = |section: SectionKind| section.index() as u16 + 1;
+    let symtab_index = 1 + 2 * SECTIONS.len() as u32;
+
+    // Locals come first: the section symbols relocations against local labels use, then
+    // the labels themselves so disassemblers and debuggers can show them
+    let mut strtab = StringTable::new();
+    let mut symbols = Vec::new();
+    write_symbol(&mut symbols, 0, 0, 0, 0, 0);
+    for section in SECTIONS {
+        write_symbol(&mut symbols, 0, STB_LOCAL, STT_SECTION, section_index(section), 0);
+    }
+    for label in object.labels.iter().filter(|label| !label.global) {
+        let name = strtab.add(&label.name);
+        write_symbol(&mut symbols, name, STB_LOCAL, STT_NOTYPE, section_index(label.section), label.offset);
+    }
+    let first_global = symbols.len() / SYMBOL_SIZE;
+
+    let mut global_indexes = Vec::new();
+    for label in object.labels.iter().filter(|label| label.global) {
+        global_indexes.push((label.name.as_str(), symbols.len() / SYMBOL_SIZE));
+        let name = strtab.add(&label.name);
+        write_symbol(&mut symbols, name, STB_GLOBAL, STT_NOTYPE, section_index(label.section), label.offset);
+    }
+    for name in &object.externs {
+        global_indexes.push((name.as_str(), symbols.len() / SYMBOL_SIZE));
+        let string = strtab.add(name);
+        write_symbol(&mut symbols, string, STB_GLOBAL, STT_NOTYPE, 0, 0);
+    }
+
+    let mut relocations: Vec<Vec<u8>> = vec![Vec::new(); SECTIONS.len()];
+    for relocation in &object.relocations {
+        let (symbol, addend) = match global_indexes.iter().find(|(name, _)| *name == relocation.symbol) {
+            Some((_, index)) => (*index, relocation.addend),
+            None => {
+                // Checked by the encoder, every other symbol is a local label
+                let label = object.label(&relocation.symbol).unwrap();
+                (section_index(label.section) as usize, label.offset as i64 + relocation.addend)
+            },
+        };
+        let bytes = &mut relocations[section_index(relocation.section) as usize - 1];
+        push_u64(bytes, relocation.offset);
+        push_u64(bytes, (symbol as u64) << 32 | relocation_type(relocation.kind) as u64);
+        push_u64(bytes, addend as u64);
+    }
+
+    let mut names = StringTable::new();
+    let mut headers = vec![SectionHeader::default()];
+    let mut bytes = vec![0; HEADER_SIZE];
+
+    for section in SECTIONS {
+        let data = object.section(section);
+        pad_to(&mut bytes, section_alignment(section) as usize);
+        headers.push(SectionHeader {
+            name: names.add(section.name()),
+            kind: SHT_PROGBITS,
+            flags: section_flags(section),
+            offset: bytes.len() as u64,
+            size: data.len() as u64,
+            alignment: section_alignment(section),
+            ..Default::default()
+        });
+        bytes.extend_from_slice(data);
+    }
+    for (i, section) in SECTIONS.iter().enumerate() {
+        pad_to(&mut bytes, 8);
+        headers.push(SectionHeader {
+            name: names.add(&format!(".rela{}", section.name())),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset: bytes.len() as u64,
+            size: relocations[i].len() as u64,
+            link: symtab_index,
+            info: section_index(*section) as u32,
+            alignment: 8,
+            entry_size: RELA_SIZE as u64,
+        });
+        bytes.extend_from_slice(&relocations[i]);
+    }
+
+    pad_to(&mut bytes, 8);
+    headers.push(SectionHeader {
+        name: names.add(".symtab"),
+        kind: SHT_SYMTAB,
+        offset: bytes.len() as u64,
+        size: symbols.len() as u64,
+        link: symtab_index + 1,
+        info: first_global as u32,
+        alignment: 8,
+        entry_size: SYMBOL_SIZE as u64,
+        ..Default::default()
+    });
+    bytes.extend_from_slice(&symbols);
+
+    headers.push(SectionHeader {
+        name: names.add(".strtab"),
+        kind: SHT_STRTAB,
+        offset: bytes.len() as u64,
+        size: strtab.bytes.len() as u64,
+        alignment: 1,
+        ..Default::default()
+    });
+    bytes.extend_from_slice(&strtab.bytes);
+
+    let shstrtab_name = names.add(".shstrtab");
+    headers.push(SectionHeader {
+        name: shstrtab_name,
+        kind: SHT_STRTAB,
+        offset: bytes.len() as u64,
+        size: names.bytes.len() as u64,
+        alignment: 1,
+        ..Default::default()
+    });
+    bytes.extend_from_slice(&names.bytes);
+
+    pad_to(&mut bytes, 8);
+    let section_headers_offset = bytes.len() as u64;
+    for header in &headers {
+        header.write(&mut bytes);
+    }
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    write_header(&mut header, ET_REL, 0, 0, section_headers_offset, headers.len() as u16, headers.len() as u16 - 1);
+    bytes[..HEADER_SIZE].copy_from_slice(&header);
+
+    bytes
+}
+
+/// Links an object on its own into a static executable starting at `entry`. Every
+/// symbol has to be defined, there is nothing else to link against.
+pub fn write_executable(object: &Object, entry: &str) -> Result<Vec<u8>, String> {
+    if let Some(name) = object.externs.first() {
+        return Err(format!("Undefined symbol: {}", name));
+    }
+
+    // Each non-empty section gets its own page aligned segment, the headers take the first page
+    let mut addresses = [0u64; 3];
+    let mut segments = Vec::new();
+    let mut offset = PAGE_SIZE;
+    for (i, section) in SECTIONS.iter().enumerate() {
+        let size = object.section(*section).len() as u64;
+        if size == 0 {
+            continue;
+        }
+
+        addresses[i] = BASE_ADDRESS + offset;
+        segments.push((*section, offset));
+        offset = (offset + size).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    }
+
+    let section_address = |section: SectionKind| addresses[section.index()];
+    let address = |name: &str| -> Result<u64, String> {
+        let label = object.label(name).ok_or_else(|| format!("Undefined symbol: {}", name))?;
+        Ok(section_address(label.section) + label.offset)
+    };
+
+    let mut sections = object.sections.clone();
+    for relocation in &object.relocations {
+        let value = (address(&relocation.symbol)? as i64).wrapping_add(relocation.addend);
+        let place = section_address(relocation.section) + relocation.offset;
+        let bytes = &mut sections[relocation.section.index()];
+        let start = relocation.offset as usize;
+
+        match relocation.kind {
+            RelocationKind::Absolute64 => bytes[start..start + 8].copy_from_slice(&value.to_le_bytes()),
+            RelocationKind::Absolute32S => {
+                let value = i32::try_from(value).map_err(|_| format!("Address of {} does not fit 32 bits", relocation.symbol))?;
+                bytes[start..start + 4].copy_from_slice(&value.to_le_bytes());
+            },
+            RelocationKind::Relative32 | RelocationKind::Plt32 => {
+                let value = i32::try_from(value - place as i64).map_err(|_| format!("{} is out of range", relocation.symbol))?;
+                bytes[start..start + 4].copy_from_slice(&value.to_le_bytes());
+            },
+        }
+    }
+
+    let mut bytes = Vec::new();
+    write_header(&mut bytes, ET_EXEC, address(entry)?, segments.len() as u16, 0, 0, 0);
+    for (section, offset) in &segments {
+        let flags = match section {
+            SectionKind::Text => PF_R | PF_X,
+            SectionKind::Data => PF_R | PF_W,
+            SectionKind::ReadOnlyData => PF_R,
+        };
+        let size = object.section(*section).len() as u64;
+
+        push_u32(&mut bytes, PT_LOAD);
+        push_u32(&mut bytes, flags);
+        push_u64(&mut bytes, *offset);
+        push_u64(&mut bytes, BASE_ADDRESS + offset);
+        push_u64(&mut bytes, BASE_ADDRESS + offset);
+        push_u64(&mut bytes, size);
+        push_u64(&mut bytes, size);
+        push_u64(&mut bytes, PAGE_SIZE);
+    }
+
+    for (section, offset) in &segments {
+        bytes.resize(*offset as usize, 0);
+        bytes.extend_from_slice(&sections[section.index()]);
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::*;
+    use crate::codegen::encoder::assemble;
+    use crate::codegen::inst::{ArithmeticOp, Memory, Operand, Register, X86Inst};
+    use crate::codegen::Assembly;
+
+    fn reg(name: &str) -> Operand {
+        Operand::Register(Register::parse(name).unwrap())
+    }
+
+    fn value(base: Option<&str>, displacement: i64) -> Operand {
+        Operand::Memory(Memory {
+            size: Some(4),
+            base: base.map(|base| Register::parse(base).unwrap()),
+            index: None,
+            symbol: base.is_none().then(|| "value".to_string()),
+            displacement,
+        })
+    }
+
+    // Exits with 40 + 2 read from .data, through an absolute and a RIP-relative address
+    fn program() -> Object {
+        let code = vec![
+            X86Inst::Global("_start".to_string()),
+            X86Inst::Label("_start".to_string()),
+            X86Inst::Call(Operand::Symbol("load".to_string(), 0)),
+            X86Inst::Mov(reg("edi"), reg("eax")),
+            X86Inst::Mov(reg("eax"), Operand::Immediate(60)),
+            X86Inst::Syscall,
+            X86Inst::Label("load".to_string()),
+            X86Inst::Mov(reg("rsi"), Operand::Symbol("value".to_string(), 0)),
+            X86Inst::Mov(reg("eax"), value(Some("rsi"), 0)),
+            X86Inst::Arithmetic(ArithmeticOp::Add, reg("eax"), value(None, 4)),
+            X86Inst::Ret,
+            X86Inst::Section(".data".to_string()),
+            X86Inst::Label("value".to_string()),
+            X86Inst::Db(vec![40, 0, 0, 0, 2, 0, 0, 0]),
+        ];
+        assemble(&[&Assembly { code }]).unwrap()
+    }
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("npjava-elf-{}-{}", std::process::id(), name))
+    }
+
+    fn run(path: &PathBuf) -> Option<i32> {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        let status = Command::new(path).status().unwrap();
+        let _ = fs::remove_file(path);
+        status.code()
+    }
+
+    #[test]
+    fn writes_executables() {
+        let bytes = write_executable(&program(), "_start").unwrap();
+        assert_eq!(&bytes[..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([bytes[16], bytes[17]]), ET_EXEC);
+        // Text and data, each in a segment of its own
+        assert_eq!(u16::from_le_bytes([bytes[56], bytes[57]]), 2);
+
+        let path = test_path("executable");
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(run(&path), Some(42));
+    }
+
+    #[test]
+    fn writes_relocatable_objects_that_ld_links() {
+        let bytes = write_relocatable(&program());
+        assert_eq!(&bytes[..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([bytes[16], bytes[17]]), ET_REL);
+        assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), EM_X86_64);
+        // null, 3 sections, 3 relocation sections, .symtab, .strtab and .shstrtab
+        assert_eq!(u16::from_le_bytes([bytes[60], bytes[61]]), 10);
+
+        if !Command::new("ld").arg("--version").output().is_ok_and(|output| output.status.success()) {
+            return;
+        }
+        let object = test_path("object.o");
+        let executable = test_path("linked");
+        fs::write(&object, bytes).unwrap();
+        let output = Command::new("ld").arg("-o").arg(&executable).arg(&object).output().unwrap();
+        let _ = fs::remove_file(&object);
+        assert!(output.status.success(), "ld failed: {}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(run(&executable), Some(42));
+    }
+}
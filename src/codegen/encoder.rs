@@ -0,0 +1,1000 @@
+use std::collections::HashMap;
+
+use crate::codegen::inst::{Operand, Register, RegisterKind, SseOp, X86Inst};
+use crate::codegen::{syntax, Assembly};
+
+// Turns the instructions the backend generates into x86_64 machine code, so programs can
+// be built without an external assembler. Only the operand forms we emit are supported,
+// anything else is an error.
+//
+// https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Text,
+    Data,
+    ReadOnlyData,
+}
+
+pub const SECTIONS: [SectionKind; 3] = [SectionKind::Text, SectionKind::Data, SectionKind::ReadOnlyData];
+
+impl SectionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SectionKind::Text => ".text",
+            SectionKind::Data => ".data",
+            SectionKind::ReadOnlyData => ".rodata",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        SECTIONS.iter().position(|s| *s == self).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    // S + A, dq of an address or mov of one into a 64-bit register
+    Absolute64,
+    // S + A sign extended from 32 bits, for [symbol + index*scale]
+    Absolute32S,
+    // S + A - P, RIP-relative memory and jumps
+    Relative32,
+    // Like Relative32 but for calls, which the linker may route through the PLT
+    Plt32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: SectionKind,
+    pub offset: u64,
+    pub symbol: String,
+    pub kind: RelocationKind,
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    pub section: SectionKind,
+    pub offset: u64,
+    pub global: bool,
+}
+
+/// An assembled object: the contents of the three sections, the labels defined in them
+/// and the references the linker still has to fill in.
+#[derive(Debug, Default)]
+pub struct Object {
+    // Indexed like SECTIONS
+    pub sections: [Vec<u8>; 3],
+    pub labels: Vec<Label>,
+    pub externs: Vec<String>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    pub fn section(&self, section: SectionKind) -> &[u8] {
+        &self.sections[section.index()]
+    }
+
+    pub fn label(&self, name: &str) -> Option<&Label> {
+        self.labels.iter().find(|label| label.name == name)
+    }
+}
+
+struct Assembler {
+    object: Object,
+    section: SectionKind,
+    label_indexes: HashMap<String, usize>,
+    globals: Vec<String>,
+}
+
+// Scalar SSE instructions taking xmm, xmm/m as (mandatory prefix, opcode after 0F)
+fn sse_encoding(op: SseOp) -> (Option<u8>, u8) {
+    match op {
+        SseOp::Movss => (Some(0xf3), 0x10),
+        SseOp::Movsd => (Some(0xf2), 0x10),
+        SseOp::Addss => (Some(0xf3), 0x58),
+        SseOp::Addsd => (Some(0xf2), 0x58),
+        SseOp::Mulss => (Some(0xf3), 0x59),
+        SseOp::Mulsd => (Some(0xf2), 0x59),
+        SseOp::Subss => (Some(0xf3), 0x5c),
+        SseOp::Subsd => (Some(0xf2), 0x5c),
+        SseOp::Divss => (Some(0xf3), 0x5e),
+        SseOp::Divsd => (Some(0xf2), 0x5e),
+        SseOp::Cvtss2sd => (Some(0xf3), 0x5a),
+        SseOp::Cvtsd2ss => (Some(0xf2), 0x5a),
+        SseOp::Ucomiss => (None, 0x2e),
+        SseOp::Ucomisd => (Some(0x66), 0x2e),
+        SseOp::Xorps => (None, 0x57),
+        SseOp::Cvtsi2ss => (Some(0xf3), 0x2a),
+        SseOp::Cvtsi2sd => (Some(0xf2), 0x2a),
+        SseOp::Cvttss2si => (Some(0xf3), 0x2c),
+        SseOp::Cvttsd2si => (Some(0xf2), 0x2c),
+        SseOp::Sqrtss => (Some(0xf3), 0x51),
+        SseOp::Sqrtsd => (Some(0xf2), 0x51),
+    }
+}
+
+fn fits_i8(value: i64) -> bool {
+    i8::try_from(value).is_ok()
+}
+
+fn fits_i32(value: i64) -> bool {
+    i32::try_from(value).is_ok()
+}
+
+// A 32-bit immediate, either signed or a bit pattern like 0x80000000
+fn imm32(value: i64, size: u8) -> Result<i64, String> {
+    if fits_i32(value) || (size <= 4 && (0..=u32::MAX as i64).contains(&value)) {
+        Ok(value)
+    } else {
+        Err(format!("Immediate out of range: {}", value))
+    }
+}
+
+// Set in a reg field above the register number for the byte registers that only exist
+// with a REX prefix
+const NEEDS_REX: u8 = 0x10;
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            object: Object::default(),
+            section: SectionKind::Text,
+            label_indexes: HashMap::new(),
+            globals: Vec::new(),
+        }
+    }
+
+    fn bytes(&mut self) -> &mut Vec<u8> {
+        &mut self.object.sections[self.section.index()]
+    }
+
+    fn offset(&self) -> u64 {
+        self.object.sections[self.section.index()].len() as u64
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes().extend_from_slice(bytes);
+    }
+
+    fn emit_immediate(&mut self, value: i64, size: usize) {
+        let bytes = value.to_le_bytes();
+        self.emit(&bytes[..size]);
+    }
+
+    // A placeholder the linker, or `finish` for local jumps, fills in
+    fn emit_fixup(&mut self, symbol: &str, kind: RelocationKind, addend: i64) {
+        let size = if kind == RelocationKind::Absolute64 { 8 } else { 4 };
+        self.object.relocations.push(Relocation {
+            section: self.section,
+            offset: self.offset(),
+            symbol: symbol.to_string(),
+            kind,
+            addend,
+        });
+        self.emit(&vec![0; size]);
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), String> {
+        if self.label_indexes.contains_key(name) {
+            return Err(format!("Label defined twice: {}", name));
+        }
+
+        self.label_indexes.insert(name.to_string(), self.object.labels.len());
+        self.object.labels.push(Label {
+            name: name.to_string(),
+            section: self.section,
+            offset: self.offset(),
+            global: false,
+        });
+        Ok(())
+    }
+
+    /// The reg field for a register, with `NEEDS_REX` set for spl, bpl, sil and dil.
+    fn reg_field(register: &Register) -> u8 {
+        if register.needs_rex() { register.number | NEEDS_REX } else { register.number }
+    }
+
+    /// Emits the prefixes, REX, opcode and the ModRM, SIB and displacement addressing
+    /// `rm` with `reg` in the reg field. `immediate_size` is the size of the immediate
+    /// the caller emits next, RIP-relative displacements are relative to its end.
+    fn emit_modrm(
+        &mut self,
+        prefix: Option<u8>,
+        wide: bool,
+        opcode: &[u8],
+        reg: u8,
+        rm: &Operand,
+        immediate_size: usize,
+    ) -> Result<(), String> {
+        let mut rex = if wide { 0x48 } else { 0 };
+        if reg & 8 != 0 {
+            rex |= 0x44;
+        }
+        if reg & NEEDS_REX != 0 {
+            rex |= 0x40;
+        }
+        let mut high_byte = false;
+        match rm {
+            Operand::Register(register) => {
+                if register.number & 8 != 0 {
+                    rex |= 0x41;
+                }
+                if register.needs_rex() {
+                    rex |= 0x40;
+                }
+                high_byte = register.high_byte;
+            },
+            Operand::Memory(memory) => {
+                if memory.base.is_some_and(|base| base.number & 8 != 0) {
+                    rex |= 0x41;
+                }
+                if memory.index.is_some_and(|(index, _)| index.number & 8 != 0) {
+                    rex |= 0x42;
+                }
+            },
+            _ => return Err(format!("Expected a register or memory operand, got {:?}", rm)),
+        }
+        if high_byte && rex != 0 {
+            return Err("ah, bh, ch and dh can't be used with a REX prefix".to_string());
+        }
+
+        if let Some(prefix) = prefix {
+            self.emit(&[prefix]);
+        }
+        if rex != 0 {
+            self.emit(&[rex]);
+        }
+        self.emit(opcode);
+
+        let reg = (reg & 7) << 3;
+        let memory = match rm {
+            Operand::Register(register) => {
+                self.emit(&[0xc0 | reg | (register.number & 7)]);
+                return Ok(());
+            },
+            Operand::Memory(memory) => memory,
+            _ => unreachable!(),
+        };
+
+        let scale_bits = |scale: u8| scale.trailing_zeros() as u8;
+        match (memory.base, memory.index, &memory.symbol) {
+            // RIP-relative
+            (None, None, Some(symbol)) => {
+                self.emit(&[reg | 0b101]);
+                let addend = memory.displacement - 4 - immediate_size as i64;
+                self.emit_fixup(symbol, RelocationKind::Relative32, addend);
+            },
+            (None, index, symbol) => {
+                let (index, scale) = match index {
+                    Some((index, scale)) => (index.number & 7, scale_bits(scale)),
+                    // No index
+                    None => (0b100, 0),
+                };
+                self.emit(&[reg | 0b100, scale << 6 | index << 3 | 0b101]);
+                match symbol {
+                    Some(symbol) => self.emit_fixup(symbol, RelocationKind::Absolute32S, memory.displacement),
+                    None => self.emit_immediate(imm32(memory.displacement, 8)?, 4),
+                }
+            },
+            (Some(base), index, None) => {
+                let displacement = memory.displacement;
+                // rbp and r13 have no form without a displacement
+                let mode = if displacement == 0 && base.number & 7 != 0b101 {
+                    0b00
+                } else if fits_i8(displacement) {
+                    0b01
+                } else if fits_i32(displacement) {
+                    0b10
+                } else {
+                    return Err(format!("Displacement out of range: {}", displacement));
+                };
+
+                // rsp and r12 as a base need a SIB byte
+                if index.is_some() || base.number & 7 == 0b100 {
+                    let (index, scale) = match index {
+                        Some((index, _)) if index.number == 4 => return Err("rsp can't be an index".to_string()),
+                        Some((index, scale)) => (index.number & 7, scale_bits(scale)),
+                        None => (0b100, 0),
+                    };
+                    self.emit(&[mode << 6 | reg | 0b100, scale << 6 | index << 3 | (base.number & 7)]);
+                } else {
+                    self.emit(&[mode << 6 | reg | (base.number & 7)]);
+                }
+
+                match mode {
+                    0b01 => self.emit_immediate(displacement, 1),
+                    0b10 => self.emit_immediate(displacement, 4),
+                    _ => {},
+                }
+            },
+            (Some(_), _, Some(_)) => return Err("A symbol can't be combined with a base register".to_string()),
+        }
+
+        Ok(())
+    }
+
+    // The prefix and REX.W for an operand size
+    fn size_prefix(size: u8) -> (Option<u8>, bool) {
+        match size {
+            2 => (Some(0x66), false),
+            8 => (None, true),
+            _ => (None, false),
+        }
+    }
+
+    fn encode_mov(&mut self, dest: &Operand, src: &Operand) -> Result<(), String> {
+        let size = dest.size().or(src.size()).ok_or("mov needs an operand size")?;
+        let (prefix, wide) = Self::size_prefix(size);
+        let byte = size == 1;
+
+        match (dest, src) {
+            (Operand::Register(_) | Operand::Memory(_), Operand::Register(src)) => {
+                self.emit_modrm(prefix, wide, &[if byte { 0x88 } else { 0x89 }], Self::reg_field(src), dest, 0)
+            },
+            (Operand::Register(dest), Operand::Memory(_)) => {
+                self.emit_modrm(prefix, wide, &[if byte { 0x8a } else { 0x8b }], Self::reg_field(dest), src, 0)
+            },
+            (Operand::Register(dest), Operand::Immediate(value)) => {
+                let value = *value;
+                // A zero extended 32-bit move is shorter than a 64-bit immediate
+                let size = if size == 8 && (0..=u32::MAX as i64).contains(&value) { 4 } else { size };
+                // And negative values can be sign extended from 32 bits
+                if size == 8 && fits_i32(value) {
+                    self.emit_modrm(None, true, &[0xc7], 0, &Operand::Register(*dest), 4)?;
+                    self.emit_immediate(value, 4);
+                    return Ok(());
+                }
+
+                let mut rex = if dest.number & 8 != 0 { 0x41 } else { 0 };
+                if size == 8 {
+                    rex |= 0x48;
+                }
+                if dest.needs_rex() {
+                    rex |= 0x40;
+                }
+                if let Some(prefix) = Self::size_prefix(size).0 {
+                    self.emit(&[prefix]);
+                }
+                if rex != 0 {
+                    self.emit(&[rex]);
+                }
+                let opcode = if size == 1 { 0xb0 } else { 0xb8 };
+                self.emit(&[opcode + (dest.number & 7)]);
+                let value = if size == 4 { imm32(value, 4)? } else { value };
+                self.emit_immediate(value, size as usize);
+                Ok(())
+            },
+            (Operand::Register(dest), Operand::Symbol(symbol, offset)) if size == 8 => {
+                let rex = if dest.number & 8 != 0 { 0x49 } else { 0x48 };
+                self.emit(&[rex, 0xb8 + (dest.number & 7)]);
+                self.emit_fixup(symbol, RelocationKind::Absolute64, *offset);
+                Ok(())
+            },
+            (Operand::Memory(_), Operand::Immediate(value)) => {
+                let immediate_size = if byte { 1 } else if size == 2 { 2 } else { 4 };
+                self.emit_modrm(prefix, wide, &[if byte { 0xc6 } else { 0xc7 }], 0, dest, immediate_size)?;
+                let value = if immediate_size == 4 { imm32(*value, size)? } else { *value };
+                self.emit_immediate(value, immediate_size);
+                Ok(())
+            },
+            _ => Err(format!("Unsupported operands for mov: {:?}, {:?}", dest, src)),
+        }
+    }
+
+    fn encode_arithmetic(&mut self, extension: u8, dest: &Operand, src: &Operand) -> Result<(), String> {
+        let size = dest.size().or(src.size()).ok_or("Missing operand size")?;
+        let (prefix, wide) = Self::size_prefix(size);
+        let byte = size == 1;
+        let row = extension << 3;
+
+        match (dest, src) {
+            (Operand::Register(_) | Operand::Memory(_), Operand::Register(src)) => {
+                self.emit_modrm(prefix, wide, &[row | if byte { 0 } else { 1 }], Self::reg_field(src), dest, 0)
+            },
+            (Operand::Register(dest), Operand::Memory(_)) => {
+                self.emit_modrm(prefix, wide, &[row | if byte { 2 } else { 3 }], Self::reg_field(dest), src, 0)
+            },
+            (Operand::Register(_) | Operand::Memory(_), Operand::Immediate(value)) => {
+                if byte {
+                    self.emit_modrm(prefix, wide, &[0x80], extension, dest, 1)?;
+                    self.emit_immediate(*value, 1);
+                } else if fits_i8(*value) {
+                    self.emit_modrm(prefix, wide, &[0x83], extension, dest, 1)?;
+                    self.emit_immediate(*value, 1);
+                } else {
+                    let immediate_size = if size == 2 { 2 } else { 4 };
+                    self.emit_modrm(prefix, wide, &[0x81], extension, dest, immediate_size)?;
+                    self.emit_immediate(imm32(*value, size)?, immediate_size);
+                }
+                Ok(())
+            },
+            _ => Err(format!("Unsupported operands: {:?}, {:?}", dest, src)),
+        }
+    }
+
+    fn encode(&mut self, inst: &X86Inst) -> Result<(), String> {
+        use Operand::{Immediate, Memory as Mem, Register as Reg, Symbol};
+
+        match inst {
+            X86Inst::Section(name) => {
+                self.section = *SECTIONS
+                    .iter()
+                    .find(|section| section.name() == name)
+                    .ok_or_else(|| format!("Unsupported section: {}", name))?;
+            },
+            X86Inst::Global(name) => self.globals.push(name.clone()),
+            X86Inst::Extern(name) => {
+                if !self.object.externs.contains(name) {
+                    self.object.externs.push(name.clone());
+                }
+            },
+            X86Inst::Label(name) => self.define_label(name)?,
+            X86Inst::Align(alignment) => {
+                // Code is padded with nops
+                let padding = if self.section == SectionKind::Text { 0x90 } else { 0 };
+                while !self.offset().is_multiple_of(*alignment) {
+                    self.emit(&[padding]);
+                }
+            },
+            X86Inst::Db(bytes) => self.emit(bytes),
+            X86Inst::Dq(Immediate(value)) => self.emit_immediate(*value, 8),
+            X86Inst::Dq(Symbol(symbol, offset)) => self.emit_fixup(symbol, RelocationKind::Absolute64, *offset),
+            X86Inst::Ret => self.emit(&[0xc3]),
+            X86Inst::Leave => self.emit(&[0xc9]),
+            X86Inst::Syscall => self.emit(&[0x0f, 0x05]),
+            X86Inst::Cdq => self.emit(&[0x99]),
+            X86Inst::Cqo => self.emit(&[0x48, 0x99]),
+            X86Inst::Fprem => self.emit(&[0xd9, 0xf8]),
+            X86Inst::Fld1 => self.emit(&[0xd9, 0xe8]),
+            X86Inst::Fyl2x => self.emit(&[0xd9, 0xf1]),
+            X86Inst::F2xm1 => self.emit(&[0xd9, 0xf0]),
+            X86Inst::Faddp => self.emit(&[0xde, 0xc1]),
+            X86Inst::Fscale => self.emit(&[0xd9, 0xfd]),
+            X86Inst::Fldln2 => self.emit(&[0xd9, 0xed]),
+            X86Inst::Fldl2e => self.emit(&[0xd9, 0xea]),
+            X86Inst::Fldpi => self.emit(&[0xd9, 0xeb]),
+            X86Inst::Fsin => self.emit(&[0xd9, 0xfe]),
+            X86Inst::Fcos => self.emit(&[0xd9, 0xff]),
+            X86Inst::Fnstsw(Reg(Register { number: 0, size: 2, .. })) => self.emit(&[0xdf, 0xe0]),
+            X86Inst::Push(Reg(register)) | X86Inst::Pop(Reg(register)) if register.size == 8 => {
+                if register.number & 8 != 0 {
+                    self.emit(&[0x41]);
+                }
+                let opcode = if matches!(inst, X86Inst::Push(_)) { 0x50 } else { 0x58 };
+                self.emit(&[opcode + (register.number & 7)]);
+            },
+            X86Inst::Push(rm @ Mem(_)) => self.emit_modrm(None, false, &[0xff], 6, rm, 0)?,
+            X86Inst::Pop(rm @ Mem(_)) => self.emit_modrm(None, false, &[0x8f], 0, rm, 0)?,
+            X86Inst::Mov(dest, src) => self.encode_mov(dest, src)?,
+            X86Inst::Arithmetic(op, dest, src) => self.encode_arithmetic(op.extension(), dest, src)?,
+            X86Inst::Test(rm, Reg(register)) => {
+                let (prefix, wide) = Self::size_prefix(register.size);
+                let opcode = if register.size == 1 { 0x84 } else { 0x85 };
+                self.emit_modrm(prefix, wide, &[opcode], Self::reg_field(register), rm, 0)?;
+            },
+            X86Inst::Test(rm, Immediate(value)) => {
+                let size = rm.size().ok_or("test needs an operand size")?;
+                let (prefix, wide) = Self::size_prefix(size);
+                let immediate_size = match size {
+                    1 => 1,
+                    2 => 2,
+                    _ => 4,
+                };
+                self.emit_modrm(prefix, wide, &[if size == 1 { 0xf6 } else { 0xf7 }], 0, rm, immediate_size)?;
+                self.emit_immediate(imm32(*value, size)?, immediate_size);
+            },
+            X86Inst::Imul(Reg(dest), rm) => {
+                let (prefix, wide) = Self::size_prefix(dest.size);
+                self.emit_modrm(prefix, wide, &[0x0f, 0xaf], dest.number, rm, 0)?;
+            },
+            X86Inst::Neg(rm) | X86Inst::Idiv(rm) => {
+                let extension = if matches!(inst, X86Inst::Neg(_)) { 3 } else { 7 };
+                let size = rm.size().ok_or("Missing operand size")?;
+                let (prefix, wide) = Self::size_prefix(size);
+                self.emit_modrm(prefix, wide, &[if size == 1 { 0xf6 } else { 0xf7 }], extension, rm, 0)?;
+            },
+            X86Inst::Shift(op, rm, count) => {
+                let size = rm.size().ok_or("Missing operand size")?;
+                let (prefix, wide) = Self::size_prefix(size);
+                let byte = size == 1;
+                match count {
+                    Reg(Register { number: 1, size: 1, high_byte: false, .. }) => {
+                        self.emit_modrm(prefix, wide, &[if byte { 0xd2 } else { 0xd3 }], op.extension(), rm, 0)?
+                    },
+                    Immediate(count) => {
+                        self.emit_modrm(prefix, wide, &[if byte { 0xc0 } else { 0xc1 }], op.extension(), rm, 1)?;
+                        self.emit_immediate(*count, 1);
+                    },
+                    _ => return Err(format!("{} shifts by cl or an immediate", op.mnemonic())),
+                }
+            },
+            X86Inst::Movsx(Reg(dest), rm) | X86Inst::Movzx(Reg(dest), rm) => {
+                let (prefix, wide) = Self::size_prefix(dest.size);
+                let opcode = match (inst, rm.size()) {
+                    (X86Inst::Movzx(..), Some(1)) => 0xb6,
+                    (X86Inst::Movzx(..), Some(2)) => 0xb7,
+                    (X86Inst::Movsx(..), Some(1)) => 0xbe,
+                    (X86Inst::Movsx(..), Some(2)) => 0xbf,
+                    _ => return Err("movsx and movzx extend a byte or a word".to_string()),
+                };
+                self.emit_modrm(prefix, wide, &[0x0f, opcode], dest.number, rm, 0)?;
+            },
+            X86Inst::Movsxd(Reg(dest), rm) if dest.size == 8 => self.emit_modrm(None, true, &[0x63], dest.number, rm, 0)?,
+            X86Inst::Btc(rm, Immediate(bit)) => {
+                let (prefix, wide) = Self::size_prefix(rm.size().ok_or("Missing operand size")?);
+                self.emit_modrm(prefix, wide, &[0x0f, 0xba], 7, rm, 1)?;
+                self.emit_immediate(*bit, 1);
+            },
+            X86Inst::Jmp(Symbol(symbol, offset)) => {
+                self.emit(&[0xe9]);
+                self.emit_fixup(symbol, RelocationKind::Relative32, offset - 4);
+            },
+            X86Inst::Jmp(rm) => self.emit_modrm(None, false, &[0xff], 4, rm, 0)?,
+            X86Inst::Call(Symbol(symbol, offset)) => {
+                self.emit(&[0xe8]);
+                self.emit_fixup(symbol, RelocationKind::Plt32, offset - 4);
+            },
+            X86Inst::Call(rm) => self.emit_modrm(None, false, &[0xff], 2, rm, 0)?,
+            X86Inst::Jcc(condition, label) => {
+                self.emit(&[0x0f, 0x80 + condition.code()]);
+                self.emit_fixup(label, RelocationKind::Relative32, -4);
+            },
+            X86Inst::Setcc(condition, rm) => {
+                if rm.size() != Some(1) {
+                    return Err(format!("set{} takes a byte operand", condition.suffix()));
+                }
+                self.emit_modrm(None, false, &[0x0f, 0x90 + condition.code()], 0, rm, 0)?;
+            },
+            X86Inst::Fld(rm @ Mem(memory)) | X86Inst::Fstp(rm @ Mem(memory)) => {
+                let opcode = match memory.size {
+                    Some(4) => 0xd9,
+                    Some(8) => 0xdd,
+                    _ => return Err("fld and fstp take a dword or qword".to_string()),
+                };
+                let extension = if matches!(inst, X86Inst::Fld(_)) { 0 } else { 3 };
+                self.emit_modrm(None, false, &[opcode], extension, rm, 0)?;
+            },
+            X86Inst::Fld(Reg(register)) if register.kind == RegisterKind::X87 => self.emit(&[0xd9, 0xc0 + register.number]),
+            X86Inst::Fstp(Reg(register)) if register.kind == RegisterKind::X87 => self.emit(&[0xdd, 0xd8 + register.number]),
+            X86Inst::Fmul(rm @ Mem(memory)) | X86Inst::Fdiv(rm @ Mem(memory)) => {
+                let opcode = match memory.size {
+                    Some(4) => 0xd8,
+                    Some(8) => 0xdc,
+                    _ => return Err("fmul and fdiv take a dword or qword".to_string()),
+                };
+                let extension = if matches!(inst, X86Inst::Fmul(_)) { 1 } else { 6 };
+                self.emit_modrm(None, false, &[opcode], extension, rm, 0)?;
+            },
+            X86Inst::Fistp(rm @ Mem(memory)) => match memory.size {
+                Some(4) => self.emit_modrm(None, false, &[0xdb], 3, rm, 0)?,
+                Some(8) => self.emit_modrm(None, false, &[0xdf], 7, rm, 0)?,
+                _ => return Err("fistp takes a dword or qword".to_string()),
+            },
+            X86Inst::Sse(op, dest, src) => {
+                let (prefix, opcode) = sse_encoding(*op);
+                match (dest, src) {
+                    // Stores, only the moves have them
+                    (Mem(_), Reg(src)) if opcode == 0x10 => self.emit_modrm(prefix, false, &[0x0f, 0x11], src.number, dest, 0)?,
+                    // The integer side decides between 32 and 64-bit conversions
+                    (Reg(dest), _) if opcode == 0x2a => {
+                        let wide = src.size() == Some(8);
+                        self.emit_modrm(prefix, wide, &[0x0f, opcode], dest.number, src, 0)?;
+                    },
+                    (Reg(dest), _) if opcode == 0x2c => {
+                        self.emit_modrm(prefix, dest.size == 8, &[0x0f, opcode], dest.number, src, 0)?;
+                    },
+                    (Reg(dest), Reg(_) | Mem(_)) if dest.kind == RegisterKind::Xmm => {
+                        self.emit_modrm(prefix, false, &[0x0f, opcode], dest.number, src, 0)?;
+                    },
+                    _ => return Err(format!("Unsupported operands for {}: {:?}, {:?}", op.mnemonic(), dest, src)),
+                }
+            },
+            _ => return Err(format!("Unsupported instruction: {:?}", inst)),
+        }
+
+        Ok(())
+    }
+
+    // Jumps to labels in the same section need no relocation, everything else is left to
+    // the linker
+    fn finish(mut self) -> Result<Object, String> {
+        for name in &self.globals {
+            let index = *self.label_indexes.get(name).ok_or_else(|| format!("Global symbol is not defined: {}", name))?;
+            self.object.labels[index].global = true;
+        }
+
+        let mut relocations = Vec::new();
+        for relocation in std::mem::take(&mut self.object.relocations) {
+            let label = self.label_indexes.get(&relocation.symbol).map(|index| &self.object.labels[*index]);
+            let pc_relative = matches!(relocation.kind, RelocationKind::Relative32 | RelocationKind::Plt32);
+            match label {
+                Some(label) if pc_relative && label.section == relocation.section => {
+                    let value = label.offset as i64 + relocation.addend - relocation.offset as i64;
+                    let value = i32::try_from(value).map_err(|_| format!("Jump out of range: {}", relocation.symbol))?;
+                    let bytes = &mut self.object.sections[relocation.section.index()];
+                    bytes[relocation.offset as usize..relocation.offset as usize + 4].copy_from_slice(&value.to_le_bytes());
+                },
+                None if !self.object.externs.contains(&relocation.symbol) => {
+                    return Err(format!("Undefined symbol: {}", relocation.symbol));
+                },
+                _ => relocations.push(relocation),
+            }
+        }
+        self.object.relocations = relocations;
+
+        Ok(self.object)
+    }
+}
+
+/// Assembles several programs into a single object, as if they were concatenated.
+pub fn assemble(sources: &[&Assembly]) -> Result<Object, String> {
+    let mut assembler = Assembler::new();
+    for source in sources {
+        assembler.section = SectionKind::Text;
+        for inst in &source.code {
+            assembler
+                .encode(inst)
+                .map_err(|e| format!("{}: {}", syntax::nasm(inst), e))?;
+        }
+    }
+
+    // Symbols one source exports and another imports are resolved here
+    let labels = assembler.label_indexes.clone();
+    assembler.object.externs.retain(|name| !labels.contains_key(name));
+
+    assembler.finish()
+}
+
+// The expected bytes are what GNU as assembles the AT&T form of each instruction, as
+// `syntax::att` prints it, into. Jumps go to externs, as shortens the ones it can resolve.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::inst::{ArithmeticOp, Condition, Memory, ShiftOp};
+
+    fn reg(name: &str) -> Operand {
+        Operand::Register(Register::parse(name).unwrap())
+    }
+
+    fn mem(size: u8, base: &str, displacement: i64) -> Operand {
+        Operand::Memory(Memory {
+            size: Some(size),
+            base: Some(Register::parse(base).unwrap()),
+            index: None,
+            symbol: None,
+            displacement,
+        })
+    }
+
+    fn indexed(size: u8, base: &str, index: &str, scale: u8, displacement: i64) -> Operand {
+        Operand::Memory(Memory {
+            size: Some(size),
+            base: Some(Register::parse(base).unwrap()),
+            index: Some((Register::parse(index).unwrap(), scale)),
+            symbol: None,
+            displacement,
+        })
+    }
+
+    // `[symbol + index*scale + displacement]`, RIP-relative without an index
+    fn global(size: u8, symbol: &str, index: Option<(&str, u8)>, displacement: i64) -> Operand {
+        Operand::Memory(Memory {
+            size: Some(size),
+            base: None,
+            index: index.map(|(index, scale)| (Register::parse(index).unwrap(), scale)),
+            symbol: Some(symbol.to_string()),
+            displacement,
+        })
+    }
+
+    fn symbol(name: &str) -> Operand {
+        Operand::Symbol(name.to_string(), 0)
+    }
+
+    fn assemble_code(code: Vec<X86Inst>) -> Result<Object, String> {
+        let mut asm = Assembly { code };
+        asm.declare_externs();
+        assemble(&[&asm])
+    }
+
+    fn assert_encodes(forms: &[(X86Inst, &[u8])]) {
+        for (inst, expected) in forms {
+            let object = assemble_code(vec![inst.clone()]).unwrap_or_else(|e| panic!("{}: {}", syntax::att(inst), e));
+            assert_eq!(object.section(SectionKind::Text), *expected, "{}", syntax::att(inst));
+        }
+    }
+
+    #[test]
+    fn encodes_instructions_without_operands() {
+        assert_encodes(&[
+            (X86Inst::Ret, &[0xc3]),
+            (X86Inst::Leave, &[0xc9]),
+            (X86Inst::Syscall, &[0x0f, 0x05]),
+            (X86Inst::Cdq, &[0x99]),
+            (X86Inst::Cqo, &[0x48, 0x99]),
+            (X86Inst::Fprem, &[0xd9, 0xf8]),
+            (X86Inst::Fld1, &[0xd9, 0xe8]),
+            (X86Inst::Fyl2x, &[0xd9, 0xf1]),
+            (X86Inst::F2xm1, &[0xd9, 0xf0]),
+            (X86Inst::Faddp, &[0xde, 0xc1]),
+            (X86Inst::Fscale, &[0xd9, 0xfd]),
+            (X86Inst::Fldln2, &[0xd9, 0xed]),
+            (X86Inst::Fldl2e, &[0xd9, 0xea]),
+            (X86Inst::Fldpi, &[0xd9, 0xeb]),
+            (X86Inst::Fsin, &[0xd9, 0xfe]),
+            (X86Inst::Fcos, &[0xd9, 0xff]),
+            (X86Inst::Fnstsw(reg("ax")), &[0xdf, 0xe0]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_push_and_pop() {
+        assert_encodes(&[
+            (X86Inst::Push(reg("rbx")), &[0x53]),
+            (X86Inst::Push(reg("r12")), &[0x41, 0x54]),
+            (X86Inst::Pop(reg("rbp")), &[0x5d]),
+            (X86Inst::Pop(reg("r15")), &[0x41, 0x5f]),
+            (X86Inst::Push(mem(8, "rbp", -8)), &[0xff, 0x75, 0xf8]),
+            (X86Inst::Pop(mem(8, "rax", 0)), &[0x8f, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_mov() {
+        assert_encodes(&[
+            (X86Inst::Mov(reg("rax"), reg("r9")), &[0x4c, 0x89, 0xc8]),
+            (X86Inst::Mov(reg("r10d"), reg("eax")), &[0x41, 0x89, 0xc2]),
+            (X86Inst::Mov(reg("cx"), reg("dx")), &[0x66, 0x89, 0xd1]),
+            (X86Inst::Mov(reg("al"), reg("ah")), &[0x88, 0xe0]),
+            (X86Inst::Mov(reg("sil"), reg("al")), &[0x40, 0x88, 0xc6]),
+            (X86Inst::Mov(reg("eax"), mem(4, "rbp", -12)), &[0x8b, 0x45, 0xf4]),
+            (X86Inst::Mov(reg("r11"), mem(8, "r13", 0)), &[0x4d, 0x8b, 0x5d, 0x00]),
+            (X86Inst::Mov(reg("rdx"), mem(8, "rsp", 16)), &[0x48, 0x8b, 0x54, 0x24, 0x10]),
+            (X86Inst::Mov(reg("rcx"), mem(8, "r12", 0)), &[0x49, 0x8b, 0x0c, 0x24]),
+            (X86Inst::Mov(reg("rsi"), mem(8, "rdi", 0x1000)), &[0x48, 0x8b, 0xb7, 0x00, 0x10, 0x00, 0x00]),
+            (X86Inst::Mov(reg("bx"), mem(2, "rax", 2)), &[0x66, 0x8b, 0x58, 0x02]),
+            (X86Inst::Mov(indexed(1, "rdi", "rcx", 2, 1), reg("sil")), &[0x40, 0x88, 0x74, 0x4f, 0x01]),
+            (X86Inst::Mov(indexed(8, "rax", "r14", 8, 24), reg("rdx")), &[0x4a, 0x89, 0x54, 0xf0, 0x18]),
+            (X86Inst::Mov(reg("rax"), global(8, "table", None, 0)), &[0x48, 0x8b, 0x05, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Mov(reg("rax"), global(8, "table", Some(("rcx", 8)), 0)), &[0x48, 0x8b, 0x04, 0xcd, 0x00, 0x00, 0x00, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_mov_immediates() {
+        assert_encodes(&[
+            (X86Inst::Mov(reg("eax"), Operand::Immediate(5)), &[0xb8, 0x05, 0x00, 0x00, 0x00]),
+            (X86Inst::Mov(reg("eax"), Operand::Immediate(0x80000000)), &[0xb8, 0x00, 0x00, 0x00, 0x80]),
+            (X86Inst::Mov(reg("rax"), Operand::Immediate(-1)), &[0x48, 0xc7, 0xc0, 0xff, 0xff, 0xff, 0xff]),
+            (X86Inst::Mov(reg("r10"), Operand::Immediate(-2)), &[0x49, 0xc7, 0xc2, 0xfe, 0xff, 0xff, 0xff]),
+            (X86Inst::Mov(reg("rcx"), Operand::Immediate(0x123456789)), &[0x48, 0xb9, 0x89, 0x67, 0x45, 0x23, 0x01, 0x00, 0x00, 0x00]),
+            (X86Inst::Mov(reg("al"), Operand::Immediate(7)), &[0xb0, 0x07]),
+            (X86Inst::Mov(reg("dil"), Operand::Immediate(7)), &[0x40, 0xb7, 0x07]),
+            (X86Inst::Mov(reg("r8b"), Operand::Immediate(7)), &[0x41, 0xb0, 0x07]),
+            (X86Inst::Mov(reg("ax"), Operand::Immediate(0x1234)), &[0x66, 0xb8, 0x34, 0x12]),
+            (X86Inst::Mov(reg("rdi"), symbol("table")), &[0x48, 0xbf, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Mov(mem(1, "rax", 8), Operand::Immediate(1)), &[0xc6, 0x40, 0x08, 0x01]),
+            (X86Inst::Mov(mem(2, "rax", 8), Operand::Immediate(-1)), &[0x66, 0xc7, 0x40, 0x08, 0xff, 0xff]),
+            (X86Inst::Mov(mem(4, "rbp", -4), Operand::Immediate(0x12345678)), &[0xc7, 0x45, 0xfc, 0x78, 0x56, 0x34, 0x12]),
+            (X86Inst::Mov(mem(8, "r12", 0), Operand::Immediate(-5)), &[0x49, 0xc7, 0x04, 0x24, 0xfb, 0xff, 0xff, 0xff]),
+            (X86Inst::Mov(global(4, "counter", None, 0), Operand::Immediate(3)), &[0xc7, 0x05, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00]),
+        ]);
+    }
+
+    // as keeps the REX.W these are printed with, the encoder drops it as writing the low
+    // half zero extends, like as does for `movl $5, %eax` and `movl $0xffffffff, %r9d`
+    #[test]
+    fn encodes_unsigned_32_bit_immediates_without_rex_w() {
+        assert_encodes(&[
+            (X86Inst::Mov(reg("rax"), Operand::Immediate(5)), &[0xb8, 0x05, 0x00, 0x00, 0x00]),
+            (X86Inst::Mov(reg("r9"), Operand::Immediate(0xffffffff)), &[0x41, 0xb9, 0xff, 0xff, 0xff, 0xff]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_arithmetic() {
+        assert_encodes(&[
+            (X86Inst::Arithmetic(ArithmeticOp::Add, reg("rax"), reg("rbx")), &[0x48, 0x01, 0xd8]),
+            (X86Inst::Arithmetic(ArithmeticOp::Add, reg("al"), reg("cl")), &[0x00, 0xc8]),
+            (X86Inst::Arithmetic(ArithmeticOp::Sub, reg("r8d"), mem(4, "rbp", -8)), &[0x44, 0x2b, 0x45, 0xf8]),
+            (X86Inst::Arithmetic(ArithmeticOp::Cmp, mem(8, "rdi", 0), reg("r11")), &[0x4c, 0x39, 0x1f]),
+            (X86Inst::Arithmetic(ArithmeticOp::Cmp, mem(1, "rsi", 3), Operand::Immediate(0x41)), &[0x80, 0x7e, 0x03, 0x41]),
+            (X86Inst::Arithmetic(ArithmeticOp::And, reg("rsp"), Operand::Immediate(-16)), &[0x48, 0x83, 0xe4, 0xf0]),
+            (X86Inst::Arithmetic(ArithmeticOp::Or, reg("ecx"), Operand::Immediate(0x10000)), &[0x81, 0xc9, 0x00, 0x00, 0x01, 0x00]),
+            (X86Inst::Arithmetic(ArithmeticOp::Xor, reg("dx"), Operand::Immediate(0x1234)), &[0x66, 0x81, 0xf2, 0x34, 0x12]),
+            (X86Inst::Arithmetic(ArithmeticOp::Sub, reg("rsp"), Operand::Immediate(1024)), &[0x48, 0x81, 0xec, 0x00, 0x04, 0x00, 0x00]),
+            (X86Inst::Arithmetic(ArithmeticOp::Cmp, reg("edx"), Operand::Immediate(0x80000000)), &[0x81, 0xfa, 0x00, 0x00, 0x00, 0x80]),
+            (X86Inst::Arithmetic(ArithmeticOp::Add, global(8, "counter", None, 0), Operand::Immediate(1)), &[0x48, 0x83, 0x05, 0x00, 0x00, 0x00, 0x00, 0x01]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_test_and_the_unary_instructions() {
+        assert_encodes(&[
+            (X86Inst::Test(reg("rax"), reg("rax")), &[0x48, 0x85, 0xc0]),
+            (X86Inst::Test(reg("r9b"), reg("r9b")), &[0x45, 0x84, 0xc9]),
+            (X86Inst::Test(mem(1, "rdi", 12), Operand::Immediate(1)), &[0xf6, 0x47, 0x0c, 0x01]),
+            (X86Inst::Test(reg("ecx"), Operand::Immediate(0x100)), &[0xf7, 0xc1, 0x00, 0x01, 0x00, 0x00]),
+            (X86Inst::Test(reg("rcx"), Operand::Immediate(7)), &[0x48, 0xf7, 0xc1, 0x07, 0x00, 0x00, 0x00]),
+            (X86Inst::Imul(reg("eax"), reg("ecx")), &[0x0f, 0xaf, 0xc1]),
+            (X86Inst::Imul(reg("r10"), mem(8, "rbp", -16)), &[0x4c, 0x0f, 0xaf, 0x55, 0xf0]),
+            (X86Inst::Neg(reg("rax")), &[0x48, 0xf7, 0xd8]),
+            (X86Inst::Neg(mem(4, "rbp", -4)), &[0xf7, 0x5d, 0xfc]),
+            (X86Inst::Idiv(reg("ecx")), &[0xf7, 0xf9]),
+            (X86Inst::Idiv(reg("r8")), &[0x49, 0xf7, 0xf8]),
+            (X86Inst::Btc(reg("eax"), Operand::Immediate(31)), &[0x0f, 0xba, 0xf8, 0x1f]),
+            (X86Inst::Btc(reg("rax"), Operand::Immediate(63)), &[0x48, 0x0f, 0xba, 0xf8, 0x3f]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_shifts_and_extensions() {
+        assert_encodes(&[
+            (X86Inst::Shift(ShiftOp::Shl, reg("eax"), reg("cl")), &[0xd3, 0xe0]),
+            (X86Inst::Shift(ShiftOp::Sar, reg("rdx"), reg("cl")), &[0x48, 0xd3, 0xfa]),
+            (X86Inst::Shift(ShiftOp::Shr, reg("r8"), Operand::Immediate(3)), &[0x49, 0xc1, 0xe8, 0x03]),
+            (X86Inst::Shift(ShiftOp::Shl, mem(4, "rbp", -4), Operand::Immediate(2)), &[0xc1, 0x65, 0xfc, 0x02]),
+            (X86Inst::Shift(ShiftOp::Sar, reg("al"), Operand::Immediate(2)), &[0xc0, 0xf8, 0x02]),
+            (X86Inst::Movsx(reg("eax"), reg("al")), &[0x0f, 0xbe, 0xc0]),
+            (X86Inst::Movsx(reg("rax"), mem(2, "rdi", 16)), &[0x48, 0x0f, 0xbf, 0x47, 0x10]),
+            (X86Inst::Movzx(reg("eax"), reg("sil")), &[0x40, 0x0f, 0xb6, 0xc6]),
+            (X86Inst::Movzx(reg("ecx"), mem(2, "rdi", 16)), &[0x0f, 0xb7, 0x4f, 0x10]),
+            (X86Inst::Movsxd(reg("rax"), reg("ecx")), &[0x48, 0x63, 0xc1]),
+            (X86Inst::Movsxd(reg("r9"), mem(4, "rbp", -4)), &[0x4c, 0x63, 0x4d, 0xfc]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_jumps_and_calls() {
+        assert_encodes(&[
+            (X86Inst::Jmp(symbol("target")), &[0xe9, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Jmp(reg("rax")), &[0xff, 0xe0]),
+            (X86Inst::Jmp(reg("r11")), &[0x41, 0xff, 0xe3]),
+            (X86Inst::Jmp(global(8, "table", Some(("rax", 8)), 0)), &[0xff, 0x24, 0xc5, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Call(symbol("target")), &[0xe8, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Call(reg("rcx")), &[0xff, 0xd1]),
+            (X86Inst::Call(mem(8, "rax", 88)), &[0xff, 0x50, 0x58]),
+            (X86Inst::Jcc(Condition::E, "target".to_string()), &[0x0f, 0x84, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Jcc(Condition::G, "target".to_string()), &[0x0f, 0x8f, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Setcc(Condition::L, reg("al")), &[0x0f, 0x9c, 0xc0]),
+            (X86Inst::Setcc(Condition::Ne, reg("sil")), &[0x40, 0x0f, 0x95, 0xc6]),
+            (X86Inst::Setcc(Condition::A, mem(1, "rbp", -1)), &[0x0f, 0x97, 0x45, 0xff]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_x87() {
+        assert_encodes(&[
+            (X86Inst::Fld(mem(4, "rsp", 0)), &[0xd9, 0x04, 0x24]),
+            (X86Inst::Fld(mem(8, "rbp", -8)), &[0xdd, 0x45, 0xf8]),
+            (X86Inst::Fld(reg("st1")), &[0xd9, 0xc1]),
+            (X86Inst::Fstp(mem(4, "rsp", 0)), &[0xd9, 0x1c, 0x24]),
+            (X86Inst::Fstp(mem(8, "rbp", -8)), &[0xdd, 0x5d, 0xf8]),
+            (X86Inst::Fstp(reg("st1")), &[0xdd, 0xd9]),
+            (X86Inst::Fmul(mem(4, "rsp", 4)), &[0xd8, 0x4c, 0x24, 0x04]),
+            (X86Inst::Fmul(mem(8, "rsp", 8)), &[0xdc, 0x4c, 0x24, 0x08]),
+            (X86Inst::Fdiv(mem(4, "rsp", 4)), &[0xd8, 0x74, 0x24, 0x04]),
+            (X86Inst::Fdiv(mem(8, "rsp", 8)), &[0xdc, 0x74, 0x24, 0x08]),
+            (X86Inst::Fistp(mem(4, "rsp", 0)), &[0xdb, 0x1c, 0x24]),
+            (X86Inst::Fistp(mem(8, "rsp", 0)), &[0xdf, 0x3c, 0x24]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_sse() {
+        assert_encodes(&[
+            (X86Inst::Sse(SseOp::Movss, reg("xmm0"), mem(4, "rbp", -4)), &[0xf3, 0x0f, 0x10, 0x45, 0xfc]),
+            (X86Inst::Sse(SseOp::Movss, mem(4, "rbp", -4), reg("xmm1")), &[0xf3, 0x0f, 0x11, 0x4d, 0xfc]),
+            (X86Inst::Sse(SseOp::Movsd, reg("xmm9"), reg("xmm0")), &[0xf2, 0x44, 0x0f, 0x10, 0xc8]),
+            (X86Inst::Sse(SseOp::Movsd, mem(8, "rsp", 0), reg("xmm8")), &[0xf2, 0x44, 0x0f, 0x11, 0x04, 0x24]),
+            (X86Inst::Sse(SseOp::Movsd, reg("xmm0"), global(8, "constant", None, 0)), &[0xf2, 0x0f, 0x10, 0x05, 0x00, 0x00, 0x00, 0x00]),
+            (X86Inst::Sse(SseOp::Addss, reg("xmm0"), reg("xmm1")), &[0xf3, 0x0f, 0x58, 0xc1]),
+            (X86Inst::Sse(SseOp::Addsd, reg("xmm0"), reg("xmm1")), &[0xf2, 0x0f, 0x58, 0xc1]),
+            (X86Inst::Sse(SseOp::Subss, reg("xmm2"), reg("xmm3")), &[0xf3, 0x0f, 0x5c, 0xd3]),
+            (X86Inst::Sse(SseOp::Subsd, reg("xmm2"), reg("xmm3")), &[0xf2, 0x0f, 0x5c, 0xd3]),
+            (X86Inst::Sse(SseOp::Mulss, reg("xmm4"), reg("xmm5")), &[0xf3, 0x0f, 0x59, 0xe5]),
+            (X86Inst::Sse(SseOp::Mulsd, reg("xmm4"), reg("xmm5")), &[0xf2, 0x0f, 0x59, 0xe5]),
+            (X86Inst::Sse(SseOp::Divss, reg("xmm6"), reg("xmm7")), &[0xf3, 0x0f, 0x5e, 0xf7]),
+            (X86Inst::Sse(SseOp::Divsd, reg("xmm6"), reg("xmm15")), &[0xf2, 0x41, 0x0f, 0x5e, 0xf7]),
+            (X86Inst::Sse(SseOp::Cvtss2sd, reg("xmm0"), reg("xmm0")), &[0xf3, 0x0f, 0x5a, 0xc0]),
+            (X86Inst::Sse(SseOp::Cvtsd2ss, reg("xmm0"), reg("xmm0")), &[0xf2, 0x0f, 0x5a, 0xc0]),
+            (X86Inst::Sse(SseOp::Cvtsi2ss, reg("xmm0"), reg("eax")), &[0xf3, 0x0f, 0x2a, 0xc0]),
+            (X86Inst::Sse(SseOp::Cvtsi2sd, reg("xmm1"), reg("rax")), &[0xf2, 0x48, 0x0f, 0x2a, 0xc8]),
+            (X86Inst::Sse(SseOp::Cvtsi2sd, reg("xmm1"), mem(4, "rbp", -4)), &[0xf2, 0x0f, 0x2a, 0x4d, 0xfc]),
+            (X86Inst::Sse(SseOp::Cvttss2si, reg("eax"), reg("xmm0")), &[0xf3, 0x0f, 0x2c, 0xc0]),
+            (X86Inst::Sse(SseOp::Cvttsd2si, reg("rax"), reg("xmm1")), &[0xf2, 0x48, 0x0f, 0x2c, 0xc1]),
+            (X86Inst::Sse(SseOp::Cvttsd2si, reg("r8"), reg("xmm9")), &[0xf2, 0x4d, 0x0f, 0x2c, 0xc1]),
+            (X86Inst::Sse(SseOp::Ucomiss, reg("xmm0"), reg("xmm1")), &[0x0f, 0x2e, 0xc1]),
+            (X86Inst::Sse(SseOp::Ucomisd, reg("xmm0"), reg("xmm1")), &[0x66, 0x0f, 0x2e, 0xc1]),
+            (X86Inst::Sse(SseOp::Xorps, reg("xmm0"), reg("xmm0")), &[0x0f, 0x57, 0xc0]),
+            (X86Inst::Sse(SseOp::Sqrtss, reg("xmm0"), reg("xmm1")), &[0xf3, 0x0f, 0x51, 0xc1]),
+            (X86Inst::Sse(SseOp::Sqrtsd, reg("xmm0"), reg("xmm1")), &[0xf2, 0x0f, 0x51, 0xc1]),
+        ]);
+    }
+
+    #[test]
+    fn resolves_jumps_within_a_section() {
+        let object = assemble_code(vec![
+            X86Inst::Label("top".to_string()),
+            X86Inst::Jcc(Condition::E, "end".to_string()),
+            X86Inst::Jmp(symbol("top")),
+            X86Inst::Label("end".to_string()),
+            X86Inst::Ret,
+        ])
+        .unwrap();
+        assert_eq!(
+            object.section(SectionKind::Text),
+            [0x0f, 0x84, 0x05, 0x00, 0x00, 0x00, 0xe9, 0xf5, 0xff, 0xff, 0xff, 0xc3]
+        );
+        assert!(object.relocations.is_empty());
+    }
+
+    #[test]
+    fn leaves_other_references_to_the_linker() {
+        let object = assemble_code(vec![
+            X86Inst::Call(symbol("print")),
+            X86Inst::Mov(reg("rax"), global(8, "value", None, 8)),
+            X86Inst::Mov(global(4, "value", None, 0), Operand::Immediate(3)),
+            X86Inst::Mov(reg("rdi"), Operand::Symbol("value".to_string(), 16)),
+            X86Inst::Jmp(global(8, "table", Some(("rax", 8)), 0)),
+            X86Inst::Section(".data".to_string()),
+            X86Inst::Label("value".to_string()),
+            X86Inst::Dq(Operand::Symbol("print".to_string(), 0)),
+        ])
+        .unwrap();
+
+        let relocation = |section, offset, symbol: &str, kind, addend| Relocation {
+            section,
+            offset,
+            symbol: symbol.to_string(),
+            kind,
+            addend,
+        };
+        assert_eq!(
+            object.relocations,
+            [
+                relocation(SectionKind::Text, 1, "print", RelocationKind::Plt32, -4),
+                relocation(SectionKind::Text, 8, "value", RelocationKind::Relative32, 4),
+                relocation(SectionKind::Text, 14, "value", RelocationKind::Relative32, -8),
+                relocation(SectionKind::Text, 24, "value", RelocationKind::Absolute64, 16),
+                relocation(SectionKind::Text, 35, "table", RelocationKind::Absolute32S, 0),
+                relocation(SectionKind::Data, 0, "print", RelocationKind::Absolute64, 0),
+            ]
+        );
+        assert_eq!(object.externs, ["print", "table"]);
+        assert_eq!(object.label("value").map(|label| (label.section, label.offset)), Some((SectionKind::Data, 0)));
+    }
+
+    #[test]
+    fn emits_data_and_alignment() {
+        let object = assemble_code(vec![
+            X86Inst::Ret,
+            X86Inst::Align(4),
+            X86Inst::Global("main".to_string()),
+            X86Inst::Label("main".to_string()),
+            X86Inst::Section(".rodata".to_string()),
+            X86Inst::Db(vec![1, 2, 3]),
+            X86Inst::Align(8),
+            X86Inst::Dq(Operand::Immediate(-2)),
+        ])
+        .unwrap();
+        assert_eq!(object.section(SectionKind::Text), [0xc3, 0x90, 0x90, 0x90]);
+        assert_eq!(object.section(SectionKind::ReadOnlyData), [1, 2, 3, 0, 0, 0, 0, 0, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert!(object.label("main").is_some_and(|label| label.global && label.offset == 4));
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_encode() {
+        let error = |code: Vec<X86Inst>| assemble(&[&Assembly { code }]).unwrap_err();
+        assert!(error(vec![X86Inst::Mov(reg("ah"), reg("sil"))]).contains("REX prefix"));
+        assert!(error(vec![X86Inst::Mov(reg("rax"), mem(8, "rsp", 0x100000000))]).contains("Displacement out of range"));
+        assert!(error(vec![X86Inst::Mov(reg("rax"), indexed(8, "rax", "rsp", 1, 0))]).contains("rsp can't be an index"));
+        assert!(error(vec![X86Inst::Shift(ShiftOp::Shl, reg("eax"), reg("dl"))]).contains("shl shifts by cl"));
+        assert!(error(vec![X86Inst::Label("a".to_string()), X86Inst::Label("a".to_string())]).contains("Label defined twice"));
+        assert!(error(vec![X86Inst::Call(symbol("missing"))]).contains("Undefined symbol: missing"));
+        assert!(error(vec![X86Inst::Global("missing".to_string())]).contains("Global symbol is not defined"));
+    }
+}
@@ -0,0 +1,699 @@
+use std::ops::{Add, Mul, Sub};
+
+// The typed form of the assembly the backend generates. `Assembly` collects these, the
+// printers in `syntax` turn them into NASM or GNU assembler source and `encoder` turns
+// them into machine code.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    General,
+    Xmm,
+    // The x87 stack registers st0 to st7
+    X87,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    pub kind: RegisterKind,
+    // The encoding, 8 and up need a REX prefix
+    pub number: u8,
+    // In bytes, xmm registers are 16
+    pub size: u8,
+    // ah, ch, dh and bh, which can't be used with a REX prefix
+    pub high_byte: bool,
+}
+
+const GENERAL_REGISTERS: [[&str; 16]; 4] = [
+    ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b"],
+    ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w", "r14w", "r15w"],
+    ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d"],
+    ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"],
+];
+
+const HIGH_BYTE_REGISTERS: [&str; 4] = ["ah", "ch", "dh", "bh"];
+
+impl Register {
+    const fn general(number: u8, size: u8) -> Register {
+        Register { kind: RegisterKind::General, number, size, high_byte: false }
+    }
+
+    const fn xmm(number: u8) -> Register {
+        Register { kind: RegisterKind::Xmm, number, size: 16, high_byte: false }
+    }
+
+    const fn x87(number: u8) -> Register {
+        Register { kind: RegisterKind::X87, number, size: 10, high_byte: false }
+    }
+
+    pub fn parse(name: &str) -> Option<Register> {
+        for (i, names) in GENERAL_REGISTERS.iter().enumerate() {
+            if let Some(number) = names.iter().position(|n| *n == name) {
+                return Some(Register { kind: RegisterKind::General, number: number as u8, size: 1 << i, high_byte: false });
+            }
+        }
+        if let Some(number) = HIGH_BYTE_REGISTERS.iter().position(|n| *n == name) {
+            return Some(Register { kind: RegisterKind::General, number: number as u8 + 4, size: 1, high_byte: true });
+        }
+        if let Some(number) = name.strip_prefix("xmm").and_then(|n| n.parse::<u8>().ok()).filter(|n| *n < 16) {
+            return Some(Register { kind: RegisterKind::Xmm, number, size: 16, high_byte: false });
+        }
+        if let Some(number) = name.strip_prefix("st").and_then(|n| n.parse::<u8>().ok()).filter(|n| *n < 8) {
+            return Some(Register { kind: RegisterKind::X87, number, size: 10, high_byte: false });
+        }
+        None
+    }
+
+    /// The Intel name, e.g. `eax`, `xmm1` or `st0`.
+    pub fn name(&self) -> String {
+        match self.kind {
+            RegisterKind::General if self.high_byte => HIGH_BYTE_REGISTERS[self.number as usize - 4].to_string(),
+            RegisterKind::General => {
+                GENERAL_REGISTERS[self.size.trailing_zeros() as usize][self.number as usize].to_string()
+            },
+            RegisterKind::Xmm => format!("xmm{}", self.number),
+            RegisterKind::X87 => format!("st{}", self.number),
+        }
+    }
+
+    // spl, bpl, sil and dil are only reachable with a REX prefix
+    pub fn needs_rex(&self) -> bool {
+        self.kind == RegisterKind::General && self.size == 1 && !self.high_byte && (4..8).contains(&self.number)
+    }
+}
+
+// The registers by name, for the backend to build operands from
+pub const RAX: Register = Register::general(0, 8);
+pub const RCX: Register = Register::general(1, 8);
+pub const RDX: Register = Register::general(2, 8);
+pub const RBX: Register = Register::general(3, 8);
+pub const RSP: Register = Register::general(4, 8);
+pub const RBP: Register = Register::general(5, 8);
+pub const RSI: Register = Register::general(6, 8);
+pub const RDI: Register = Register::general(7, 8);
+pub const R8: Register = Register::general(8, 8);
+pub const R9: Register = Register::general(9, 8);
+pub const R10: Register = Register::general(10, 8);
+pub const R11: Register = Register::general(11, 8);
+pub const R12: Register = Register::general(12, 8);
+pub const R13: Register = Register::general(13, 8);
+pub const R14: Register = Register::general(14, 8);
+pub const R15: Register = Register::general(15, 8);
+
+pub const EAX: Register = Register::general(0, 4);
+pub const ECX: Register = Register::general(1, 4);
+pub const EDX: Register = Register::general(2, 4);
+pub const EBX: Register = Register::general(3, 4);
+pub const ESP: Register = Register::general(4, 4);
+pub const EBP: Register = Register::general(5, 4);
+pub const ESI: Register = Register::general(6, 4);
+pub const EDI: Register = Register::general(7, 4);
+pub const R8D: Register = Register::general(8, 4);
+pub const R9D: Register = Register::general(9, 4);
+pub const R10D: Register = Register::general(10, 4);
+pub const R11D: Register = Register::general(11, 4);
+pub const R12D: Register = Register::general(12, 4);
+pub const R13D: Register = Register::general(13, 4);
+pub const R14D: Register = Register::general(14, 4);
+pub const R15D: Register = Register::general(15, 4);
+
+pub const AX: Register = Register::general(0, 2);
+pub const CX: Register = Register::general(1, 2);
+pub const DX: Register = Register::general(2, 2);
+pub const BX: Register = Register::general(3, 2);
+pub const SP: Register = Register::general(4, 2);
+pub const BP: Register = Register::general(5, 2);
+pub const SI: Register = Register::general(6, 2);
+pub const DI: Register = Register::general(7, 2);
+pub const R8W: Register = Register::general(8, 2);
+pub const R9W: Register = Register::general(9, 2);
+pub const R10W: Register = Register::general(10, 2);
+pub const R11W: Register = Register::general(11, 2);
+pub const R12W: Register = Register::general(12, 2);
+pub const R13W: Register = Register::general(13, 2);
+pub const R14W: Register = Register::general(14, 2);
+pub const R15W: Register = Register::general(15, 2);
+
+pub const AL: Register = Register::general(0, 1);
+pub const CL: Register = Register::general(1, 1);
+pub const DL: Register = Register::general(2, 1);
+pub const BL: Register = Register::general(3, 1);
+pub const SPL: Register = Register::general(4, 1);
+pub const BPL: Register = Register::general(5, 1);
+pub const SIL: Register = Register::general(6, 1);
+pub const DIL: Register = Register::general(7, 1);
+pub const R8B: Register = Register::general(8, 1);
+pub const R9B: Register = Register::general(9, 1);
+pub const R10B: Register = Register::general(10, 1);
+pub const R11B: Register = Register::general(11, 1);
+pub const R12B: Register = Register::general(12, 1);
+pub const R13B: Register = Register::general(13, 1);
+pub const R14B: Register = Register::general(14, 1);
+pub const R15B: Register = Register::general(15, 1);
+pub const AH: Register = Register { kind: RegisterKind::General, number: 4, size: 1, high_byte: true };
+
+pub const XMM0: Register = Register::xmm(0);
+pub const XMM1: Register = Register::xmm(1);
+pub const XMM2: Register = Register::xmm(2);
+pub const XMM3: Register = Register::xmm(3);
+pub const XMM4: Register = Register::xmm(4);
+pub const XMM5: Register = Register::xmm(5);
+pub const XMM6: Register = Register::xmm(6);
+pub const XMM7: Register = Register::xmm(7);
+pub const XMM8: Register = Register::xmm(8);
+pub const XMM9: Register = Register::xmm(9);
+pub const XMM10: Register = Register::xmm(10);
+pub const XMM11: Register = Register::xmm(11);
+pub const XMM12: Register = Register::xmm(12);
+pub const XMM13: Register = Register::xmm(13);
+pub const XMM14: Register = Register::xmm(14);
+pub const XMM15: Register = Register::xmm(15);
+
+pub const ST0: Register = Register::x87(0);
+pub const ST1: Register = Register::x87(1);
+pub const ST2: Register = Register::x87(2);
+pub const ST3: Register = Register::x87(3);
+pub const ST4: Register = Register::x87(4);
+pub const ST5: Register = Register::x87(5);
+pub const ST6: Register = Register::x87(6);
+pub const ST7: Register = Register::x87(7);
+
+/// A memory operand, `size [base + index*scale + symbol + displacement]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    // From `byte`, `word`, `dword` or `qword`, in bytes
+    pub size: Option<u8>,
+    pub base: Option<Register>,
+    pub index: Option<(Register, u8)>,
+    pub symbol: Option<String>,
+    pub displacement: i64,
+}
+
+impl Memory {
+    /// `[symbol]`, addressed relative to rip.
+    pub fn symbol(symbol: &str) -> Memory {
+        Memory { size: None, base: None, index: None, symbol: Some(symbol.to_string()), displacement: 0 }
+    }
+}
+
+// Memory operands are written like NASM's, `qword(RBP - 8)` is `qword [rbp - 8]` and
+// `RAX + RCX * 2 + 16` is `[rax + rcx*2 + 16]`
+
+impl From<Register> for Memory {
+    fn from(base: Register) -> Memory {
+        Memory { size: None, base: Some(base), index: None, symbol: None, displacement: 0 }
+    }
+}
+
+/// A scaled index register, `RCX * 8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Index(Register, u8);
+
+impl Mul<u8> for Register {
+    type Output = Index;
+
+    fn mul(self, scale: u8) -> Index {
+        Index(self, scale)
+    }
+}
+
+impl Add<usize> for Register {
+    type Output = Memory;
+
+    fn add(self, displacement: usize) -> Memory {
+        Memory::from(self) + displacement
+    }
+}
+
+impl Sub<usize> for Register {
+    type Output = Memory;
+
+    fn sub(self, displacement: usize) -> Memory {
+        Memory::from(self) - displacement
+    }
+}
+
+impl Add<Register> for Register {
+    type Output = Memory;
+
+    fn add(self, index: Register) -> Memory {
+        Memory::from(self) + index * 1
+    }
+}
+
+impl Add<Index> for Register {
+    type Output = Memory;
+
+    fn add(self, index: Index) -> Memory {
+        Memory::from(self) + index
+    }
+}
+
+impl Add<Index> for Memory {
+    type Output = Memory;
+
+    fn add(self, Index(index, scale): Index) -> Memory {
+        Memory { index: Some((index, scale)), ..self }
+    }
+}
+
+impl Add<usize> for Memory {
+    type Output = Memory;
+
+    fn add(self, displacement: usize) -> Memory {
+        Memory { displacement: self.displacement + displacement as i64, ..self }
+    }
+}
+
+impl Sub<usize> for Memory {
+    type Output = Memory;
+
+    fn sub(self, displacement: usize) -> Memory {
+        Memory { displacement: self.displacement - displacement as i64, ..self }
+    }
+}
+
+fn sized(size: u8, address: impl Into<Memory>) -> Memory {
+    Memory { size: Some(size), ..address.into() }
+}
+
+pub fn byte(address: impl Into<Memory>) -> Memory {
+    sized(1, address)
+}
+
+pub fn word(address: impl Into<Memory>) -> Memory {
+    sized(2, address)
+}
+
+pub fn dword(address: impl Into<Memory>) -> Memory {
+    sized(4, address)
+}
+
+pub fn qword(address: impl Into<Memory>) -> Memory {
+    sized(8, address)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Memory(Memory),
+    Immediate(i64),
+    // The address of a symbol plus an offset, as a jump target or an immediate
+    Symbol(String, i64),
+}
+
+impl Operand {
+    pub fn size(&self) -> Option<u8> {
+        match self {
+            Operand::Register(register) => Some(register.size),
+            Operand::Memory(memory) => memory.size,
+            _ => None,
+        }
+    }
+}
+
+impl From<Register> for Operand {
+    fn from(register: Register) -> Operand {
+        Operand::Register(register)
+    }
+}
+
+impl From<Memory> for Operand {
+    fn from(memory: Memory) -> Operand {
+        Operand::Memory(memory)
+    }
+}
+
+impl From<i64> for Operand {
+    fn from(value: i64) -> Operand {
+        Operand::Immediate(value)
+    }
+}
+
+impl From<i32> for Operand {
+    fn from(value: i32) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<i16> for Operand {
+    fn from(value: i16) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<u8> for Operand {
+    fn from(value: u8) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<u16> for Operand {
+    fn from(value: u16) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<u64> for Operand {
+    fn from(value: u64) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<u32> for Operand {
+    fn from(value: u32) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+impl From<usize> for Operand {
+    fn from(value: usize) -> Operand {
+        Operand::Immediate(value as i64)
+    }
+}
+
+// A name is the address of the symbol
+impl From<&str> for Operand {
+    fn from(symbol: &str) -> Operand {
+        Operand::Symbol(symbol.to_string(), 0)
+    }
+}
+
+impl From<&String> for Operand {
+    fn from(symbol: &String) -> Operand {
+        Operand::from(symbol.as_str())
+    }
+}
+
+impl From<String> for Operand {
+    fn from(symbol: String) -> Operand {
+        Operand::from(symbol.as_str())
+    }
+}
+
+/// The condition codes of jcc and setcc, numbered like their encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    O,
+    No,
+    B,
+    Ae,
+    E,
+    Ne,
+    Be,
+    A,
+    S,
+    Ns,
+    P,
+    Np,
+    L,
+    Ge,
+    Le,
+    G,
+}
+
+impl Condition {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Condition::O => "o",
+            Condition::No => "no",
+            Condition::B => "b",
+            Condition::Ae => "ae",
+            Condition::E => "e",
+            Condition::Ne => "ne",
+            Condition::Be => "be",
+            Condition::A => "a",
+            Condition::S => "s",
+            Condition::Ns => "ns",
+            Condition::P => "p",
+            Condition::Np => "np",
+            Condition::L => "l",
+            Condition::Ge => "ge",
+            Condition::Le => "le",
+            Condition::G => "g",
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+/// The two operand integer instructions of the arithmetic group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+impl ArithmeticOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "add",
+            ArithmeticOp::Or => "or",
+            ArithmeticOp::And => "and",
+            ArithmeticOp::Sub => "sub",
+            ArithmeticOp::Xor => "xor",
+            ArithmeticOp::Cmp => "cmp",
+        }
+    }
+
+    // The /digit of the group, also the row of their r/m, reg opcodes
+    pub fn extension(self) -> u8 {
+        match self {
+            ArithmeticOp::Add => 0,
+            ArithmeticOp::Or => 1,
+            ArithmeticOp::And => 4,
+            ArithmeticOp::Sub => 5,
+            ArithmeticOp::Xor => 6,
+            ArithmeticOp::Cmp => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Shl,
+    Shr,
+    Sar,
+}
+
+impl ShiftOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            ShiftOp::Shl => "shl",
+            ShiftOp::Shr => "shr",
+            ShiftOp::Sar => "sar",
+        }
+    }
+
+    pub fn extension(self) -> u8 {
+        match self {
+            ShiftOp::Shl => 4,
+            ShiftOp::Shr => 5,
+            ShiftOp::Sar => 7,
+        }
+    }
+}
+
+/// The scalar SSE instructions, all taking xmm or an integer register and xmm, memory or
+/// an integer register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SseOp {
+    Movss,
+    Movsd,
+    Addss,
+    Addsd,
+    Subss,
+    Subsd,
+    Mulss,
+    Mulsd,
+    Divss,
+    Divsd,
+    Cvtss2sd,
+    Cvtsd2ss,
+    Cvtsi2ss,
+    Cvtsi2sd,
+    Cvttss2si,
+    Cvttsd2si,
+    Ucomiss,
+    Ucomisd,
+    Xorps,
+    Sqrtss,
+    Sqrtsd,
+}
+
+impl SseOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            SseOp::Movss => "movss",
+            SseOp::Movsd => "movsd",
+            SseOp::Addss => "addss",
+            SseOp::Addsd => "addsd",
+            SseOp::Subss => "subss",
+            SseOp::Subsd => "subsd",
+            SseOp::Mulss => "mulss",
+            SseOp::Mulsd => "mulsd",
+            SseOp::Divss => "divss",
+            SseOp::Divsd => "divsd",
+            SseOp::Cvtss2sd => "cvtss2sd",
+            SseOp::Cvtsd2ss => "cvtsd2ss",
+            SseOp::Cvtsi2ss => "cvtsi2ss",
+            SseOp::Cvtsi2sd => "cvtsi2sd",
+            SseOp::Cvttss2si => "cvttss2si",
+            SseOp::Cvttsd2si => "cvttsd2si",
+            SseOp::Ucomiss => "ucomiss",
+            SseOp::Ucomisd => "ucomisd",
+            SseOp::Xorps => "xorps",
+            SseOp::Sqrtss => "sqrtss",
+            SseOp::Sqrtsd => "sqrtsd",
+        }
+    }
+}
+
+/// An instruction or directive. Operands are in Intel order, destination first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum X86Inst {
+    Section(String),
+    Global(String),
+    Extern(String),
+    Label(String),
+    Align(u64),
+    Db(Vec<u8>),
+    // An immediate or the address of a symbol
+    Dq(Operand),
+
+    Mov(Operand, Operand),
+    Movsx(Operand, Operand),
+    Movsxd(Operand, Operand),
+    Movzx(Operand, Operand),
+    Push(Operand),
+    Pop(Operand),
+    Arithmetic(ArithmeticOp, Operand, Operand),
+    Test(Operand, Operand),
+    Imul(Operand, Operand),
+    Neg(Operand),
+    Idiv(Operand),
+    Shift(ShiftOp, Operand, Operand),
+    Btc(Operand, Operand),
+    // Sign extend eax into edx:eax and rax into rdx:rax
+    Cdq,
+    Cqo,
+    Setcc(Condition, Operand),
+    Jmp(Operand),
+    Jcc(Condition, String),
+    Call(Operand),
+    Sse(SseOp, Operand, Operand),
+    // x87, for frem and drem, Math's logarithms, powers and trigonometry and printing
+    // floating point numbers
+    Fld(Operand),
+    Fstp(Operand),
+    Fmul(Operand),
+    Fdiv(Operand),
+    Fistp(Operand),
+    Fld1,
+    Fyl2x,
+    F2xm1,
+    Faddp,
+    Fscale,
+    Fprem,
+    Fldln2,
+    Fldl2e,
+    Fldpi,
+    Fsin,
+    Fcos,
+    Fnstsw(Operand),
+    Leave,
+    Ret,
+    Syscall,
+}
+
+impl X86Inst {
+    /// The Intel mnemonic of an instruction, None for directives.
+    pub fn mnemonic(&self) -> Option<String> {
+        let mnemonic = match self {
+            X86Inst::Section(_)
+            | X86Inst::Global(_)
+            | X86Inst::Extern(_)
+            | X86Inst::Label(_)
+            | X86Inst::Align(_)
+            | X86Inst::Db(_)
+            | X86Inst::Dq(_) => return None,
+            X86Inst::Mov(..) => "mov",
+            X86Inst::Movsx(..) => "movsx",
+            X86Inst::Movsxd(..) => "movsxd",
+            X86Inst::Movzx(..) => "movzx",
+            X86Inst::Push(_) => "push",
+            X86Inst::Pop(_) => "pop",
+            X86Inst::Arithmetic(op, ..) => op.mnemonic(),
+            X86Inst::Test(..) => "test",
+            X86Inst::Imul(..) => "imul",
+            X86Inst::Neg(_) => "neg",
+            X86Inst::Idiv(_) => "idiv",
+            X86Inst::Shift(op, ..) => op.mnemonic(),
+            X86Inst::Btc(..) => "btc",
+            X86Inst::Cdq => "cdq",
+            X86Inst::Cqo => "cqo",
+            X86Inst::Setcc(condition, _) => return Some(format!("set{}", condition.suffix())),
+            X86Inst::Jmp(_) => "jmp",
+            X86Inst::Jcc(condition, _) => return Some(format!("j{}", condition.suffix())),
+            X86Inst::Call(_) => "call",
+            X86Inst::Sse(op, ..) => op.mnemonic(),
+            X86Inst::Fld(_) => "fld",
+            X86Inst::Fstp(_) => "fstp",
+            X86Inst::Fmul(_) => "fmul",
+            X86Inst::Fdiv(_) => "fdiv",
+            X86Inst::Fistp(_) => "fistp",
+            X86Inst::Fld1 => "fld1",
+            X86Inst::Fyl2x => "fyl2x",
+            X86Inst::F2xm1 => "f2xm1",
+            X86Inst::Faddp => "faddp",
+            X86Inst::Fscale => "fscale",
+            X86Inst::Fprem => "fprem",
+            X86Inst::Fldln2 => "fldln2",
+            X86Inst::Fldl2e => "fldl2e",
+            X86Inst::Fldpi => "fldpi",
+            X86Inst::Fsin => "fsin",
+            X86Inst::Fcos => "fcos",
+            X86Inst::Fnstsw(_) => "fnstsw",
+            X86Inst::Leave => "leave",
+            X86Inst::Ret => "ret",
+            X86Inst::Syscall => "syscall",
+        };
+        Some(mnemonic.to_string())
+    }
+
+    /// The operands of an instruction in Intel order, jcc targets as symbols.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            X86Inst::Mov(dest, src)
+            | X86Inst::Movsx(dest, src)
+            | X86Inst::Movsxd(dest, src)
+            | X86Inst::Movzx(dest, src)
+            | X86Inst::Arithmetic(_, dest, src)
+            | X86Inst::Test(dest, src)
+            | X86Inst::Imul(dest, src)
+            | X86Inst::Shift(_, dest, src)
+            | X86Inst::Btc(dest, src)
+            | X86Inst::Sse(_, dest, src) => vec![dest.clone(), src.clone()],
+            X86Inst::Push(operand)
+            | X86Inst::Pop(operand)
+            | X86Inst::Neg(operand)
+            | X86Inst::Idiv(operand)
+            | X86Inst::Setcc(_, operand)
+            | X86Inst::Jmp(operand)
+            | X86Inst::Call(operand)
+            | X86Inst::Fld(operand)
+            | X86Inst::Fstp(operand)
+            | X86Inst::Fmul(operand)
+            | X86Inst::Fdiv(operand)
+            | X86Inst::Fistp(operand)
+            | X86Inst::Fnstsw(operand) => vec![operand.clone()],
+            X86Inst::Jcc(_, label) => vec![Operand::Symbol(label.clone(), 0)],
+            _ => Vec::new(),
+        }
+    }
+}
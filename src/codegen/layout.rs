@@ -0,0 +1,616 @@
+use crate::bytecode::classpath::ClassPath;
+use crate::bytecode::descriptor::{parse_field_descriptor, FieldType};
+use crate::bytecode::method::Method;
+use crate::bytecode::{ParsedBytecode, ACC_ABSTRACT, ACC_FINAL, ACC_INTERFACE, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC};
+use crate::codegen::library::library_methods;
+
+// Every object starts with a two word header:
+//
+// [object + 0]  the class pointer, the address of the class's metadata
+// [object + 8]  the hash and lock word, zero until something needs it. The identity hash
+//               is its low half and the number of times the object's monitor is held
+//               the high half.
+//
+// The instance fields follow, the superclass's first so a subclass instance can be used
+// wherever its superclass is expected. Each field is aligned to its own size and objects
+// are a multiple of 8 bytes.
+//
+// An array has its length after the header and then its elements:
+//
+// [array + 16]  the length
+// [array + 24]  the first element
+
+// A class's metadata is read only and laid out as:
+//
+// [class + 0]  the superclass's metadata, zero for java/lang/Object
+// [class + 8]  the size of an instance, without the elements for arrays
+// [class + 16] the address of the offsets of the instance's reference fields, a count
+//              followed by the offsets, which the collector follows
+// [class + 24] the address of the itable, a count followed by (selector, method) pairs
+//              for the methods interface calls may reach
+// [class + 32] the size of an element for arrays, zero for other classes
+// [class + 40] the element class's metadata for arrays of references, zero otherwise
+// [class + 48] the access flags, ACC_INTERFACE telling interfaces apart
+// [class + 56] the address of the class's name, a length followed by the bytes of the
+//              name with dots, as Java prints it
+// [class + 64] the address of the interfaces the class implements, a count followed by
+//              their metadata, the ones they and the superclasses extend included
+// [class + 72] the class's depth, the number of superclasses it has
+// [class + 80] the address of the class's display, the metadata of java/lang/Object and
+//              its other superclasses from the top down and last its own
+// [class + 88] the vtable, the address of each virtual method, zero for abstract ones
+//
+// A class C is a subclass of a class T at depth d when C's display has T at index d,
+// which takes one compare however deep the hierarchy is. Interfaces have
+// java/lang/Object for their superclass and are found in the list of interfaces
+// instead.
+//
+// The runtime defines the metadata of the classes `runtime_classes` lists, the compiler
+// every other class's. A class with no class file that the program implements, has
+// arrays of or tests objects against gets a stub, see `stub_layout`. A virtual method has the same vtable index in a class and all its
+// subclasses. Interface methods are looked up in the itable by a selector, a number the
+// compiler gives each name and descriptor interface calls use.
+//
+// Static fields are not in the metadata: each is a qword of its own in the data section,
+// whatever its type, starting out with its ConstantValue or zero. The collector finds
+// the ones holding references through a table the compiler emits. A class whose
+// initialization runs code has a state qword next to them and a function initializing
+// it, which compiled code calls before the first new, static field access or static call
+// on the class.
+//
+// The Class objects ldc loads, which static synchronized methods also lock, are outside
+// the heap like string literals, one for each class the program refers to that way:
+//
+// [class object + 16] the class's metadata
+// [class object + 24] for an enum class, the address of the static field holding its
+//                     constants, javac's $VALUES, and zero for other classes
+// [class object + 32] the function initializing the class, zero when it has none
+// [class object + 40] the address of the class's canonical name, a length followed by
+//                     the bytes, which Enum.valueOf puts in its message
+
+pub const SUPER_CLASS_OFFSET: usize = 0;
+pub const INSTANCE_SIZE_OFFSET: usize = 8;
+pub const REFERENCE_FIELDS_OFFSET: usize = 16;
+pub const ITABLE_OFFSET: usize = 24;
+pub const ELEMENT_SIZE_OFFSET: usize = 32;
+pub const ELEMENT_CLASS_OFFSET: usize = 40;
+pub const ACCESS_FLAGS_OFFSET: usize = 48;
+pub const NAME_OFFSET: usize = 56;
+pub const INTERFACES_OFFSET: usize = 64;
+pub const DEPTH_OFFSET: usize = 72;
+pub const DISPLAY_OFFSET: usize = 80;
+pub const VTABLE_OFFSET: usize = 88;
+
+pub const CLASS_POINTER_OFFSET: usize = 0;
+pub const HASH_LOCK_OFFSET: usize = 8;
+pub const LOCK_COUNT_OFFSET: usize = 12;
+pub const HEADER_SIZE: usize = 16;
+
+pub const ARRAY_LENGTH_OFFSET: usize = 16;
+pub const ARRAY_DATA_OFFSET: usize = 24;
+
+pub const CLASS_METADATA_OFFSET: usize = 16;
+pub const CLASS_ENUM_CONSTANTS_OFFSET: usize = 24;
+pub const CLASS_INITIALIZER_OFFSET: usize = 32;
+pub const CLASS_CANONICAL_NAME_OFFSET: usize = 40;
+pub const CLASS_OBJECT_SIZE: usize = 48;
+
+// What the JVM gives array classes
+const ARRAY_ACCESS_FLAGS: u16 = ACC_PUBLIC | ACC_FINAL | ACC_ABSTRACT;
+
+pub const CLONEABLE_CLASS: &str = "java/lang/Cloneable";
+pub const SERIALIZABLE_CLASS: &str = "java/io/Serializable";
+
+// Every array class implements them (JLS 10.8)
+const ARRAY_INTERFACES: [&str; 2] = [CLONEABLE_CLASS, SERIALIZABLE_CLASS];
+
+// The JDK interfaces the runtime has metadata for, those of arrays and of the runtime's
+// classes, with the interfaces they extend
+const RUNTIME_INTERFACES: [(&str, &[&str]); 10] = [
+    (CLONEABLE_CLASS, &[]),
+    (SERIALIZABLE_CLASS, &[]),
+    ("java/lang/Comparable", &[]),
+    ("java/lang/CharSequence", &[]),
+    ("java/lang/Appendable", &[]),
+    ("java/lang/AutoCloseable", &[]),
+    ("java/io/Closeable", &["java/lang/AutoCloseable"]),
+    ("java/io/Flushable", &[]),
+    ("java/lang/constant/Constable", &[]),
+    ("java/lang/constant/ConstantDesc", &[]),
+];
+
+pub const OBJECT_CLASS: &str = "java/lang/Object";
+pub const STRING_CLASS: &str = "java/lang/String";
+
+// A string's characters are in a byte array, one byte each when its coder is Latin-1 and
+// two when it is UTF-16, like the JDK's own String. The literals are laid out the same
+// way in the program's data, one object per text, and String.intern maps equal strings
+// to them.
+pub const STRING_VALUE_OFFSET: usize = 16;
+pub const STRING_CODER_OFFSET: usize = 24;
+pub const STRING_SIZE: usize = 32;
+pub const LATIN1: u8 = 0;
+
+pub const STRING_BUILDER_CLASS: &str = "java/lang/StringBuilder";
+
+// A string builder keeps its characters in UTF-16 whatever they are, the array's length
+// is twice its capacity. Its value and coder are where a string has them, so the same
+// code reads the characters of both.
+pub const STRING_BUILDER_VALUE_OFFSET: usize = 16;
+pub const STRING_BUILDER_CODER_OFFSET: usize = 24;
+pub const STRING_BUILDER_COUNT_OFFSET: usize = 28;
+pub const UTF16: u8 = 1;
+
+pub const BOOLEAN_CLASS: &str = "java/lang/Boolean";
+pub const INTEGER_CLASS: &str = "java/lang/Integer";
+pub const LONG_CLASS: &str = "java/lang/Long";
+pub const CHARACTER_CLASS: &str = "java/lang/Character";
+pub const FLOAT_CLASS: &str = "java/lang/Float";
+pub const DOUBLE_CLASS: &str = "java/lang/Double";
+pub const NUMBER_CLASS: &str = "java/lang/Number";
+
+// Where the boxes keep their value
+pub const BOX_VALUE_OFFSET: usize = 16;
+pub const BOX_SIZE: usize = 24;
+
+pub const PRINT_STREAM_CLASS: &str = "java/io/PrintStream";
+
+// A print stream writes to a file descriptor
+pub const PRINT_STREAM_FD_OFFSET: usize = 16;
+
+pub const CLASS_CLASS: &str = "java/lang/Class";
+pub const ENUM_CLASS: &str = "java/lang/Enum";
+
+// Where an enum constant keeps its name and ordinal
+pub const ENUM_NAME_OFFSET: usize = 16;
+pub const ENUM_ORDINAL_OFFSET: usize = 24;
+
+pub const THROWABLE_CLASS: &str = "java/lang/Throwable";
+pub const ERROR_CLASS: &str = "java/lang/Error";
+pub const EXCEPTION_IN_INITIALIZER_ERROR_CLASS: &str = "java/lang/ExceptionInInitializerError";
+pub const NO_CLASS_DEF_FOUND_ERROR_CLASS: &str = "java/lang/NoClassDefFoundError";
+pub const ILLEGAL_MONITOR_STATE_EXCEPTION_CLASS: &str = "java/lang/IllegalMonitorStateException";
+pub const CLONE_NOT_SUPPORTED_EXCEPTION_CLASS: &str = "java/lang/CloneNotSupportedException";
+
+// A throwable's message is a string, or null
+pub const THROWABLE_MESSAGE_OFFSET: usize = 16;
+pub const THROWABLE_SIZE: usize = 24;
+
+// The classes the runtime has metadata for besides its interfaces and exceptions, the
+// primitive arrays and main's String[] among them
+const RUNTIME_CLASSES: [&str; 22] = [
+    OBJECT_CLASS,
+    CLASS_CLASS,
+    ENUM_CLASS,
+    STRING_CLASS,
+    STRING_BUILDER_CLASS,
+    BOOLEAN_CLASS,
+    INTEGER_CLASS,
+    LONG_CLASS,
+    CHARACTER_CLASS,
+    FLOAT_CLASS,
+    DOUBLE_CLASS,
+    NUMBER_CLASS,
+    PRINT_STREAM_CLASS,
+    "[Z",
+    "[B",
+    "[C",
+    "[S",
+    "[I",
+    "[J",
+    "[F",
+    "[D",
+    "[Ljava/lang/String;",
+];
+
+type RuntimeFields = (&'static str, &'static [(&'static str, &'static str, usize)]);
+
+// The fields of the runtime's classes that have any, which its routines use at fixed
+// offsets, as (name, descriptor, offset)
+const RUNTIME_FIELDS: [RuntimeFields; 10] = [
+    (STRING_CLASS, &[("value", "[B", STRING_VALUE_OFFSET), ("coder", "B", STRING_CODER_OFFSET)]),
+    (
+        STRING_BUILDER_CLASS,
+        &[
+            ("value", "[B", STRING_BUILDER_VALUE_OFFSET),
+            ("coder", "B", STRING_BUILDER_CODER_OFFSET),
+            ("count", "I", STRING_BUILDER_COUNT_OFFSET),
+        ],
+    ),
+    (BOOLEAN_CLASS, &[("value", "Z", BOX_VALUE_OFFSET)]),
+    (INTEGER_CLASS, &[("value", "I", BOX_VALUE_OFFSET)]),
+    (LONG_CLASS, &[("value", "J", BOX_VALUE_OFFSET)]),
+    (CHARACTER_CLASS, &[("value", "C", BOX_VALUE_OFFSET)]),
+    (FLOAT_CLASS, &[("value", "F", BOX_VALUE_OFFSET)]),
+    (DOUBLE_CLASS, &[("value", "D", BOX_VALUE_OFFSET)]),
+    (PRINT_STREAM_CLASS, &[("fd", "I", PRINT_STREAM_FD_OFFSET)]),
+    (ENUM_CLASS, &[("name", "Ljava/lang/String;", ENUM_NAME_OFFSET), ("ordinal", "I", ENUM_ORDINAL_OFFSET)]),
+];
+
+// The JDK interfaces of the runtime's classes, which are all they implement in the JDK
+// but for PrintStream's and Class's, whose superclasses and reflection interfaces it
+// leaves out. A class's superclass's are not repeated.
+const RUNTIME_CLASS_INTERFACES: [(&str, &[&str]); 12] = [
+    (CLASS_CLASS, &[SERIALIZABLE_CLASS, "java/lang/constant/Constable"]),
+    (ENUM_CLASS, &["java/lang/Comparable", SERIALIZABLE_CLASS, "java/lang/constant/Constable"]),
+    (
+        STRING_CLASS,
+        &[SERIALIZABLE_CLASS, "java/lang/Comparable", "java/lang/CharSequence", "java/lang/constant/Constable", "java/lang/constant/ConstantDesc"],
+    ),
+    (STRING_BUILDER_CLASS, &[SERIALIZABLE_CLASS, "java/lang/Comparable", "java/lang/CharSequence", "java/lang/Appendable"]),
+    (NUMBER_CLASS, &[SERIALIZABLE_CLASS]),
+    (BOOLEAN_CLASS, &[SERIALIZABLE_CLASS, "java/lang/Comparable", "java/lang/constant/Constable"]),
+    (INTEGER_CLASS, &["java/lang/Comparable", "java/lang/constant/Constable", "java/lang/constant/ConstantDesc"]),
+    (LONG_CLASS, &["java/lang/Comparable", "java/lang/constant/Constable", "java/lang/constant/ConstantDesc"]),
+    (FLOAT_CLASS, &["java/lang/Comparable", "java/lang/constant/Constable", "java/lang/constant/ConstantDesc"]),
+    (DOUBLE_CLASS, &["java/lang/Comparable", "java/lang/constant/Constable", "java/lang/constant/ConstantDesc"]),
+    (CHARACTER_CLASS, &[SERIALIZABLE_CLASS, "java/lang/Comparable", "java/lang/constant/Constable"]),
+    (PRINT_STREAM_CLASS, &["java/lang/Appendable", "java/io/Closeable", "java/lang/AutoCloseable", "java/io/Flushable"]),
+];
+
+/// The exceptions the runtime has metadata and constructors for, with their superclasses.
+/// Programs can throw, catch and extend them.
+pub const THROWABLE_CLASSES: [(&str, &str); 30] = [
+    (THROWABLE_CLASS, OBJECT_CLASS),
+    ("java/lang/Exception", THROWABLE_CLASS),
+    (ERROR_CLASS, THROWABLE_CLASS),
+    (CLONE_NOT_SUPPORTED_EXCEPTION_CLASS, "java/lang/Exception"),
+    ("java/lang/RuntimeException", "java/lang/Exception"),
+    ("java/lang/ArithmeticException", "java/lang/RuntimeException"),
+    ("java/lang/ArrayStoreException", "java/lang/RuntimeException"),
+    ("java/lang/ClassCastException", "java/lang/RuntimeException"),
+    ("java/lang/IllegalArgumentException", "java/lang/RuntimeException"),
+    ("java/lang/NumberFormatException", "java/lang/IllegalArgumentException"),
+    ("java/lang/IllegalStateException", "java/lang/RuntimeException"),
+    (ILLEGAL_MONITOR_STATE_EXCEPTION_CLASS, "java/lang/RuntimeException"),
+    ("java/lang/IndexOutOfBoundsException", "java/lang/RuntimeException"),
+    ("java/lang/ArrayIndexOutOfBoundsException", "java/lang/IndexOutOfBoundsException"),
+    ("java/lang/StringIndexOutOfBoundsException", "java/lang/IndexOutOfBoundsException"),
+    ("java/lang/NegativeArraySizeException", "java/lang/RuntimeException"),
+    ("java/lang/NullPointerException", "java/lang/RuntimeException"),
+    ("java/lang/UnsupportedOperationException", "java/lang/RuntimeException"),
+    ("java/util/IllegalFormatException", "java/lang/IllegalArgumentException"),
+    ("java/util/UnknownFormatConversionException", "java/util/IllegalFormatException"),
+    ("java/util/MissingFormatArgumentException", "java/util/IllegalFormatException"),
+    ("java/util/IllegalFormatConversionException", "java/util/IllegalFormatException"),
+    ("java/lang/LinkageError", "java/lang/Error"),
+    ("java/lang/IncompatibleClassChangeError", "java/lang/LinkageError"),
+    ("java/lang/AbstractMethodError", "java/lang/IncompatibleClassChangeError"),
+    ("java/lang/NoSuchFieldError", "java/lang/IncompatibleClassChangeError"),
+    (EXCEPTION_IN_INITIALIZER_ERROR_CLASS, "java/lang/LinkageError"),
+    (NO_CLASS_DEF_FOUND_ERROR_CLASS, "java/lang/LinkageError"),
+    ("java/lang/VirtualMachineError", "java/lang/Error"),
+    ("java/lang/OutOfMemoryError", "java/lang/VirtualMachineError"),
+];
+
+/// The classes the runtime has metadata for.
+pub fn runtime_classes() -> impl Iterator<Item = &'static str> {
+    RUNTIME_CLASSES
+        .into_iter()
+        .chain(RUNTIME_INTERFACES.into_iter().map(|(class_name, _)| class_name))
+        .chain(THROWABLE_CLASSES.into_iter().map(|(class_name, _)| class_name))
+}
+
+pub fn is_runtime_class(class_name: &str) -> bool {
+    runtime_classes().any(|c| c == class_name)
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldLayout {
+    pub name: String,
+    pub descriptor: String,
+    pub field_type: FieldType,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassLayout {
+    pub class_name: String,
+    // None for java/lang/Object
+    pub super_class: Option<String>,
+    pub access_flags: u16,
+    // The inherited fields first
+    pub fields: Vec<FieldLayout>,
+    pub size: usize,
+    // The size of an element, zero unless this is an array class
+    pub element_size: usize,
+    // The element class of an array of references
+    pub element_class: Option<String>,
+    // The interfaces the class implements, directly or not
+    pub interfaces: Vec<String>,
+    // java/lang/Object, the other superclasses and the class itself, see the display
+    pub display: Vec<String>,
+}
+
+impl ClassLayout {
+    /// Finds a field the way getfield and putfield resolve it, a field declared in a
+    /// subclass hides one with the same name in its superclasses.
+    pub fn field(&self, name: &str, descriptor: &str) -> Option<&FieldLayout> {
+        self.fields.iter().rev().find(|field| field.name == name && field.descriptor == descriptor)
+    }
+
+    /// The offsets of the fields holding references, inherited ones included.
+    pub fn reference_offsets(&self) -> Vec<usize> {
+        self.fields
+            .iter()
+            .filter(|field| matches!(field.field_type, FieldType::Object(_) | FieldType::Array(_)))
+            .map(|field| field.offset)
+            .collect()
+    }
+}
+
+/// The size of a field in an object.
+pub fn field_size(field_type: &FieldType) -> usize {
+    match field_type {
+        FieldType::Boolean | FieldType::Byte => 1,
+        FieldType::Char | FieldType::Short => 2,
+        FieldType::Int | FieldType::Float => 4,
+        FieldType::Long | FieldType::Double | FieldType::Object(_) | FieldType::Array(_) => 8,
+    }
+}
+
+/// Whether a class has metadata of its own, from its class file or the runtime, rather
+/// than a stub.
+pub fn has_metadata(classes: &ClassPath, class_name: &str) -> bool {
+    class_name.starts_with('[') || is_runtime_class(class_name) || classes.find(class_name).is_some()
+}
+
+/// The name Java prints for a class, with dots between the packages.
+pub fn java_name(class_name: &str) -> String {
+    class_name.replace('/', ".")
+}
+
+/// Lays out the instances of `class_name`, which has to be on the class path unless it
+/// is an array class or one of the runtime's.
+pub fn class_layout(classes: &ClassPath, class_name: &str) -> Result<ClassLayout, String> {
+    let mut layout = ClassLayout {
+        class_name: class_name.to_string(),
+        super_class: Some(OBJECT_CLASS.to_string()),
+        access_flags: ACC_PUBLIC,
+        fields: Vec::new(),
+        size: HEADER_SIZE,
+        element_size: 0,
+        element_class: None,
+        interfaces: Vec::new(),
+        display: vec![OBJECT_CLASS.to_string(), class_name.to_string()],
+    };
+
+    if class_name == OBJECT_CLASS {
+        layout.super_class = None;
+        layout.display.pop();
+        return Ok(layout);
+    }
+
+    if let Some(element) = class_name.strip_prefix('[') {
+        let element_type = parse_field_descriptor(element)?;
+        layout.access_flags = ARRAY_ACCESS_FLAGS;
+        layout.size = ARRAY_DATA_OFFSET;
+        layout.element_size = field_size(&element_type);
+        layout.element_class = match element_type {
+            FieldType::Object(name) => Some(name),
+            FieldType::Array(_) => Some(element.to_string()),
+            _ => None,
+        };
+        layout.interfaces = ARRAY_INTERFACES.map(String::from).to_vec();
+        return Ok(layout);
+    }
+
+    if let Some((_, superinterfaces)) = RUNTIME_INTERFACES.iter().find(|(c, _)| *c == class_name) {
+        layout.access_flags = ACC_PUBLIC | ACC_INTERFACE | ACC_ABSTRACT;
+        layout.interfaces = superinterfaces.iter().map(|interface| interface.to_string()).collect();
+        return Ok(layout);
+    }
+
+    if let Some((_, interfaces)) = RUNTIME_CLASS_INTERFACES.iter().find(|(c, _)| *c == class_name) {
+        // The boxes of numbers extend Number, which Serializable comes from
+        if matches!(class_name, INTEGER_CLASS | LONG_CLASS | FLOAT_CLASS | DOUBLE_CLASS) {
+            layout = class_layout(classes, NUMBER_CLASS)?;
+            layout.class_name = class_name.to_string();
+            layout.super_class = Some(NUMBER_CLASS.to_string());
+            layout.display.push(class_name.to_string());
+        }
+        layout.interfaces.extend(interfaces.iter().map(|interface| interface.to_string()));
+    }
+
+    // Its instances are made by the compiler, not by new
+    if class_name == CLASS_CLASS {
+        layout.access_flags = ACC_PUBLIC | ACC_FINAL;
+        layout.size = CLASS_OBJECT_SIZE;
+        return Ok(layout);
+    }
+
+    if class_name == NUMBER_CLASS {
+        layout.access_flags = ACC_PUBLIC | ACC_ABSTRACT;
+        return Ok(layout);
+    }
+
+    if let Some((_, fields)) = RUNTIME_FIELDS.iter().find(|(c, _)| *c == class_name) {
+        // Like the JDK's, all but PrintStream and Enum are final
+        layout.access_flags = match class_name {
+            PRINT_STREAM_CLASS => ACC_PUBLIC,
+            ENUM_CLASS => ACC_PUBLIC | ACC_ABSTRACT,
+            _ => ACC_PUBLIC | ACC_FINAL,
+        };
+        for (name, descriptor, offset) in fields.iter() {
+            let field_type = parse_field_descriptor(descriptor)?;
+            layout.size = layout.size.max(offset + field_size(&field_type));
+            layout.fields.push(FieldLayout {
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+                field_type,
+                offset: *offset,
+            });
+        }
+        layout.size = layout.size.next_multiple_of(8);
+        return Ok(layout);
+    }
+
+    if let Some((_, super_class)) = THROWABLE_CLASSES.iter().find(|(c, _)| *c == class_name) {
+        layout.super_class = Some(super_class.to_string());
+        layout.display = class_layout(classes, super_class)?.display;
+        layout.display.push(class_name.to_string());
+        layout.interfaces = vec![SERIALIZABLE_CLASS.to_string()];
+        layout.fields = vec![FieldLayout {
+            name: "detailMessage".to_string(),
+            descriptor: "Ljava/lang/String;".to_string(),
+            field_type: FieldType::Object(STRING_CLASS.to_string()),
+            offset: THROWABLE_MESSAGE_OFFSET,
+        }];
+        layout.size = THROWABLE_SIZE;
+        return Ok(layout);
+    }
+
+    let class = classes
+        .find(class_name)
+        .ok_or_else(|| format!("Class {} was not found next to the main class", class_name))?;
+    let super_class = class.super_class_name()?.unwrap_or_else(|| OBJECT_CLASS.to_string());
+    let mut layout = class_layout(classes, &super_class)?;
+    layout.access_flags = class.access_flags;
+
+    let mut offset = layout.size;
+    for field in &class.fields {
+        if field.access_flags & ACC_STATIC != 0 {
+            continue;
+        }
+
+        let descriptor = class.field_descriptor(field)?;
+        let field_type = parse_field_descriptor(&descriptor)?;
+        let size = field_size(&field_type);
+        offset = offset.next_multiple_of(size);
+        layout.fields.push(FieldLayout {
+            name: class.field_name(field)?,
+            descriptor,
+            field_type,
+            offset,
+        });
+        offset += size;
+    }
+
+    // The class path doesn't say what the JDK's interfaces extend, the runtime does for
+    // its own
+    for interface in classes.superinterfaces(class.interface_names()?)? {
+        let superinterfaces = match RUNTIME_INTERFACES.iter().find(|(c, _)| *c == interface) {
+            Some((_, superinterfaces)) => superinterfaces.iter().map(|s| s.to_string()).collect(),
+            None => Vec::new(),
+        };
+        for interface in std::iter::once(interface).chain(superinterfaces) {
+            if !layout.interfaces.contains(&interface) {
+                layout.interfaces.push(interface);
+            }
+        }
+    }
+
+    layout.class_name = class_name.to_string();
+    layout.super_class = Some(super_class);
+    layout.size = offset.next_multiple_of(8);
+    layout.display.push(class_name.to_string());
+    Ok(layout)
+}
+
+/// The metadata of a class with no class file, which the compiler emits so objects can
+/// be tested against it. It is laid out as an interface with java/lang/Object's methods:
+/// the program's classes can only extend the JDK's classes the runtime has, so they are
+/// instances of it only when they implement it. Neither the JDK interfaces it extends nor
+/// any it is extended by are known.
+pub fn stub_layout(class_name: &str) -> ClassLayout {
+    ClassLayout {
+        class_name: class_name.to_string(),
+        super_class: Some(OBJECT_CLASS.to_string()),
+        access_flags: ACC_PUBLIC | ACC_INTERFACE | ACC_ABSTRACT,
+        fields: Vec::new(),
+        size: HEADER_SIZE,
+        element_size: 0,
+        element_class: None,
+        interfaces: Vec::new(),
+        display: vec![OBJECT_CLASS.to_string(), class_name.to_string()],
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VirtualMethod {
+    pub name: String,
+    pub descriptor: String,
+    // The class or interface whose code runs, None while the method is abstract
+    pub implementation: Option<String>,
+}
+
+// Private, static and initialization methods are never dispatched on the receiver
+fn is_virtual(class: &ParsedBytecode, method: &Method) -> Result<bool, String> {
+    let name = class.method_name(method)?;
+    Ok(method.access_flags & (ACC_PRIVATE | ACC_STATIC) == 0 && name != "<init>" && name != "<clinit>")
+}
+
+/// The vtable of `class_name`: the superclass's methods first, with the ones the class
+/// overrides replaced, then the methods the class adds and last those of its interfaces
+/// it has no slot for yet. A default method fills an abstract slot.
+pub fn vtable(classes: &ClassPath, class_name: &str) -> Result<Vec<VirtualMethod>, String> {
+    // Arrays have java/lang/Object's methods
+    if class_name.starts_with('[') {
+        return vtable(classes, OBJECT_CLASS);
+    }
+
+    // The runtime's classes have the methods the library implements for them
+    if is_runtime_class(class_name) {
+        let layout = class_layout(classes, class_name)?;
+        let mut vtable = match &layout.super_class {
+            Some(super_class) => vtable(classes, super_class)?,
+            None => Vec::new(),
+        };
+        for method in library_methods(class_name).filter(|method| method.is_virtual()) {
+            let implementation = Some(class_name.to_string());
+            match vtable.iter_mut().find(|slot| slot.name == method.name && slot.descriptor == method.descriptor) {
+                Some(slot) => slot.implementation = implementation,
+                None => vtable.push(VirtualMethod {
+                    name: method.name.to_string(),
+                    descriptor: method.descriptor.to_string(),
+                    implementation,
+                }),
+            }
+        }
+        return Ok(vtable);
+    }
+
+    let class = classes
+        .find(class_name)
+        .ok_or_else(|| format!("Class {} was not found next to the main class", class_name))?;
+    let super_class = class.super_class_name()?.unwrap_or_else(|| OBJECT_CLASS.to_string());
+    let mut vtable = vtable(classes, &super_class)?;
+
+    for method in &class.methods {
+        if !is_virtual(class, method)? {
+            continue;
+        }
+
+        let name = class.method_name(method)?;
+        let descriptor = class.method_descriptor(method)?;
+        let implementation = (method.access_flags & ACC_ABSTRACT == 0).then(|| class_name.to_string());
+        match vtable.iter_mut().find(|slot| slot.name == name && slot.descriptor == descriptor) {
+            Some(slot) => slot.implementation = implementation,
+            None => vtable.push(VirtualMethod { name, descriptor, implementation }),
+        }
+    }
+
+    for interface_name in classes.superinterfaces(class.interface_names()?)? {
+        // The JDK's interfaces are only reached through selectors
+        let Some(interface) = classes.find(&interface_name) else {
+            continue;
+        };
+
+        for method in &interface.methods {
+            if !is_virtual(interface, method)? {
+                continue;
+            }
+
+            let name = interface.method_name(method)?;
+            let descriptor = interface.method_descriptor(method)?;
+            let implementation = (method.access_flags & ACC_ABSTRACT == 0).then(|| interface_name.clone());
+            match vtable.iter_mut().find(|slot| slot.name == name && slot.descriptor == descriptor) {
+                Some(slot) if slot.implementation.is_none() => slot.implementation = implementation,
+                Some(_) => {},
+                None => vtable.push(VirtualMethod { name, descriptor, implementation }),
+            }
+        }
+    }
+
+    Ok(vtable)
+}
@@ -0,0 +1,4270 @@
+use crate::bytecode::classpath::ClassPath;
+use crate::bytecode::{ACC_PUBLIC, ACC_STATIC};
+use crate::codegen::inst::{
+    byte, dword, qword, word, Condition, Memory, Operand, Register, SseOp, AH, AL, AX, CL, CX, DI, DL, DX, EAX, EBX, ECX, EDI, EDX, ESI, R10, R10D, R10W, R11, R11D, R12,
+    R13, R14, R14D, R15, R8, R8B, R8D, R9, R9B, R9D, R9W, RAX, RBP, RBX, RCX, RDI, RDX, RSI, RSP, SI, SIL, ST0, ST1, XMM0, XMM1, XMM2,
+};
+use crate::codegen::layout::{
+    class_layout, vtable, ARRAY_DATA_OFFSET, ARRAY_LENGTH_OFFSET, BOOLEAN_CLASS, BOX_SIZE, BOX_VALUE_OFFSET, CHARACTER_CLASS, CLASS_CANONICAL_NAME_OFFSET,
+    CLASS_ENUM_CONSTANTS_OFFSET, CLASS_INITIALIZER_OFFSET, CLASS_METADATA_OFFSET, CLASS_POINTER_OFFSET, CLONEABLE_CLASS, CLONE_NOT_SUPPORTED_EXCEPTION_CLASS,
+    DOUBLE_CLASS, ELEMENT_CLASS_OFFSET, ELEMENT_SIZE_OFFSET, ENUM_CLASS, ENUM_NAME_OFFSET, ENUM_ORDINAL_OFFSET, FLOAT_CLASS, HASH_LOCK_OFFSET,
+    HEADER_SIZE, INTEGER_CLASS, LONG_CLASS, NAME_OFFSET, OBJECT_CLASS,
+    PRINT_STREAM_CLASS, PRINT_STREAM_FD_OFFSET, STRING_BUILDER_CLASS, STRING_BUILDER_CODER_OFFSET, STRING_BUILDER_COUNT_OFFSET,
+    STRING_BUILDER_VALUE_OFFSET, STRING_CLASS, STRING_CODER_OFFSET, STRING_SIZE, STRING_VALUE_OFFSET, THROWABLE_CLASS,
+    THROWABLE_CLASSES, THROWABLE_MESSAGE_OFFSET, UTF16, VTABLE_OFFSET,
+};
+use crate::codegen::mangle::{mangle_class, mangle_method};
+use crate::codegen::runtime::{
+    emit_append, emit_clear_message, emit_object_size, emit_pop_handle, emit_push_handle, emit_throw_new, emit_top_handle,
+};
+use crate::codegen::target::{OsAbi, Section};
+use crate::codegen::Assembly;
+
+// The methods of the JDK's classes programs can call, written in assembly and assembled
+// with the runtime. Compiled code calls them like its own methods, by their mangled
+// names, and the runtime's classes have them in their vtables.
+//
+// Most are leaf routines, which never change rbp and find the return address into
+// compiled code at [rsp]. The ones that allocate and have objects to keep, or call back
+// into compiled code, set up a frame of their own instead. Their slots are at rbp - 8,
+// rbp - 16 and so on, and each call that can collect garbage or throw has a stack map
+// in `runtime$library_stack_maps`, so the collector and the unwinder walk through them
+// like through compiled methods.
+//
+// They are a subset of the JDK: the methods `LIBRARY` lists and the exceptions'
+// constructors. There is no reflection, Object.getClass included, and a call to any
+// other method of the JDK fails the build as not supported by the runtime.
+
+const MATH_CLASS: &str = "java/lang/Math";
+const SYSTEM_CLASS: &str = "java/lang/System";
+const OBJECTS_CLASS: &str = "java/util/Objects";
+
+const ARRAY_STORE_EXCEPTION: &str = "java/lang/ArrayStoreException";
+const ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION: &str = "java/lang/ArrayIndexOutOfBoundsException";
+const STRING_INDEX_OUT_OF_BOUNDS_EXCEPTION: &str = "java/lang/StringIndexOutOfBoundsException";
+const NUMBER_FORMAT_EXCEPTION: &str = "java/lang/NumberFormatException";
+const NULL_POINTER_EXCEPTION: &str = "java/lang/NullPointerException";
+const ILLEGAL_ARGUMENT_EXCEPTION: &str = "java/lang/IllegalArgumentException";
+const UNKNOWN_FORMAT_CONVERSION_EXCEPTION: &str = "java/util/UnknownFormatConversionException";
+const MISSING_FORMAT_ARGUMENT_EXCEPTION: &str = "java/util/MissingFormatArgumentException";
+const ILLEGAL_FORMAT_CONVERSION_EXCEPTION: &str = "java/util/IllegalFormatConversionException";
+
+const PRINTF_DESCRIPTOR: &str = "(Ljava/lang/String;[Ljava/lang/Object;)Ljava/io/PrintStream;";
+
+const PUBLIC: u16 = ACC_PUBLIC;
+const STATIC: u16 = ACC_PUBLIC | ACC_STATIC;
+
+type LibraryClass = (&'static str, &'static [(&'static str, &'static str, u16)]);
+
+// The methods the library implements, by class, as (name, descriptor, access flags)
+static LIBRARY: [LibraryClass; 15] = [
+    (
+        OBJECT_CLASS,
+        &[
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("clone", "()Ljava/lang/Object;", PUBLIC),
+        ],
+    ),
+    (
+        ENUM_CLASS,
+        &[
+            ("<init>", "(Ljava/lang/String;I)V", PUBLIC),
+            ("name", "()Ljava/lang/String;", PUBLIC),
+            ("ordinal", "()I", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("compareTo", "(Ljava/lang/Enum;)I", PUBLIC),
+            ("valueOf", "(Ljava/lang/Class;Ljava/lang/String;)Ljava/lang/Enum;", STATIC),
+        ],
+    ),
+    (
+        THROWABLE_CLASS,
+        &[("getMessage", "()Ljava/lang/String;", PUBLIC), ("toString", "()Ljava/lang/String;", PUBLIC), ("printStackTrace", "()V", PUBLIC)],
+    ),
+    (
+        STRING_CLASS,
+        &[
+            ("length", "()I", PUBLIC),
+            ("isEmpty", "()Z", PUBLIC),
+            ("charAt", "(I)C", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("intern", "()Ljava/lang/String;", PUBLIC),
+            ("compareTo", "(Ljava/lang/String;)I", PUBLIC),
+            ("concat", "(Ljava/lang/String;)Ljava/lang/String;", PUBLIC),
+            ("substring", "(I)Ljava/lang/String;", PUBLIC),
+            ("substring", "(II)Ljava/lang/String;", PUBLIC),
+            ("indexOf", "(I)I", PUBLIC),
+            ("indexOf", "(Ljava/lang/String;)I", PUBLIC),
+            ("startsWith", "(Ljava/lang/String;)Z", PUBLIC),
+            ("endsWith", "(Ljava/lang/String;)Z", PUBLIC),
+            ("contains", "(Ljava/lang/CharSequence;)Z", PUBLIC),
+            ("toCharArray", "()[C", PUBLIC),
+            ("valueOf", "(I)Ljava/lang/String;", STATIC),
+            ("valueOf", "(J)Ljava/lang/String;", STATIC),
+            ("valueOf", "(C)Ljava/lang/String;", STATIC),
+            ("valueOf", "(Z)Ljava/lang/String;", STATIC),
+            ("valueOf", "(D)Ljava/lang/String;", STATIC),
+            ("valueOf", "(F)Ljava/lang/String;", STATIC),
+            ("valueOf", "(Ljava/lang/Object;)Ljava/lang/String;", STATIC),
+        ],
+    ),
+    (
+        STRING_BUILDER_CLASS,
+        &[
+            ("<init>", "()V", PUBLIC),
+            ("<init>", "(I)V", PUBLIC),
+            ("<init>", "(Ljava/lang/String;)V", PUBLIC),
+            ("append", "(Ljava/lang/String;)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(I)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(J)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(C)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(Z)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(D)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(F)Ljava/lang/StringBuilder;", PUBLIC),
+            ("append", "(Ljava/lang/Object;)Ljava/lang/StringBuilder;", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("length", "()I", PUBLIC),
+            ("charAt", "(I)C", PUBLIC),
+            ("setCharAt", "(IC)V", PUBLIC),
+            ("deleteCharAt", "(I)Ljava/lang/StringBuilder;", PUBLIC),
+            ("setLength", "(I)V", PUBLIC),
+            ("reverse", "()Ljava/lang/StringBuilder;", PUBLIC),
+        ],
+    ),
+    (
+        BOOLEAN_CLASS,
+        &[
+            ("valueOf", "(Z)Ljava/lang/Boolean;", STATIC),
+            ("booleanValue", "()Z", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(Z)Ljava/lang/String;", STATIC),
+        ],
+    ),
+    (
+        INTEGER_CLASS,
+        &[
+            ("valueOf", "(I)Ljava/lang/Integer;", STATIC),
+            ("intValue", "()I", PUBLIC),
+            ("longValue", "()J", PUBLIC),
+            ("doubleValue", "()D", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(I)Ljava/lang/String;", STATIC),
+            ("toHexString", "(I)Ljava/lang/String;", STATIC),
+            ("parseInt", "(Ljava/lang/String;)I", STATIC),
+            ("compare", "(II)I", STATIC),
+            ("sum", "(II)I", STATIC),
+            ("max", "(II)I", STATIC),
+            ("min", "(II)I", STATIC),
+        ],
+    ),
+    (
+        LONG_CLASS,
+        &[
+            ("valueOf", "(J)Ljava/lang/Long;", STATIC),
+            ("longValue", "()J", PUBLIC),
+            ("intValue", "()I", PUBLIC),
+            ("doubleValue", "()D", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(J)Ljava/lang/String;", STATIC),
+            ("toHexString", "(J)Ljava/lang/String;", STATIC),
+            ("parseLong", "(Ljava/lang/String;)J", STATIC),
+            ("compare", "(JJ)I", STATIC),
+            ("sum", "(JJ)J", STATIC),
+            ("max", "(JJ)J", STATIC),
+            ("min", "(JJ)J", STATIC),
+        ],
+    ),
+    (
+        CHARACTER_CLASS,
+        &[
+            ("valueOf", "(C)Ljava/lang/Character;", STATIC),
+            ("charValue", "()C", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(C)Ljava/lang/String;", STATIC),
+            ("isDigit", "(C)Z", STATIC),
+            ("isLetter", "(C)Z", STATIC),
+            ("isLetterOrDigit", "(C)Z", STATIC),
+            ("isWhitespace", "(C)Z", STATIC),
+            ("isUpperCase", "(C)Z", STATIC),
+            ("isLowerCase", "(C)Z", STATIC),
+            ("toUpperCase", "(C)C", STATIC),
+            ("toLowerCase", "(C)C", STATIC),
+        ],
+    ),
+    (
+        FLOAT_CLASS,
+        &[
+            ("valueOf", "(F)Ljava/lang/Float;", STATIC),
+            ("floatValue", "()F", PUBLIC),
+            ("doubleValue", "()D", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(F)Ljava/lang/String;", STATIC),
+        ],
+    ),
+    (
+        DOUBLE_CLASS,
+        &[
+            ("valueOf", "(D)Ljava/lang/Double;", STATIC),
+            ("doubleValue", "()D", PUBLIC),
+            ("hashCode", "()I", PUBLIC),
+            ("equals", "(Ljava/lang/Object;)Z", PUBLIC),
+            ("toString", "()Ljava/lang/String;", PUBLIC),
+            ("toString", "(D)Ljava/lang/String;", STATIC),
+        ],
+    ),
+    (
+        MATH_CLASS,
+        &[
+            ("abs", "(I)I", STATIC),
+            ("abs", "(J)J", STATIC),
+            ("abs", "(F)F", STATIC),
+            ("abs", "(D)D", STATIC),
+            ("max", "(II)I", STATIC),
+            ("max", "(JJ)J", STATIC),
+            ("max", "(FF)F", STATIC),
+            ("max", "(DD)D", STATIC),
+            ("min", "(II)I", STATIC),
+            ("min", "(JJ)J", STATIC),
+            ("min", "(FF)F", STATIC),
+            ("min", "(DD)D", STATIC),
+            ("sqrt", "(D)D", STATIC),
+            ("pow", "(DD)D", STATIC),
+            ("exp", "(D)D", STATIC),
+            ("log", "(D)D", STATIC),
+            ("sin", "(D)D", STATIC),
+            ("cos", "(D)D", STATIC),
+            ("floor", "(D)D", STATIC),
+            ("ceil", "(D)D", STATIC),
+            ("round", "(D)J", STATIC),
+            ("round", "(F)I", STATIC),
+            ("floorDiv", "(II)I", STATIC),
+            ("floorDiv", "(JJ)J", STATIC),
+            ("floorMod", "(II)I", STATIC),
+            ("floorMod", "(JJ)J", STATIC),
+            ("random", "()D", STATIC),
+        ],
+    ),
+    (
+        SYSTEM_CLASS,
+        &[
+            ("exit", "(I)V", STATIC),
+            ("currentTimeMillis", "()J", STATIC),
+            ("nanoTime", "()J", STATIC),
+            ("arraycopy", "(Ljava/lang/Object;ILjava/lang/Object;II)V", STATIC),
+            ("identityHashCode", "(Ljava/lang/Object;)I", STATIC),
+        ],
+    ),
+    // javac checks the receiver of a bound method reference with it
+    (OBJECTS_CLASS, &[("requireNonNull", "(Ljava/lang/Object;)Ljava/lang/Object;", STATIC)]),
+    (
+        PRINT_STREAM_CLASS,
+        &[
+            ("print", "(Z)V", PUBLIC),
+            ("print", "(C)V", PUBLIC),
+            ("print", "(I)V", PUBLIC),
+            ("print", "(J)V", PUBLIC),
+            ("print", "(F)V", PUBLIC),
+            ("print", "(D)V", PUBLIC),
+            ("print", "([C)V", PUBLIC),
+            ("print", "(Ljava/lang/String;)V", PUBLIC),
+            ("print", "(Ljava/lang/Object;)V", PUBLIC),
+            ("println", "()V", PUBLIC),
+            ("println", "(Z)V", PUBLIC),
+            ("println", "(C)V", PUBLIC),
+            ("println", "(I)V", PUBLIC),
+            ("println", "(J)V", PUBLIC),
+            ("println", "(F)V", PUBLIC),
+            ("println", "(D)V", PUBLIC),
+            ("println", "([C)V", PUBLIC),
+            ("println", "(Ljava/lang/String;)V", PUBLIC),
+            ("println", "(Ljava/lang/Object;)V", PUBLIC),
+            ("printf", PRINTF_DESCRIPTOR, PUBLIC),
+            ("format", PRINTF_DESCRIPTOR, PUBLIC),
+            ("flush", "()V", PUBLIC),
+        ],
+    ),
+];
+
+// Every exception the runtime has can be made with and without a message
+const THROWABLE_CONSTRUCTORS: [&str; 2] = ["()V", "(Ljava/lang/String;)V"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct LibraryMethod {
+    pub class_name: &'static str,
+    pub name: &'static str,
+    pub descriptor: &'static str,
+    pub access_flags: u16,
+}
+
+impl LibraryMethod {
+    /// Whether calls dispatch on the receiver's class.
+    pub fn is_virtual(&self) -> bool {
+        self.access_flags & ACC_STATIC == 0 && self.name != "<init>"
+    }
+}
+
+/// The methods the library implements for `class_name`, constructors included.
+pub fn library_methods(class_name: &str) -> impl Iterator<Item = LibraryMethod> + '_ {
+    let methods = LIBRARY.iter().filter(move |(c, _)| *c == class_name).flat_map(|(c, methods)| {
+        methods.iter().map(|&(name, descriptor, access_flags)| LibraryMethod { class_name: c, name, descriptor, access_flags })
+    });
+    let constructors = THROWABLE_CLASSES.iter().filter(move |(c, _)| *c == class_name).flat_map(|(c, _)| {
+        THROWABLE_CONSTRUCTORS.iter().map(|descriptor| LibraryMethod { class_name: c, name: "<init>", descriptor, access_flags: PUBLIC })
+    });
+    methods.chain(constructors)
+}
+
+/// Every method the library implements.
+pub fn all_library_methods() -> impl Iterator<Item = LibraryMethod> {
+    let class_names = LIBRARY.iter().map(|(class_name, _)| *class_name);
+    class_names.chain(THROWABLE_CLASSES.iter().map(|(class_name, _)| *class_name)).flat_map(library_methods)
+}
+
+/// Finds the method a call to `class_name` reaches in the library, searching the
+/// superclasses like method resolution does. Constructors are not inherited.
+pub fn find_library_method(class_name: &str, name: &str, descriptor: &str) -> Option<LibraryMethod> {
+    let mut class_name = class_name.to_string();
+    loop {
+        if let Some(method) = library_methods(&class_name).find(|m| m.name == name && m.descriptor == descriptor) {
+            return Some(method);
+        }
+        if name == "<init>" {
+            return None;
+        }
+        class_name = class_layout(&ClassPath::default(), &class_name).ok()?.super_class?;
+    }
+}
+
+// The text the routines put together, as (label, text)
+const NULL_TEXT: (&str, &str) = ("runtime$null_text", "null");
+const TRUE_TEXT: (&str, &str) = ("runtime$true_text", "true");
+const FALSE_TEXT: (&str, &str) = ("runtime$false_text", "false");
+const AT_SIGN_TEXT: (&str, &str) = ("runtime$at_sign_text", "@");
+const SEPARATOR_TEXT: (&str, &str) = ("runtime$separator_text", ": ");
+const ZERO_TEXT: (&str, &str) = ("runtime$zero_text", "0.0");
+const NAN_TEXT: (&str, &str) = ("runtime$nan_text", "NaN");
+const INFINITY_TEXT: (&str, &str) = ("runtime$infinity_text", "Infinity");
+const STRING_INDEX_TEXT: (&str, &str) = ("runtime$string_index_text", "String index out of range: ");
+const INDEX_TEXT: (&str, &str) = ("runtime$index_text", "index ");
+const LENGTH_TEXT: (&str, &str) = ("runtime$length_text", ", length ");
+const BEGIN_TEXT: (&str, &str) = ("runtime$begin_text", "begin ");
+const END_TEXT: (&str, &str) = ("runtime$end_text", ", end ");
+const INPUT_STRING_TEXT: (&str, &str) = ("runtime$input_string_text", "For input string: \"");
+const QUOTE_TEXT: (&str, &str) = ("runtime$quote_text", "\"");
+const PARSE_NULL_TEXT: (&str, &str) = ("runtime$parse_null_text", "Cannot parse null string");
+const SOURCE_TYPE_TEXT: (&str, &str) = ("runtime$source_type_text", "arraycopy: source type ");
+const DESTINATION_TYPE_TEXT: (&str, &str) = ("runtime$destination_type_text", "arraycopy: destination type ");
+const NOT_AN_ARRAY_TEXT: (&str, &str) = ("runtime$not_an_array_text", " is not an array");
+const TYPE_MISMATCH_TEXT: (&str, &str) = ("runtime$type_mismatch_text", "arraycopy: type mismatch: can not copy ");
+const ARRAY_INTO_TEXT: (&str, &str) = ("runtime$array_into_text", "[] into ");
+const ARRAY_TEXT: (&str, &str) = ("runtime$array_text", "[]");
+const SOURCE_INDEX_TEXT: (&str, &str) = ("runtime$source_index_text", "arraycopy: source index ");
+const DESTINATION_INDEX_TEXT: (&str, &str) = ("runtime$destination_index_text", "arraycopy: destination index ");
+const LAST_SOURCE_INDEX_TEXT: (&str, &str) = ("runtime$last_source_index_text", "arraycopy: last source index ");
+const LAST_DESTINATION_INDEX_TEXT: (&str, &str) = ("runtime$last_destination_index_text", "arraycopy: last destination index ");
+const OUT_OF_BOUNDS_FOR_TEXT: (&str, &str) = ("runtime$out_of_bounds_for_text", " out of bounds for ");
+const OPEN_BRACKET_TEXT: (&str, &str) = ("runtime$open_bracket_text", "[");
+const CLOSE_BRACKET_TEXT: (&str, &str) = ("runtime$close_bracket_text", "]");
+const LENGTH_PREFIX_TEXT: (&str, &str) = ("runtime$length_prefix_text", "arraycopy: length ");
+const IS_NEGATIVE_TEXT: (&str, &str) = ("runtime$is_negative_text", " is negative");
+const ELEMENT_MISMATCH_TEXT: (&str, &str) =
+    ("runtime$element_mismatch_text", "arraycopy: element type mismatch: can not cast one of the elements of ");
+const TO_DESTINATION_TEXT: (&str, &str) = ("runtime$to_destination_text", "[] to the type of the destination array, ");
+const OBJECT_ARRAY_TEXT: (&str, &str) = ("runtime$object_array_text", "object array");
+const CONVERSION_TEXT: (&str, &str) = ("runtime$conversion_text", "Conversion = '");
+const FORMAT_SPECIFIER_TEXT: (&str, &str) = ("runtime$format_specifier_text", "Format specifier '");
+const APOSTROPHE_TEXT: (&str, &str) = ("runtime$apostrophe_text", "'");
+const NOT_CONVERTIBLE_TEXT: (&str, &str) = ("runtime$not_convertible_text", " != ");
+const NO_ENUM_CONSTANT_TEXT: (&str, &str) = ("runtime$no_enum_constant_text", "No enum constant ");
+const DOT_TEXT: (&str, &str) = ("runtime$dot_text", ".");
+const NOT_AN_ENUM_TEXT: (&str, &str) = ("runtime$not_an_enum_text", " is not an enum class");
+const NAME_IS_NULL_TEXT: (&str, &str) = ("runtime$name_is_null_text", "Name is null");
+
+const TEXTS: [(&str, &str); 42] = [
+    NULL_TEXT,
+    TRUE_TEXT,
+    FALSE_TEXT,
+    AT_SIGN_TEXT,
+    SEPARATOR_TEXT,
+    ZERO_TEXT,
+    NAN_TEXT,
+    INFINITY_TEXT,
+    STRING_INDEX_TEXT,
+    INDEX_TEXT,
+    LENGTH_TEXT,
+    BEGIN_TEXT,
+    END_TEXT,
+    INPUT_STRING_TEXT,
+    QUOTE_TEXT,
+    PARSE_NULL_TEXT,
+    SOURCE_TYPE_TEXT,
+    DESTINATION_TYPE_TEXT,
+    NOT_AN_ARRAY_TEXT,
+    TYPE_MISMATCH_TEXT,
+    ARRAY_INTO_TEXT,
+    ARRAY_TEXT,
+    SOURCE_INDEX_TEXT,
+    DESTINATION_INDEX_TEXT,
+    LAST_SOURCE_INDEX_TEXT,
+    LAST_DESTINATION_INDEX_TEXT,
+    OUT_OF_BOUNDS_FOR_TEXT,
+    OPEN_BRACKET_TEXT,
+    CLOSE_BRACKET_TEXT,
+    LENGTH_PREFIX_TEXT,
+    IS_NEGATIVE_TEXT,
+    ELEMENT_MISMATCH_TEXT,
+    TO_DESTINATION_TEXT,
+    OBJECT_ARRAY_TEXT,
+    CONVERSION_TEXT,
+    FORMAT_SPECIFIER_TEXT,
+    APOSTROPHE_TEXT,
+    NOT_CONVERTIBLE_TEXT,
+    NO_ENUM_CONSTANT_TEXT,
+    DOT_TEXT,
+    NOT_AN_ENUM_TEXT,
+    NAME_IS_NULL_TEXT,
+];
+
+// The names of the primitive types, by their descriptor
+const PRIMITIVE_NAMES: [(u8, &str); 8] = [
+    (b'Z', "boolean"),
+    (b'B', "byte"),
+    (b'C', "char"),
+    (b'S', "short"),
+    (b'I', "int"),
+    (b'J', "long"),
+    (b'F', "float"),
+    (b'D', "double"),
+];
+
+// Output is collected here and written when a print is done or the buffer fills up
+const OUTPUT_BUFFER_SIZE: usize = 1024;
+
+// The boxes valueOf returns without allocating, like the JDK's caches
+const INTEGER_CACHE: std::ops::RangeInclusive<i64> = -128..=127;
+const CHARACTER_CACHE: std::ops::RangeInclusive<i64> = 0..=127;
+
+// pi/2 as the sum of three doubles, fdlibm's pio2_1, pio2_2 and pio2_2t, the first two
+// with 33 bits so that multiples of them below 2^31 have the x87's 64
+const PI_OVER_TWO: [(&str, u64); 3] = [
+    ("runtime$pi_over_two_1", 0x3ff921fb54400000),
+    ("runtime$pi_over_two_2", 0x3dd0b4611a600000),
+    ("runtime$pi_over_two_3", 0x3ba3198a2e037073),
+];
+
+// The flags of the Latin-1 characters in `runtime$character_flags`
+const DIGIT: u8 = 1;
+const LETTER: u8 = 2;
+const WHITESPACE: u8 = 4;
+const UPPER_CASE: u8 = 8;
+const LOWER_CASE: u8 = 16;
+
+// The return addresses of the library's calls that have a stack map, and the offsets
+// below rbp of the slots holding references there
+type StackMaps = Vec<(String, Vec<usize>)>;
+
+/// Generates the library's methods into the runtime, along with the constants and
+/// state they use.
+pub fn emit_library(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let mut stack_maps = StackMaps::new();
+
+    asm.emit_section(abi.section_name(Section::Text));
+    emit_strings(asm, abi);
+    emit_message_helpers(asm, abi);
+    emit_floating_point_text(asm);
+    emit_fixed_point_text(asm);
+    emit_output(asm, abi);
+    emit_object(asm, abi, &mut stack_maps);
+    emit_enum(asm, abi, &mut stack_maps);
+    emit_throwable(asm, abi, &mut stack_maps);
+    emit_string(asm, abi, &mut stack_maps);
+    emit_string_builder(asm, abi, &mut stack_maps);
+    emit_boxes(asm, abi);
+    emit_boolean(asm, abi);
+    emit_character(asm, abi);
+    emit_float(asm, abi);
+    emit_double(asm, abi);
+    emit_math(asm, abi);
+    emit_system(asm, abi);
+    emit_objects(asm, abi);
+    emit_print_stream(asm, abi, &mut stack_maps);
+    emit_printf(asm, abi, &mut stack_maps);
+
+    asm.emit_section(abi.section_name(Section::Data));
+    asm.emit_align(8);
+    // The state of the identity hashes and of Math.random, a xorshift generator each
+    asm.emit_label("runtime$hash_seed");
+    asm.emit_dq(2463534242i64);
+    asm.emit_label("runtime$random_state");
+    asm.emit_dq(0);
+    // The pool of String.intern, a [Ljava/lang/String; hash set made on first use, and
+    // how many strings it holds
+    asm.emit_label("runtime$intern_table");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$intern_count");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$output_length");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$output");
+    for _ in 0..OUTPUT_BUFFER_SIZE / 8 {
+        asm.emit_dq(0);
+    }
+    // The cached boxes are outside the heap, like the OutOfMemoryError
+    asm.emit_label("runtime$integer_cache");
+    for value in INTEGER_CACHE {
+        asm.emit_dq(abi.symbol(&mangle_class(INTEGER_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(value);
+    }
+    asm.emit_label("runtime$long_cache");
+    for value in INTEGER_CACHE {
+        asm.emit_dq(abi.symbol(&mangle_class(LONG_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(value);
+    }
+    asm.emit_label("runtime$boolean_cache");
+    for value in [0, 1] {
+        asm.emit_dq(abi.symbol(&mangle_class(BOOLEAN_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(value);
+    }
+    asm.emit_label("runtime$character_cache");
+    for value in CHARACTER_CACHE {
+        asm.emit_dq(abi.symbol(&mangle_class(CHARACTER_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(value);
+    }
+
+    asm.emit_section(abi.section_name(Section::ReadOnlyData));
+    asm.emit_align(8);
+    asm.emit_label("runtime$powers_of_ten");
+    for exponent in 0..=22 {
+        asm.emit_dq(10f64.powi(exponent).to_bits());
+    }
+    asm.emit_label("runtime$integer_powers_of_ten");
+    for exponent in 0..=18 {
+        asm.emit_dq(10u64.pow(exponent));
+    }
+    for (label, value) in [
+        ("runtime$one", 1.0f64),
+        ("runtime$half", 0.5),
+        ("runtime$minus_half", -0.5),
+        ("runtime$sixty_four", 64.0),
+        ("runtime$two_to_31", 2f64.powi(31)),
+        ("runtime$two_to_63", 2f64.powi(63)),
+        ("runtime$two_to_minus_53", 2f64.powi(-53)),
+        ("runtime$infinity", f64::INFINITY),
+        ("runtime$nan", f64::NAN),
+        ("runtime$two_over_pi", std::f64::consts::FRAC_2_PI),
+    ] {
+        asm.emit_label(label);
+        asm.emit_dq(value.to_bits());
+    }
+    for (label, bits) in PI_OVER_TWO {
+        asm.emit_label(label);
+        asm.emit_dq(bits);
+    }
+
+    asm.emit_label("runtime$library_stack_maps");
+    asm.emit_dq(stack_maps.len());
+    for (label, offsets) in &stack_maps {
+        asm.emit_dq(label);
+        asm.emit_dq(offsets.len());
+        for offset in offsets {
+            asm.emit_dq(*offset);
+        }
+    }
+
+    let mut flags = Vec::new();
+    let mut upper_case = Vec::new();
+    let mut lower_case = Vec::new();
+    for byte in 0..=255u8 {
+        let c = char::from(byte);
+        flags.push(
+            [
+                (c.is_ascii_digit(), DIGIT),
+                (c.is_alphabetic(), LETTER),
+                (matches!(byte, 9..=13 | 28..=32), WHITESPACE),
+                (c.is_uppercase(), UPPER_CASE),
+                (c.is_lowercase(), LOWER_CASE),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |flags, (_, flag)| flags | flag),
+        );
+        upper_case.extend(single_char(c.to_uppercase(), c).to_le_bytes());
+        lower_case.extend(single_char(c.to_lowercase(), c).to_le_bytes());
+    }
+    asm.emit_label("runtime$character_flags");
+    asm.emit_db_bytes(&flags);
+    asm.emit_label("runtime$upper_case");
+    asm.emit_db_bytes(&upper_case);
+    asm.emit_label("runtime$lower_case");
+    asm.emit_db_bytes(&lower_case);
+
+    for (label, text) in TEXTS {
+        asm.emit_label(label);
+        asm.emit_db_bytes(text.as_bytes());
+    }
+    for (_, name) in PRIMITIVE_NAMES {
+        asm.emit_label(&format!("runtime$primitive_name.{}", name));
+        asm.emit_db_bytes(name.as_bytes());
+    }
+}
+
+// The case mapping of a character, unless it maps to several like ß does
+fn single_char(mut mapped: impl ExactSizeIterator<Item = char>, c: char) -> u16 {
+    match (mapped.len(), mapped.next()) {
+        (1, Some(mapped)) => mapped as u16,
+        _ => c as u16,
+    }
+}
+
+fn method_symbol(abi: &dyn OsAbi, class_name: &str, name: &str, descriptor: &str) -> String {
+    abi.symbol(&mangle_method(class_name, name, descriptor))
+}
+
+// Starts a method, returning its symbol, which its local labels are named after
+fn emit_method(asm: &mut Assembly, abi: &dyn OsAbi, class_name: &str, name: &str, descriptor: &str) -> String {
+    let symbol = method_symbol(abi, class_name, name, descriptor);
+    asm.emit_global(&symbol);
+    asm.emit_function_start(&symbol);
+    symbol
+}
+
+// The slot of a virtual method of one of the runtime's classes, as an operand off the
+// class of the receiver in rax
+fn virtual_method(class_name: &str, name: &str, descriptor: &str) -> Memory {
+    let index = vtable(&ClassPath::default(), class_name)
+        .ok()
+        .and_then(|vtable| vtable.iter().position(|method| method.name == name && method.descriptor == descriptor))
+        .expect("The library's virtual methods are in the vtables of its classes");
+    qword(RAX + VTABLE_OFFSET + 8 * index)
+}
+
+fn slot(index: usize) -> Memory {
+    qword(RBP - 8 * (index + 1))
+}
+
+// Sets up a frame with `slots` slots, keeping rsp aligned for calls
+fn emit_enter(asm: &mut Assembly, slots: usize) {
+    asm.emit_push(RBP);
+    asm.emit_mov(RBP, RSP);
+    asm.emit_sub(RSP, (8 * slots).next_multiple_of(16));
+}
+
+// Calls `target` from a routine with a frame, passing the return address in rcx for the
+// routines that allocate. The slots in `references` are the ones holding objects.
+fn emit_call_mapped(asm: &mut Assembly, stack_maps: &mut StackMaps, target: impl Into<Operand>, references: &[usize]) {
+    let label = format!("runtime$library.return{}", stack_maps.len());
+    asm.emit_mov(RCX, &label);
+    asm.emit_call(target);
+    asm.emit_label(&label);
+    stack_maps.push((label, references.iter().map(|slot| 8 * (slot + 1)).collect()));
+}
+
+// Jumps from a leaf routine to one that allocates, which returns to the caller
+fn emit_tail_allocate(asm: &mut Assembly, target: &str) {
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_jmp(target);
+}
+
+fn emit_text(asm: &mut Assembly, (label, text): (&str, &str)) {
+    emit_append(asm, label, text);
+}
+
+// `register` at another size, e.g. eax for rax and 4
+fn resized(register: Register, size: u8) -> Register {
+    Register { size, ..register }
+}
+
+// Puts the number of characters of the string in `string` into `dest`, changing rcx
+fn emit_string_length(asm: &mut Assembly, dest: Register, string: Register) {
+    asm.emit_mov(dest, qword(string + STRING_VALUE_OFFSET));
+    asm.emit_mov(dest, qword(dest + ARRAY_LENGTH_OFFSET));
+    asm.emit_movzx(ECX, byte(string + STRING_CODER_OFFSET));
+    asm.emit_shr(dest, CL);
+}
+
+// Loads the character at `index` of the string or builder in `string` into `dest`, which
+// holds the address of its value on the way
+fn emit_load_char(asm: &mut Assembly, dest: Register, string: Register, index: Register, label: &str) {
+    asm.emit_mov(dest, qword(string + STRING_VALUE_OFFSET));
+    asm.emit_cmp(byte(string + STRING_CODER_OFFSET), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.utf16", label));
+    asm.emit_movzx(resized(dest, 4), byte(dest + index + ARRAY_DATA_OFFSET));
+    asm.emit_jmp(format!("{}.loaded", label));
+    asm.emit_label(&format!("{}.utf16", label));
+    asm.emit_movzx(resized(dest, 4), word(dest + index * 2 + ARRAY_DATA_OFFSET));
+    asm.emit_label(&format!("{}.loaded", label));
+}
+
+// Stores the character in `character` at `index` of the string or builder in `string`,
+// with the address of its value in `scratch`
+fn emit_store_char(asm: &mut Assembly, string: Register, index: Register, character: Register, scratch: Register, label: &str) {
+    asm.emit_mov(scratch, qword(string + STRING_VALUE_OFFSET));
+    asm.emit_cmp(byte(string + STRING_CODER_OFFSET), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.utf16", label));
+    asm.emit_mov(byte(scratch + index + ARRAY_DATA_OFFSET), resized(character, 1));
+    asm.emit_jmp(format!("{}.stored", label));
+    asm.emit_label(&format!("{}.utf16", label));
+    asm.emit_mov(word(scratch + index * 2 + ARRAY_DATA_OFFSET), resized(character, 2));
+    asm.emit_label(&format!("{}.stored", label));
+}
+
+/// The routines that make strings and copy characters between them.
+///
+/// `runtime$new_string` allocates a string of rdi characters, all zero, in the coder in
+/// rsi, and `runtime$string_from_bytes` one with the rdx Latin-1 bytes at rsi, which
+/// must be outside the heap. `runtime$string_from_message` makes one of the message.
+/// Like `runtime$allocate` they take the return address into compiled code in rcx.
+///
+/// `runtime$copy_chars` copies r8 characters from index rdx of the string or builder in
+/// rsi to index rcx of the one in rdi, leaving rcx after the last one. It keeps rdi and
+/// rsi. `runtime$region_matches` tells whether the rdx characters of rsi are at index
+/// rcx of rdi, changing only rax, r10 and r11.
+fn emit_strings(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$new_string");
+    asm.emit_push(RSI);
+    asm.emit_push(RCX);
+    asm.emit_mov(RCX, RSI);
+    asm.emit_mov(RSI, RDI);
+    asm.emit_shl(RSI, CL);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class("[B")));
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_call("runtime$allocate_array");
+    emit_push_handle(asm, RAX);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(STRING_CLASS)));
+    asm.emit_mov(RSI, STRING_SIZE);
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_call("runtime$allocate");
+    emit_top_handle(asm, RDX);
+    emit_pop_handle(asm);
+    asm.emit_mov(qword(RAX + STRING_VALUE_OFFSET), RDX);
+    asm.emit_pop(RCX);
+    asm.emit_pop(RSI);
+    asm.emit_mov(byte(RAX + STRING_CODER_OFFSET), SIL);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$string_from_message");
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_label("runtime$string_from_bytes");
+    asm.emit_push(RSI);
+    asm.emit_push(RDX);
+    asm.emit_mov(RDI, RDX);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_call("runtime$new_string");
+    asm.emit_pop(RDX);
+    asm.emit_pop(RSI);
+    asm.emit_mov(R8, qword(RAX + STRING_VALUE_OFFSET));
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label("runtime$string_from_bytes.copy");
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$string_from_bytes.done");
+    asm.emit_mov(R9B, byte(RSI + RCX));
+    asm.emit_mov(byte(R8 + RCX + ARRAY_DATA_OFFSET), R9B);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp("runtime$string_from_bytes.copy");
+    asm.emit_label("runtime$string_from_bytes.done");
+    asm.emit_ret();
+
+    asm.emit_label("runtime$copy_chars");
+    asm.emit_test(R8, R8);
+    asm.emit_jcc(Condition::E, "runtime$copy_chars.done");
+    asm.emit_label("runtime$copy_chars.next");
+    emit_load_char(asm, RAX, RSI, RDX, "runtime$copy_chars.load");
+    emit_store_char(asm, RDI, RCX, RAX, R9, "runtime$copy_chars.store");
+    asm.emit_add(RDX, 1);
+    asm.emit_add(RCX, 1);
+    asm.emit_sub(R8, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$copy_chars.next");
+    asm.emit_label("runtime$copy_chars.done");
+    asm.emit_ret();
+
+    asm.emit_label("runtime$region_matches");
+    asm.emit_xor(R11D, R11D);
+    asm.emit_label("runtime$region_matches.next");
+    asm.emit_cmp(R11, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$region_matches.matches");
+    asm.emit_mov(R10, RCX);
+    asm.emit_add(R10, R11);
+    emit_load_char(asm, RAX, RDI, R10, "runtime$region_matches.this");
+    emit_load_char(asm, R10, RSI, R11, "runtime$region_matches.other");
+    asm.emit_cmp(EAX, R10D);
+    asm.emit_jcc(Condition::Ne, "runtime$region_matches.differs");
+    asm.emit_add(R11, 1);
+    asm.emit_jmp("runtime$region_matches.next");
+    asm.emit_label("runtime$region_matches.matches");
+    asm.emit_mov(EAX, 1);
+    asm.emit_ret();
+    asm.emit_label("runtime$region_matches.differs");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_ret();
+}
+
+/// More routines adding to the message. `runtime$append_string` adds the string in rsi,
+/// with a question mark for each character outside Latin-1, and
+/// `runtime$append_element_type` the name arraycopy gives the elements of the array
+/// class in rsi. They keep rdi.
+///
+/// The exceptions the library throws are put together by `runtime$throw_string_index`
+/// from the index in rdi, by `runtime$throw_index_length` from the index in rdi and the
+/// length in rsi and by `runtime$throw_begin_end` from the begin, end and length in rdi,
+/// rsi and rdx. They are jumped to with the return address into compiled code at [rsp].
+fn emit_message_helpers(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$append_string");
+    asm.emit_push(RDI);
+    asm.emit_mov(R10, RSI);
+    emit_string_length(asm, R11, R10);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label("runtime$append_string.next");
+    asm.emit_cmp(RDX, R11);
+    asm.emit_jcc(Condition::Ae, "runtime$append_string.done");
+    emit_load_char(asm, RDI, R10, RDX, "runtime$append_string.load");
+    asm.emit_cmp(EDI, 255);
+    asm.emit_jcc(Condition::Be, "runtime$append_string.append");
+    asm.emit_mov(EDI, 63);
+    asm.emit_label("runtime$append_string.append");
+    asm.emit_call("runtime$append_byte");
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp("runtime$append_string.next");
+    asm.emit_label("runtime$append_string.done");
+    asm.emit_pop(RDI);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$append_element_type");
+    asm.emit_cmp(qword(RSI + ELEMENT_CLASS_OFFSET), 0);
+    asm.emit_jcc(Condition::E, "runtime$append_element_type.primitive");
+    emit_text(asm, OBJECT_ARRAY_TEXT);
+    asm.emit_ret();
+    // The name of a primitive array class is [ and the element's descriptor
+    asm.emit_label("runtime$append_element_type.primitive");
+    asm.emit_mov(RAX, qword(RSI + NAME_OFFSET));
+    asm.emit_movzx(EAX, byte(RAX + 9));
+    for (descriptor, name) in PRIMITIVE_NAMES {
+        let label = format!("runtime$append_element_type.not_{}", name);
+        asm.emit_cmp(EAX, descriptor);
+        asm.emit_jcc(Condition::Ne, &label);
+        emit_append(asm, &format!("runtime$primitive_name.{}", name), name);
+        asm.emit_ret();
+        asm.emit_label(&label);
+    }
+    asm.emit_ret();
+
+    asm.emit_label("runtime$throw_string_index");
+    asm.emit_mov(RBX, RDI);
+    emit_clear_message(asm);
+    emit_text(asm, STRING_INDEX_TEXT);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_decimal");
+    emit_throw_new(asm, abi, STRING_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+
+    asm.emit_label("runtime$throw_index_length");
+    asm.emit_mov(RBX, RDI);
+    asm.emit_mov(R12, RSI);
+    emit_clear_message(asm);
+    emit_text(asm, INDEX_TEXT);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_decimal");
+    emit_text(asm, LENGTH_TEXT);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_decimal");
+    emit_throw_new(asm, abi, STRING_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+
+    asm.emit_label("runtime$throw_begin_end");
+    asm.emit_mov(RBX, RDI);
+    asm.emit_mov(R12, RSI);
+    asm.emit_mov(R13, RDX);
+    emit_clear_message(asm);
+    emit_text(asm, BEGIN_TEXT);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_decimal");
+    emit_text(asm, END_TEXT);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_decimal");
+    emit_text(asm, LENGTH_TEXT);
+    asm.emit_mov(RSI, R13);
+    asm.emit_call("runtime$append_decimal");
+    emit_throw_new(asm, abi, STRING_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+}
+
+
+/// `runtime$append_double` and `runtime$append_float` add the number in xmm0 to the
+/// message the way Double.toString and Float.toString print it, with the fewest digits
+/// that read back as the same number.
+///
+/// `runtime$append_floating` does the work for a positive finite number, given as the
+/// double in xmm0, its significand m in rdi and in rsi whether the next smaller number
+/// is half as far away as the next larger, for powers of two. The number is scaled by a
+/// power of ten with the x87 unit to an integer Y of about 19 digits, including six
+/// binary places. The numbers that read back as it are within Y / 2m of Y, so the
+/// shortest digits are the shortest multiple of a power of ten there.
+fn emit_floating_point_text(asm: &mut Assembly) {
+    asm.emit_label("runtime$append_double");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_mov(RCX, RAX);
+    asm.emit_shr(RCX, 52);
+    asm.emit_and(ECX, 0x7ff);
+    asm.emit_mov(RDX, 0xfffffffffffffi64);
+    asm.emit_cmp(ECX, 0x7ff);
+    asm.emit_jcc(Condition::Ne, "runtime$append_double.finite");
+    asm.emit_test(RAX, RDX);
+    asm.emit_jcc(Condition::Ne, "runtime$append_floating.nan");
+    asm.emit_label("runtime$append_double.finite");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ns, "runtime$append_double.positive");
+    asm.emit_mov(RSI, RAX);
+    asm.emit_mov(EDI, 45);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(RAX, RSI);
+    asm.emit_shl(RAX, 1);
+    asm.emit_shr(RAX, 1);
+    asm.emit_label("runtime$append_double.positive");
+    asm.emit_cmp(ECX, 0x7ff);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.infinity");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.zero");
+    asm.emit_mov(RDI, RAX);
+    asm.emit_and(RDI, RDX);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_test(ECX, ECX);
+    asm.emit_jcc(Condition::E, "runtime$append_double.subnormal");
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::Ne, "runtime$append_double.implicit_bit");
+    asm.emit_cmp(ECX, 1);
+    asm.emit_setcc(Condition::Ne, SIL);
+    asm.emit_label("runtime$append_double.implicit_bit");
+    asm.emit_add(RDX, 1);
+    asm.emit_or(RDI, RDX);
+    asm.emit_label("runtime$append_double.subnormal");
+    asm.emit_mov(qword(RSP - 8), RAX);
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 8));
+    asm.emit_jmp("runtime$append_floating");
+
+    asm.emit_label("runtime$append_float");
+    asm.emit_sse(SseOp::Movss, dword(RSP - 8), XMM0);
+    asm.emit_mov(EAX, dword(RSP - 8));
+    asm.emit_mov(ECX, EAX);
+    asm.emit_shr(ECX, 23);
+    asm.emit_and(ECX, 0xff);
+    asm.emit_cmp(ECX, 0xff);
+    asm.emit_jcc(Condition::Ne, "runtime$append_float.finite");
+    asm.emit_test(EAX, 0x7fffff);
+    asm.emit_jcc(Condition::Ne, "runtime$append_floating.nan");
+    asm.emit_label("runtime$append_float.finite");
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::Ns, "runtime$append_float.positive");
+    asm.emit_mov(ESI, EAX);
+    asm.emit_mov(EDI, 45);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(EAX, ESI);
+    asm.emit_and(EAX, 0x7fffffff);
+    asm.emit_label("runtime$append_float.positive");
+    asm.emit_cmp(ECX, 0xff);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.infinity");
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.zero");
+    asm.emit_mov(EDI, EAX);
+    asm.emit_and(EDI, 0x7fffff);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_test(ECX, ECX);
+    asm.emit_jcc(Condition::E, "runtime$append_float.subnormal");
+    asm.emit_test(EDI, EDI);
+    asm.emit_jcc(Condition::Ne, "runtime$append_float.implicit_bit");
+    asm.emit_cmp(ECX, 1);
+    asm.emit_setcc(Condition::Ne, SIL);
+    asm.emit_label("runtime$append_float.implicit_bit");
+    asm.emit_or(EDI, 0x800000);
+    asm.emit_label("runtime$append_float.subnormal");
+    asm.emit_mov(dword(RSP - 8), EAX);
+    asm.emit_sse(SseOp::Cvtss2sd, XMM0, dword(RSP - 8));
+    asm.emit_jmp("runtime$append_floating");
+
+    asm.emit_label("runtime$append_floating.nan");
+    emit_text(asm, NAN_TEXT);
+    asm.emit_ret();
+    asm.emit_label("runtime$append_floating.infinity");
+    emit_text(asm, INFINITY_TEXT);
+    asm.emit_ret();
+    asm.emit_label("runtime$append_floating.zero");
+    emit_text(asm, ZERO_TEXT);
+    asm.emit_ret();
+
+    // The slots hold the number, m, the flag, the decimal exponent E, Y, the distances to
+    // the neighbours above and below, and from slot 12 up a buffer for the digits
+    asm.emit_label("runtime$append_floating");
+    emit_enter(asm, 13);
+    asm.emit_sse(SseOp::Movsd, slot(0), XMM0);
+    asm.emit_mov(slot(1), RDI);
+    asm.emit_mov(slot(2), RSI);
+    // E starts at an estimate from the exponent of the highest bit, log10(2) being about
+    // 78913 / 2^18
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RCX, RAX);
+    asm.emit_shr(RCX, 52);
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.subnormal");
+    asm.emit_sub(RCX, 1023);
+    asm.emit_jmp("runtime$append_floating.estimate");
+    asm.emit_label("runtime$append_floating.subnormal");
+    asm.emit_mov(RCX, -1075);
+    asm.emit_label("runtime$append_floating.highest_bit");
+    asm.emit_add(RCX, 1);
+    asm.emit_shr(RAX, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$append_floating.highest_bit");
+    asm.emit_label("runtime$append_floating.estimate");
+    asm.emit_mov(RAX, 78913);
+    asm.emit_imul(RAX, RCX);
+    asm.emit_sar(RAX, 18);
+    asm.emit_mov(slot(3), RAX);
+
+    // Y is the number times 10^(16 - E) times 64, between 64 * 10^16 and 64 * 10^17
+    asm.emit_label("runtime$append_floating.scale");
+    asm.emit_mov(RCX, 16);
+    asm.emit_sub(RCX, slot(3));
+    asm.emit_mov(R8, "runtime$powers_of_ten");
+    asm.emit_fld(slot(0));
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::S, "runtime$append_floating.divide");
+    asm.emit_label("runtime$append_floating.multiply");
+    asm.emit_cmp(RCX, 22);
+    asm.emit_jcc(Condition::Be, "runtime$append_floating.multiplied");
+    asm.emit_fmul(qword(R8 + 176));
+    asm.emit_sub(RCX, 22);
+    asm.emit_jmp("runtime$append_floating.multiply");
+    asm.emit_label("runtime$append_floating.multiplied");
+    asm.emit_fmul(qword(R8 + RCX * 8));
+    asm.emit_jmp("runtime$append_floating.scaled");
+    asm.emit_label("runtime$append_floating.divide");
+    asm.emit_neg(RCX);
+    asm.emit_label("runtime$append_floating.divide_more");
+    asm.emit_cmp(RCX, 22);
+    asm.emit_jcc(Condition::Be, "runtime$append_floating.divided");
+    asm.emit_fdiv(qword(R8 + 176));
+    asm.emit_sub(RCX, 22);
+    asm.emit_jmp("runtime$append_floating.divide_more");
+    asm.emit_label("runtime$append_floating.divided");
+    asm.emit_fdiv(qword(R8 + RCX * 8));
+    asm.emit_label("runtime$append_floating.scaled");
+    asm.emit_fmul(qword(Memory::symbol("runtime$sixty_four")));
+    asm.emit_fistp(slot(4));
+    // Too large a Y comes back as 2^63, which is above the range unsigned
+    asm.emit_mov(RAX, slot(4));
+    asm.emit_mov(RDX, 6400000000000000000i64);
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::B, "runtime$append_floating.not_above");
+    asm.emit_add(slot(3), 1);
+    asm.emit_jmp("runtime$append_floating.scale");
+    asm.emit_label("runtime$append_floating.not_above");
+    asm.emit_mov(RDX, 640000000000000000i64);
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$append_floating.in_range");
+    asm.emit_sub(slot(3), 1);
+    asm.emit_jmp("runtime$append_floating.scale");
+
+    asm.emit_label("runtime$append_floating.in_range");
+    asm.emit_cqo();
+    asm.emit_mov(RCX, slot(1));
+    asm.emit_shl(RCX, 1);
+    asm.emit_idiv(RCX);
+    asm.emit_mov(slot(5), RAX);
+    asm.emit_cmp(slot(2), 0);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.below");
+    asm.emit_sar(RAX, 1);
+    asm.emit_label("runtime$append_floating.below");
+    asm.emit_mov(slot(6), RAX);
+
+    // Dropping r9 digits of the 17, from 15 down, Y lies between two multiples of
+    // U = 64 * 10^r9, the one below Y - r and the one above U - r away
+    asm.emit_mov(R9, 15);
+    asm.emit_label("runtime$append_floating.try");
+    asm.emit_mov(R8, "runtime$integer_powers_of_ten");
+    asm.emit_mov(RCX, qword(R8 + R9 * 8));
+    asm.emit_shl(RCX, 6);
+    asm.emit_mov(RAX, slot(4));
+    asm.emit_cqo();
+    asm.emit_idiv(RCX);
+    asm.emit_mov(R10, RCX);
+    asm.emit_sub(R10, RDX);
+    asm.emit_xor(R11D, R11D);
+    asm.emit_cmp(RDX, slot(6));
+    asm.emit_jcc(Condition::G, "runtime$append_floating.below_too_far");
+    asm.emit_or(R11, 1);
+    asm.emit_label("runtime$append_floating.below_too_far");
+    asm.emit_cmp(R10, slot(5));
+    asm.emit_jcc(Condition::G, "runtime$append_floating.above_too_far");
+    asm.emit_or(R11, 2);
+    asm.emit_label("runtime$append_floating.above_too_far");
+    asm.emit_cmp(R11, 1);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.round_down");
+    asm.emit_cmp(R11, 2);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.round_up");
+    asm.emit_cmp(R11, 3);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.nearest");
+    asm.emit_sub(R9, 1);
+    asm.emit_jmp("runtime$append_floating.try");
+    // Both read back as the number, the closer one wins and a tie goes to the even one
+    asm.emit_label("runtime$append_floating.nearest");
+    asm.emit_cmp(RDX, R10);
+    asm.emit_jcc(Condition::L, "runtime$append_floating.round_down");
+    asm.emit_jcc(Condition::G, "runtime$append_floating.round_up");
+    asm.emit_test(AL, 1);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.round_down");
+    asm.emit_label("runtime$append_floating.round_up");
+    asm.emit_add(RAX, 1);
+    // Rounding up may have carried into another digit, 10^(17 - r9)
+    asm.emit_label("runtime$append_floating.round_down");
+    asm.emit_mov(RCX, 17);
+    asm.emit_sub(RCX, R9);
+    asm.emit_cmp(RAX, qword(R8 + RCX * 8));
+    asm.emit_jcc(Condition::Ne, "runtime$append_floating.strip");
+    asm.emit_mov(RAX, qword(R8 + RCX * 8 - 8));
+    asm.emit_add(slot(3), 1);
+    asm.emit_label("runtime$append_floating.strip");
+    asm.emit_mov(RCX, 10);
+    asm.emit_label("runtime$append_floating.strip_zero");
+    asm.emit_mov(R10, RAX);
+    asm.emit_cqo();
+    asm.emit_idiv(RCX);
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.strip_zero");
+    asm.emit_mov(RAX, R10);
+
+    // r10 points to the first digit and r11 holds their count
+    asm.emit_mov(R10, RBP);
+    asm.emit_sub(R10, 72);
+    asm.emit_mov(R11, R10);
+    asm.emit_label("runtime$append_floating.digit");
+    asm.emit_cqo();
+    asm.emit_idiv(RCX);
+    asm.emit_add(DL, 48);
+    asm.emit_sub(R10, 1);
+    asm.emit_mov(byte(R10), DL);
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, "runtime$append_floating.digit");
+    asm.emit_sub(R11, R10);
+
+    // Numbers from 10^-3 up to 10^7 are written out, the others in computerized
+    // scientific notation. There is always a digit after the point.
+    asm.emit_mov(R9, slot(3));
+    asm.emit_cmp(R9, -3);
+    asm.emit_jcc(Condition::L, "runtime$append_floating.scientific");
+    asm.emit_cmp(R9, 7);
+    asm.emit_jcc(Condition::Ge, "runtime$append_floating.scientific");
+    asm.emit_test(R9, R9);
+    asm.emit_jcc(Condition::S, "runtime$append_floating.fraction");
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label("runtime$append_floating.integer_digit");
+    asm.emit_mov(EDI, 48);
+    asm.emit_cmp(RCX, R11);
+    asm.emit_jcc(Condition::Ae, "runtime$append_floating.integer_zero");
+    asm.emit_movzx(EDI, byte(R10 + RCX));
+    asm.emit_label("runtime$append_floating.integer_zero");
+    asm.emit_call("runtime$append_byte");
+    asm.emit_add(RCX, 1);
+    asm.emit_cmp(RCX, R9);
+    asm.emit_jcc(Condition::Le, "runtime$append_floating.integer_digit");
+    asm.emit_mov(EDI, 46);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_cmp(RCX, R11);
+    asm.emit_jcc(Condition::Ae, "runtime$append_floating.zero_digit");
+    asm.emit_mov(RSI, R10);
+    asm.emit_add(RSI, RCX);
+    asm.emit_mov(RDX, R11);
+    asm.emit_sub(RDX, RCX);
+    asm.emit_call("runtime$append");
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label("runtime$append_floating.fraction");
+    asm.emit_mov(EDI, 48);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(EDI, 46);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(RCX, R9);
+    asm.emit_neg(RCX);
+    asm.emit_label("runtime$append_floating.leading_zero");
+    asm.emit_sub(RCX, 1);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.all_digits");
+    asm.emit_mov(EDI, 48);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_jmp("runtime$append_floating.leading_zero");
+    asm.emit_label("runtime$append_floating.all_digits");
+    asm.emit_mov(RSI, R10);
+    asm.emit_mov(RDX, R11);
+    asm.emit_call("runtime$append");
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label("runtime$append_floating.scientific");
+    asm.emit_movzx(EDI, byte(R10));
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(EDI, 46);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_cmp(R11, 1);
+    asm.emit_jcc(Condition::E, "runtime$append_floating.no_fraction");
+    asm.emit_mov(RSI, R10);
+    asm.emit_add(RSI, 1);
+    asm.emit_mov(RDX, R11);
+    asm.emit_sub(RDX, 1);
+    asm.emit_call("runtime$append");
+    asm.emit_jmp("runtime$append_floating.exponent");
+    asm.emit_label("runtime$append_floating.no_fraction");
+    asm.emit_mov(EDI, 48);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_label("runtime$append_floating.exponent");
+    asm.emit_mov(EDI, 69);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(RSI, R9);
+    asm.emit_call("runtime$append_decimal");
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label("runtime$append_floating.zero_digit");
+    asm.emit_mov(EDI, 48);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_leave();
+    asm.emit_ret();
+}
+
+/// `runtime$append_fixed` adds the number in xmm0 to the message with rdi digits after
+/// the point, the way %f formats it. Like the JDK it rounds half up the digits
+/// Double.toString gives, so it starts from those. NaN and the infinities stay as they
+/// are, and numbers too long for the message are cut off with it.
+fn emit_fixed_point_text(asm: &mut Assembly) {
+    // The slots hold the precision, where the number starts in the message and whether
+    // it is negative. The digits are copied to a buffer from rbp - 88 up, the point
+    // being after the first rdx of them, and leading zeros are stripped. rcx holds how
+    // many digits there are.
+    asm.emit_label("runtime$append_fixed");
+    emit_enter(asm, 12);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_mov(slot(1), RAX);
+    asm.emit_call("runtime$append_double");
+    asm.emit_mov(R10, "runtime$message");
+    asm.emit_mov(R11, R10);
+    asm.emit_add(R11, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_add(R10, slot(1));
+    asm.emit_mov(slot(2), 0);
+    asm.emit_cmp(byte(R10), 45);
+    asm.emit_jcc(Condition::Ne, "runtime$append_fixed.unsigned");
+    asm.emit_mov(slot(2), 1);
+    asm.emit_add(R10, 1);
+    asm.emit_label("runtime$append_fixed.unsigned");
+    asm.emit_movzx(EAX, byte(R10));
+    asm.emit_sub(EAX, 48);
+    asm.emit_cmp(EAX, 9);
+    asm.emit_jcc(Condition::A, "runtime$append_fixed.done");
+    asm.emit_mov(R9, RBP);
+    asm.emit_sub(R9, 88);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label("runtime$append_fixed.copy");
+    asm.emit_cmp(R10, R11);
+    asm.emit_jcc(Condition::Ae, "runtime$append_fixed.strip");
+    asm.emit_movzx(EAX, byte(R10));
+    asm.emit_add(R10, 1);
+    asm.emit_cmp(EAX, 46);
+    asm.emit_jcc(Condition::Ne, "runtime$append_fixed.not_point");
+    asm.emit_mov(RDX, RCX);
+    asm.emit_jmp("runtime$append_fixed.copy");
+    asm.emit_label("runtime$append_fixed.not_point");
+    asm.emit_cmp(EAX, 69);
+    asm.emit_jcc(Condition::E, "runtime$append_fixed.exponent");
+    asm.emit_mov(byte(R9 + RCX), AL);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp("runtime$append_fixed.copy");
+
+    // Scientific notation moves the point by the exponent, in r8 with its sign in esi
+    asm.emit_label("runtime$append_fixed.exponent");
+    asm.emit_xor(R8D, R8D);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_cmp(byte(R10), 45);
+    asm.emit_jcc(Condition::Ne, "runtime$append_fixed.exponent_digit");
+    asm.emit_mov(ESI, 1);
+    asm.emit_add(R10, 1);
+    asm.emit_label("runtime$append_fixed.exponent_digit");
+    asm.emit_cmp(R10, R11);
+    asm.emit_jcc(Condition::Ae, "runtime$append_fixed.exponent_done");
+    asm.emit_movzx(EAX, byte(R10));
+    asm.emit_add(R10, 1);
+    asm.emit_sub(EAX, 48);
+    asm.emit_mov(RDI, R8);
+    asm.emit_shl(R8, 3);
+    asm.emit_add(R8, RDI);
+    asm.emit_add(R8, RDI);
+    asm.emit_add(R8, RAX);
+    asm.emit_jmp("runtime$append_fixed.exponent_digit");
+    asm.emit_label("runtime$append_fixed.exponent_done");
+    asm.emit_test(ESI, ESI);
+    asm.emit_jcc(Condition::E, "runtime$append_fixed.exponent_positive");
+    asm.emit_neg(R8);
+    asm.emit_label("runtime$append_fixed.exponent_positive");
+    asm.emit_add(RDX, R8);
+
+    asm.emit_label("runtime$append_fixed.strip");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$append_fixed.stripped");
+    asm.emit_cmp(byte(R9), 48);
+    asm.emit_jcc(Condition::Ne, "runtime$append_fixed.stripped");
+    asm.emit_add(R9, 1);
+    asm.emit_sub(RCX, 1);
+    asm.emit_sub(RDX, 1);
+    asm.emit_jmp("runtime$append_fixed.strip");
+
+    // The first rax digits are kept, the one after them rounds them half up. Carrying
+    // out of the first one makes it a 1 before them.
+    asm.emit_label("runtime$append_fixed.stripped");
+    asm.emit_mov(RAX, RDX);
+    asm.emit_add(RAX, slot(0));
+    asm.emit_cmp(RAX, RCX);
+    asm.emit_jcc(Condition::Ge, "runtime$append_fixed.rounded");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::S, "runtime$append_fixed.zero");
+    asm.emit_movzx(ESI, byte(R9 + RAX));
+    asm.emit_mov(RCX, RAX);
+    asm.emit_cmp(ESI, 53);
+    asm.emit_jcc(Condition::B, "runtime$append_fixed.rounded");
+    asm.emit_mov(RSI, RAX);
+    asm.emit_label("runtime$append_fixed.carry");
+    asm.emit_sub(RSI, 1);
+    asm.emit_jcc(Condition::S, "runtime$append_fixed.carry_out");
+    asm.emit_movzx(EAX, byte(R9 + RSI));
+    asm.emit_add(EAX, 1);
+    asm.emit_mov(byte(R9 + RSI), AL);
+    asm.emit_cmp(EAX, 58);
+    asm.emit_jcc(Condition::Ne, "runtime$append_fixed.rounded");
+    asm.emit_mov(byte(R9 + RSI), 48);
+    asm.emit_jmp("runtime$append_fixed.carry");
+    asm.emit_label("runtime$append_fixed.carry_out");
+    asm.emit_sub(R9, 1);
+    asm.emit_mov(byte(R9), 49);
+    asm.emit_add(RCX, 1);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp("runtime$append_fixed.rounded");
+    asm.emit_label("runtime$append_fixed.zero");
+    asm.emit_xor(ECX, ECX);
+
+    // The number is written over what Double.toString gave, the sign kept even for zero
+    asm.emit_label("runtime$append_fixed.rounded");
+    asm.emit_mov(RAX, slot(1));
+    asm.emit_mov(qword(Memory::symbol("runtime$message_length")), RAX);
+    asm.emit_cmp(slot(2), 0);
+    asm.emit_jcc(Condition::E, "runtime$append_fixed.positive");
+    asm.emit_mov(EDI, 45);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_label("runtime$append_fixed.positive");
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::G, "runtime$append_fixed.integer");
+    asm.emit_mov(EDI, 48);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_jmp("runtime$append_fixed.point");
+    asm.emit_label("runtime$append_fixed.integer");
+    asm.emit_xor(R10D, R10D);
+    asm.emit_label("runtime$append_fixed.integer_digit");
+    asm.emit_mov(EDI, 48);
+    asm.emit_cmp(R10, RCX);
+    asm.emit_jcc(Condition::Ge, "runtime$append_fixed.integer_zero");
+    asm.emit_movzx(EDI, byte(R9 + R10));
+    asm.emit_label("runtime$append_fixed.integer_zero");
+    asm.emit_call("runtime$append_byte");
+    asm.emit_add(R10, 1);
+    asm.emit_cmp(R10, RDX);
+    asm.emit_jcc(Condition::L, "runtime$append_fixed.integer_digit");
+    asm.emit_label("runtime$append_fixed.point");
+    asm.emit_cmp(slot(0), 0);
+    asm.emit_jcc(Condition::E, "runtime$append_fixed.done");
+    asm.emit_mov(EDI, 46);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_mov(R10, RDX);
+    asm.emit_mov(R11, RDX);
+    asm.emit_add(R11, slot(0));
+    asm.emit_label("runtime$append_fixed.fraction_digit");
+    asm.emit_mov(EDI, 48);
+    asm.emit_test(R10, R10);
+    asm.emit_jcc(Condition::S, "runtime$append_fixed.fraction_zero");
+    asm.emit_cmp(R10, RCX);
+    asm.emit_jcc(Condition::Ge, "runtime$append_fixed.fraction_zero");
+    asm.emit_movzx(EDI, byte(R9 + R10));
+    asm.emit_label("runtime$append_fixed.fraction_zero");
+    asm.emit_call("runtime$append_byte");
+    asm.emit_add(R10, 1);
+    asm.emit_cmp(R10, R11);
+    asm.emit_jcc(Condition::L, "runtime$append_fixed.fraction_digit");
+    asm.emit_label("runtime$append_fixed.done");
+    asm.emit_leave();
+    asm.emit_ret();
+}
+
+/// Output goes through a buffer, `runtime$flush` writes it to the file descriptor in rdi
+/// and changes only rax. `runtime$write_chars` adds the rdx characters at rsi, Latin-1
+/// when rcx is zero and UTF-16 otherwise, encoded in UTF-8. A surrogate without its
+/// other half becomes a question mark, like the JDK's encoder does. It keeps rdi, as do
+/// `runtime$write_bytes`, for Latin-1 characters, and `runtime$write_string`, for the
+/// string in rsi.
+fn emit_output(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$flush");
+    asm.emit_push(RCX);
+    asm.emit_push(RDX);
+    asm.emit_push(RSI);
+    asm.emit_push(R11);
+    asm.emit_mov(RAX, abi.write_syscall());
+    asm.emit_mov(RSI, "runtime$output");
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$output_length")));
+    asm.emit_syscall();
+    asm.emit_mov(qword(Memory::symbol("runtime$output_length")), 0);
+    asm.emit_pop(R11);
+    asm.emit_pop(RSI);
+    asm.emit_pop(RDX);
+    asm.emit_pop(RCX);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$write_string");
+    asm.emit_mov(RAX, qword(RSI + STRING_VALUE_OFFSET));
+    asm.emit_movzx(ECX, byte(RSI + STRING_CODER_OFFSET));
+    asm.emit_mov(RDX, qword(RAX + ARRAY_LENGTH_OFFSET));
+    asm.emit_shr(RDX, CL);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_add(RSI, ARRAY_DATA_OFFSET);
+    asm.emit_jmp("runtime$write_chars");
+
+    asm.emit_label("runtime$write_bytes");
+    asm.emit_xor(ECX, ECX);
+
+    // r9 holds the coder, r10 the address of the next character and r11 how many are left
+    asm.emit_label("runtime$write_chars");
+    asm.emit_mov(R9, RCX);
+    asm.emit_mov(R10, RSI);
+    asm.emit_mov(R11, RDX);
+    asm.emit_label("runtime$write_chars.next");
+    asm.emit_test(R11, R11);
+    asm.emit_jcc(Condition::E, "runtime$write_chars.done");
+    // A character takes up to 4 bytes
+    asm.emit_cmp(qword(Memory::symbol("runtime$output_length")), OUTPUT_BUFFER_SIZE - 4);
+    asm.emit_jcc(Condition::Be, "runtime$write_chars.room");
+    asm.emit_call("runtime$flush");
+    asm.emit_label("runtime$write_chars.room");
+    asm.emit_sub(R11, 1);
+    asm.emit_test(R9, R9);
+    asm.emit_jcc(Condition::Ne, "runtime$write_chars.utf16");
+    asm.emit_movzx(EAX, byte(R10));
+    asm.emit_add(R10, 1);
+    asm.emit_jmp("runtime$write_chars.encode");
+    asm.emit_label("runtime$write_chars.utf16");
+    asm.emit_movzx(EAX, word(R10));
+    asm.emit_add(R10, 2);
+    asm.emit_cmp(EAX, 0xd800);
+    asm.emit_jcc(Condition::B, "runtime$write_chars.encode");
+    asm.emit_cmp(EAX, 0xdfff);
+    asm.emit_jcc(Condition::A, "runtime$write_chars.encode");
+    asm.emit_cmp(EAX, 0xdc00);
+    asm.emit_jcc(Condition::Ae, "runtime$write_chars.malformed");
+    asm.emit_test(R11, R11);
+    asm.emit_jcc(Condition::E, "runtime$write_chars.malformed");
+    asm.emit_movzx(ECX, word(R10));
+    asm.emit_cmp(ECX, 0xdc00);
+    asm.emit_jcc(Condition::B, "runtime$write_chars.malformed");
+    asm.emit_cmp(ECX, 0xdfff);
+    asm.emit_jcc(Condition::A, "runtime$write_chars.malformed");
+    asm.emit_add(R10, 2);
+    asm.emit_sub(R11, 1);
+    // 0x10000 + (high - 0xd800) * 0x400 + low - 0xdc00
+    asm.emit_sub(EAX, 0xd800);
+    asm.emit_shl(EAX, 10);
+    asm.emit_add(EAX, ECX);
+    asm.emit_add(EAX, 0x2400);
+    asm.emit_jmp("runtime$write_chars.encode");
+    asm.emit_label("runtime$write_chars.malformed");
+    asm.emit_mov(EAX, 63);
+
+    asm.emit_label("runtime$write_chars.encode");
+    asm.emit_mov(R8, "runtime$output");
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$output_length")));
+    asm.emit_add(R8, RDX);
+    asm.emit_cmp(EAX, 0x80);
+    asm.emit_jcc(Condition::Ae, "runtime$write_chars.two_bytes");
+    asm.emit_mov(byte(R8), AL);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp("runtime$write_chars.written");
+    asm.emit_label("runtime$write_chars.two_bytes");
+    asm.emit_cmp(EAX, 0x800);
+    asm.emit_jcc(Condition::Ae, "runtime$write_chars.three_bytes");
+    emit_utf8_lead(asm, 6, 0xc0);
+    emit_utf8_continuation(asm, 0, 1);
+    asm.emit_add(RDX, 2);
+    asm.emit_jmp("runtime$write_chars.written");
+    asm.emit_label("runtime$write_chars.three_bytes");
+    asm.emit_cmp(EAX, 0x10000);
+    asm.emit_jcc(Condition::Ae, "runtime$write_chars.four_bytes");
+    emit_utf8_lead(asm, 12, 0xe0);
+    emit_utf8_continuation(asm, 6, 1);
+    emit_utf8_continuation(asm, 0, 2);
+    asm.emit_add(RDX, 3);
+    asm.emit_jmp("runtime$write_chars.written");
+    asm.emit_label("runtime$write_chars.four_bytes");
+    emit_utf8_lead(asm, 18, 0xf0);
+    emit_utf8_continuation(asm, 12, 1);
+    emit_utf8_continuation(asm, 6, 2);
+    emit_utf8_continuation(asm, 0, 3);
+    asm.emit_add(RDX, 4);
+    asm.emit_label("runtime$write_chars.written");
+    asm.emit_mov(qword(Memory::symbol("runtime$output_length")), RDX);
+    asm.emit_jmp("runtime$write_chars.next");
+    asm.emit_label("runtime$write_chars.done");
+    asm.emit_ret();
+}
+
+// Writes the first byte of the UTF-8 encoding of the code point in eax to [r8]
+fn emit_utf8_lead(asm: &mut Assembly, shift: u8, marker: u8) {
+    asm.emit_mov(ECX, EAX);
+    asm.emit_shr(ECX, shift);
+    asm.emit_or(ECX, marker);
+    asm.emit_mov(byte(R8), CL);
+}
+
+// Writes the six bits of the code point in eax from `shift` up to [r8 + index]
+fn emit_utf8_continuation(asm: &mut Assembly, shift: u8, index: usize) {
+    asm.emit_mov(ECX, EAX);
+    if shift > 0 {
+        asm.emit_shr(ECX, shift);
+    }
+    asm.emit_and(ECX, 0x3f);
+    asm.emit_or(ECX, 0x80);
+    asm.emit_mov(byte(R8 + index), CL);
+}
+
+fn emit_object(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    emit_method(asm, abi, OBJECT_CLASS, "equals", "(Ljava/lang/Object;)Z");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_cmp(RDI, RSI);
+    asm.emit_setcc(Condition::E, AL);
+    asm.emit_ret();
+
+    // The identity hash is made up the first time it is asked for and kept in the header
+    emit_method(asm, abi, SYSTEM_CLASS, "identityHashCode", "(Ljava/lang/Object;)I");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, "runtime$identity_hash.done");
+    emit_method(asm, abi, OBJECT_CLASS, "hashCode", "()I");
+    asm.emit_mov(EAX, dword(RDI + HASH_LOCK_OFFSET));
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::Ne, "runtime$identity_hash.done");
+    asm.emit_mov(EAX, dword(Memory::symbol("runtime$hash_seed")));
+    for (shift, left) in [(13, true), (17, false), (5, true)] {
+        asm.emit_mov(ECX, EAX);
+        match left {
+            true => asm.emit_shl(ECX, shift),
+            false => asm.emit_shr(ECX, shift),
+        }
+        asm.emit_xor(EAX, ECX);
+    }
+    asm.emit_mov(dword(Memory::symbol("runtime$hash_seed")), EAX);
+    // Hashes are positive and zero means there is none yet
+    asm.emit_and(EAX, 0x7fffffff);
+    asm.emit_jcc(Condition::Ne, "runtime$identity_hash.store");
+    asm.emit_mov(EAX, 1);
+    asm.emit_label("runtime$identity_hash.store");
+    asm.emit_mov(dword(RDI + HASH_LOCK_OFFSET), EAX);
+    asm.emit_label("runtime$identity_hash.done");
+    asm.emit_ret();
+
+    // The class's name, an @ and the hash code in hexadecimal
+    emit_method(asm, abi, OBJECT_CLASS, "toString", "()Ljava/lang/String;");
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    emit_call_mapped(asm, stack_maps, virtual_method(OBJECT_CLASS, "hashCode", "()I"), &[0]);
+    asm.emit_mov(slot(1), RAX);
+    emit_clear_message(asm);
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RSI, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_call("runtime$append_class_name");
+    emit_text(asm, AT_SIGN_TEXT);
+    asm.emit_mov(ESI, dword(RBP - 16));
+    asm.emit_call("runtime$append_hex");
+    asm.emit_leave();
+    emit_tail_allocate(asm, "runtime$string_from_message");
+
+    emit_clone(asm, abi, stack_maps);
+}
+
+// Object.clone copies an array, or an instance of a class implementing Cloneable, all
+// but the header, into a new object of its class
+fn emit_clone(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    let clone = emit_method(asm, abi, OBJECT_CLASS, "clone", "()Ljava/lang/Object;");
+    emit_enter(asm, 1);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RDI, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_cmp(qword(RDI + ELEMENT_SIZE_OFFSET), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.cloneable", clone));
+    asm.emit_mov(RSI, abi.symbol(&mangle_class(CLONEABLE_CLASS)));
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &format!("{}.not_cloneable", clone));
+    asm.emit_label(&format!("{}.cloneable", clone));
+    asm.emit_mov(RDX, slot(0));
+    asm.emit_mov(RDI, qword(RDX + CLASS_POINTER_OFFSET));
+    emit_object_size(asm, RDX, RDI, RSI);
+    emit_call_mapped(asm, stack_maps, "runtime$allocate", &[0]);
+    // The collector may have moved the object
+    asm.emit_mov(RDX, slot(0));
+    asm.emit_mov(RCX, qword(RDX + CLASS_POINTER_OFFSET));
+    emit_object_size(asm, RDX, RCX, R8);
+    asm.emit_mov(R9, HEADER_SIZE);
+    asm.emit_label(&format!("{}.next", clone));
+    asm.emit_cmp(R9, R8);
+    asm.emit_jcc(Condition::Ae, &format!("{}.done", clone));
+    asm.emit_mov(R10, qword(RDX + R9));
+    asm.emit_mov(qword(RAX + R9), R10);
+    asm.emit_add(R9, 8);
+    asm.emit_jmp(format!("{}.next", clone));
+    asm.emit_label(&format!("{}.done", clone));
+    asm.emit_leave();
+    asm.emit_ret();
+
+    // The message is the class's name
+    asm.emit_label(&format!("{}.not_cloneable", clone));
+    emit_clear_message(asm);
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RSI, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_call("runtime$append_class_name");
+    asm.emit_leave();
+    emit_throw_new(asm, abi, CLONE_NOT_SUPPORTED_EXCEPTION_CLASS);
+}
+
+fn emit_enum(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    let null_pointer = abi.symbol("runtime$throw_null_pointer_exception");
+
+    emit_method(asm, abi, ENUM_CLASS, "<init>", "(Ljava/lang/String;I)V");
+    asm.emit_mov(qword(RDI + ENUM_NAME_OFFSET), RSI);
+    asm.emit_mov(dword(RDI + ENUM_ORDINAL_OFFSET), EDX);
+    asm.emit_ret();
+
+    emit_method(asm, abi, ENUM_CLASS, "name", "()Ljava/lang/String;");
+    emit_method(asm, abi, ENUM_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_mov(RAX, qword(RDI + ENUM_NAME_OFFSET));
+    asm.emit_ret();
+
+    emit_method(asm, abi, ENUM_CLASS, "ordinal", "()I");
+    asm.emit_mov(EAX, dword(RDI + ENUM_ORDINAL_OFFSET));
+    asm.emit_ret();
+
+    emit_method(asm, abi, ENUM_CLASS, "compareTo", "(Ljava/lang/Enum;)I");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_mov(EAX, dword(RDI + ENUM_ORDINAL_OFFSET));
+    asm.emit_sub(EAX, dword(RSI + ENUM_ORDINAL_OFFSET));
+    asm.emit_ret();
+
+    // Searches the constants of the enum whose Class object is in rdi for the one named
+    // by the string in rsi, after initializing the enum like calling values() would.
+    // Slot 0 is the Class object, slot 1 the name, slot 2 the constants and slot 3 the
+    // index of the next one.
+    let value_of = emit_method(asm, abi, ENUM_CLASS, "valueOf", "(Ljava/lang/Class;Ljava/lang/String;)Ljava/lang/Enum;");
+    let label = |name: &str| format!("{}.{}", value_of, name);
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    emit_enter(asm, 4);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(slot(1), RSI);
+    asm.emit_mov(RAX, qword(RDI + CLASS_INITIALIZER_OFFSET));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("initialized"));
+    emit_call_mapped(asm, stack_maps, RAX, &[0, 1]);
+    asm.emit_label(&label("initialized"));
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RAX, qword(RAX + CLASS_ENUM_CONSTANTS_OFFSET));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("not_an_enum"));
+    // Still null while the enum's own initializer creates them
+    asm.emit_mov(RAX, qword(RAX));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("null"));
+    asm.emit_mov(slot(2), RAX);
+    asm.emit_mov(slot(3), 0);
+    asm.emit_label(&label("next"));
+    asm.emit_mov(RAX, slot(2));
+    asm.emit_mov(RCX, slot(3));
+    asm.emit_cmp(RCX, qword(RAX + ARRAY_LENGTH_OFFSET));
+    asm.emit_jcc(Condition::Ae, &label("missing"));
+    asm.emit_mov(RDI, qword(RAX + RCX * 8 + ARRAY_DATA_OFFSET));
+    asm.emit_mov(RDI, qword(RDI + ENUM_NAME_OFFSET));
+    asm.emit_mov(RSI, slot(1));
+    asm.emit_call(method_symbol(abi, STRING_CLASS, "equals", "(Ljava/lang/Object;)Z"));
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::Ne, &label("found"));
+    asm.emit_add(slot(3), 1);
+    asm.emit_jmp(label("next"));
+    asm.emit_label(&label("found"));
+    asm.emit_mov(RAX, slot(2));
+    asm.emit_mov(RCX, slot(3));
+    asm.emit_mov(RAX, qword(RAX + RCX * 8 + ARRAY_DATA_OFFSET));
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label(&label("null"));
+    asm.emit_leave();
+    asm.emit_jmp(&null_pointer);
+
+    asm.emit_label(&label("not_an_enum"));
+    emit_clear_message(asm);
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RSI, qword(RAX + CLASS_METADATA_OFFSET));
+    asm.emit_call("runtime$append_class_name");
+    emit_text(asm, NOT_AN_ENUM_TEXT);
+    asm.emit_leave();
+    emit_throw_new(asm, abi, ILLEGAL_ARGUMENT_EXCEPTION);
+
+    asm.emit_label(&label("missing"));
+    emit_clear_message(asm);
+    asm.emit_cmp(slot(1), 0);
+    asm.emit_jcc(Condition::Ne, &label("no_constant"));
+    emit_text(asm, NAME_IS_NULL_TEXT);
+    asm.emit_leave();
+    emit_throw_new(asm, abi, NULL_POINTER_EXCEPTION);
+    asm.emit_label(&label("no_constant"));
+    emit_text(asm, NO_ENUM_CONSTANT_TEXT);
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RAX, qword(RAX + CLASS_CANONICAL_NAME_OFFSET));
+    asm.emit_mov(RDX, qword(RAX));
+    asm.emit_mov(RSI, RAX);
+    asm.emit_add(RSI, 8);
+    asm.emit_call("runtime$append");
+    emit_text(asm, DOT_TEXT);
+    asm.emit_mov(RSI, slot(1));
+    asm.emit_call("runtime$append_string");
+    asm.emit_leave();
+    emit_throw_new(asm, abi, ILLEGAL_ARGUMENT_EXCEPTION);
+}
+
+fn emit_throwable(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    emit_method(asm, abi, THROWABLE_CLASS, "getMessage", "()Ljava/lang/String;");
+    asm.emit_mov(RAX, qword(RDI + THROWABLE_MESSAGE_OFFSET));
+    asm.emit_ret();
+
+    // The class's name, then a colon and the message if there is one
+    let to_string = emit_method(asm, abi, THROWABLE_CLASS, "toString", "()Ljava/lang/String;");
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    emit_call_mapped(asm, stack_maps, virtual_method(THROWABLE_CLASS, "getMessage", "()Ljava/lang/String;"), &[0]);
+    asm.emit_mov(slot(1), RAX);
+    emit_clear_message(asm);
+    asm.emit_mov(RAX, slot(0));
+    asm.emit_mov(RSI, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_call("runtime$append_class_name");
+    asm.emit_cmp(slot(1), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.message", to_string));
+    asm.emit_leave();
+    emit_tail_allocate(asm, "runtime$string_from_message");
+    asm.emit_label(&format!("{}.message", to_string));
+    emit_text(asm, SEPARATOR_TEXT);
+    emit_call_mapped(asm, stack_maps, "runtime$string_from_message", &[0, 1]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(1));
+    asm.emit_leave();
+    asm.emit_jmp(method_symbol(abi, STRING_CLASS, "concat", "(Ljava/lang/String;)Ljava/lang/String;"));
+
+    // Without a stack trace to print, only the first line
+    emit_method(asm, abi, THROWABLE_CLASS, "printStackTrace", "()V");
+    emit_enter(asm, 1);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    emit_call_mapped(asm, stack_maps, virtual_method(THROWABLE_CLASS, "toString", "()Ljava/lang/String;"), &[0]);
+    asm.emit_mov(EDI, 2);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_call("runtime$print_string");
+    asm.emit_leave();
+    asm.emit_jmp("runtime$print_newline");
+}
+
+fn emit_string(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    let null_pointer = abi.symbol("runtime$throw_null_pointer_exception");
+
+    emit_method(asm, abi, STRING_CLASS, "length", "()I");
+    emit_string_length(asm, RAX, RDI);
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_CLASS, "isEmpty", "()Z");
+    emit_string_length(asm, RDX, RDI);
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RDX, RDX);
+    asm.emit_setcc(Condition::E, AL);
+    asm.emit_ret();
+
+    let char_at = emit_method(asm, abi, STRING_CLASS, "charAt", "(I)C");
+    asm.emit_movsxd(RSI, ESI);
+    emit_string_length(asm, RAX, RDI);
+    asm.emit_cmp(RSI, RAX);
+    asm.emit_jcc(Condition::Ae, &format!("{}.out_of_range", char_at));
+    emit_load_char(asm, RAX, RDI, RSI, &char_at);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.out_of_range", char_at));
+    asm.emit_mov(RDI, RSI);
+    asm.emit_jmp("runtime$throw_string_index");
+
+    let equals = emit_method(asm, abi, STRING_CLASS, "equals", "(Ljava/lang/Object;)Z");
+    asm.emit_cmp(RDI, RSI);
+    asm.emit_jcc(Condition::E, &format!("{}.equal", equals));
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &format!("{}.different", equals));
+    asm.emit_mov(RAX, abi.symbol(&mangle_class(STRING_CLASS)));
+    asm.emit_cmp(qword(RSI + CLASS_POINTER_OFFSET), RAX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.different", equals));
+    emit_string_length(asm, R8, RDI);
+    emit_string_length(asm, RDX, RSI);
+    asm.emit_cmp(R8, RDX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.different", equals));
+    asm.emit_xor(ECX, ECX);
+    asm.emit_jmp("runtime$region_matches");
+    asm.emit_label(&format!("{}.equal", equals));
+    asm.emit_mov(EAX, 1);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.different", equals));
+    asm.emit_xor(EAX, EAX);
+    asm.emit_ret();
+
+    // s[0]*31^(n-1) + s[1]*31^(n-2) + ... + s[n-1]
+    let hash_code = emit_method(asm, abi, STRING_CLASS, "hashCode", "()I");
+    emit_string_length(asm, R8, RDI);
+    asm.emit_xor(EAX, EAX);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&format!("{}.next", hash_code));
+    asm.emit_cmp(RDX, R8);
+    asm.emit_jcc(Condition::Ae, &format!("{}.done", hash_code));
+    asm.emit_mov(ECX, EAX);
+    asm.emit_shl(EAX, 5);
+    asm.emit_sub(EAX, ECX);
+    emit_load_char(asm, RCX, RDI, RDX, &hash_code);
+    asm.emit_add(EAX, ECX);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(format!("{}.next", hash_code));
+    asm.emit_label(&format!("{}.done", hash_code));
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_mov(RAX, RDI);
+    asm.emit_ret();
+
+    emit_intern(asm, abi, stack_maps);
+
+    // The difference of the first characters that differ, or else of the lengths
+    let compare_to = emit_method(asm, abi, STRING_CLASS, "compareTo", "(Ljava/lang/String;)I");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    emit_string_length(asm, R8, RDI);
+    emit_string_length(asm, R9, RSI);
+    asm.emit_mov(R11, R8);
+    asm.emit_cmp(R11, R9);
+    asm.emit_jcc(Condition::Be, &format!("{}.shorter", compare_to));
+    asm.emit_mov(R11, R9);
+    asm.emit_label(&format!("{}.shorter", compare_to));
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&format!("{}.next", compare_to));
+    asm.emit_cmp(RDX, R11);
+    asm.emit_jcc(Condition::Ae, &format!("{}.lengths", compare_to));
+    emit_load_char(asm, RAX, RDI, RDX, &format!("{}.this", compare_to));
+    emit_load_char(asm, RCX, RSI, RDX, &format!("{}.other", compare_to));
+    asm.emit_sub(EAX, ECX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.done", compare_to));
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(format!("{}.next", compare_to));
+    asm.emit_label(&format!("{}.lengths", compare_to));
+    asm.emit_mov(RAX, R8);
+    asm.emit_sub(RAX, R9);
+    asm.emit_label(&format!("{}.done", compare_to));
+    asm.emit_ret();
+
+    // Empty strings are left out rather than copied
+    let concat = emit_method(asm, abi, STRING_CLASS, "concat", "(Ljava/lang/String;)Ljava/lang/String;");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_mov(RAX, RDI);
+    emit_string_length(asm, RDX, RSI);
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, &format!("{}.done", concat));
+    asm.emit_mov(RAX, RSI);
+    emit_string_length(asm, RDX, RDI);
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, &format!("{}.done", concat));
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(slot(1), RSI);
+    emit_string_length(asm, RAX, RSI);
+    asm.emit_add(RDX, RAX);
+    asm.emit_movzx(EAX, byte(RDI + STRING_CODER_OFFSET));
+    asm.emit_movzx(ESI, byte(RSI + STRING_CODER_OFFSET));
+    asm.emit_or(ESI, EAX);
+    asm.emit_mov(RDI, RDX);
+    emit_call_mapped(asm, stack_maps, "runtime$new_string", &[0, 1]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(0));
+    emit_string_length(asm, R8, RSI);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_call("runtime$copy_chars");
+    // The other string goes where this one ends, which is where rcx was left
+    asm.emit_mov(R11, RCX);
+    asm.emit_mov(RSI, slot(1));
+    emit_string_length(asm, R8, RSI);
+    asm.emit_mov(RCX, R11);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_call("runtime$copy_chars");
+    asm.emit_mov(RAX, RDI);
+    asm.emit_leave();
+    asm.emit_label(&format!("{}.done", concat));
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_CLASS, "substring", "(I)Ljava/lang/String;");
+    emit_string_length(asm, RDX, RDI);
+    let substring = emit_method(asm, abi, STRING_CLASS, "substring", "(II)Ljava/lang/String;");
+    asm.emit_movsxd(RSI, ESI);
+    asm.emit_movsxd(RDX, EDX);
+    emit_string_length(asm, R8, RDI);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::S, &format!("{}.out_of_range", substring));
+    asm.emit_cmp(RSI, RDX);
+    asm.emit_jcc(Condition::G, &format!("{}.out_of_range", substring));
+    asm.emit_cmp(RDX, R8);
+    asm.emit_jcc(Condition::G, &format!("{}.out_of_range", substring));
+    // The whole string is the string itself
+    asm.emit_mov(RAX, RDI);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::Ne, &format!("{}.copy", substring));
+    asm.emit_cmp(RDX, R8);
+    asm.emit_jcc(Condition::Ne, &format!("{}.copy", substring));
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.copy", substring));
+    emit_enter(asm, 3);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(slot(1), RSI);
+    asm.emit_mov(slot(2), RDX);
+    asm.emit_sub(RDX, RSI);
+    asm.emit_movzx(ESI, byte(RDI + STRING_CODER_OFFSET));
+    asm.emit_mov(RDI, RDX);
+    emit_call_mapped(asm, stack_maps, "runtime$new_string", &[0]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(0));
+    asm.emit_mov(RDX, slot(1));
+    asm.emit_mov(R8, slot(2));
+    asm.emit_sub(R8, RDX);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_call("runtime$copy_chars");
+    asm.emit_mov(RAX, RDI);
+    asm.emit_leave();
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.out_of_range", substring));
+    asm.emit_mov(RDI, RSI);
+    asm.emit_mov(RSI, RDX);
+    asm.emit_mov(RDX, R8);
+    asm.emit_jmp("runtime$throw_begin_end");
+
+    // Characters outside the Basic Multilingual Plane are never found
+    let index_of_char = emit_method(asm, abi, STRING_CLASS, "indexOf", "(I)I");
+    emit_string_length(asm, R8, RDI);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&format!("{}.next", index_of_char));
+    asm.emit_cmp(RDX, R8);
+    asm.emit_jcc(Condition::Ae, &format!("{}.missing", index_of_char));
+    emit_load_char(asm, RAX, RDI, RDX, &index_of_char);
+    asm.emit_cmp(EAX, ESI);
+    asm.emit_jcc(Condition::E, &format!("{}.found", index_of_char));
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(format!("{}.next", index_of_char));
+    asm.emit_label(&format!("{}.found", index_of_char));
+    asm.emit_mov(RAX, RDX);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.missing", index_of_char));
+    asm.emit_mov(EAX, -1);
+    asm.emit_ret();
+
+    // r9 walks the indexes the other string fits at
+    let index_of = emit_method(asm, abi, STRING_CLASS, "indexOf", "(Ljava/lang/String;)I");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_label("runtime$index_of");
+    emit_string_length(asm, R8, RDI);
+    emit_string_length(asm, RDX, RSI);
+    asm.emit_sub(R8, RDX);
+    asm.emit_xor(R9D, R9D);
+    asm.emit_label(&format!("{}.next", index_of));
+    asm.emit_cmp(R9, R8);
+    asm.emit_jcc(Condition::G, &format!("{}.missing", index_of));
+    asm.emit_mov(RCX, R9);
+    asm.emit_call("runtime$region_matches");
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.found", index_of));
+    asm.emit_add(R9, 1);
+    asm.emit_jmp(format!("{}.next", index_of));
+    asm.emit_label(&format!("{}.found", index_of));
+    asm.emit_mov(RAX, R9);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.missing", index_of));
+    asm.emit_mov(EAX, -1);
+    asm.emit_ret();
+
+    // Only strings are taken for a CharSequence
+    emit_method(asm, abi, STRING_CLASS, "contains", "(Ljava/lang/CharSequence;)Z");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_call("runtime$index_of");
+    asm.emit_xor(ECX, ECX);
+    asm.emit_test(EAX, EAX);
+    asm.emit_setcc(Condition::Ns, CL);
+    asm.emit_mov(EAX, ECX);
+    asm.emit_ret();
+
+    for (name, from_end) in [("startsWith", false), ("endsWith", true)] {
+        let symbol = emit_method(asm, abi, STRING_CLASS, name, "(Ljava/lang/String;)Z");
+        asm.emit_test(RSI, RSI);
+        asm.emit_jcc(Condition::E, &null_pointer);
+        emit_string_length(asm, R8, RDI);
+        emit_string_length(asm, RDX, RSI);
+        asm.emit_xor(EAX, EAX);
+        asm.emit_cmp(RDX, R8);
+        asm.emit_jcc(Condition::A, &format!("{}.longer", symbol));
+        match from_end {
+            true => {
+                asm.emit_mov(RCX, R8);
+                asm.emit_sub(RCX, RDX);
+            },
+            false => asm.emit_xor(ECX, ECX),
+        }
+        asm.emit_jmp("runtime$region_matches");
+        asm.emit_label(&format!("{}.longer", symbol));
+        asm.emit_ret();
+    }
+
+    let to_char_array = emit_method(asm, abi, STRING_CLASS, "toCharArray", "()[C");
+    emit_enter(asm, 1);
+    asm.emit_mov(slot(0), RDI);
+    emit_string_length(asm, RSI, RDI);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class("[C")));
+    emit_call_mapped(asm, stack_maps, "runtime$allocate_array", &[0]);
+    asm.emit_mov(RDI, slot(0));
+    emit_string_length(asm, R8, RDI);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&format!("{}.next", to_char_array));
+    asm.emit_cmp(RDX, R8);
+    asm.emit_jcc(Condition::Ae, &format!("{}.done", to_char_array));
+    emit_load_char(asm, RCX, RDI, RDX, &to_char_array);
+    asm.emit_mov(word(RAX + RDX * 2 + ARRAY_DATA_OFFSET), CX);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(format!("{}.next", to_char_array));
+    asm.emit_label(&format!("{}.done", to_char_array));
+    asm.emit_leave();
+    asm.emit_ret();
+
+    // The numbers are put together in the message and copied from there
+    emit_method(asm, abi, STRING_CLASS, "valueOf", "(I)Ljava/lang/String;");
+    emit_method(asm, abi, INTEGER_CLASS, "toString", "(I)Ljava/lang/String;");
+    asm.emit_movsxd(RDI, EDI);
+    emit_method(asm, abi, STRING_CLASS, "valueOf", "(J)Ljava/lang/String;");
+    emit_method(asm, abi, LONG_CLASS, "toString", "(J)Ljava/lang/String;");
+    emit_clear_message(asm);
+    asm.emit_mov(RSI, RDI);
+    asm.emit_call("runtime$append_decimal");
+    emit_tail_allocate(asm, "runtime$string_from_message");
+
+    for (descriptor, append) in [("(D)Ljava/lang/String;", "runtime$append_double"), ("(F)Ljava/lang/String;", "runtime$append_float")] {
+        emit_method(asm, abi, STRING_CLASS, "valueOf", descriptor);
+        emit_clear_message(asm);
+        asm.emit_call(append);
+        emit_tail_allocate(asm, "runtime$string_from_message");
+    }
+
+    let value_of_boolean = emit_method(asm, abi, STRING_CLASS, "valueOf", "(Z)Ljava/lang/String;");
+    asm.emit_test(EDI, EDI);
+    asm.emit_jcc(Condition::E, &format!("{}.false", value_of_boolean));
+    asm.emit_mov(RSI, TRUE_TEXT.0);
+    asm.emit_mov(RDX, TRUE_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$string_from_bytes");
+    asm.emit_label(&format!("{}.false", value_of_boolean));
+    asm.emit_mov(RSI, FALSE_TEXT.0);
+    asm.emit_mov(RDX, FALSE_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$string_from_bytes");
+
+    // A string of one character, Latin-1 if it can be
+    let value_of_char = emit_method(asm, abi, STRING_CLASS, "valueOf", "(C)Ljava/lang/String;");
+    emit_method(asm, abi, CHARACTER_CLASS, "toString", "(C)Ljava/lang/String;");
+    asm.emit_movzx(EAX, DI);
+    asm.emit_push(RAX);
+    asm.emit_mov(EDI, 1);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_cmp(EAX, 255);
+    asm.emit_setcc(Condition::A, SIL);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$new_string");
+    asm.emit_pop(RDX);
+    asm.emit_mov(R8, qword(RAX + STRING_VALUE_OFFSET));
+    asm.emit_cmp(byte(RAX + STRING_CODER_OFFSET), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.utf16", value_of_char));
+    asm.emit_mov(byte(R8 + ARRAY_DATA_OFFSET), DL);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.utf16", value_of_char));
+    asm.emit_mov(word(R8 + ARRAY_DATA_OFFSET), DX);
+    asm.emit_ret();
+
+    // Calls toString, unless the object is null
+    let value_of_object = emit_method(asm, abi, STRING_CLASS, "valueOf", "(Ljava/lang/Object;)Ljava/lang/String;");
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &format!("{}.null", value_of_object));
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_jmp(virtual_method(OBJECT_CLASS, "toString", "()Ljava/lang/String;"));
+    asm.emit_label(&format!("{}.null", value_of_object));
+    asm.emit_mov(RSI, NULL_TEXT.0);
+    asm.emit_mov(RDX, NULL_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$string_from_bytes");
+}
+
+/// A builder's routines take the return address into compiled code in rcx, like the
+/// allocator. `runtime$builder_init` gives the builder in rdi room for rsi characters
+/// and `runtime$builder_ensure` for at least rsi, doubling it as the JDK does.
+/// `runtime$builder_append_bytes` appends the rdx Latin-1 bytes at rsi, which must be
+/// outside the heap. They return the builder in rax.
+// The pool starts out with the literals, which are never moved, so a string equal to a
+// literal interns to the object ldc loads. It is kept at most half full.
+fn emit_intern(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    let string_array = abi.symbol(&mangle_class("[Ljava/lang/String;"));
+    let literals = abi.symbol("runtime$string_literals");
+
+    // The slot for the string in rdi, holding an equal string or 0 where it goes, in rax.
+    // Keeps rdi, r13 and r14.
+    asm.emit_label("runtime$intern_slot");
+    asm.emit_call(method_symbol(abi, STRING_CLASS, "hashCode", "()I"));
+    asm.emit_mov(R9, qword(Memory::symbol("runtime$intern_table")));
+    asm.emit_mov(R12, qword(R9 + ARRAY_LENGTH_OFFSET));
+    asm.emit_sub(R12, 1);
+    asm.emit_mov(EBX, EAX);
+    asm.emit_label("runtime$intern_slot.probe");
+    asm.emit_and(RBX, R12);
+    asm.emit_mov(R15, RBX);
+    asm.emit_shl(R15, 3);
+    asm.emit_add(R15, R9);
+    asm.emit_add(R15, ARRAY_DATA_OFFSET);
+    asm.emit_mov(RSI, qword(R15));
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, "runtime$intern_slot.found");
+    asm.emit_call(method_symbol(abi, STRING_CLASS, "equals", "(Ljava/lang/Object;)Z"));
+    asm.emit_test(EAX, EAX);
+    asm.emit_jcc(Condition::Ne, "runtime$intern_slot.found");
+    asm.emit_add(RBX, 1);
+    asm.emit_jmp("runtime$intern_slot.probe");
+    asm.emit_label("runtime$intern_slot.found");
+    asm.emit_mov(RAX, R15);
+    asm.emit_ret();
+
+    // Slot 0 is the string and slot 1 the next literal to add
+    let intern = emit_method(asm, abi, STRING_CLASS, "intern", "()Ljava/lang/String;");
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_cmp(qword(Memory::symbol("runtime$intern_table")), 0);
+    asm.emit_jcc(Condition::Ne, &format!("{}.lookup", intern));
+
+    // The first table has room for four times the literals
+    asm.emit_mov(RAX, &literals);
+    asm.emit_mov(RDX, qword(RAX));
+    asm.emit_shl(RDX, 2);
+    asm.emit_mov(ESI, 16);
+    asm.emit_label(&format!("{}.size", intern));
+    asm.emit_cmp(RSI, RDX);
+    asm.emit_jcc(Condition::Ae, &format!("{}.sized", intern));
+    asm.emit_shl(RSI, 1);
+    asm.emit_jmp(format!("{}.size", intern));
+    asm.emit_label(&format!("{}.sized", intern));
+    asm.emit_mov(RDI, &string_array);
+    emit_call_mapped(asm, stack_maps, "runtime$allocate_array", &[0]);
+    asm.emit_mov(qword(Memory::symbol("runtime$intern_table")), RAX);
+    asm.emit_mov(slot(1), 0);
+    asm.emit_label(&format!("{}.literal", intern));
+    asm.emit_mov(RAX, &literals);
+    asm.emit_mov(RCX, slot(1));
+    asm.emit_cmp(RCX, qword(RAX));
+    asm.emit_jcc(Condition::Ae, &format!("{}.lookup", intern));
+    asm.emit_mov(RDI, qword(RAX + RCX * 8 + 8));
+    asm.emit_call("runtime$intern_slot");
+    asm.emit_mov(qword(RAX), RDI);
+    asm.emit_add(qword(Memory::symbol("runtime$intern_count")), 1);
+    asm.emit_add(slot(1), 1);
+    asm.emit_jmp(format!("{}.literal", intern));
+
+    asm.emit_label(&format!("{}.lookup", intern));
+    asm.emit_mov(RDI, slot(0));
+    asm.emit_call("runtime$intern_slot");
+    asm.emit_cmp(qword(RAX), 0);
+    asm.emit_jcc(Condition::E, &format!("{}.absent", intern));
+    asm.emit_mov(RAX, qword(RAX));
+    asm.emit_leave();
+    asm.emit_ret();
+
+    // Added to the table, unless that makes it over half full and it doubles first
+    asm.emit_label(&format!("{}.absent", intern));
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$intern_count")));
+    asm.emit_add(RDX, 1);
+    asm.emit_shl(RDX, 1);
+    asm.emit_mov(R9, qword(Memory::symbol("runtime$intern_table")));
+    asm.emit_cmp(RDX, qword(R9 + ARRAY_LENGTH_OFFSET));
+    asm.emit_jcc(Condition::Be, &format!("{}.add", intern));
+    asm.emit_mov(RSI, qword(R9 + ARRAY_LENGTH_OFFSET));
+    asm.emit_shl(RSI, 1);
+    asm.emit_mov(RDI, &string_array);
+    emit_call_mapped(asm, stack_maps, "runtime$allocate_array", &[0]);
+    // r13 is the old table and r14 walks it
+    asm.emit_mov(R13, qword(Memory::symbol("runtime$intern_table")));
+    asm.emit_mov(qword(Memory::symbol("runtime$intern_table")), RAX);
+    asm.emit_xor(R14D, R14D);
+    asm.emit_label(&format!("{}.rehash", intern));
+    asm.emit_cmp(R14, qword(R13 + ARRAY_LENGTH_OFFSET));
+    asm.emit_jcc(Condition::Ae, &format!("{}.lookup", intern));
+    asm.emit_mov(RDI, qword(R13 + R14 * 8 + ARRAY_DATA_OFFSET));
+    asm.emit_add(R14, 1);
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &format!("{}.rehash", intern));
+    asm.emit_call("runtime$intern_slot");
+    asm.emit_mov(qword(RAX), RDI);
+    asm.emit_jmp(format!("{}.rehash", intern));
+
+    asm.emit_label(&format!("{}.add", intern));
+    asm.emit_mov(RDI, slot(0));
+    asm.emit_mov(qword(RAX), RDI);
+    asm.emit_add(qword(Memory::symbol("runtime$intern_count")), 1);
+    asm.emit_mov(RAX, RDI);
+    asm.emit_leave();
+    asm.emit_ret();
+}
+
+fn emit_string_builder(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    let byte_array = abi.symbol(&mangle_class("[B"));
+    let value = qword(RDI + STRING_BUILDER_VALUE_OFFSET);
+    let count = dword(RDI + STRING_BUILDER_COUNT_OFFSET);
+
+    asm.emit_label("runtime$builder_init");
+    emit_push_handle(asm, RDI);
+    asm.emit_shl(RSI, 1);
+    asm.emit_mov(RDI, &byte_array);
+    asm.emit_call("runtime$allocate_array");
+    emit_top_handle(asm, RDI);
+    emit_pop_handle(asm);
+    asm.emit_mov(value.clone(), RAX);
+    asm.emit_mov(byte(RDI + STRING_BUILDER_CODER_OFFSET), UTF16);
+    asm.emit_mov(RAX, RDI);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$builder_ensure");
+    asm.emit_mov(RAX, RDI);
+    asm.emit_mov(RDX, value.clone());
+    asm.emit_mov(RDX, qword(RDX + ARRAY_LENGTH_OFFSET));
+    asm.emit_shr(RDX, 1);
+    asm.emit_cmp(RSI, RDX);
+    asm.emit_jcc(Condition::G, "runtime$builder_ensure.grow");
+    asm.emit_ret();
+    asm.emit_label("runtime$builder_ensure.grow");
+    asm.emit_shl(RDX, 1);
+    asm.emit_add(RDX, 2);
+    asm.emit_cmp(RDX, RSI);
+    asm.emit_jcc(Condition::Ge, "runtime$builder_ensure.allocate");
+    asm.emit_mov(RDX, RSI);
+    asm.emit_label("runtime$builder_ensure.allocate");
+    emit_push_handle(asm, RDI);
+    asm.emit_mov(RSI, RDX);
+    asm.emit_shl(RSI, 1);
+    asm.emit_mov(RDI, &byte_array);
+    asm.emit_call("runtime$allocate_array");
+    emit_top_handle(asm, RDI);
+    emit_pop_handle(asm);
+    asm.emit_mov(RSI, value.clone());
+    asm.emit_movsxd(RDX, count.clone());
+    asm.emit_shl(RDX, 1);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label("runtime$builder_ensure.copy");
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$builder_ensure.copied");
+    asm.emit_mov(R8B, byte(RSI + RCX + ARRAY_DATA_OFFSET));
+    asm.emit_mov(byte(RAX + RCX + ARRAY_DATA_OFFSET), R8B);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp("runtime$builder_ensure.copy");
+    asm.emit_label("runtime$builder_ensure.copied");
+    asm.emit_mov(value.clone(), RAX);
+    asm.emit_mov(RAX, RDI);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$builder_append_bytes");
+    asm.emit_push(RSI);
+    asm.emit_push(RDX);
+    asm.emit_movsxd(RSI, count.clone());
+    asm.emit_add(RSI, RDX);
+    asm.emit_call("runtime$builder_ensure");
+    asm.emit_pop(RDX);
+    asm.emit_pop(RSI);
+    asm.emit_movsxd(RCX, dword(RAX + STRING_BUILDER_COUNT_OFFSET));
+    asm.emit_mov(R8, qword(RAX + STRING_BUILDER_VALUE_OFFSET));
+    asm.emit_xor(R9D, R9D);
+    asm.emit_label("runtime$builder_append_bytes.next");
+    asm.emit_cmp(R9, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$builder_append_bytes.done");
+    asm.emit_movzx(R10D, byte(RSI + R9));
+    asm.emit_mov(word(R8 + RCX * 2 + ARRAY_DATA_OFFSET), R10W);
+    asm.emit_add(RCX, 1);
+    asm.emit_add(R9, 1);
+    asm.emit_jmp("runtime$builder_append_bytes.next");
+    asm.emit_label("runtime$builder_append_bytes.done");
+    asm.emit_mov(dword(RAX + STRING_BUILDER_COUNT_OFFSET), ECX);
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "<init>", "()V");
+    asm.emit_mov(ESI, 16);
+    emit_tail_allocate(asm, "runtime$builder_init");
+
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "<init>", "(I)V");
+    asm.emit_movsxd(RSI, ESI);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::S, "runtime$builder_init.negative");
+    emit_tail_allocate(asm, "runtime$builder_init");
+    asm.emit_label("runtime$builder_init.negative");
+    asm.emit_mov(RDI, RSI);
+    asm.emit_jmp("runtime$throw_negative_array_size");
+
+    let append_string = method_symbol(abi, STRING_BUILDER_CLASS, "append", "(Ljava/lang/String;)Ljava/lang/StringBuilder;");
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "<init>", "(Ljava/lang/String;)V");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &abi.symbol("runtime$throw_null_pointer_exception"));
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(slot(1), RSI);
+    emit_string_length(asm, RAX, RSI);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_add(RSI, 16);
+    emit_call_mapped(asm, stack_maps, "runtime$builder_init", &[0, 1]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(1));
+    asm.emit_leave();
+    asm.emit_jmp(&append_string);
+
+    // null is appended as "null"
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(Ljava/lang/String;)Ljava/lang/StringBuilder;");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::Ne, &format!("{}.string", append_string));
+    asm.emit_label("runtime$builder_append_null");
+    asm.emit_mov(RSI, NULL_TEXT.0);
+    asm.emit_mov(RDX, NULL_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$builder_append_bytes");
+    asm.emit_label(&format!("{}.string", append_string));
+    emit_enter(asm, 2);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(slot(1), RSI);
+    emit_string_length(asm, RAX, RSI);
+    asm.emit_movsxd(RSI, count.clone());
+    asm.emit_add(RSI, RAX);
+    emit_call_mapped(asm, stack_maps, "runtime$builder_ensure", &[0, 1]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(1));
+    emit_string_length(asm, R8, RSI);
+    asm.emit_movsxd(RCX, count.clone());
+    asm.emit_xor(EDX, EDX);
+    asm.emit_call("runtime$copy_chars");
+    asm.emit_mov(count.clone(), ECX);
+    asm.emit_mov(RAX, RDI);
+    asm.emit_leave();
+    asm.emit_ret();
+
+    // Numbers are put together in the message first
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(I)Ljava/lang/StringBuilder;");
+    asm.emit_movsxd(RSI, ESI);
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(J)Ljava/lang/StringBuilder;");
+    emit_clear_message(asm);
+    asm.emit_call("runtime$append_decimal");
+    asm.emit_label("runtime$builder_append_message");
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$message_length")));
+    emit_tail_allocate(asm, "runtime$builder_append_bytes");
+
+    for (descriptor, append) in [
+        ("(D)Ljava/lang/StringBuilder;", "runtime$append_double"),
+        ("(F)Ljava/lang/StringBuilder;", "runtime$append_float"),
+    ] {
+        emit_method(asm, abi, STRING_BUILDER_CLASS, "append", descriptor);
+        emit_clear_message(asm);
+        asm.emit_push(RDI);
+        asm.emit_call(append);
+        asm.emit_pop(RDI);
+        asm.emit_jmp("runtime$builder_append_message");
+    }
+
+    let append_boolean = emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(Z)Ljava/lang/StringBuilder;");
+    asm.emit_test(ESI, ESI);
+    asm.emit_jcc(Condition::E, &format!("{}.false", append_boolean));
+    asm.emit_mov(RSI, TRUE_TEXT.0);
+    asm.emit_mov(RDX, TRUE_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$builder_append_bytes");
+    asm.emit_label(&format!("{}.false", append_boolean));
+    asm.emit_mov(RSI, FALSE_TEXT.0);
+    asm.emit_mov(RDX, FALSE_TEXT.1.len());
+    emit_tail_allocate(asm, "runtime$builder_append_bytes");
+
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(C)Ljava/lang/StringBuilder;");
+    asm.emit_push(RSI);
+    asm.emit_movsxd(RSI, count.clone());
+    asm.emit_add(RSI, 1);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$builder_ensure");
+    asm.emit_pop(RSI);
+    asm.emit_movsxd(RCX, dword(RAX + STRING_BUILDER_COUNT_OFFSET));
+    asm.emit_mov(RDX, qword(RAX + STRING_BUILDER_VALUE_OFFSET));
+    asm.emit_mov(word(RDX + RCX * 2 + ARRAY_DATA_OFFSET), SI);
+    asm.emit_add(dword(RAX + STRING_BUILDER_COUNT_OFFSET), 1);
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "append", "(Ljava/lang/Object;)Ljava/lang/StringBuilder;");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, "runtime$builder_append_null");
+    emit_enter(asm, 1);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_mov(RDI, RSI);
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    emit_call_mapped(asm, stack_maps, virtual_method(OBJECT_CLASS, "toString", "()Ljava/lang/String;"), &[0]);
+    asm.emit_mov(RDI, slot(0));
+    asm.emit_mov(RSI, RAX);
+    asm.emit_leave();
+    asm.emit_jmp(&append_string);
+
+    // The string is Latin-1 if every character fits
+    let to_string = emit_method(asm, abi, STRING_BUILDER_CLASS, "toString", "()Ljava/lang/String;");
+    emit_enter(asm, 1);
+    asm.emit_mov(slot(0), RDI);
+    asm.emit_movsxd(R8, count.clone());
+    asm.emit_mov(RDX, value.clone());
+    asm.emit_xor(ECX, ECX);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_label(&format!("{}.next", to_string));
+    asm.emit_cmp(RCX, R8);
+    asm.emit_jcc(Condition::Ae, &format!("{}.allocate", to_string));
+    asm.emit_movzx(EAX, word(RDX + RCX * 2 + ARRAY_DATA_OFFSET));
+    asm.emit_add(RCX, 1);
+    asm.emit_cmp(EAX, 255);
+    asm.emit_jcc(Condition::Be, &format!("{}.next", to_string));
+    asm.emit_mov(ESI, UTF16);
+    asm.emit_label(&format!("{}.allocate", to_string));
+    asm.emit_mov(RDI, R8);
+    emit_call_mapped(asm, stack_maps, "runtime$new_string", &[0]);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, slot(0));
+    asm.emit_movsxd(R8, dword(RSI + STRING_BUILDER_COUNT_OFFSET));
+    asm.emit_xor(EDX, EDX);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_call("runtime$copy_chars");
+    asm.emit_mov(RAX, RDI);
+    asm.emit_leave();
+    asm.emit_ret();
+
+    emit_method(asm, abi, STRING_BUILDER_CLASS, "length", "()I");
+    asm.emit_mov(EAX, count.clone());
+    asm.emit_ret();
+
+    // rsi holds the index, checked against the count in rax
+    for (name, descriptor) in [("charAt", "(I)C"), ("setCharAt", "(IC)V"), ("deleteCharAt", "(I)Ljava/lang/StringBuilder;")] {
+        let symbol = emit_method(asm, abi, STRING_BUILDER_CLASS, name, descriptor);
+        asm.emit_movsxd(RSI, ESI);
+        asm.emit_movsxd(RAX, count.clone());
+        asm.emit_cmp(RSI, RAX);
+        asm.emit_jcc(Condition::Ae, "runtime$builder_index.out_of_range");
+        asm.emit_mov(R8, value.clone());
+        match name {
+            "charAt" => asm.emit_movzx(EAX, word(R8 + RSI * 2 + ARRAY_DATA_OFFSET)),
+            "setCharAt" => asm.emit_mov(word(R8 + RSI * 2 + ARRAY_DATA_OFFSET), DX),
+            _ => {
+                asm.emit_sub(RAX, 1);
+                asm.emit_label(&format!("{}.next", symbol));
+                asm.emit_cmp(RSI, RAX);
+                asm.emit_jcc(Condition::Ae, &format!("{}.done", symbol));
+                asm.emit_movzx(ECX, word(R8 + RSI * 2 + (ARRAY_DATA_OFFSET + 2)));
+                asm.emit_mov(word(R8 + RSI * 2 + ARRAY_DATA_OFFSET), CX);
+                asm.emit_add(RSI, 1);
+                asm.emit_jmp(format!("{}.next", symbol));
+                asm.emit_label(&format!("{}.done", symbol));
+                asm.emit_mov(count.clone(), EAX);
+                asm.emit_mov(RAX, RDI);
+            },
+        }
+        asm.emit_ret();
+    }
+    asm.emit_label("runtime$builder_index.out_of_range");
+    asm.emit_mov(RDI, RSI);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_jmp("runtime$throw_index_length");
+
+    // The characters past the count are zero
+    let set_length = emit_method(asm, abi, STRING_BUILDER_CLASS, "setLength", "(I)V");
+    asm.emit_movsxd(RSI, ESI);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::S, &format!("{}.negative", set_length));
+    asm.emit_push(RSI);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$builder_ensure");
+    asm.emit_pop(RSI);
+    asm.emit_movsxd(RCX, dword(RAX + STRING_BUILDER_COUNT_OFFSET));
+    asm.emit_mov(R8, qword(RAX + STRING_BUILDER_VALUE_OFFSET));
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&format!("{}.next", set_length));
+    asm.emit_cmp(RCX, RSI);
+    asm.emit_jcc(Condition::Ge, &format!("{}.done", set_length));
+    asm.emit_mov(word(R8 + RCX * 2 + ARRAY_DATA_OFFSET), DX);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp(format!("{}.next", set_length));
+    asm.emit_label(&format!("{}.done", set_length));
+    asm.emit_mov(dword(RAX + STRING_BUILDER_COUNT_OFFSET), ESI);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.negative", set_length));
+    asm.emit_mov(RDI, RSI);
+    asm.emit_jmp("runtime$throw_string_index");
+
+    // Surrogate pairs end up backwards and are turned around again
+    let reverse = emit_method(asm, abi, STRING_BUILDER_CLASS, "reverse", "()Ljava/lang/StringBuilder;");
+    asm.emit_movsxd(RDX, count.clone());
+    asm.emit_mov(R8, value.clone());
+    asm.emit_xor(ECX, ECX);
+    asm.emit_mov(RSI, RDX);
+    asm.emit_sub(RSI, 1);
+    asm.emit_label(&format!("{}.swap", reverse));
+    asm.emit_cmp(RCX, RSI);
+    asm.emit_jcc(Condition::Ge, &format!("{}.pairs", reverse));
+    asm.emit_movzx(EAX, word(R8 + RCX * 2 + ARRAY_DATA_OFFSET));
+    asm.emit_movzx(R9D, word(R8 + RSI * 2 + ARRAY_DATA_OFFSET));
+    asm.emit_mov(word(R8 + RCX * 2 + ARRAY_DATA_OFFSET), R9W);
+    asm.emit_mov(word(R8 + RSI * 2 + ARRAY_DATA_OFFSET), AX);
+    asm.emit_add(RCX, 1);
+    asm.emit_sub(RSI, 1);
+    asm.emit_jmp(format!("{}.swap", reverse));
+    asm.emit_label(&format!("{}.pairs", reverse));
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label(&format!("{}.pair", reverse));
+    asm.emit_mov(RAX, RCX);
+    asm.emit_add(RAX, 1);
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ge, &format!("{}.done", reverse));
+    asm.emit_movzx(EAX, word(R8 + RCX * 2 + ARRAY_DATA_OFFSET));
+    asm.emit_movzx(R9D, word(R8 + RCX * 2 + (ARRAY_DATA_OFFSET + 2)));
+    asm.emit_add(RCX, 1);
+    asm.emit_cmp(EAX, 0xdc00);
+    asm.emit_jcc(Condition::B, &format!("{}.pair", reverse));
+    asm.emit_cmp(EAX, 0xdfff);
+    asm.emit_jcc(Condition::A, &format!("{}.pair", reverse));
+    asm.emit_cmp(R9D, 0xd800);
+    asm.emit_jcc(Condition::B, &format!("{}.pair", reverse));
+    asm.emit_cmp(R9D, 0xdbff);
+    asm.emit_jcc(Condition::A, &format!("{}.pair", reverse));
+    asm.emit_mov(word(R8 + RCX * 2 + (ARRAY_DATA_OFFSET - 2)), R9W);
+    asm.emit_mov(word(R8 + RCX * 2 + ARRAY_DATA_OFFSET), AX);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp(format!("{}.pair", reverse));
+    asm.emit_label(&format!("{}.done", reverse));
+    asm.emit_mov(RAX, RDI);
+    asm.emit_ret();
+}
+
+/// Integer and Long, and `runtime$parse_integer`, which parses the string in rdi like
+/// Integer.parseInt does, for numbers down to the minimum in rsi.
+fn emit_boxes(asm: &mut Assembly, abi: &dyn OsAbi) {
+    for (class_name, wide) in [(INTEGER_CLASS, false), (LONG_CLASS, true)] {
+        let (primitive, value, minimum) = match wide {
+            true => ("J", RDI, i64::MIN),
+            false => ("I", EDI, i64::from(i32::MIN)),
+        };
+        let field = if wide { qword(RDI + BOX_VALUE_OFFSET) } else { dword(RDI + BOX_VALUE_OFFSET) };
+        let cache = if wide { "runtime$long_cache" } else { "runtime$integer_cache" };
+
+        // Small numbers are boxed once and for all
+        let value_of = emit_method(asm, abi, class_name, "valueOf", &format!("({})L{};", primitive, class_name));
+        if !wide {
+            asm.emit_movsxd(RDI, EDI);
+        }
+        asm.emit_mov(RAX, RDI);
+        asm.emit_cmp(RAX, *INTEGER_CACHE.start());
+        asm.emit_jcc(Condition::L, &format!("{}.allocate", value_of));
+        asm.emit_cmp(RAX, *INTEGER_CACHE.end());
+        asm.emit_jcc(Condition::G, &format!("{}.allocate", value_of));
+        emit_cache_entry(asm, cache, -INTEGER_CACHE.start());
+        asm.emit_ret();
+        asm.emit_label(&format!("{}.allocate", value_of));
+        asm.emit_push(RDI);
+        asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+        asm.emit_mov(RSI, BOX_SIZE);
+        asm.emit_mov(RCX, qword(RSP + 8));
+        asm.emit_call("runtime$allocate");
+        asm.emit_pop(RDI);
+        asm.emit_mov(qword(RAX + BOX_VALUE_OFFSET), RDI);
+        asm.emit_ret();
+
+        emit_method(asm, abi, class_name, "intValue", "()I");
+        asm.emit_mov(EAX, dword(RDI + BOX_VALUE_OFFSET));
+        asm.emit_ret();
+
+        emit_method(asm, abi, class_name, "longValue", "()J");
+        match wide {
+            true => asm.emit_mov(RAX, field.clone()),
+            false => asm.emit_movsxd(RAX, field.clone()),
+        }
+        asm.emit_ret();
+
+        let result = if wide { RAX } else { EAX };
+        emit_method(asm, abi, class_name, "doubleValue", "()D");
+        asm.emit_mov(result, field.clone());
+        asm.emit_sse(SseOp::Cvtsi2sd, XMM0, result);
+        asm.emit_ret();
+
+        // A long's hash is its halves xored
+        emit_method(asm, abi, class_name, "hashCode", "()I");
+        asm.emit_mov(result, field.clone());
+        if wide {
+            asm.emit_mov(RCX, RAX);
+            asm.emit_shr(RCX, 32);
+            asm.emit_xor(EAX, ECX);
+        }
+        asm.emit_ret();
+
+        emit_box_equals(asm, abi, class_name, result);
+
+        emit_method(asm, abi, class_name, "toString", "()Ljava/lang/String;");
+        asm.emit_mov(if wide { RDI } else { EDI }, field);
+        asm.emit_jmp(method_symbol(abi, class_name, "toString", &format!("({})Ljava/lang/String;", primitive)));
+
+        // Unsigned, without leading zeros
+        emit_method(asm, abi, class_name, "toHexString", &format!("({})Ljava/lang/String;", primitive));
+        emit_clear_message(asm);
+        asm.emit_mov(if wide { RSI } else { ESI }, value);
+        asm.emit_call("runtime$append_hex");
+        emit_tail_allocate(asm, "runtime$string_from_message");
+
+        let parse = if wide { "parseLong" } else { "parseInt" };
+        emit_method(asm, abi, class_name, parse, &format!("(Ljava/lang/String;){}", primitive));
+        asm.emit_mov(RSI, minimum);
+        asm.emit_jmp("runtime$parse_integer");
+
+        let (lhs, rhs) = if wide { (RDI, RSI) } else { (EDI, ESI) };
+        emit_method(asm, abi, class_name, "compare", &format!("({0}{0})I", primitive));
+        asm.emit_xor(EAX, EAX);
+        asm.emit_xor(ECX, ECX);
+        asm.emit_cmp(lhs, rhs);
+        asm.emit_setcc(Condition::G, AL);
+        asm.emit_setcc(Condition::L, CL);
+        asm.emit_sub(EAX, ECX);
+        asm.emit_ret();
+
+        emit_method(asm, abi, class_name, "sum", &format!("({0}{0}){0}", primitive));
+        asm.emit_mov(result, lhs);
+        asm.emit_add(result, rhs);
+        asm.emit_ret();
+
+        // Math has the same
+        for (name, condition) in [("max", Condition::Ge), ("min", Condition::Le)] {
+            let descriptor = format!("({0}{0}){0}", primitive);
+            let symbol = emit_method(asm, abi, class_name, name, &descriptor);
+            emit_method(asm, abi, MATH_CLASS, name, &descriptor);
+            asm.emit_mov(result, lhs);
+            asm.emit_cmp(lhs, rhs);
+            asm.emit_jcc(condition, &format!("{}.done", symbol));
+            asm.emit_mov(result, rhs);
+            asm.emit_label(&format!("{}.done", symbol));
+            asm.emit_ret();
+        }
+    }
+
+    // The JDK's algorithm, accumulating the number negatively so that the minimum fits.
+    // r8 holds the length, r9 the index, r10 the number, r11 the limit and rbx whether
+    // there was a minus sign.
+    asm.emit_label("runtime$parse_integer");
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, "runtime$parse_integer.null");
+    emit_string_length(asm, R8, RDI);
+    asm.emit_test(R8, R8);
+    asm.emit_jcc(Condition::E, "runtime$parse_integer.invalid");
+    asm.emit_mov(R11, RSI);
+    asm.emit_add(R11, 1);
+    asm.emit_xor(EBX, EBX);
+    asm.emit_xor(R9D, R9D);
+    asm.emit_xor(R10D, R10D);
+    emit_load_char(asm, RAX, RDI, R9, "runtime$parse_integer.first");
+    asm.emit_cmp(EAX, 48);
+    asm.emit_jcc(Condition::Ae, "runtime$parse_integer.digits");
+    asm.emit_cmp(EAX, 43);
+    asm.emit_jcc(Condition::E, "runtime$parse_integer.sign");
+    asm.emit_cmp(EAX, 45);
+    asm.emit_jcc(Condition::Ne, "runtime$parse_integer.invalid");
+    asm.emit_mov(EBX, 1);
+    asm.emit_mov(R11, RSI);
+    asm.emit_label("runtime$parse_integer.sign");
+    asm.emit_cmp(R8, 1);
+    asm.emit_jcc(Condition::E, "runtime$parse_integer.invalid");
+    asm.emit_add(R9, 1);
+    // rsi holds the limit divided by ten, the number can't be multiplied below that
+    asm.emit_label("runtime$parse_integer.digits");
+    asm.emit_mov(RAX, R11);
+    asm.emit_cqo();
+    asm.emit_mov(ECX, 10);
+    asm.emit_idiv(RCX);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_label("runtime$parse_integer.next");
+    asm.emit_cmp(R9, R8);
+    asm.emit_jcc(Condition::Ae, "runtime$parse_integer.done");
+    emit_load_char(asm, RAX, RDI, R9, "runtime$parse_integer.digit");
+    asm.emit_add(R9, 1);
+    asm.emit_sub(EAX, 48);
+    asm.emit_cmp(EAX, 9);
+    asm.emit_jcc(Condition::A, "runtime$parse_integer.invalid");
+    asm.emit_cmp(R10, RSI);
+    asm.emit_jcc(Condition::L, "runtime$parse_integer.invalid");
+    asm.emit_mov(RCX, R10);
+    asm.emit_shl(R10, 3);
+    asm.emit_add(R10, RCX);
+    asm.emit_add(R10, RCX);
+    asm.emit_mov(RCX, R11);
+    asm.emit_add(RCX, RAX);
+    asm.emit_cmp(R10, RCX);
+    asm.emit_jcc(Condition::L, "runtime$parse_integer.invalid");
+    asm.emit_sub(R10, RAX);
+    asm.emit_jmp("runtime$parse_integer.next");
+    asm.emit_label("runtime$parse_integer.done");
+    asm.emit_mov(RAX, R10);
+    asm.emit_test(EBX, EBX);
+    asm.emit_jcc(Condition::Ne, "runtime$parse_integer.negative");
+    asm.emit_neg(RAX);
+    asm.emit_label("runtime$parse_integer.negative");
+    asm.emit_ret();
+    asm.emit_label("runtime$parse_integer.null");
+    emit_clear_message(asm);
+    emit_text(asm, PARSE_NULL_TEXT);
+    emit_throw_new(asm, abi, NUMBER_FORMAT_EXCEPTION);
+    asm.emit_label("runtime$parse_integer.invalid");
+    emit_clear_message(asm);
+    emit_text(asm, INPUT_STRING_TEXT);
+    asm.emit_mov(RSI, RDI);
+    asm.emit_call("runtime$append_string");
+    emit_text(asm, QUOTE_TEXT);
+    emit_throw_new(asm, abi, NUMBER_FORMAT_EXCEPTION);
+}
+
+// Puts the address of the cached box for the number in rax into rax, `bias` being the
+// number of the first box
+fn emit_cache_entry(asm: &mut Assembly, cache: &str, bias: i64) {
+    if bias != 0 {
+        asm.emit_add(RAX, bias);
+    }
+    // The index times 24, a box's size, as times 16 plus times 8
+    asm.emit_mov(RCX, RAX);
+    asm.emit_shl(RAX, 4);
+    asm.emit_shl(RCX, 3);
+    asm.emit_add(RAX, RCX);
+    asm.emit_mov(RCX, cache);
+    asm.emit_add(RAX, RCX);
+}
+
+// equals of a box, which compares its value as `value`, eax or rax, with the other's
+fn emit_box_equals(asm: &mut Assembly, abi: &dyn OsAbi, class_name: &str, value: Register) {
+    let size = if value == RAX { qword } else { dword };
+    let equals = emit_method(asm, abi, class_name, "equals", "(Ljava/lang/Object;)Z");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &format!("{}.done", equals));
+    asm.emit_mov(RCX, abi.symbol(&mangle_class(class_name)));
+    asm.emit_cmp(qword(RSI + CLASS_POINTER_OFFSET), RCX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.done", equals));
+    asm.emit_mov(value, size(RDI + BOX_VALUE_OFFSET));
+    asm.emit_cmp(value, size(RSI + BOX_VALUE_OFFSET));
+    asm.emit_setcc(Condition::E, CL);
+    asm.emit_movzx(EAX, CL);
+    asm.emit_label(&format!("{}.done", equals));
+    asm.emit_ret();
+}
+
+/// Boolean, whose valueOf returns one of two cached boxes like the JDK's TRUE and FALSE.
+fn emit_boolean(asm: &mut Assembly, abi: &dyn OsAbi) {
+    // Like the JVM, only the lowest bit of a boolean counts
+    emit_method(asm, abi, BOOLEAN_CLASS, "valueOf", "(Z)Ljava/lang/Boolean;");
+    asm.emit_mov(EAX, EDI);
+    asm.emit_and(EAX, 1);
+    emit_cache_entry(asm, "runtime$boolean_cache", 0);
+    asm.emit_ret();
+
+    emit_method(asm, abi, BOOLEAN_CLASS, "booleanValue", "()Z");
+    asm.emit_movzx(EAX, byte(RDI + BOX_VALUE_OFFSET));
+    asm.emit_ret();
+
+    // 1231 for true and 1237 for false
+    emit_method(asm, abi, BOOLEAN_CLASS, "hashCode", "()I");
+    asm.emit_movzx(ECX, byte(RDI + BOX_VALUE_OFFSET));
+    asm.emit_mov(EAX, 1237);
+    asm.emit_shl(ECX, 1);
+    asm.emit_sub(EAX, ECX);
+    asm.emit_shl(ECX, 1);
+    asm.emit_sub(EAX, ECX);
+    asm.emit_ret();
+
+    // The upper bytes of a Boolean are zero
+    emit_box_equals(asm, abi, BOOLEAN_CLASS, EAX);
+
+    emit_method(asm, abi, BOOLEAN_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_movzx(EDI, byte(RDI + BOX_VALUE_OFFSET));
+    emit_method(asm, abi, BOOLEAN_CLASS, "toString", "(Z)Ljava/lang/String;");
+    asm.emit_jmp(method_symbol(abi, STRING_CLASS, "valueOf", "(Z)Ljava/lang/String;"));
+}
+
+/// Character, whose predicates and case mappings look Latin-1 characters up in tables.
+/// The others are taken to be neither letters nor digits, and to have no case.
+fn emit_character(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let value_of = emit_method(asm, abi, CHARACTER_CLASS, "valueOf", "(C)Ljava/lang/Character;");
+    asm.emit_movzx(EDI, DI);
+    asm.emit_mov(EAX, EDI);
+    asm.emit_cmp(EAX, *CHARACTER_CACHE.end());
+    asm.emit_jcc(Condition::A, &format!("{}.allocate", value_of));
+    emit_cache_entry(asm, "runtime$character_cache", 0);
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.allocate", value_of));
+    asm.emit_push(RDI);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(CHARACTER_CLASS)));
+    asm.emit_mov(RSI, BOX_SIZE);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$allocate");
+    asm.emit_pop(RDI);
+    asm.emit_mov(qword(RAX + BOX_VALUE_OFFSET), RDI);
+    asm.emit_ret();
+
+    emit_method(asm, abi, CHARACTER_CLASS, "charValue", "()C");
+    emit_method(asm, abi, CHARACTER_CLASS, "hashCode", "()I");
+    asm.emit_movzx(EAX, word(RDI + BOX_VALUE_OFFSET));
+    asm.emit_ret();
+
+    // The upper bytes of a Character are zero
+    emit_box_equals(asm, abi, CHARACTER_CLASS, EAX);
+
+    emit_method(asm, abi, CHARACTER_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_movzx(EDI, word(RDI + BOX_VALUE_OFFSET));
+    asm.emit_jmp(method_symbol(abi, CHARACTER_CLASS, "toString", "(C)Ljava/lang/String;"));
+
+    for (name, flags) in [
+        ("isDigit", DIGIT),
+        ("isLetter", LETTER),
+        ("isLetterOrDigit", LETTER | DIGIT),
+        ("isWhitespace", WHITESPACE),
+        ("isUpperCase", UPPER_CASE),
+        ("isLowerCase", LOWER_CASE),
+    ] {
+        let symbol = emit_method(asm, abi, CHARACTER_CLASS, name, "(C)Z");
+        asm.emit_movzx(ECX, DI);
+        asm.emit_xor(EAX, EAX);
+        asm.emit_cmp(ECX, 255);
+        asm.emit_jcc(Condition::A, &format!("{}.done", symbol));
+        asm.emit_mov(RDX, "runtime$character_flags");
+        asm.emit_movzx(ECX, byte(RDX + RCX));
+        asm.emit_test(ECX, flags);
+        asm.emit_setcc(Condition::Ne, AL);
+        asm.emit_label(&format!("{}.done", symbol));
+        asm.emit_ret();
+    }
+
+    for (name, table) in [("toUpperCase", "runtime$upper_case"), ("toLowerCase", "runtime$lower_case")] {
+        let symbol = emit_method(asm, abi, CHARACTER_CLASS, name, "(C)C");
+        asm.emit_movzx(EAX, DI);
+        asm.emit_cmp(EAX, 255);
+        asm.emit_jcc(Condition::A, &format!("{}.done", symbol));
+        asm.emit_mov(RDX, table);
+        asm.emit_movzx(EAX, word(RDX + RAX * 2));
+        asm.emit_label(&format!("{}.done", symbol));
+        asm.emit_ret();
+    }
+}
+
+/// Float, which goes by its bits like Double does.
+fn emit_float(asm: &mut Assembly, abi: &dyn OsAbi) {
+    emit_method(asm, abi, FLOAT_CLASS, "valueOf", "(F)Ljava/lang/Float;");
+    asm.emit_sse(SseOp::Movss, dword(RSP - 8), XMM0);
+    asm.emit_mov(EAX, dword(RSP - 8));
+    asm.emit_push(RAX);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(FLOAT_CLASS)));
+    asm.emit_mov(RSI, BOX_SIZE);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$allocate");
+    asm.emit_pop(RDI);
+    asm.emit_mov(qword(RAX + BOX_VALUE_OFFSET), RDI);
+    asm.emit_ret();
+
+    emit_method(asm, abi, FLOAT_CLASS, "floatValue", "()F");
+    asm.emit_sse(SseOp::Movss, XMM0, dword(RDI + BOX_VALUE_OFFSET));
+    asm.emit_ret();
+
+    emit_method(asm, abi, FLOAT_CLASS, "doubleValue", "()D");
+    asm.emit_sse(SseOp::Cvtss2sd, XMM0, dword(RDI + BOX_VALUE_OFFSET));
+    asm.emit_ret();
+
+    let hash_code = emit_method(asm, abi, FLOAT_CLASS, "hashCode", "()I");
+    asm.emit_mov(EAX, dword(RDI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, EAX, &format!("{}.bits", hash_code));
+    asm.emit_ret();
+
+    let equals = emit_method(asm, abi, FLOAT_CLASS, "equals", "(Ljava/lang/Object;)Z");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &format!("{}.done", equals));
+    asm.emit_mov(RCX, abi.symbol(&mangle_class(FLOAT_CLASS)));
+    asm.emit_cmp(qword(RSI + CLASS_POINTER_OFFSET), RCX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.done", equals));
+    asm.emit_mov(R9D, dword(RDI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, R9D, &format!("{}.this", equals));
+    asm.emit_mov(EAX, dword(RSI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, EAX, &format!("{}.other", equals));
+    asm.emit_cmp(EAX, R9D);
+    asm.emit_setcc(Condition::E, CL);
+    asm.emit_movzx(EAX, CL);
+    asm.emit_label(&format!("{}.done", equals));
+    asm.emit_ret();
+
+    emit_method(asm, abi, FLOAT_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_sse(SseOp::Movss, XMM0, dword(RDI + BOX_VALUE_OFFSET));
+    emit_method(asm, abi, FLOAT_CLASS, "toString", "(F)Ljava/lang/String;");
+    asm.emit_jmp(method_symbol(abi, STRING_CLASS, "valueOf", "(F)Ljava/lang/String;"));
+}
+
+/// Double, which printf's floating point arguments come boxed in. Its hash and equals
+/// go by the bits, every NaN counting as the one Double.NaN is.
+fn emit_double(asm: &mut Assembly, abi: &dyn OsAbi) {
+    emit_method(asm, abi, DOUBLE_CLASS, "valueOf", "(D)Ljava/lang/Double;");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_push(RAX);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(DOUBLE_CLASS)));
+    asm.emit_mov(RSI, BOX_SIZE);
+    asm.emit_mov(RCX, qword(RSP + 8));
+    asm.emit_call("runtime$allocate");
+    asm.emit_pop(RDI);
+    asm.emit_mov(qword(RAX + BOX_VALUE_OFFSET), RDI);
+    asm.emit_ret();
+
+    emit_method(asm, abi, DOUBLE_CLASS, "doubleValue", "()D");
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RDI + BOX_VALUE_OFFSET));
+    asm.emit_ret();
+
+    let hash_code = emit_method(asm, abi, DOUBLE_CLASS, "hashCode", "()I");
+    asm.emit_mov(RAX, qword(RDI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, RAX, &format!("{}.bits", hash_code));
+    asm.emit_mov(RCX, RAX);
+    asm.emit_shr(RCX, 32);
+    asm.emit_xor(EAX, ECX);
+    asm.emit_ret();
+
+    let equals = emit_method(asm, abi, DOUBLE_CLASS, "equals", "(Ljava/lang/Object;)Z");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &format!("{}.done", equals));
+    asm.emit_mov(RCX, abi.symbol(&mangle_class(DOUBLE_CLASS)));
+    asm.emit_cmp(qword(RSI + CLASS_POINTER_OFFSET), RCX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.done", equals));
+    asm.emit_mov(R9, qword(RDI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, R9, &format!("{}.this", equals));
+    asm.emit_mov(RAX, qword(RSI + BOX_VALUE_OFFSET));
+    emit_canonical_nan(asm, RAX, &format!("{}.other", equals));
+    asm.emit_cmp(RAX, R9);
+    asm.emit_setcc(Condition::E, CL);
+    asm.emit_movzx(EAX, CL);
+    asm.emit_label(&format!("{}.done", equals));
+    asm.emit_ret();
+
+    emit_method(asm, abi, DOUBLE_CLASS, "toString", "()Ljava/lang/String;");
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RDI + BOX_VALUE_OFFSET));
+    emit_method(asm, abi, DOUBLE_CLASS, "toString", "(D)Ljava/lang/String;");
+    asm.emit_jmp(method_symbol(abi, STRING_CLASS, "valueOf", "(D)Ljava/lang/String;"));
+}
+
+// Replaces the bits of a NaN in `register` with those of Double.NaN, or of Float.NaN in
+// a 32-bit register, changing rdx and r8
+fn emit_canonical_nan(asm: &mut Assembly, register: Register, label: &str) {
+    let (infinity, nan) = match register.size {
+        4 => (u64::from(f32::INFINITY.to_bits()), u64::from(f32::NAN.to_bits())),
+        _ => (f64::INFINITY.to_bits(), f64::NAN.to_bits()),
+    };
+    let (magnitude, limit) = (resized(RDX, register.size), resized(R8, register.size));
+    asm.emit_mov(magnitude, register);
+    asm.emit_shl(magnitude, 1);
+    asm.emit_shr(magnitude, 1);
+    asm.emit_mov(limit, infinity);
+    asm.emit_cmp(magnitude, limit);
+    asm.emit_jcc(Condition::Be, label);
+    asm.emit_mov(register, nan);
+    asm.emit_label(label);
+}
+
+// Math's methods on floating point numbers move them through the red zone below rsp
+// to work on their bits
+fn emit_math(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let arithmetic = abi.symbol("runtime$throw_arithmetic_exception");
+
+    // x xored with its sign, minus the sign
+    for (descriptor, value, result, bits) in [("(I)I", EDI, EAX, 31), ("(J)J", RDI, RAX, 63)] {
+        emit_method(asm, abi, MATH_CLASS, "abs", descriptor);
+        let sign = resized(RCX, value.size);
+        asm.emit_mov(result, value);
+        asm.emit_mov(sign, value);
+        asm.emit_sar(sign, bits);
+        asm.emit_xor(result, sign);
+        asm.emit_sub(result, sign);
+        asm.emit_ret();
+    }
+
+    emit_method(asm, abi, MATH_CLASS, "abs", "(F)F");
+    asm.emit_sse(SseOp::Movss, dword(RSP - 8), XMM0);
+    asm.emit_and(dword(RSP - 8), 0x7fffffff);
+    asm.emit_sse(SseOp::Movss, XMM0, dword(RSP - 8));
+    asm.emit_ret();
+
+    emit_method(asm, abi, MATH_CLASS, "abs", "(D)D");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_shl(RAX, 1);
+    asm.emit_shr(RAX, 1);
+    asm.emit_mov(qword(RSP - 8), RAX);
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 8));
+    asm.emit_ret();
+
+    // NaN wins, and of two zeros max takes the positive and min the negative one, which
+    // anding and oring their bits does
+    for (name, larger) in [("max", true), ("min", false)] {
+        for (descriptor, size, mov, compare, add, bits) in [
+            ("(FF)F", dword as fn(Memory) -> Memory, SseOp::Movss, SseOp::Ucomiss, SseOp::Addss, EAX),
+            ("(DD)D", qword, SseOp::Movsd, SseOp::Ucomisd, SseOp::Addsd, RAX),
+        ] {
+            let symbol = emit_method(asm, abi, MATH_CLASS, name, descriptor);
+            asm.emit_sse(compare, XMM0, XMM1);
+            asm.emit_jcc(Condition::P, &format!("{}.nan", symbol));
+            asm.emit_jcc(Condition::Ne, &format!("{}.different", symbol));
+            asm.emit_sse(mov, size(RSP - 8), XMM0);
+            asm.emit_sse(mov, size(RSP - 16), XMM1);
+            asm.emit_mov(bits, size(RSP - 8));
+            match larger {
+                true => asm.emit_and(bits, size(RSP - 16)),
+                false => asm.emit_or(bits, size(RSP - 16)),
+            }
+            asm.emit_mov(size(RSP - 8), bits);
+            asm.emit_sse(mov, XMM0, size(RSP - 8));
+            asm.emit_ret();
+            asm.emit_label(&format!("{}.different", symbol));
+            asm.emit_jcc(if larger { Condition::A } else { Condition::B }, &format!("{}.done", symbol));
+            asm.emit_sse(mov, XMM0, XMM1);
+            asm.emit_label(&format!("{}.done", symbol));
+            asm.emit_ret();
+            asm.emit_label(&format!("{}.nan", symbol));
+            asm.emit_sse(add, XMM0, XMM1);
+            asm.emit_ret();
+        }
+    }
+
+    emit_method(asm, abi, MATH_CLASS, "sqrt", "(D)D");
+    asm.emit_sse(SseOp::Sqrtsd, XMM0, XMM0);
+    asm.emit_ret();
+
+    emit_pow(asm, abi);
+    emit_transcendental(asm, abi);
+
+    // Numbers of 2^52 and more are integers already. The others are truncated and moved
+    // by one if that went the wrong way, and a zero keeps the sign of the argument.
+    for (name, floor) in [("floor", true), ("ceil", false)] {
+        let symbol = emit_method(asm, abi, MATH_CLASS, name, "(D)D");
+        asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+        asm.emit_mov(RCX, qword(RSP - 8));
+        asm.emit_shl(RCX, 1);
+        asm.emit_shr(RCX, 1);
+        asm.emit_mov(RDX, 2f64.powi(52).to_bits());
+        asm.emit_cmp(RCX, RDX);
+        asm.emit_jcc(Condition::Ae, &format!("{}.done", symbol));
+        asm.emit_sse(SseOp::Cvttsd2si, RAX, XMM0);
+        asm.emit_sse(SseOp::Cvtsi2sd, XMM1, RAX);
+        asm.emit_sse(SseOp::Ucomisd, XMM1, XMM0);
+        match floor {
+            true => {
+                asm.emit_jcc(Condition::Be, &format!("{}.rounded", symbol));
+                asm.emit_sse(SseOp::Subsd, XMM1, qword(Memory::symbol("runtime$one")));
+            },
+            false => {
+                asm.emit_jcc(Condition::Ae, &format!("{}.rounded", symbol));
+                asm.emit_sse(SseOp::Addsd, XMM1, qword(Memory::symbol("runtime$one")));
+            },
+        }
+        asm.emit_label(&format!("{}.rounded", symbol));
+        asm.emit_sse(SseOp::Movsd, qword(RSP - 16), XMM1);
+        asm.emit_mov(RCX, qword(RSP - 16));
+        asm.emit_test(RCX, RCX);
+        asm.emit_jcc(Condition::Ne, &format!("{}.signed", symbol));
+        asm.emit_mov(RCX, qword(RSP - 8));
+        asm.emit_shr(RCX, 63);
+        asm.emit_shl(RCX, 63);
+        asm.emit_label(&format!("{}.signed", symbol));
+        asm.emit_mov(qword(RSP - 16), RCX);
+        asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 16));
+        asm.emit_label(&format!("{}.done", symbol));
+        asm.emit_ret();
+    }
+
+    // The floor, plus one if the fraction is a half or more. NaN rounds to zero and the
+    // others beyond the range to its ends.
+    let floor = method_symbol(abi, MATH_CLASS, "floor", "(D)D");
+    for (descriptor, result, maximum, limit) in [("(D)J", RAX, i64::MAX, "runtime$two_to_63"), ("(F)I", EAX, i64::from(i32::MAX), "runtime$two_to_31")] {
+        let symbol = emit_method(asm, abi, MATH_CLASS, "round", descriptor);
+        if descriptor == "(F)I" {
+            asm.emit_sse(SseOp::Cvtss2sd, XMM0, XMM0);
+        }
+        asm.emit_xor(EAX, EAX);
+        asm.emit_sse(SseOp::Ucomisd, XMM0, XMM0);
+        asm.emit_jcc(Condition::P, &format!("{}.done", symbol));
+        asm.emit_mov(RAX, maximum);
+        asm.emit_sse(SseOp::Ucomisd, XMM0, qword(Memory::symbol(limit)));
+        asm.emit_jcc(Condition::Ae, &format!("{}.done", symbol));
+        asm.emit_sub(RSP, 8);
+        asm.emit_sse(SseOp::Movsd, qword(RSP), XMM0);
+        asm.emit_call(&floor);
+        asm.emit_sse(SseOp::Movsd, XMM1, qword(RSP));
+        asm.emit_add(RSP, 8);
+        asm.emit_sse(SseOp::Subsd, XMM1, XMM0);
+        asm.emit_sse(SseOp::Cvttsd2si, result, XMM0);
+        asm.emit_sse(SseOp::Ucomisd, XMM1, qword(Memory::symbol("runtime$half")));
+        asm.emit_jcc(Condition::B, &format!("{}.done", symbol));
+        asm.emit_add(result, 1);
+        asm.emit_label(&format!("{}.done", symbol));
+        asm.emit_ret();
+    }
+
+    // The quotient rounded down, and the remainder with the sign of the divisor. Dividing
+    // the minimum by -1 overflows, idiv would trap on it.
+    for (descriptor, lhs, rhs, quotient, remainder) in [("(II)I", EDI, ESI, EAX, EDX), ("(JJ)J", RDI, RSI, RAX, RDX)] {
+        let wide = lhs == RDI;
+        for (name, modulo) in [("floorDiv", false), ("floorMod", true)] {
+            let symbol = emit_method(asm, abi, MATH_CLASS, name, descriptor);
+            asm.emit_test(rhs, rhs);
+            asm.emit_jcc(Condition::E, &arithmetic);
+            asm.emit_mov(quotient, lhs);
+            asm.emit_cmp(rhs, -1);
+            asm.emit_jcc(Condition::Ne, &format!("{}.divide", symbol));
+            match modulo {
+                true => asm.emit_xor(EAX, EAX),
+                false => asm.emit_neg(quotient),
+            }
+            asm.emit_ret();
+            asm.emit_label(&format!("{}.divide", symbol));
+            match wide {
+                true => asm.emit_cqo(),
+                false => asm.emit_cdq(),
+            }
+            asm.emit_idiv(rhs);
+            if modulo {
+                asm.emit_mov(quotient, remainder);
+            }
+            asm.emit_test(remainder, remainder);
+            asm.emit_jcc(Condition::E, &format!("{}.done", symbol));
+            asm.emit_xor(remainder, rhs);
+            asm.emit_jcc(Condition::Ns, &format!("{}.done", symbol));
+            match modulo {
+                true => asm.emit_add(quotient, rhs),
+                false => asm.emit_sub(quotient, 1),
+            }
+            asm.emit_label(&format!("{}.done", symbol));
+            asm.emit_ret();
+        }
+    }
+
+    // The generator is seeded from the time of day on first use and its upper 53 bits
+    // make the fraction
+    let random = emit_method(asm, abi, MATH_CLASS, "random", "()D");
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$random_state")));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.seeded", random));
+    asm.emit_call("runtime$time_of_day");
+    asm.emit_mov(RCX, 1000000);
+    asm.emit_imul(RAX, RCX);
+    asm.emit_add(RAX, RDX);
+    asm.emit_or(RAX, 1);
+    asm.emit_label(&format!("{}.seeded", random));
+    for (shift, left) in [(13, true), (7, false), (17, true)] {
+        asm.emit_mov(RCX, RAX);
+        match left {
+            true => asm.emit_shl(RCX, shift),
+            false => asm.emit_shr(RCX, shift),
+        }
+        asm.emit_xor(RAX, RCX);
+    }
+    asm.emit_mov(qword(Memory::symbol("runtime$random_state")), RAX);
+    asm.emit_shr(RAX, 11);
+    asm.emit_sse(SseOp::Cvtsi2sd, XMM0, RAX);
+    asm.emit_sse(SseOp::Mulsd, XMM0, qword(Memory::symbol("runtime$two_to_minus_53")));
+    asm.emit_ret();
+}
+
+// Math.pow. Apart from the special cases the JDK lists, |x|^y is 2^(y log2 |x|), which
+// the x87 unit works out in extended precision. x's sign is applied afterwards, with r8
+// telling whether y is an integer, 1 if it is odd and 2 if even.
+fn emit_pow(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let pow = emit_method(asm, abi, MATH_CLASS, "pow", "(DD)D");
+    let label = |name: &str| format!("{}.{}", pow, name);
+    let infinity = f64::INFINITY.to_bits();
+
+    asm.emit_sse(SseOp::Xorps, XMM2, XMM2);
+    asm.emit_sse(SseOp::Ucomisd, XMM1, XMM2);
+    asm.emit_jcc(Condition::P, &label("nan"));
+    asm.emit_jcc(Condition::Ne, &label("nonzero"));
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(Memory::symbol("runtime$one")));
+    asm.emit_ret();
+    asm.emit_label(&label("nonzero"));
+    asm.emit_sse(SseOp::Ucomisd, XMM0, XMM0);
+    asm.emit_jcc(Condition::P, &label("nan"));
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 16), XMM1);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_shl(RAX, 1);
+    asm.emit_shr(RAX, 1);
+    asm.emit_mov(qword(RSP - 24), RAX);
+
+    // An infinite y takes |x| to infinity or zero, and 1 to NaN
+    asm.emit_mov(RCX, qword(RSP - 16));
+    asm.emit_shl(RCX, 1);
+    asm.emit_shr(RCX, 1);
+    asm.emit_mov(RDX, infinity);
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::Ne, &label("finite"));
+    asm.emit_mov(RDX, 1f64.to_bits());
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::E, &label("nan"));
+    asm.emit_setcc(Condition::A, CL);
+    asm.emit_movzx(ECX, CL);
+    asm.emit_mov(RDX, qword(RSP - 16));
+    asm.emit_shr(RDX, 63);
+    asm.emit_cmp(ECX, EDX);
+    asm.emit_jcc(Condition::Ne, &label("infinity"));
+    asm.emit_sse(SseOp::Xorps, XMM0, XMM0);
+    asm.emit_ret();
+
+    asm.emit_label(&label("finite"));
+    asm.emit_mov(R8D, 2);
+    asm.emit_mov(RDX, 2f64.powi(53).to_bits());
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::Ae, &label("classified"));
+    asm.emit_sse(SseOp::Cvttsd2si, R9, XMM1);
+    asm.emit_sse(SseOp::Cvtsi2sd, XMM2, R9);
+    asm.emit_xor(R8D, R8D);
+    asm.emit_sse(SseOp::Ucomisd, XMM2, XMM1);
+    asm.emit_jcc(Condition::Ne, &label("classified"));
+    asm.emit_mov(R8D, 2);
+    asm.emit_test(R9, 1);
+    asm.emit_jcc(Condition::E, &label("classified"));
+    asm.emit_mov(R8D, 1);
+
+    asm.emit_label(&label("classified"));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("zero"));
+    asm.emit_mov(RDX, infinity);
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::E, &label("infinite"));
+    asm.emit_fld(qword(RSP - 16));
+    asm.emit_fld(qword(RSP - 24));
+    asm.emit_fyl2x();
+    emit_power_of_two(asm, &label("reduce"));
+    asm.emit_fstp(qword(RSP - 24));
+    asm.emit_fstp(ST0);
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 24));
+    asm.emit_jmp(label("sign"));
+    // 0^y is 0 for a positive y and infinite for a negative one, infinity^y the other way
+    for (name, condition) in [("zero", Condition::A), ("infinite", Condition::B)] {
+        asm.emit_label(&label(name));
+        asm.emit_sse(SseOp::Xorps, XMM0, XMM0);
+        asm.emit_sse(SseOp::Xorps, XMM2, XMM2);
+        asm.emit_sse(SseOp::Ucomisd, XMM1, XMM2);
+        asm.emit_jcc(condition, &label("sign"));
+        asm.emit_sse(SseOp::Movsd, XMM0, qword(Memory::symbol("runtime$infinity")));
+        asm.emit_jmp(label("sign"));
+    }
+
+    // A negative x makes odd powers negative, and fractional ones NaN unless it is -0 or
+    // -infinity
+    asm.emit_label(&label("sign"));
+    asm.emit_cmp(qword(RSP - 8), 0);
+    asm.emit_jcc(Condition::Ge, &label("done"));
+    asm.emit_cmp(R8D, 1);
+    asm.emit_jcc(Condition::E, &label("negate"));
+    asm.emit_cmp(R8D, 2);
+    asm.emit_jcc(Condition::E, &label("done"));
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_shl(RAX, 1);
+    asm.emit_jcc(Condition::E, &label("done"));
+    asm.emit_shr(RAX, 1);
+    asm.emit_mov(RDX, infinity);
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::E, &label("done"));
+    asm.emit_label(&label("nan"));
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(Memory::symbol("runtime$nan")));
+    asm.emit_ret();
+    asm.emit_label(&label("negate"));
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 24), XMM0);
+    asm.emit_btc(qword(RSP - 24), 63);
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 24));
+    asm.emit_label(&label("done"));
+    asm.emit_ret();
+    asm.emit_label(&label("infinity"));
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(Memory::symbol("runtime$infinity")));
+    asm.emit_ret();
+}
+
+// Raises 2 to st0, leaving the power in st0 above the exponent. f2xm1 takes the fraction
+// of the exponent, which fprem splits off in as many rounds as it takes, and fscale the
+// integer part.
+fn emit_power_of_two(asm: &mut Assembly, reduce: &str) {
+    asm.emit_fld1();
+    asm.emit_fld(ST1);
+    asm.emit_label(reduce);
+    asm.emit_fprem();
+    asm.emit_fnstsw(AX);
+    // C2 is set while the reduction is incomplete
+    asm.emit_test(AH, 4);
+    asm.emit_jcc(Condition::Ne, reduce);
+    asm.emit_fstp(ST1);
+    asm.emit_f2xm1();
+    asm.emit_fld1();
+    asm.emit_faddp();
+    asm.emit_fscale();
+}
+
+// Math.exp, Math.log, Math.sin and Math.cos, in the x87's extended precision. Their
+// results are within an ulp of the JDK's, which may round the other way, but for the
+// sine and cosine of numbers of 2^30 and more, whose reduction loses the bits of pi
+// the x87 lacks.
+fn emit_transcendental(asm: &mut Assembly, abi: &dyn OsAbi) {
+    // e^x is 2^(x log2 e), NaN and infinity are their own and -infinity's is 0
+    let exp = emit_method(asm, abi, MATH_CLASS, "exp", "(D)D");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_mov(RCX, RAX);
+    asm.emit_shl(RCX, 1);
+    asm.emit_shr(RCX, 1);
+    asm.emit_mov(RDX, f64::INFINITY.to_bits());
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::B, &format!("{}.finite", exp));
+    asm.emit_mov(RDX, f64::NEG_INFINITY.to_bits());
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ne, &format!("{}.done", exp));
+    asm.emit_sse(SseOp::Xorps, XMM0, XMM0);
+    asm.emit_label(&format!("{}.done", exp));
+    asm.emit_ret();
+    asm.emit_label(&format!("{}.finite", exp));
+    asm.emit_fldl2e();
+    asm.emit_fmul(qword(RSP - 8));
+    emit_power_of_two(asm, &format!("{}.reduce", exp));
+    asm.emit_fstp(qword(RSP - 8));
+    asm.emit_fstp(ST0);
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 8));
+    asm.emit_ret();
+
+    // ln x is ln 2 log2 x, which makes NaN of negative numbers and -infinity of zeros
+    emit_method(asm, abi, MATH_CLASS, "log", "(D)D");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_fldln2();
+    asm.emit_fld(qword(RSP - 8));
+    asm.emit_fyl2x();
+    asm.emit_fstp(qword(RSP - 8));
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 8));
+    asm.emit_ret();
+
+    // x less the nearest multiple k of pi/2, with pi/2 in three parts like fdlibm has it
+    // so that the first two products are exact, and the sine or cosine of that by the
+    // quadrant, which edi starts at 0 for the sine and 1 for the cosine. Numbers of 2^30
+    // and more are reduced by the x87's 2pi instead.
+    emit_method(asm, abi, MATH_CLASS, "sin", "(D)D");
+    asm.emit_xor(EDI, EDI);
+    asm.emit_jmp("runtime$sine");
+    emit_method(asm, abi, MATH_CLASS, "cos", "(D)D");
+    asm.emit_mov(EDI, 1);
+    asm.emit_label("runtime$sine");
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 8), XMM0);
+    asm.emit_mov(RAX, qword(RSP - 8));
+    asm.emit_shl(RAX, 1);
+    asm.emit_shr(RAX, 1);
+    asm.emit_mov(RDX, f64::INFINITY.to_bits());
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$sine.nan");
+    asm.emit_mov(RDX, 2f64.powi(30).to_bits());
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ae, "runtime$sine.large");
+    asm.emit_fld(qword(RSP - 8));
+    asm.emit_fld(qword(RSP - 8));
+    asm.emit_fmul(qword(Memory::symbol("runtime$two_over_pi")));
+    asm.emit_fistp(qword(RSP - 16));
+    asm.emit_mov(RCX, qword(RSP - 16));
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$sine.reduced");
+    asm.emit_add(EDI, ECX);
+    asm.emit_neg(RCX);
+    asm.emit_sse(SseOp::Cvtsi2sd, XMM1, RCX);
+    asm.emit_sse(SseOp::Movsd, qword(RSP - 16), XMM1);
+    for (part, _) in PI_OVER_TWO {
+        asm.emit_fld(qword(RSP - 16));
+        asm.emit_fmul(qword(Memory::symbol(part)));
+        asm.emit_faddp();
+    }
+    asm.emit_jmp("runtime$sine.reduced");
+    asm.emit_label("runtime$sine.large");
+    asm.emit_fldpi();
+    asm.emit_fld(ST0);
+    asm.emit_faddp();
+    asm.emit_fld(qword(RSP - 8));
+    asm.emit_label("runtime$sine.reduce");
+    asm.emit_fprem();
+    asm.emit_fnstsw(AX);
+    asm.emit_test(AH, 4);
+    asm.emit_jcc(Condition::Ne, "runtime$sine.reduce");
+    asm.emit_fstp(ST1);
+    // The odd quadrants swap sine and cosine, the last two negate them
+    asm.emit_label("runtime$sine.reduced");
+    asm.emit_test(EDI, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$sine.cosine");
+    asm.emit_fsin();
+    asm.emit_jmp("runtime$sine.sign");
+    asm.emit_label("runtime$sine.cosine");
+    asm.emit_fcos();
+    asm.emit_label("runtime$sine.sign");
+    asm.emit_fstp(qword(RSP - 16));
+    asm.emit_test(EDI, 2);
+    asm.emit_jcc(Condition::E, "runtime$sine.done");
+    asm.emit_btc(qword(RSP - 16), 63);
+    asm.emit_label("runtime$sine.done");
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(RSP - 16));
+    asm.emit_ret();
+    asm.emit_label("runtime$sine.nan");
+    asm.emit_sse(SseOp::Movsd, XMM0, qword(Memory::symbol("runtime$nan")));
+    asm.emit_ret();
+}
+
+/// `runtime$time_of_day` returns the seconds since the epoch in rax and the microseconds
+/// in rdx. macOS returns them from the system call itself, Linux only fills the buffer.
+fn emit_system(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$time_of_day");
+    asm.emit_sub(RSP, 24);
+    asm.emit_mov(RDI, RSP);
+    asm.emit_xor(ESI, ESI);
+    asm.emit_mov(RAX, abi.gettimeofday_syscall());
+    asm.emit_syscall();
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, "runtime$time_of_day.returned");
+    asm.emit_mov(RAX, qword(RSP));
+    asm.emit_mov(RDX, qword(RSP + 8));
+    asm.emit_label("runtime$time_of_day.returned");
+    asm.emit_add(RSP, 24);
+    asm.emit_ret();
+
+    emit_method(asm, abi, SYSTEM_CLASS, "exit", "(I)V");
+    asm.emit_mov(RAX, abi.exit_syscall());
+    asm.emit_syscall();
+
+    for (name, second, microsecond) in [("currentTimeMillis", 1000, 1000), ("nanoTime", 1_000_000_000, 1000)] {
+        emit_method(asm, abi, SYSTEM_CLASS, name, "()J");
+        asm.emit_call("runtime$time_of_day");
+        asm.emit_mov(RCX, second);
+        asm.emit_imul(RAX, RCX);
+        asm.emit_mov(RSI, RAX);
+        asm.emit_mov(RAX, RDX);
+        asm.emit_mov(RCX, microsecond);
+        match name {
+            "nanoTime" => asm.emit_imul(RAX, RCX),
+            _ => {
+                asm.emit_cqo();
+                asm.emit_idiv(RCX);
+            },
+        }
+        asm.emit_add(RAX, RSI);
+        asm.emit_ret();
+    }
+
+    emit_arraycopy(asm, abi);
+}
+
+fn emit_objects(asm: &mut Assembly, abi: &dyn OsAbi) {
+    emit_method(asm, abi, OBJECTS_CLASS, "requireNonNull", "(Ljava/lang/Object;)Ljava/lang/Object;");
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &abi.symbol("runtime$throw_null_pointer_exception"));
+    asm.emit_mov(RAX, RDI);
+    asm.emit_ret();
+}
+
+// System.arraycopy, throwing what HotSpot does with the same messages. rbx holds the
+// source, r12 the index into it, r13 the destination, r14 the index into that and r15
+// the length. Nothing is returned to if it throws, so they need not be saved.
+fn emit_arraycopy(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let arraycopy = emit_method(asm, abi, SYSTEM_CLASS, "arraycopy", "(Ljava/lang/Object;ILjava/lang/Object;II)V");
+    let label = |name: &str| format!("{}.{}", arraycopy, name);
+    let null_pointer = abi.symbol("runtime$throw_null_pointer_exception");
+    let class = |object: Register| qword(object + CLASS_POINTER_OFFSET);
+    let length = |array: Register| qword(array + ARRAY_LENGTH_OFFSET);
+
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, &null_pointer);
+    asm.emit_mov(RBX, RDI);
+    asm.emit_movsxd(R12, ESI);
+    asm.emit_mov(R13, RDX);
+    asm.emit_movsxd(R14, ECX);
+    asm.emit_movsxd(R15, R8D);
+    // r9 holds the source's class and r10 the destination's
+    asm.emit_mov(R9, class(RBX));
+    asm.emit_mov(R10, class(R13));
+    asm.emit_cmp(qword(R9 + ELEMENT_SIZE_OFFSET), 0);
+    asm.emit_jcc(Condition::E, &label("source_type"));
+    asm.emit_cmp(qword(R10 + ELEMENT_SIZE_OFFSET), 0);
+    asm.emit_jcc(Condition::E, &label("destination_type"));
+    // Arrays of primitives only copy into arrays of the same class, object arrays into
+    // any other object array
+    asm.emit_cmp(R9, R10);
+    asm.emit_jcc(Condition::E, &label("types_match"));
+    asm.emit_cmp(qword(R9 + ELEMENT_CLASS_OFFSET), 0);
+    asm.emit_jcc(Condition::E, &label("type_mismatch"));
+    asm.emit_cmp(qword(R10 + ELEMENT_CLASS_OFFSET), 0);
+    asm.emit_jcc(Condition::E, &label("type_mismatch"));
+    asm.emit_label(&label("types_match"));
+    asm.emit_test(R12, R12);
+    asm.emit_jcc(Condition::S, &label("source_index"));
+    asm.emit_test(R14, R14);
+    asm.emit_jcc(Condition::S, &label("destination_index"));
+    asm.emit_test(R15, R15);
+    asm.emit_jcc(Condition::S, &label("negative_length"));
+    asm.emit_mov(R11, R12);
+    asm.emit_add(R11, R15);
+    asm.emit_cmp(R11, length(RBX));
+    asm.emit_jcc(Condition::G, &label("last_source_index"));
+    asm.emit_mov(R11, R14);
+    asm.emit_add(R11, R15);
+    asm.emit_cmp(R11, length(R13));
+    asm.emit_jcc(Condition::G, &label("last_destination_index"));
+    asm.emit_test(R15, R15);
+    asm.emit_jcc(Condition::E, &label("done"));
+    // Unless every element of the source fits the destination, each one is checked
+    asm.emit_cmp(R9, R10);
+    asm.emit_jcc(Condition::E, &label("copy"));
+    asm.emit_mov(RDI, qword(R9 + ELEMENT_CLASS_OFFSET));
+    asm.emit_mov(RSI, qword(R10 + ELEMENT_CLASS_OFFSET));
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("checked"));
+
+    // The bytes are copied backwards when the destination is after the source, in case
+    // they overlap
+    asm.emit_label(&label("copy"));
+    asm.emit_mov(RCX, qword(R9 + ELEMENT_SIZE_OFFSET));
+    for (address, array, index) in [(RSI, RBX, R12), (RDI, R13, R14)] {
+        asm.emit_mov(address, index);
+        asm.emit_imul(address, RCX);
+        asm.emit_add(address, array);
+        asm.emit_add(address, ARRAY_DATA_OFFSET);
+    }
+    asm.emit_mov(RDX, R15);
+    asm.emit_imul(RDX, RCX);
+    asm.emit_cmp(RDI, RSI);
+    asm.emit_jcc(Condition::A, &label("backwards"));
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label(&label("forwards"));
+    asm.emit_mov(AL, byte(RSI + RCX));
+    asm.emit_mov(byte(RDI + RCX), AL);
+    asm.emit_add(RCX, 1);
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::B, &label("forwards"));
+    asm.emit_ret();
+    asm.emit_label(&label("backwards"));
+    asm.emit_sub(RDX, 1);
+    asm.emit_mov(AL, byte(RSI + RDX));
+    asm.emit_mov(byte(RDI + RDX), AL);
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::Ne, &label("backwards"));
+    asm.emit_label(&label("done"));
+    asm.emit_ret();
+
+    // r9 holds the destination's element class and r8 counts the elements. The ones
+    // before the element that fails are copied.
+    asm.emit_label(&label("checked"));
+    asm.emit_mov(R9, qword(R10 + ELEMENT_CLASS_OFFSET));
+    asm.emit_xor(R8D, R8D);
+    asm.emit_label(&label("element"));
+    asm.emit_mov(RAX, R12);
+    asm.emit_add(RAX, R8);
+    asm.emit_mov(RDX, qword(RBX + RAX * 8 + ARRAY_DATA_OFFSET));
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, &label("store"));
+    asm.emit_mov(RDI, class(RDX));
+    asm.emit_mov(RSI, R9);
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("element_mismatch"));
+    asm.emit_label(&label("store"));
+    asm.emit_mov(RAX, R14);
+    asm.emit_add(RAX, R8);
+    asm.emit_mov(qword(R13 + RAX * 8 + ARRAY_DATA_OFFSET), RDX);
+    asm.emit_add(R8, 1);
+    asm.emit_cmp(R8, R15);
+    asm.emit_jcc(Condition::L, &label("element"));
+    asm.emit_ret();
+
+    for (name, text, array) in [("source_type", SOURCE_TYPE_TEXT, R9), ("destination_type", DESTINATION_TYPE_TEXT, R10)] {
+        asm.emit_label(&label(name));
+        emit_clear_message(asm);
+        emit_text(asm, text);
+        asm.emit_mov(RSI, array);
+        asm.emit_call("runtime$append_class_name");
+        emit_text(asm, NOT_AN_ARRAY_TEXT);
+        emit_throw_new(asm, abi, ARRAY_STORE_EXCEPTION);
+    }
+
+    asm.emit_label(&label("type_mismatch"));
+    emit_clear_message(asm);
+    emit_text(asm, TYPE_MISMATCH_TEXT);
+    asm.emit_mov(RSI, R9);
+    asm.emit_call("runtime$append_element_type");
+    emit_text(asm, ARRAY_INTO_TEXT);
+    asm.emit_mov(RSI, R10);
+    asm.emit_call("runtime$append_element_type");
+    emit_text(asm, ARRAY_TEXT);
+    emit_throw_new(asm, abi, ARRAY_STORE_EXCEPTION);
+
+    // The index in r11, then the array's type and length
+    for (name, text, index, array) in [
+        ("source_index", SOURCE_INDEX_TEXT, Some(R12), RBX),
+        ("destination_index", DESTINATION_INDEX_TEXT, Some(R14), R13),
+        ("last_source_index", LAST_SOURCE_INDEX_TEXT, None, RBX),
+        ("last_destination_index", LAST_DESTINATION_INDEX_TEXT, None, R13),
+    ] {
+        asm.emit_label(&label(name));
+        if let Some(index) = index {
+            asm.emit_mov(R11, index);
+        }
+        emit_clear_message(asm);
+        emit_text(asm, text);
+        asm.emit_mov(RSI, R11);
+        asm.emit_call("runtime$append_decimal");
+        emit_text(asm, OUT_OF_BOUNDS_FOR_TEXT);
+        asm.emit_mov(RSI, class(array));
+        asm.emit_call("runtime$append_element_type");
+        emit_text(asm, OPEN_BRACKET_TEXT);
+        asm.emit_mov(RSI, length(array));
+        asm.emit_call("runtime$append_decimal");
+        emit_text(asm, CLOSE_BRACKET_TEXT);
+        emit_throw_new(asm, abi, ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+    }
+
+    asm.emit_label(&label("negative_length"));
+    emit_clear_message(asm);
+    emit_text(asm, LENGTH_PREFIX_TEXT);
+    asm.emit_mov(RSI, R15);
+    asm.emit_call("runtime$append_decimal");
+    emit_text(asm, IS_NEGATIVE_TEXT);
+    emit_throw_new(asm, abi, ARRAY_INDEX_OUT_OF_BOUNDS_EXCEPTION);
+
+    // When the destination's element class is not even a subclass of the source's, the
+    // JDK reports the classes as not matching
+    asm.emit_label(&label("element_mismatch"));
+    asm.emit_mov(R12, class(RBX));
+    asm.emit_mov(R12, qword(R12 + ELEMENT_CLASS_OFFSET));
+    asm.emit_mov(RDI, R9);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_mov(RBX, RAX);
+    emit_clear_message(asm);
+    asm.emit_test(RBX, RBX);
+    asm.emit_jcc(Condition::E, &label("class_mismatch"));
+    emit_text(asm, ELEMENT_MISMATCH_TEXT);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_class_name");
+    emit_text(asm, TO_DESTINATION_TEXT);
+    asm.emit_mov(RSI, R9);
+    asm.emit_call("runtime$append_class_name");
+    emit_throw_new(asm, abi, ARRAY_STORE_EXCEPTION);
+    asm.emit_label(&label("class_mismatch"));
+    emit_text(asm, TYPE_MISMATCH_TEXT);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_class_name");
+    emit_text(asm, ARRAY_INTO_TEXT);
+    asm.emit_mov(RSI, R9);
+    asm.emit_call("runtime$append_class_name");
+    emit_text(asm, ARRAY_TEXT);
+    emit_throw_new(asm, abi, ARRAY_STORE_EXCEPTION);
+}
+
+/// PrintStream writes to the file descriptor in the stream. Each print goes through the
+/// output buffer and flushes it, so that what goes to stdout and stderr comes out in
+/// order. `runtime$print_string` writes the string in rsi, or null, to the file
+/// descriptor in rdi and `runtime$print_newline` a newline, flushing the buffer.
+fn emit_print_stream(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    asm.emit_label("runtime$print_string");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::Ne, "runtime$write_string");
+    asm.emit_mov(RSI, NULL_TEXT.0);
+    asm.emit_mov(RDX, NULL_TEXT.1.len());
+    asm.emit_jmp("runtime$write_bytes");
+
+    asm.emit_label("runtime$print_newline");
+    asm.emit_mov(RSI, "runtime$newline");
+    asm.emit_mov(RDX, 1);
+    asm.emit_call("runtime$write_bytes");
+    asm.emit_jmp("runtime$flush");
+
+    let fd = qword(RDI + PRINT_STREAM_FD_OFFSET);
+    for (name, end) in [("print", "runtime$flush"), ("println", "runtime$print_newline")] {
+        let symbol = emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(Z)V");
+        asm.emit_mov(RDI, fd.clone());
+        asm.emit_test(ESI, ESI);
+        asm.emit_mov(RSI, TRUE_TEXT.0);
+        asm.emit_mov(RDX, TRUE_TEXT.1.len());
+        asm.emit_jcc(Condition::Ne, &format!("{}.write", symbol));
+        asm.emit_mov(RSI, FALSE_TEXT.0);
+        asm.emit_mov(RDX, FALSE_TEXT.1.len());
+        asm.emit_label(&format!("{}.write", symbol));
+        asm.emit_call("runtime$write_bytes");
+        asm.emit_jmp(end);
+
+        // The character is written from the stack
+        emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(C)V");
+        asm.emit_mov(RDI, fd.clone());
+        asm.emit_push(RSI);
+        asm.emit_mov(RSI, RSP);
+        asm.emit_mov(EDX, 1);
+        asm.emit_mov(ECX, UTF16);
+        asm.emit_call("runtime$write_chars");
+        asm.emit_pop(RSI);
+        asm.emit_jmp(end);
+
+        let print_int = emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(I)V");
+        asm.emit_movsxd(RSI, ESI);
+        asm.emit_jmp(format!("{}.long", print_int));
+        emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(J)V");
+        asm.emit_label(&format!("{}.long", print_int));
+        asm.emit_mov(RDI, fd.clone());
+        emit_clear_message(asm);
+        asm.emit_call("runtime$append_decimal");
+        asm.emit_label(&format!("{}.message", print_int));
+        asm.emit_mov(RSI, "runtime$message");
+        asm.emit_mov(RDX, qword(Memory::symbol("runtime$message_length")));
+        asm.emit_call("runtime$write_bytes");
+        asm.emit_jmp(end);
+
+        for (descriptor, append) in [("(F)V", "runtime$append_float"), ("(D)V", "runtime$append_double")] {
+            emit_method(asm, abi, PRINT_STREAM_CLASS, name, descriptor);
+            asm.emit_mov(RAX, fd.clone());
+            asm.emit_push(RAX);
+            emit_clear_message(asm);
+            asm.emit_call(append);
+            asm.emit_pop(RDI);
+            asm.emit_jmp(format!("{}.message", print_int));
+        }
+
+        emit_method(asm, abi, PRINT_STREAM_CLASS, name, "([C)V");
+        asm.emit_test(RSI, RSI);
+        asm.emit_jcc(Condition::E, &abi.symbol("runtime$throw_null_pointer_exception"));
+        asm.emit_mov(RDI, fd.clone());
+        asm.emit_mov(RDX, qword(RSI + ARRAY_LENGTH_OFFSET));
+        asm.emit_add(RSI, ARRAY_DATA_OFFSET);
+        asm.emit_mov(ECX, UTF16);
+        asm.emit_call("runtime$write_chars");
+        asm.emit_jmp(end);
+
+        emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(Ljava/lang/String;)V");
+        asm.emit_mov(RDI, fd.clone());
+        asm.emit_call("runtime$print_string");
+        asm.emit_jmp(end);
+
+        // The object's toString may run compiled code, the file descriptor is kept in the frame
+        emit_method(asm, abi, PRINT_STREAM_CLASS, name, "(Ljava/lang/Object;)V");
+        emit_enter(asm, 1);
+        asm.emit_mov(RAX, fd.clone());
+        asm.emit_mov(slot(0), RAX);
+        asm.emit_mov(RDI, RSI);
+        emit_call_mapped(asm, stack_maps, method_symbol(abi, STRING_CLASS, "valueOf", "(Ljava/lang/Object;)Ljava/lang/String;"), &[]);
+        asm.emit_mov(RDI, slot(0));
+        asm.emit_mov(RSI, RAX);
+        asm.emit_leave();
+        asm.emit_call("runtime$print_string");
+        asm.emit_jmp(end);
+    }
+
+    emit_method(asm, abi, PRINT_STREAM_CLASS, "println", "()V");
+    asm.emit_mov(RDI, fd.clone());
+    asm.emit_jmp("runtime$print_newline");
+
+    // Every print is flushed already
+    emit_method(asm, abi, PRINT_STREAM_CLASS, "flush", "()V");
+    asm.emit_ret();
+}
+
+/// PrintStream.printf and format, which know the %s, %d, %x, %c, %b, %f, %n and %%
+/// conversions with the '-' and '0' flags, a width and a precision. Once the format is
+/// checked for unknown conversions, literal text and the conversions are written as it
+/// is read, a conversion with a null argument writing null, or false for %b.
+///
+/// The helpers write to the file descriptor in rdi and keep it. `runtime$format_char`
+/// returns in eax the character at index rsi of the string in rdi, or -1 past its end,
+/// changing rcx and r8. `runtime$write_padding` writes the character in dl rsi times,
+/// if rsi is positive. `runtime$write_padded` writes characters like
+/// `runtime$write_chars` does, padded with spaces to the width in r8 on the left, or on
+/// the right when bit 1 of r9 has the '-' flag. `runtime$format_message` writes the
+/// message padded to the width in rsi with the flags in rdx, whose bit 2 has the '0'
+/// flag putting zeros after a sign.
+fn emit_printf(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &mut StackMaps) {
+    asm.emit_label("runtime$format_char");
+    emit_string_length(asm, R8, RDI);
+    asm.emit_mov(EAX, -1);
+    asm.emit_cmp(RSI, R8);
+    asm.emit_jcc(Condition::Ae, "runtime$format_char.done");
+    emit_load_char(asm, RAX, RDI, RSI, "runtime$format_char.load");
+    asm.emit_label("runtime$format_char.done");
+    asm.emit_ret();
+
+    // The character is written from the stack, the count is kept there too
+    asm.emit_label("runtime$write_padding");
+    asm.emit_sub(RSP, 16);
+    asm.emit_mov(qword(RSP + 8), RSI);
+    asm.emit_mov(byte(RSP), DL);
+    asm.emit_label("runtime$write_padding.next");
+    asm.emit_cmp(qword(RSP + 8), 0);
+    asm.emit_jcc(Condition::Le, "runtime$write_padding.done");
+    asm.emit_mov(RSI, RSP);
+    asm.emit_mov(EDX, 1);
+    asm.emit_call("runtime$write_bytes");
+    asm.emit_sub(qword(RSP + 8), 1);
+    asm.emit_jmp("runtime$write_padding.next");
+    asm.emit_label("runtime$write_padding.done");
+    asm.emit_add(RSP, 16);
+    asm.emit_ret();
+
+    // The slots hold the characters' address, count and coder, and how much padding
+    // they need
+    asm.emit_label("runtime$write_padded");
+    emit_enter(asm, 4);
+    asm.emit_mov(slot(0), RSI);
+    asm.emit_mov(slot(1), RDX);
+    asm.emit_mov(slot(2), RCX);
+    asm.emit_sub(R8, RDX);
+    asm.emit_mov(slot(3), R8);
+    asm.emit_test(R9, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$write_padded.left");
+    asm.emit_mov(RSI, R8);
+    asm.emit_mov(EDX, 32);
+    asm.emit_call("runtime$write_padding");
+    asm.emit_mov(RSI, slot(0));
+    asm.emit_mov(RDX, slot(1));
+    asm.emit_mov(RCX, slot(2));
+    asm.emit_call("runtime$write_chars");
+    asm.emit_leave();
+    asm.emit_ret();
+    asm.emit_label("runtime$write_padded.left");
+    asm.emit_call("runtime$write_chars");
+    asm.emit_mov(RSI, slot(3));
+    asm.emit_mov(EDX, 32);
+    asm.emit_call("runtime$write_padding");
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label("runtime$format_message");
+    asm.emit_test(RDX, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$format_message.spaces");
+    asm.emit_test(RDX, 2);
+    asm.emit_jcc(Condition::Ne, "runtime$format_message.zeros");
+    asm.emit_label("runtime$format_message.spaces");
+    asm.emit_mov(R8, RSI);
+    asm.emit_mov(R9, RDX);
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_xor(ECX, ECX);
+    asm.emit_jmp("runtime$write_padded");
+    // The slots hold how many zeros there are and how much of the message is written
+    // before them
+    asm.emit_label("runtime$format_message.zeros");
+    emit_enter(asm, 2);
+    asm.emit_sub(RSI, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_mov(slot(0), RSI);
+    asm.emit_mov(slot(1), 0);
+    asm.emit_cmp(qword(Memory::symbol("runtime$message_length")), 0);
+    asm.emit_jcc(Condition::E, "runtime$format_message.pad");
+    asm.emit_cmp(byte(Memory::symbol("runtime$message")), 45);
+    asm.emit_jcc(Condition::Ne, "runtime$format_message.pad");
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_mov(EDX, 1);
+    asm.emit_call("runtime$write_bytes");
+    asm.emit_mov(slot(1), 1);
+    asm.emit_label("runtime$format_message.pad");
+    asm.emit_mov(RSI, slot(0));
+    asm.emit_mov(EDX, 48);
+    asm.emit_call("runtime$write_padding");
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_add(RSI, slot(1));
+    asm.emit_mov(RDX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_sub(RDX, slot(1));
+    asm.emit_call("runtime$write_bytes");
+    asm.emit_leave();
+    asm.emit_ret();
+
+    let printf = emit_method(asm, abi, PRINT_STREAM_CLASS, "printf", PRINTF_DESCRIPTOR);
+    emit_method(asm, abi, PRINT_STREAM_CLASS, "format", PRINTF_DESCRIPTOR);
+    let label = |name: &str| format!("{}.{}", printf, name);
+    let class = |class_name: &str| abi.symbol(&mangle_class(class_name));
+    let value = |size: fn(Memory) -> Memory| size(RAX + BOX_VALUE_OFFSET);
+    // The slots hold the stream, the format, the arguments, the index in the format and
+    // of the next argument, where the conversion starts, its flags, width, precision
+    // (-1 when it has none), argument and conversion character, and from slot 11 up
+    // room for a character
+    let (stream, format, arguments, index, next, start, flags, width, precision, argument, conversion) = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+    let references = [stream, format, arguments, argument];
+    let fd = |register: Register| qword(register + PRINT_STREAM_FD_OFFSET);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, &abi.symbol("runtime$throw_null_pointer_exception"));
+    emit_enter(asm, 12);
+    asm.emit_mov(slot(stream), RDI);
+    asm.emit_mov(slot(format), RSI);
+    asm.emit_mov(slot(arguments), RDX);
+    asm.emit_mov(slot(index), 0);
+    asm.emit_mov(slot(next), 0);
+    asm.emit_mov(slot(argument), 0);
+
+    // Like the JDK's Formatter, nothing is written when a conversion is unknown
+    asm.emit_mov(RDI, RSI);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_label(&label("check"));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_add(RDX, 1);
+    asm.emit_cmp(EAX, -1);
+    asm.emit_jcc(Condition::E, &label("literal"));
+    asm.emit_cmp(EAX, 37);
+    asm.emit_jcc(Condition::Ne, &label("check"));
+    asm.emit_label(&label("check_specifier"));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_add(RDX, 1);
+    // The flags, digits and point come from '-' to '9', but for '/'
+    asm.emit_cmp(EAX, 47);
+    asm.emit_jcc(Condition::E, &label("check_unknown"));
+    asm.emit_mov(ECX, EAX);
+    asm.emit_sub(ECX, 45);
+    asm.emit_cmp(ECX, 12);
+    asm.emit_jcc(Condition::Be, &label("check_specifier"));
+    for conversion in ['s', 'd', 'x', 'c', 'b', 'f', 'n', '%'] {
+        asm.emit_cmp(EAX, conversion as u32);
+        asm.emit_jcc(Condition::E, &label("check"));
+    }
+    asm.emit_label(&label("check_unknown"));
+    asm.emit_mov(slot(conversion), RAX);
+    emit_clear_message(asm);
+    asm.emit_jmp(label("unknown"));
+
+    // The text up to the next % is written as it is
+    asm.emit_label(&label("literal"));
+    asm.emit_mov(RDX, slot(index));
+    asm.emit_label(&label("scan"));
+    asm.emit_mov(RDI, slot(format));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_cmp(EAX, 37);
+    asm.emit_jcc(Condition::E, &label("text"));
+    asm.emit_cmp(EAX, -1);
+    asm.emit_jcc(Condition::E, &label("text"));
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(label("scan"));
+    asm.emit_label(&label("text"));
+    asm.emit_mov(slot(start), RDX);
+    asm.emit_sub(RDX, slot(index));
+    asm.emit_jcc(Condition::E, &label("specifier"));
+    asm.emit_mov(R9, slot(format));
+    asm.emit_movzx(ECX, byte(R9 + STRING_CODER_OFFSET));
+    asm.emit_mov(RAX, slot(index));
+    asm.emit_shl(RAX, CL);
+    asm.emit_mov(RSI, qword(R9 + STRING_VALUE_OFFSET));
+    asm.emit_add(RSI, ARRAY_DATA_OFFSET);
+    asm.emit_add(RSI, RAX);
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_mov(RDI, fd(RAX));
+    asm.emit_call("runtime$write_chars");
+
+    // A format specifier, or the end of the format
+    asm.emit_label(&label("specifier"));
+    asm.emit_mov(RDX, slot(start));
+    asm.emit_mov(RDI, slot(format));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_cmp(EAX, -1);
+    asm.emit_jcc(Condition::E, &label("done"));
+    asm.emit_add(RDX, 1);
+    asm.emit_mov(slot(flags), 0);
+    asm.emit_mov(slot(width), 0);
+    asm.emit_mov(slot(precision), -1);
+    asm.emit_label(&label("flag"));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_cmp(EAX, 45);
+    asm.emit_jcc(Condition::Ne, &label("not_left"));
+    asm.emit_or(slot(flags), 1);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(label("flag"));
+    asm.emit_label(&label("not_left"));
+    asm.emit_cmp(EAX, 48);
+    asm.emit_jcc(Condition::Ne, &label("width"));
+    asm.emit_or(slot(flags), 2);
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(label("flag"));
+    for (number, after) in [(width, "precision"), (precision, "conversion")] {
+        let digit = format!("{}_digit", after);
+        let next_digit = label(&digit);
+        if number == width {
+            asm.emit_label(&label("width"));
+        } else {
+            asm.emit_label(&label("precision"));
+            asm.emit_cmp(EAX, 46);
+            asm.emit_jcc(Condition::Ne, &label("conversion"));
+            asm.emit_mov(slot(precision), 0);
+            asm.emit_add(RDX, 1);
+            asm.emit_mov(RSI, RDX);
+            asm.emit_call("runtime$format_char");
+        }
+        asm.emit_label(&next_digit);
+        asm.emit_mov(ECX, EAX);
+        asm.emit_sub(ECX, 48);
+        asm.emit_cmp(ECX, 9);
+        asm.emit_jcc(Condition::A, &label(after));
+        asm.emit_mov(RAX, slot(number));
+        asm.emit_mov(R8, RAX);
+        asm.emit_shl(RAX, 3);
+        asm.emit_add(RAX, R8);
+        asm.emit_add(RAX, R8);
+        asm.emit_add(RAX, RCX);
+        asm.emit_mov(slot(number), RAX);
+        asm.emit_add(RDX, 1);
+        asm.emit_mov(RSI, RDX);
+        asm.emit_call("runtime$format_char");
+        asm.emit_jmp(&next_digit);
+    }
+
+    asm.emit_label(&label("conversion"));
+    asm.emit_add(RDX, 1);
+    asm.emit_mov(slot(index), RDX);
+    asm.emit_mov(slot(conversion), RAX);
+    emit_clear_message(asm);
+    asm.emit_cmp(EAX, 110);
+    asm.emit_jcc(Condition::E, &label("newline"));
+    asm.emit_cmp(EAX, 37);
+    asm.emit_jcc(Condition::E, &label("percent"));
+    // The check let only the conversions with an argument through
+    asm.emit_jmp(label("argument"));
+
+    asm.emit_label(&label("newline"));
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_mov(RDI, fd(RAX));
+    asm.emit_mov(RSI, "runtime$newline");
+    asm.emit_mov(EDX, 1);
+    asm.emit_call("runtime$write_bytes");
+    asm.emit_jmp(label("literal"));
+
+    asm.emit_label(&label("percent"));
+    asm.emit_mov(EDI, 37);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_jmp(label("message"));
+
+    // A null array of arguments has only nulls
+    asm.emit_label(&label("argument"));
+    asm.emit_xor(EAX, EAX);
+    asm.emit_mov(RCX, slot(arguments));
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, &label("loaded"));
+    asm.emit_mov(RAX, slot(next));
+    asm.emit_cmp(RAX, qword(RCX + ARRAY_LENGTH_OFFSET));
+    asm.emit_jcc(Condition::Ae, &label("missing"));
+    asm.emit_mov(RAX, qword(RCX + RAX * 8 + ARRAY_DATA_OFFSET));
+    asm.emit_add(slot(next), 1);
+    asm.emit_label(&label("loaded"));
+    asm.emit_mov(slot(argument), RAX);
+    asm.emit_mov(RDX, slot(conversion));
+    asm.emit_cmp(EDX, 'b' as u32);
+    asm.emit_jcc(Condition::E, &label("boolean"));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, &label("not_null"));
+    emit_text(asm, NULL_TEXT);
+    asm.emit_jmp(label("message"));
+    asm.emit_label(&label("not_null"));
+    asm.emit_mov(RCX, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_mov(RDX, slot(conversion));
+    for (conversion, name) in [('s', "string"), ('d', "decimal"), ('x', "hex"), ('c', "char")] {
+        asm.emit_cmp(EDX, conversion as u32);
+        asm.emit_jcc(Condition::E, &label(name));
+    }
+
+    // Doubles, and floats widened to them
+    asm.emit_mov(R8, class(DOUBLE_CLASS));
+    asm.emit_cmp(RCX, R8);
+    asm.emit_jcc(Condition::Ne, &label("float"));
+    asm.emit_sse(SseOp::Movsd, XMM0, value(qword));
+    asm.emit_jmp(label("floating"));
+    asm.emit_label(&label("float"));
+    asm.emit_mov(R8, class(FLOAT_CLASS));
+    asm.emit_cmp(RCX, R8);
+    asm.emit_jcc(Condition::Ne, &label("mismatch"));
+    asm.emit_sse(SseOp::Cvtss2sd, XMM0, value(dword));
+    asm.emit_label(&label("floating"));
+    asm.emit_mov(RDI, slot(precision));
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::Ns, &label("fixed"));
+    asm.emit_mov(EDI, 6);
+    asm.emit_label(&label("fixed"));
+    asm.emit_call("runtime$append_fixed");
+    asm.emit_jmp(label("message"));
+
+    // Integers and longs
+    for (name, append) in [("decimal", "runtime$append_decimal"), ("hex", "runtime$append_hex")] {
+        asm.emit_label(&label(name));
+        asm.emit_mov(R8, class(INTEGER_CLASS));
+        asm.emit_cmp(RCX, R8);
+        asm.emit_jcc(Condition::Ne, &label(&format!("{}_long", name)));
+        match name {
+            "decimal" => asm.emit_movsxd(RSI, value(dword)),
+            _ => asm.emit_mov(ESI, value(dword)),
+        }
+        asm.emit_jmp(label(&format!("{}_append", name)));
+        asm.emit_label(&label(&format!("{}_long", name)));
+        asm.emit_mov(R8, class(LONG_CLASS));
+        asm.emit_cmp(RCX, R8);
+        asm.emit_jcc(Condition::Ne, &label("mismatch"));
+        asm.emit_mov(RSI, value(qword));
+        asm.emit_label(&label(&format!("{}_append", name)));
+        asm.emit_call(append);
+        asm.emit_jmp(label("message"));
+    }
+
+    // A Character, or the character an Integer holds, written from slot 11
+    asm.emit_label(&label("char"));
+    asm.emit_mov(R8, class(CHARACTER_CLASS));
+    asm.emit_cmp(RCX, R8);
+    asm.emit_jcc(Condition::E, &label("character"));
+    asm.emit_mov(R8, class(INTEGER_CLASS));
+    asm.emit_cmp(RCX, R8);
+    asm.emit_jcc(Condition::Ne, &label("mismatch"));
+    asm.emit_label(&label("character"));
+    asm.emit_movzx(EAX, value(word));
+    asm.emit_mov(slot(11), RAX);
+    asm.emit_mov(RSI, RBP);
+    asm.emit_sub(RSI, 8 * 12);
+    asm.emit_mov(EDX, 1);
+    asm.emit_mov(ECX, UTF16);
+    asm.emit_jmp(label("padded"));
+
+    // Null and a false Boolean are false and anything else true, cut off at the precision
+    asm.emit_label(&label("boolean"));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, &label("false"));
+    asm.emit_mov(R8, class(BOOLEAN_CLASS));
+    asm.emit_cmp(qword(RAX + CLASS_POINTER_OFFSET), R8);
+    asm.emit_jcc(Condition::Ne, &label("true"));
+    asm.emit_cmp(value(byte), 0);
+    asm.emit_jcc(Condition::E, &label("false"));
+    asm.emit_label(&label("true"));
+    emit_text(asm, TRUE_TEXT);
+    asm.emit_jmp(label("truncate"));
+    asm.emit_label(&label("false"));
+    emit_text(asm, FALSE_TEXT);
+    asm.emit_label(&label("truncate"));
+    asm.emit_mov(RAX, slot(precision));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::S, &label("message"));
+    asm.emit_cmp(RAX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_jcc(Condition::Ae, &label("message"));
+    asm.emit_mov(qword(Memory::symbol("runtime$message_length")), RAX);
+    asm.emit_jmp(label("message"));
+
+    // Anything else is written as String.valueOf makes it, cut off at the precision
+    asm.emit_label(&label("string"));
+    asm.emit_mov(RDI, RAX);
+    emit_call_mapped(asm, stack_maps, method_symbol(abi, STRING_CLASS, "valueOf", "(Ljava/lang/Object;)Ljava/lang/String;"), &references);
+    asm.emit_mov(slot(argument), RAX);
+    asm.emit_mov(R9, RAX);
+    emit_string_length(asm, RDX, R9);
+    asm.emit_mov(RAX, slot(precision));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::S, &label("whole"));
+    asm.emit_cmp(RAX, RDX);
+    asm.emit_jcc(Condition::Ae, &label("whole"));
+    asm.emit_mov(RDX, RAX);
+    asm.emit_label(&label("whole"));
+    asm.emit_mov(RSI, qword(R9 + STRING_VALUE_OFFSET));
+    asm.emit_add(RSI, ARRAY_DATA_OFFSET);
+    asm.emit_movzx(ECX, byte(R9 + STRING_CODER_OFFSET));
+    asm.emit_label(&label("padded"));
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_mov(RDI, fd(RAX));
+    asm.emit_mov(R8, slot(width));
+    asm.emit_mov(R9, slot(flags));
+    asm.emit_call("runtime$write_padded");
+    asm.emit_jmp(label("literal"));
+
+    asm.emit_label(&label("message"));
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_mov(RDI, fd(RAX));
+    asm.emit_mov(RSI, slot(width));
+    asm.emit_mov(RDX, slot(flags));
+    asm.emit_call("runtime$format_message");
+    asm.emit_jmp(label("literal"));
+
+    asm.emit_label(&label("done"));
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_mov(RDI, fd(RAX));
+    asm.emit_call("runtime$flush");
+    asm.emit_mov(RAX, slot(stream));
+    asm.emit_leave();
+    asm.emit_ret();
+
+    // The exceptions are thrown once the frame is left. A % at the end of the format is
+    // an unknown conversion of its own.
+    asm.emit_label(&label("unknown"));
+    emit_text(asm, CONVERSION_TEXT);
+    asm.emit_mov(RDI, slot(conversion));
+    asm.emit_cmp(EDI, -1);
+    asm.emit_jcc(Condition::Ne, &label("unknown_char"));
+    asm.emit_mov(EDI, 37);
+    asm.emit_label(&label("unknown_char"));
+    asm.emit_call("runtime$append_byte");
+    emit_text(asm, APOSTROPHE_TEXT);
+    asm.emit_leave();
+    emit_throw_new(asm, abi, UNKNOWN_FORMAT_CONVERSION_EXCEPTION);
+
+    asm.emit_label(&label("missing"));
+    emit_text(asm, FORMAT_SPECIFIER_TEXT);
+    asm.emit_mov(RDX, slot(start));
+    asm.emit_label(&label("missing_char"));
+    asm.emit_cmp(RDX, slot(index));
+    asm.emit_jcc(Condition::Ae, &label("missing_end"));
+    asm.emit_mov(RDI, slot(format));
+    asm.emit_mov(RSI, RDX);
+    asm.emit_call("runtime$format_char");
+    asm.emit_mov(EDI, EAX);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_add(RDX, 1);
+    asm.emit_jmp(label("missing_char"));
+    asm.emit_label(&label("missing_end"));
+    emit_text(asm, APOSTROPHE_TEXT);
+    asm.emit_leave();
+    emit_throw_new(asm, abi, MISSING_FORMAT_ARGUMENT_EXCEPTION);
+
+    asm.emit_label(&label("mismatch"));
+    asm.emit_mov(RDI, slot(conversion));
+    asm.emit_call("runtime$append_byte");
+    emit_text(asm, NOT_CONVERTIBLE_TEXT);
+    asm.emit_mov(RAX, slot(argument));
+    asm.emit_mov(RSI, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_call("runtime$append_class_name");
+    asm.emit_leave();
+    emit_throw_new(asm, abi, ILLEGAL_FORMAT_CONVERSION_EXCEPTION);
+}
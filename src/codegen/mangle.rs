@@ -0,0 +1,60 @@
+// Methods are named the way JNI names native methods, which keeps symbols unique across
+// overloads and free of characters assemblers reject, with the return type added:
+//
+// Java_<class>_<name>__<parameter descriptors>__<return descriptor>
+//
+// A class can have two methods differing only in their return type, a covariant
+// override and the bridge method javac adds for it.
+//
+// https://docs.oracle.com/en/java/javase/17/docs/specs/jni/design.html#resolving-native-method-names
+
+fn mangle(name: &str) -> String {
+    let mut mangled = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '/' => mangled.push('_'),
+            '_' => mangled.push_str("_1"),
+            ';' => mangled.push_str("_2"),
+            '[' => mangled.push_str("_3"),
+            c if c.is_ascii_alphanumeric() => mangled.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    mangled.push_str(&format!("_0{:04x}", unit));
+                }
+            },
+        }
+    }
+    mangled
+}
+
+/// The symbol of a method, `descriptor` is its full method descriptor.
+pub fn mangle_method(class_name: &str, name: &str, descriptor: &str) -> String {
+    let (parameters, return_type) = descriptor
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+        .unwrap_or(("", ""));
+
+    format!("Java_{}_{}__{}__{}", mangle(class_name), mangle(name), mangle(parameters), mangle(return_type))
+}
+
+/// The symbol of a class's metadata. `$` never comes out of `mangle`, so it can't clash
+/// with a method.
+pub fn mangle_class(class_name: &str) -> String {
+    format!("Java_{}$class", mangle(class_name))
+}
+
+/// The symbol of the slot a static field is kept in.
+pub fn mangle_static_field(class_name: &str, name: &str) -> String {
+    format!("Java_{}$static_{}", mangle(class_name), mangle(name))
+}
+
+/// The symbol of a class's initialization state, see `x86_64::emit_initializer`.
+pub fn mangle_class_state(class_name: &str) -> String {
+    format!("Java_{}$state", mangle(class_name))
+}
+
+/// The symbol of the function that initializes a class.
+pub fn mangle_initializer(class_name: &str) -> String {
+    format!("Java_{}$initialize", mangle(class_name))
+}
@@ -1,8 +1,21 @@
+pub mod elf;
+pub mod encoder;
+pub mod inst;
+pub mod layout;
+pub mod library;
+pub mod mangle;
+pub mod runtime;
+pub mod syntax;
+pub mod target;
 pub mod x86_64;
 
+use std::collections::HashSet;
+
+use inst::{ArithmeticOp, Condition, Operand, ShiftOp, SseOp, X86Inst};
+
 #[derive(Debug, Default)]
 pub struct Assembly {
-    pub code: Vec<String>,
+    pub code: Vec<X86Inst>,
 }
 
 impl Assembly {
@@ -10,43 +23,275 @@ impl Assembly {
         Self::default()
     }
 
-    pub fn emit_section_text(&mut self) {
-        self.code.push("section .text\n".to_string());
+    pub fn emit(&mut self, inst: X86Inst) {
+        self.code.push(inst);
+    }
+
+    pub fn emit_section(&mut self, name: &str) {
+        self.emit(X86Inst::Section(name.to_string()));
     }
 
-    pub fn emit_global_main(&mut self) {
-        self.code.push("global _main\n".to_string());
+    pub fn emit_global(&mut self, name: &str) {
+        self.emit(X86Inst::Global(name.to_string()));
+    }
+
+    /// Declares every symbol the code refers to without defining it extern, ahead of the
+    /// code, which nasm and the built-in assembler need to leave it to the linker.
+    pub fn declare_externs(&mut self) {
+        let defined: HashSet<&str> = self
+            .code
+            .iter()
+            .filter_map(|inst| match inst {
+                X86Inst::Label(name) | X86Inst::Extern(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let mut externs: Vec<String> = Vec::new();
+        for inst in &self.code {
+            let operands = match inst {
+                X86Inst::Dq(value) => vec![value.clone()],
+                _ => inst.operands(),
+            };
+            for operand in operands {
+                let symbol = match operand {
+                    Operand::Symbol(symbol, _) => symbol,
+                    Operand::Memory(memory) => match memory.symbol {
+                        Some(symbol) => symbol,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                if !defined.contains(symbol.as_str()) && !externs.contains(&symbol) {
+                    externs.push(symbol);
+                }
+            }
+        }
+
+        self.code.splice(0..0, externs.into_iter().map(X86Inst::Extern));
     }
 
     pub fn emit_function_start(&mut self, name: &str) {
-        self.code.push(format!("{}:\n", name));
+        self.emit(X86Inst::Label(name.to_string()));
     }
 
-    pub fn emit_mov(&mut self, dest: &str, src: &str) {
-        self.code.push(format!("mov {}, {}\n", dest, src));
+    pub fn emit_mov(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Mov(dest.into(), src.into()));
     }
 
-    pub fn emit_call(&mut self, name: &str) {
-        self.code.push(format!("call {}\n", name));
+    pub fn emit_call(&mut self, name: impl Into<Operand>) {
+        self.emit(X86Inst::Call(name.into()));
     }
 
-    pub fn emit_syscall(&mut self) {
-        self.code.push("syscall\n".to_string());
+    pub fn emit_label(&mut self, name: &str) {
+        self.emit(X86Inst::Label(name.to_string()));
+    }
+
+    pub fn emit_push(&mut self, src: impl Into<Operand>) {
+        self.emit(X86Inst::Push(src.into()));
+    }
+
+    pub fn emit_pop(&mut self, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Pop(dest.into()));
+    }
+
+    fn emit_arithmetic(&mut self, op: ArithmeticOp, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Arithmetic(op, dest.into(), src.into()));
+    }
+
+    pub fn emit_add(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::Add, dest, src);
+    }
+
+    pub fn emit_sub(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::Sub, dest, src);
+    }
+
+    pub fn emit_imul(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Imul(dest.into(), src.into()));
+    }
+
+    pub fn emit_and(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::And, dest, src);
+    }
+
+    pub fn emit_or(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::Or, dest, src);
+    }
+
+    pub fn emit_xor(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::Xor, dest, src);
+    }
+
+    pub fn emit_neg(&mut self, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Neg(dest.into()));
+    }
+
+    pub fn emit_shl(&mut self, dest: impl Into<Operand>, count: impl Into<Operand>) {
+        self.emit(X86Inst::Shift(ShiftOp::Shl, dest.into(), count.into()));
+    }
+
+    pub fn emit_sar(&mut self, dest: impl Into<Operand>, count: impl Into<Operand>) {
+        self.emit(X86Inst::Shift(ShiftOp::Sar, dest.into(), count.into()));
+    }
+
+    pub fn emit_shr(&mut self, dest: impl Into<Operand>, count: impl Into<Operand>) {
+        self.emit(X86Inst::Shift(ShiftOp::Shr, dest.into(), count.into()));
+    }
+
+    // Sign extends eax into edx:eax
+    pub fn emit_cdq(&mut self) {
+        self.emit(X86Inst::Cdq);
+    }
+
+    // Sign extends rax into rdx:rax
+    pub fn emit_cqo(&mut self) {
+        self.emit(X86Inst::Cqo);
+    }
+
+    pub fn emit_movsx(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Movsx(dest.into(), src.into()));
+    }
+
+    pub fn emit_movsxd(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Movsxd(dest.into(), src.into()));
+    }
+
+    pub fn emit_movzx(&mut self, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Movzx(dest.into(), src.into()));
+    }
+
+    pub fn emit_setcc(&mut self, condition: Condition, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Setcc(condition, dest.into()));
+    }
+
+    pub fn emit_btc(&mut self, dest: impl Into<Operand>, bit: impl Into<Operand>) {
+        self.emit(X86Inst::Btc(dest.into(), bit.into()));
+    }
+
+    // Scalar SSE instructions
+    pub fn emit_sse(&mut self, op: SseOp, dest: impl Into<Operand>, src: impl Into<Operand>) {
+        self.emit(X86Inst::Sse(op, dest.into(), src.into()));
+    }
+
+    // x87 instructions, for what SSE has no instruction for
+    pub fn emit_fld(&mut self, src: impl Into<Operand>) {
+        self.emit(X86Inst::Fld(src.into()));
+    }
+
+    pub fn emit_fstp(&mut self, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Fstp(dest.into()));
+    }
+
+    pub fn emit_fmul(&mut self, src: impl Into<Operand>) {
+        self.emit(X86Inst::Fmul(src.into()));
+    }
+
+    pub fn emit_fdiv(&mut self, src: impl Into<Operand>) {
+        self.emit(X86Inst::Fdiv(src.into()));
+    }
+
+    // Stores st0 as an integer rounded to nearest and pops it
+    pub fn emit_fistp(&mut self, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Fistp(dest.into()));
+    }
+
+    pub fn emit_fld1(&mut self) {
+        self.emit(X86Inst::Fld1);
+    }
+
+    // st1 * log2(st0), popping st0
+    pub fn emit_fyl2x(&mut self) {
+        self.emit(X86Inst::Fyl2x);
+    }
+
+    // 2^st0 - 1 for st0 between -1 and 1
+    pub fn emit_f2xm1(&mut self) {
+        self.emit(X86Inst::F2xm1);
+    }
+
+    // st1 + st0, popping st0
+    pub fn emit_faddp(&mut self) {
+        self.emit(X86Inst::Faddp);
+    }
+
+    // st0 * 2^st1, st1 truncated to an integer
+    pub fn emit_fscale(&mut self) {
+        self.emit(X86Inst::Fscale);
+    }
+
+    pub fn emit_fprem(&mut self) {
+        self.emit(X86Inst::Fprem);
+    }
+
+    // Pushes ln 2, log2 e or pi, in the x87's 64 bits
+    pub fn emit_fldln2(&mut self) {
+        self.emit(X86Inst::Fldln2);
     }
 
-    pub fn emit_section_data(&mut self) {
-        self.code.push("section .data\n".to_string());
+    pub fn emit_fldl2e(&mut self) {
+        self.emit(X86Inst::Fldl2e);
+    }
+
+    pub fn emit_fldpi(&mut self) {
+        self.emit(X86Inst::Fldpi);
+    }
+
+    // The sine and cosine of st0, for st0 below 2^63
+    pub fn emit_fsin(&mut self) {
+        self.emit(X86Inst::Fsin);
+    }
+
+    pub fn emit_fcos(&mut self) {
+        self.emit(X86Inst::Fcos);
+    }
+
+    pub fn emit_fnstsw(&mut self, dest: impl Into<Operand>) {
+        self.emit(X86Inst::Fnstsw(dest.into()));
+    }
+
+    pub fn emit_idiv(&mut self, src: impl Into<Operand>) {
+        self.emit(X86Inst::Idiv(src.into()));
+    }
+
+    pub fn emit_cmp(&mut self, lhs: impl Into<Operand>, rhs: impl Into<Operand>) {
+        self.emit_arithmetic(ArithmeticOp::Cmp, lhs, rhs);
+    }
+
+    pub fn emit_test(&mut self, lhs: impl Into<Operand>, rhs: impl Into<Operand>) {
+        self.emit(X86Inst::Test(lhs.into(), rhs.into()));
+    }
+
+    // A label or a memory operand holding the target
+    pub fn emit_jmp(&mut self, target: impl Into<Operand>) {
+        self.emit(X86Inst::Jmp(target.into()));
+    }
+
+    pub fn emit_jcc(&mut self, condition: Condition, label: &str) {
+        self.emit(X86Inst::Jcc(condition, label.to_string()));
+    }
+
+    pub fn emit_leave(&mut self) {
+        self.emit(X86Inst::Leave);
+    }
+
+    pub fn emit_ret(&mut self) {
+        self.emit(X86Inst::Ret);
+    }
+
+    pub fn emit_syscall(&mut self) {
+        self.emit(X86Inst::Syscall);
     }
 
-    pub fn emit_global_data_section_elements(&mut self) {
-        self.code.push("global data_section_elements\n".to_string());
+    pub fn emit_align(&mut self, alignment: usize) {
+        self.emit(X86Inst::Align(alignment as u64));
     }
 
-    pub fn emit_data_section_elements(&mut self) {
-        self.code.push("data_section_elements:\n".to_string());
+    pub fn emit_dq(&mut self, value: impl Into<Operand>) {
+        self.emit(X86Inst::Dq(value.into()));
     }
 
-    pub fn emit_db(&mut self, value: &str) {
-        self.code.push(format!("db \"{}\", 10\n", value));
+    pub fn emit_db_bytes(&mut self, bytes: &[u8]) {
+        self.emit(X86Inst::Db(bytes.to_vec()));
     }
 }
@@ -0,0 +1,1344 @@
+use crate::bytecode::classpath::ClassPath;
+use crate::bytecode::ACC_INTERFACE;
+use crate::codegen::inst::{
+    byte, dword, qword, word, Condition, Memory, Register, AL, AX, CL, CX, DIL, DL, EAX, ECX, EDI, EDX, ESI, R10, R10D, R11, R12, R13, R14, R14D, R15, R15D, R8, R8D, R9,
+    R9D, RAX, RBP, RBX, RCX, RDI, RDX, RSI, RSP,
+};
+use crate::codegen::layout::{
+    class_layout, runtime_classes, vtable, ACCESS_FLAGS_OFFSET, ARRAY_DATA_OFFSET, ARRAY_LENGTH_OFFSET, CLASS_POINTER_OFFSET,
+    DEPTH_OFFSET, DISPLAY_OFFSET, ELEMENT_CLASS_OFFSET, ELEMENT_SIZE_OFFSET, INSTANCE_SIZE_OFFSET, INTERFACES_OFFSET,
+    ITABLE_OFFSET, NAME_OFFSET, PRINT_STREAM_CLASS, REFERENCE_FIELDS_OFFSET, STRING_CLASS, STRING_CODER_OFFSET, STRING_SIZE, STRING_VALUE_OFFSET, THROWABLE_CLASSES, UTF16,
+    ILLEGAL_MONITOR_STATE_EXCEPTION_CLASS, LOCK_COUNT_OFFSET, NO_CLASS_DEF_FOUND_ERROR_CLASS, THROWABLE_MESSAGE_OFFSET,
+};
+use crate::codegen::library::emit_library;
+use crate::codegen::mangle::{mangle_class, mangle_method};
+use crate::codegen::target::{OsAbi, Section, SignalAbi};
+use crate::codegen::x86_64::emit_class;
+use crate::codegen::Assembly;
+
+// The routines compiled code calls into, generated for the target and assembled into
+// their own object file.
+
+const UNCAUGHT_EXCEPTION_MESSAGE: &str = "Exception in thread \"main\" ";
+const MESSAGE_SEPARATOR: &str = ": ";
+const SEGMENTATION_FAULT_MESSAGE: &str = "A fatal error has been detected by the runtime: SIGSEGV outside of a null check\n";
+
+// The messages of the exceptions the runtime throws, put together like the JVM's
+const DIVIDE_BY_ZERO_MESSAGE: &str = "/ by zero";
+const INDEX_MESSAGE: &str = "Index ";
+const OUT_OF_BOUNDS_FOR_LENGTH_MESSAGE: &str = " out of bounds for length ";
+const CLASS_MESSAGE: &str = "class ";
+const CANNOT_BE_CAST_MESSAGE: &str = " cannot be cast to class ";
+const OUT_OF_MEMORY_MESSAGE: &str = "Java heap space";
+const COULD_NOT_INITIALIZE_MESSAGE: &str = "Could not initialize class ";
+
+// The longest message the runtime puts together, longer ones are cut off
+const MAX_MESSAGE_LENGTH: usize = 256;
+
+// The most objects the runtime's own routines hold on to at once, one for each dimension
+// of a multianewarray at most
+const MAX_HANDLES: usize = 256;
+
+pub const DEFAULT_HEAP_SIZE: usize = 64 << 20;
+
+/// Generates the runtime for programs with a heap of `heap_size` bytes, half of which
+/// holds objects while the collector copies them to the other half.
+pub fn generate(abi: &dyn OsAbi, heap_size: usize) -> Assembly {
+    let mut asm = Assembly::new();
+
+    asm.emit_section(abi.section_name(Section::Text));
+
+    emit_initialize(&mut asm, abi);
+    emit_implicit_exceptions(&mut asm, abi);
+    emit_message(&mut asm, abi);
+    emit_interface_method(&mut asm, abi);
+    emit_type_checks(&mut asm, abi);
+    emit_throwable_constructors(&mut asm, abi);
+    emit_throw(&mut asm, abi);
+    emit_monitors(&mut asm, abi);
+    emit_find_stack_map(&mut asm, abi);
+
+    let semispace_size = heap_size / 2 / 8 * 8;
+    emit_new(&mut asm, abi, semispace_size);
+    emit_new_array(&mut asm, abi);
+    emit_new_multi_array(&mut asm, abi);
+    emit_main_args(&mut asm, abi);
+    emit_collect(&mut asm, abi, semispace_size);
+    emit_library(&mut asm, abi);
+
+    asm.emit_section(abi.section_name(Section::Data));
+    asm.emit_align(8);
+    // The half of the heap objects are allocated in and the other one, then the free
+    // part of the first, all zero until the heap is mapped by the first allocation
+    asm.emit_label("runtime$heap_start");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$heap_spare");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$heap_top");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$heap_end");
+    asm.emit_dq(0);
+    // The objects the runtime's routines are working on, which the collector updates
+    asm.emit_label("runtime$handle_count");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$handles");
+    for _ in 0..MAX_HANDLES {
+        asm.emit_dq(0);
+    }
+    // The message of the next exception the runtime throws
+    asm.emit_label("runtime$message_length");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$message");
+    for _ in 0..MAX_MESSAGE_LENGTH / 8 {
+        asm.emit_dq(0);
+    }
+    if let Some(signal_abi) = abi.signal_abi() {
+        emit_sigaction(&mut asm, &signal_abi);
+    }
+    // Allocating an OutOfMemoryError would need the memory that ran out, so there is one
+    // outside the heap. The collector leaves it alone.
+    asm.emit_label("runtime$out_of_memory_error");
+    asm.emit_dq(abi.symbol(&mangle_class("java/lang/OutOfMemoryError")));
+    asm.emit_dq(0);
+    asm.emit_dq("runtime$out_of_memory_string");
+    asm.emit_label("runtime$out_of_memory_string");
+    asm.emit_dq(abi.symbol(&mangle_class(STRING_CLASS)));
+    asm.emit_dq(0);
+    asm.emit_dq("runtime$out_of_memory_bytes");
+    asm.emit_dq(0);
+    asm.emit_label("runtime$out_of_memory_bytes");
+    asm.emit_dq(abi.symbol(&mangle_class("[B")));
+    asm.emit_dq(0);
+    asm.emit_dq(OUT_OF_MEMORY_MESSAGE.len());
+    asm.emit_db_bytes(OUT_OF_MEMORY_MESSAGE.as_bytes());
+    // System.out and System.err, which compiled code loads the address of
+    asm.emit_align(8);
+    for (name, fd) in [("runtime$system_out", 1), ("runtime$system_err", 2)] {
+        let symbol = abi.symbol(name);
+        asm.emit_global(&symbol);
+        asm.emit_label(&symbol);
+        asm.emit_dq(abi.symbol(&mangle_class(PRINT_STREAM_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(fd);
+    }
+
+    asm.emit_section(abi.section_name(Section::ReadOnlyData));
+    asm.emit_align(8);
+    // None of them is on a class path
+    let classes = ClassPath::default();
+    for class_name in runtime_classes() {
+        let layout = class_layout(&classes, class_name).expect("The runtime's classes always have a layout");
+        let vtable = vtable(&classes, class_name).expect("The runtime's classes always have a vtable");
+        emit_class(&mut asm, abi, &layout, &vtable, &[]);
+    }
+
+    asm.emit_label("runtime$newline");
+    asm.emit_db_bytes(b"\n");
+    asm.emit_label("runtime$uncaught_exception_message");
+    asm.emit_db_bytes(UNCAUGHT_EXCEPTION_MESSAGE.as_bytes());
+    asm.emit_label("runtime$message_separator");
+    asm.emit_db_bytes(MESSAGE_SEPARATOR.as_bytes());
+    asm.emit_label("runtime$segmentation_fault_message");
+    asm.emit_db_bytes(SEGMENTATION_FAULT_MESSAGE.as_bytes());
+    asm.emit_label("runtime$divide_by_zero_message");
+    asm.emit_db_bytes(DIVIDE_BY_ZERO_MESSAGE.as_bytes());
+    asm.emit_label("runtime$index_message");
+    asm.emit_db_bytes(INDEX_MESSAGE.as_bytes());
+    asm.emit_label("runtime$out_of_bounds_for_length_message");
+    asm.emit_db_bytes(OUT_OF_BOUNDS_FOR_LENGTH_MESSAGE.as_bytes());
+    asm.emit_label("runtime$class_message");
+    asm.emit_db_bytes(CLASS_MESSAGE.as_bytes());
+    asm.emit_label("runtime$cannot_be_cast_message");
+    asm.emit_db_bytes(CANNOT_BE_CAST_MESSAGE.as_bytes());
+    asm.emit_label("runtime$could_not_initialize_message");
+    asm.emit_db_bytes(COULD_NOT_INITIALIZE_MESSAGE.as_bytes());
+
+    // The tables the compiler emits with the program
+    asm.declare_externs();
+    asm
+}
+
+// Writes `message`, which is at `label`, to stderr
+fn emit_write_error(asm: &mut Assembly, abi: &dyn OsAbi, label: &str, message: &str) {
+    asm.emit_mov(RAX, abi.write_syscall());
+    asm.emit_mov(RDI, 2);
+    asm.emit_mov(RSI, label);
+    asm.emit_mov(RDX, message.len());
+    asm.emit_syscall();
+}
+
+fn emit_exit_failure(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_mov(RAX, abi.exit_syscall());
+    asm.emit_mov(RDI, 1);
+    asm.emit_syscall();
+}
+
+// Writes `message` to stderr and exits with status 1
+fn emit_fatal_error(asm: &mut Assembly, abi: &dyn OsAbi, label: &str, message: &str) {
+    emit_write_error(asm, abi, label, message);
+    emit_exit_failure(asm, abi);
+}
+
+/// `runtime$initialize` prepares the runtime before main runs. Where the target allows
+/// it a SIGSEGV handler turns the faults of implicit null checks into
+/// NullPointerExceptions: the compiler lists each access that faults on null with the
+/// address after it, which has a stack map, in a table:
+///
+/// dq <number of accesses>
+/// dq <faulting instruction>, <address after it>...
+///
+/// The handler makes it look like the access called `runtime$throw_null_pointer_exception`
+/// and returned to the address after it.
+fn emit_initialize(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let initialize = abi.symbol("runtime$initialize");
+    asm.emit_global(&initialize);
+    asm.emit_function_start(&initialize);
+    let Some(signal_abi) = abi.signal_abi() else {
+        asm.emit_ret();
+        return;
+    };
+
+    // rt_sigaction(SIGSEGV, &action, NULL, sizeof(sigset_t))
+    asm.emit_mov(RAX, signal_abi.sigaction_syscall);
+    asm.emit_mov(RDI, signal_abi.sigsegv);
+    asm.emit_mov(RSI, "runtime$sigaction");
+    asm.emit_xor(EDX, EDX);
+    asm.emit_mov(R10, 8);
+    asm.emit_syscall();
+    asm.emit_ret();
+
+    // rdx holds the ucontext with the registers at the fault
+    asm.emit_label("runtime$segmentation_fault");
+    asm.emit_mov(RAX, qword(RDX + signal_abi.rip_offset));
+    asm.emit_mov(R8, abi.symbol("runtime$null_checks"));
+    asm.emit_mov(RCX, qword(R8));
+    asm.emit_add(R8, 8);
+    asm.emit_label("runtime$segmentation_fault.find");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$segmentation_fault.fatal");
+    asm.emit_cmp(qword(R8), RAX);
+    asm.emit_jcc(Condition::E, "runtime$segmentation_fault.null_check");
+    asm.emit_add(R8, 16);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$segmentation_fault.find");
+    asm.emit_label("runtime$segmentation_fault.null_check");
+    asm.emit_mov(RCX, qword(RDX + signal_abi.rsp_offset));
+    asm.emit_sub(RCX, 8);
+    asm.emit_mov(RAX, qword(R8 + 8));
+    asm.emit_mov(qword(RCX), RAX);
+    asm.emit_mov(qword(RDX + signal_abi.rsp_offset), RCX);
+    asm.emit_mov(RAX, abi.symbol("runtime$throw_null_pointer_exception"));
+    asm.emit_mov(qword(RDX + signal_abi.rip_offset), RAX);
+    asm.emit_ret();
+    asm.emit_label("runtime$segmentation_fault.fatal");
+    emit_fatal_error(asm, abi, "runtime$segmentation_fault_message", SEGMENTATION_FAULT_MESSAGE);
+
+    // The handler returns here, which has the kernel restore the registers
+    asm.emit_label("runtime$signal_return");
+    asm.emit_mov(RAX, signal_abi.sigreturn_syscall);
+    asm.emit_syscall();
+}
+
+// The kernel's struct sigaction for the SIGSEGV handler: the handler, the flags, the
+// restorer and an empty signal mask
+fn emit_sigaction(asm: &mut Assembly, signal_abi: &SignalAbi) {
+    asm.emit_label("runtime$sigaction");
+    asm.emit_dq("runtime$segmentation_fault");
+    asm.emit_dq(signal_abi.flags);
+    asm.emit_dq("runtime$signal_return");
+    asm.emit_dq(0);
+}
+
+// Throws a new exception of class `class_name` with the message put together so far
+pub(crate) fn emit_throw_new(asm: &mut Assembly, abi: &dyn OsAbi, class_name: &str) {
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_jmp("runtime$throw_new");
+}
+
+pub(crate) fn emit_clear_message(asm: &mut Assembly) {
+    asm.emit_mov(qword(Memory::symbol("runtime$message_length")), 0);
+}
+
+// Adds the text at `label` to the message
+pub(crate) fn emit_append(asm: &mut Assembly, label: &str, text: &str) {
+    asm.emit_mov(RSI, label);
+    asm.emit_mov(RDX, text.len());
+    asm.emit_call("runtime$append");
+}
+
+/// The exceptions the JVM throws without an athrow. Compiled code calls them, so the
+/// return address is where the exception is thrown from.
+fn emit_implicit_exceptions(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let throw_arithmetic_exception = abi.symbol("runtime$throw_arithmetic_exception");
+    asm.emit_global(&throw_arithmetic_exception);
+    asm.emit_function_start(&throw_arithmetic_exception);
+    emit_clear_message(asm);
+    emit_append(asm, "runtime$divide_by_zero_message", DIVIDE_BY_ZERO_MESSAGE);
+    emit_throw_new(asm, abi, "java/lang/ArithmeticException");
+
+    let throw_null_pointer_exception = abi.symbol("runtime$throw_null_pointer_exception");
+    asm.emit_global(&throw_null_pointer_exception);
+    asm.emit_function_start(&throw_null_pointer_exception);
+    emit_clear_message(asm);
+    emit_throw_new(asm, abi, "java/lang/NullPointerException");
+
+    // edi holds the index and esi the length. Nothing returns here, so any register will
+    // do to keep them.
+    let index_out_of_bounds = abi.symbol("runtime$throw_array_index_out_of_bounds");
+    asm.emit_global(&index_out_of_bounds);
+    asm.emit_function_start(&index_out_of_bounds);
+    asm.emit_movsxd(RBX, EDI);
+    asm.emit_movsxd(R12, ESI);
+    emit_clear_message(asm);
+    emit_append(asm, "runtime$index_message", INDEX_MESSAGE);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_decimal");
+    emit_append(asm, "runtime$out_of_bounds_for_length_message", OUT_OF_BOUNDS_FOR_LENGTH_MESSAGE);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_decimal");
+    emit_throw_new(asm, abi, "java/lang/ArrayIndexOutOfBoundsException");
+
+    // rdi holds the size
+    asm.emit_label("runtime$throw_negative_array_size");
+    asm.emit_mov(RBX, RDI);
+    emit_clear_message(asm);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_decimal");
+    emit_throw_new(asm, abi, "java/lang/NegativeArraySizeException");
+
+    // rdi holds the class of the object that was stored
+    asm.emit_label("runtime$throw_array_store");
+    asm.emit_mov(RBX, RDI);
+    emit_clear_message(asm);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_class_name");
+    emit_throw_new(asm, abi, "java/lang/ArrayStoreException");
+
+    // rdi holds the object's class and rsi the class it was cast to
+    asm.emit_label("runtime$throw_class_cast");
+    asm.emit_mov(RBX, RDI);
+    asm.emit_mov(R12, RSI);
+    emit_clear_message(asm);
+    emit_append(asm, "runtime$class_message", CLASS_MESSAGE);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_class_name");
+    emit_append(asm, "runtime$cannot_be_cast_message", CANNOT_BE_CAST_MESSAGE);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$append_class_name");
+    emit_throw_new(asm, abi, "java/lang/ClassCastException");
+
+    asm.emit_label("runtime$throw_abstract_method_error");
+    emit_clear_message(asm);
+    emit_throw_new(asm, abi, "java/lang/AbstractMethodError");
+
+    // rdi holds the class whose initializer threw before
+    let no_class_def_found = abi.symbol("runtime$throw_no_class_def_found_error");
+    asm.emit_global(&no_class_def_found);
+    asm.emit_function_start(&no_class_def_found);
+    asm.emit_mov(RBX, RDI);
+    emit_clear_message(asm);
+    emit_append(asm, "runtime$could_not_initialize_message", COULD_NOT_INITIALIZE_MESSAGE);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_call("runtime$append_class_name");
+    emit_throw_new(asm, abi, NO_CLASS_DEF_FOUND_ERROR_CLASS);
+}
+
+/// `runtime$monitor_enter` and `runtime$monitor_exit` take the monitor of the object in
+/// rdi, which is not null, and let it go. There is one thread, so entering always
+/// succeeds and only counts how many times the monitor is held, which exiting one that
+/// isn't held finds out. Threads would have these wait and record the owner.
+fn emit_monitors(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let monitor_enter = abi.symbol("runtime$monitor_enter");
+    asm.emit_global(&monitor_enter);
+    asm.emit_function_start(&monitor_enter);
+    asm.emit_add(dword(RDI + LOCK_COUNT_OFFSET), 1);
+    asm.emit_ret();
+
+    let monitor_exit = abi.symbol("runtime$monitor_exit");
+    asm.emit_global(&monitor_exit);
+    asm.emit_function_start(&monitor_exit);
+    asm.emit_cmp(dword(RDI + LOCK_COUNT_OFFSET), 0);
+    asm.emit_jcc(Condition::E, "runtime$monitor_exit.not_held");
+    asm.emit_sub(dword(RDI + LOCK_COUNT_OFFSET), 1);
+    asm.emit_ret();
+    asm.emit_label("runtime$monitor_exit.not_held");
+    emit_clear_message(asm);
+    emit_throw_new(asm, abi, ILLEGAL_MONITOR_STATE_EXCEPTION_CLASS);
+}
+
+/// `runtime$throw_new` throws a new exception of the class in rdi, with the message put
+/// together by `runtime$append` and the routines after it unless it is empty. rcx holds
+/// the return address into the compiled code throwing it.
+fn emit_message(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$throw_new");
+    asm.emit_push(RDI);
+    asm.emit_push(RCX);
+    asm.emit_xor(EAX, EAX);
+    asm.emit_cmp(qword(Memory::symbol("runtime$message_length")), 0);
+    asm.emit_jcc(Condition::E, "runtime$throw_new.exception");
+    asm.emit_mov(RDI, abi.symbol(&mangle_class("[B")));
+    asm.emit_mov(RSI, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_call("runtime$allocate_array");
+    asm.emit_mov(RSI, "runtime$message");
+    asm.emit_xor(ECX, ECX);
+    asm.emit_label("runtime$throw_new.copy");
+    asm.emit_cmp(RCX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_jcc(Condition::Ae, "runtime$throw_new.string");
+    asm.emit_mov(DL, byte(RSI + RCX));
+    asm.emit_mov(byte(RAX + RCX + ARRAY_DATA_OFFSET), DL);
+    asm.emit_add(RCX, 1);
+    asm.emit_jmp("runtime$throw_new.copy");
+    // A Latin-1 string, its coder is zero
+    asm.emit_label("runtime$throw_new.string");
+    emit_push_handle(asm, RAX);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(STRING_CLASS)));
+    asm.emit_mov(RSI, STRING_SIZE);
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_call("runtime$allocate");
+    emit_top_handle(asm, RDX);
+    emit_pop_handle(asm);
+    asm.emit_mov(qword(RAX + STRING_VALUE_OFFSET), RDX);
+    asm.emit_label("runtime$throw_new.exception");
+    emit_push_handle(asm, RAX);
+    asm.emit_mov(RDI, qword(RSP + 8));
+    asm.emit_mov(RSI, qword(RDI + INSTANCE_SIZE_OFFSET));
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_call("runtime$allocate");
+    emit_top_handle(asm, RDX);
+    emit_pop_handle(asm);
+    asm.emit_mov(qword(RAX + THROWABLE_MESSAGE_OFFSET), RDX);
+    asm.emit_pop(RSI);
+    asm.emit_pop(RDI);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RDX, RBP);
+    asm.emit_jmp("runtime$unwind");
+
+    // rsi holds the address of the text and rdx its length, rax, rcx and r8 are changed
+    asm.emit_label("runtime$append");
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_mov(R8, "runtime$message");
+    asm.emit_label("runtime$append.byte");
+    asm.emit_test(RDX, RDX);
+    asm.emit_jcc(Condition::E, "runtime$append.done");
+    asm.emit_cmp(RAX, MAX_MESSAGE_LENGTH);
+    asm.emit_jcc(Condition::Ae, "runtime$append.done");
+    asm.emit_mov(CL, byte(RSI));
+    asm.emit_mov(byte(R8 + RAX), CL);
+    asm.emit_add(RAX, 1);
+    asm.emit_add(RSI, 1);
+    asm.emit_sub(RDX, 1);
+    asm.emit_jmp("runtime$append.byte");
+    asm.emit_label("runtime$append.done");
+    asm.emit_mov(qword(Memory::symbol("runtime$message_length")), RAX);
+    asm.emit_ret();
+
+    // dil holds the byte, rax and r8 are changed
+    asm.emit_label("runtime$append_byte");
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$message_length")));
+    asm.emit_cmp(RAX, MAX_MESSAGE_LENGTH);
+    asm.emit_jcc(Condition::Ae, "runtime$append_byte.done");
+    asm.emit_mov(R8, "runtime$message");
+    asm.emit_mov(byte(R8 + RAX), DIL);
+    asm.emit_add(RAX, 1);
+    asm.emit_mov(qword(Memory::symbol("runtime$message_length")), RAX);
+    asm.emit_label("runtime$append_byte.done");
+    asm.emit_ret();
+
+    // rsi holds the class
+    asm.emit_label("runtime$append_class_name");
+    asm.emit_mov(RAX, qword(RSI + NAME_OFFSET));
+    asm.emit_mov(RDX, qword(RAX));
+    asm.emit_mov(RSI, RAX);
+    asm.emit_add(RSI, 8);
+    asm.emit_jmp("runtime$append");
+
+    // rsi holds a signed number. Its digits are put together from the end of a buffer on
+    // the stack, from its negative so the smallest long has one too.
+    asm.emit_label("runtime$append_decimal");
+    asm.emit_sub(RSP, 32);
+    asm.emit_mov(R9, RSP);
+    asm.emit_add(R9, 32);
+    asm.emit_mov(RAX, RSI);
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::S, "runtime$append_decimal.digit");
+    asm.emit_neg(RAX);
+    asm.emit_label("runtime$append_decimal.digit");
+    asm.emit_mov(RCX, 10);
+    asm.emit_cqo();
+    asm.emit_idiv(RCX);
+    asm.emit_neg(RDX);
+    asm.emit_add(DL, 48);
+    asm.emit_sub(R9, 1);
+    asm.emit_mov(byte(R9), DL);
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, "runtime$append_decimal.digit");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::Ns, "runtime$append_decimal.append");
+    asm.emit_sub(R9, 1);
+    asm.emit_mov(byte(R9), 45);
+    asm.emit_label("runtime$append_decimal.append");
+    asm.emit_mov(RSI, R9);
+    asm.emit_mov(RDX, RSP);
+    asm.emit_add(RDX, 32);
+    asm.emit_sub(RDX, R9);
+    asm.emit_call("runtime$append");
+    asm.emit_add(RSP, 32);
+    asm.emit_ret();
+
+    // rsi holds an unsigned number, whose digits are appended from the first one that
+    // isn't zero. rdi is kept.
+    asm.emit_label("runtime$append_hex");
+    asm.emit_push(RDI);
+    asm.emit_mov(ECX, 60);
+    asm.emit_label("runtime$append_hex.skip");
+    asm.emit_test(ECX, ECX);
+    asm.emit_jcc(Condition::E, "runtime$append_hex.digit");
+    asm.emit_mov(RAX, RSI);
+    asm.emit_shr(RAX, CL);
+    asm.emit_test(EAX, 15);
+    asm.emit_jcc(Condition::Ne, "runtime$append_hex.digit");
+    asm.emit_sub(ECX, 4);
+    asm.emit_jmp("runtime$append_hex.skip");
+    asm.emit_label("runtime$append_hex.digit");
+    asm.emit_mov(RAX, RSI);
+    asm.emit_shr(RAX, CL);
+    asm.emit_and(EAX, 15);
+    asm.emit_cmp(EAX, 10);
+    asm.emit_jcc(Condition::B, "runtime$append_hex.decimal");
+    // From 'a' on instead of after '9'
+    asm.emit_add(EAX, 39);
+    asm.emit_label("runtime$append_hex.decimal");
+    asm.emit_add(EAX, 48);
+    asm.emit_mov(EDI, EAX);
+    asm.emit_call("runtime$append_byte");
+    asm.emit_sub(ECX, 4);
+    asm.emit_jcc(Condition::Ns, "runtime$append_hex.digit");
+    asm.emit_pop(RDI);
+    asm.emit_ret();
+}
+
+/// `runtime$interface_method` returns in rax the method the receiver in rdi has for the
+/// selector in rax, searching its class's itable. It leaves the argument registers alone
+/// so the method can be called right after.
+fn emit_interface_method(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let interface_method = abi.symbol("runtime$interface_method");
+    asm.emit_global(&interface_method);
+    asm.emit_function_start(&interface_method);
+    asm.emit_mov(R11, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_mov(R11, qword(R11 + ITABLE_OFFSET));
+    asm.emit_mov(R10, qword(R11));
+    asm.emit_label("runtime$interface_method.find");
+    asm.emit_test(R10, R10);
+    asm.emit_jcc(Condition::E, "runtime$interface_method.missing");
+    asm.emit_cmp(qword(R11 + 8), RAX);
+    asm.emit_jcc(Condition::E, "runtime$interface_method.found");
+    asm.emit_add(R11, 16);
+    asm.emit_sub(R10, 1);
+    asm.emit_jmp("runtime$interface_method.find");
+    asm.emit_label("runtime$interface_method.found");
+    asm.emit_mov(RAX, qword(R11 + 16));
+    asm.emit_ret();
+
+    // The class has no method for it, or only an abstract one
+    asm.emit_label("runtime$interface_method.missing");
+    asm.emit_jmp("runtime$throw_abstract_method_error");
+}
+
+/// The constructors of the runtime's exceptions taking nothing or a message, each class
+/// has its own symbols for the same code.
+fn emit_throwable_constructors(asm: &mut Assembly, abi: &dyn OsAbi) {
+    for (class_name, _) in THROWABLE_CLASSES {
+        let symbol = abi.symbol(&mangle_method(class_name, "<init>", "()V"));
+        asm.emit_global(&symbol);
+        asm.emit_function_start(&symbol);
+    }
+    // A new object's message is already null
+    asm.emit_ret();
+
+    for (class_name, _) in THROWABLE_CLASSES {
+        let symbol = abi.symbol(&mangle_method(class_name, "<init>", "(Ljava/lang/String;)V"));
+        asm.emit_global(&symbol);
+        asm.emit_function_start(&symbol);
+    }
+    asm.emit_mov(qword(RDI + THROWABLE_MESSAGE_OFFSET), RSI);
+    asm.emit_ret();
+}
+
+/// `runtime$throw` throws the exception in rdi. Starting with the method that called it,
+/// each frame's return address is looked up in the exception table the compiler emits:
+///
+/// dq <number of ranges>
+/// dq <start>, <end>, <handler>, <class caught or zero for any>...
+///
+/// The ranges of a method come in the order of its exception table, so an inner handler
+/// is found before an outer one. A handler is entered with rbp set to its frame and the
+/// exception in rax. Once a return address has no stack map the frames of compiled code
+/// have run out and the exception is reported as uncaught.
+///
+/// `runtime$unwind` does the same for the return address in rsi and the frame in rdx.
+fn emit_throw(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let throw = abi.symbol("runtime$throw");
+    asm.emit_global(&throw);
+    asm.emit_function_start(&throw);
+    // Throwing null throws a NullPointerException instead
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, &abi.symbol("runtime$throw_null_pointer_exception"));
+    asm.emit_mov(RSI, qword(RSP));
+    asm.emit_mov(RDX, RBP);
+
+    // rbx holds the exception, r12 the return address, r13 the frame, r14 walks the
+    // exception table and r15 counts its ranges down. Nothing is returned to, so they
+    // need not be saved.
+    asm.emit_label("runtime$unwind");
+    asm.emit_mov(RBX, RDI);
+    asm.emit_mov(R12, RSI);
+    asm.emit_mov(R13, RDX);
+    asm.emit_label("runtime$unwind.frame");
+    asm.emit_mov(RDI, R12);
+    asm.emit_call("runtime$find_stack_map");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$unwind.uncaught");
+    asm.emit_mov(R14, abi.symbol("runtime$exception_table"));
+    asm.emit_mov(R15, qword(R14));
+    asm.emit_add(R14, 8);
+
+    // The return address is after the call, so it can be the end of its range
+    asm.emit_label("runtime$unwind.range");
+    asm.emit_test(R15, R15);
+    asm.emit_jcc(Condition::E, "runtime$unwind.caller");
+    asm.emit_cmp(R12, qword(R14));
+    asm.emit_jcc(Condition::Be, "runtime$unwind.next");
+    asm.emit_cmp(R12, qword(R14 + 8));
+    asm.emit_jcc(Condition::A, "runtime$unwind.next");
+    asm.emit_mov(RSI, qword(R14 + 24));
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, "runtime$unwind.catch");
+    asm.emit_mov(RDI, qword(RBX + CLASS_POINTER_OFFSET));
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, "runtime$unwind.catch");
+    asm.emit_label("runtime$unwind.next");
+    asm.emit_add(R14, 32);
+    asm.emit_sub(R15, 1);
+    asm.emit_jmp("runtime$unwind.range");
+
+    asm.emit_label("runtime$unwind.caller");
+    asm.emit_mov(R12, qword(R13 + 8));
+    asm.emit_mov(R13, qword(R13));
+    asm.emit_jmp("runtime$unwind.frame");
+
+    // The handler sets rsp for its frame
+    asm.emit_label("runtime$unwind.catch");
+    // The runtime's routines the exception came through are left for good, and with
+    // them the objects they held
+    asm.emit_mov(qword(Memory::symbol("runtime$handle_count")), 0);
+    asm.emit_mov(RBP, R13);
+    asm.emit_mov(RAX, RBX);
+    asm.emit_jmp(qword(R14 + 16));
+
+    // Like the JVM, the class's name and then the message if there is one
+    asm.emit_label("runtime$unwind.uncaught");
+    emit_write_error(asm, abi, "runtime$uncaught_exception_message", UNCAUGHT_EXCEPTION_MESSAGE);
+    asm.emit_mov(RAX, qword(RBX + CLASS_POINTER_OFFSET));
+    asm.emit_mov(RCX, qword(RAX + NAME_OFFSET));
+    asm.emit_mov(RAX, abi.write_syscall());
+    asm.emit_mov(RDI, 2);
+    asm.emit_mov(RSI, RCX);
+    asm.emit_add(RSI, 8);
+    asm.emit_mov(RDX, qword(RCX));
+    asm.emit_syscall();
+    asm.emit_mov(R12, qword(RBX + THROWABLE_MESSAGE_OFFSET));
+    asm.emit_test(R12, R12);
+    asm.emit_jcc(Condition::E, "runtime$unwind.exit");
+    emit_write_error(asm, abi, "runtime$message_separator", MESSAGE_SEPARATOR);
+    asm.emit_mov(EDI, 2);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call("runtime$write_string");
+    asm.emit_call("runtime$flush");
+    asm.emit_label("runtime$unwind.exit");
+    emit_write_error(asm, abi, "runtime$newline", "\n");
+    emit_exit_failure(asm, abi);
+}
+
+/// `runtime$find_stack_map` returns the address of the stack map for the return address
+/// in rdi, or zero when it has none, changing only rax, rcx and r8. The compiler's
+/// stack maps are searched first, then the library's.
+fn emit_find_stack_map(asm: &mut Assembly, abi: &dyn OsAbi) {
+    asm.emit_label("runtime$find_stack_map");
+    asm.emit_mov(RAX, abi.symbol("runtime$stack_maps"));
+    asm.emit_call("runtime$find_stack_map.search");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, "runtime$find_stack_map.found");
+    asm.emit_mov(RAX, "runtime$library_stack_maps");
+
+    // rax holds the table
+    asm.emit_label("runtime$find_stack_map.search");
+    asm.emit_mov(RCX, qword(RAX));
+    asm.emit_add(RAX, 8);
+    asm.emit_label("runtime$find_stack_map.next");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$find_stack_map.missing");
+    asm.emit_cmp(qword(RAX), RDI);
+    asm.emit_jcc(Condition::E, "runtime$find_stack_map.found");
+    asm.emit_mov(R8, qword(RAX + 8));
+    asm.emit_shl(R8, 3);
+    asm.emit_add(R8, 16);
+    asm.emit_add(RAX, R8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$find_stack_map.next");
+    asm.emit_label("runtime$find_stack_map.missing");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_label("runtime$find_stack_map.found");
+    asm.emit_ret();
+}
+
+/// `runtime$new` allocates an instance of the class whose metadata is in rdi and returns
+/// it in rax, zeroed apart from its class pointer.
+///
+/// Objects are bump allocated by `runtime$allocate` and the collector runs when the
+/// current half of the heap is full. It takes the class in rdi, the size in rsi and the
+/// return address into compiled code in rcx, which is where the collector starts walking
+/// the stack, along with rbp. The runtime's routines never change rbp, apart from the
+/// library's methods with a frame, which have stack maps like compiled code.
+fn emit_new(asm: &mut Assembly, abi: &dyn OsAbi, semispace_size: usize) {
+    let new = abi.symbol("runtime$new");
+    asm.emit_global(&new);
+    asm.emit_function_start(&new);
+    asm.emit_mov(RSI, qword(RDI + INSTANCE_SIZE_OFFSET));
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_jmp("runtime$allocate");
+
+    asm.emit_label("runtime$allocate");
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$heap_top")));
+    asm.emit_mov(RDX, RAX);
+    asm.emit_add(RDX, RSI);
+    asm.emit_cmp(RDX, qword(Memory::symbol("runtime$heap_end")));
+    asm.emit_jcc(Condition::A, "runtime$allocate.collect");
+    asm.emit_label("runtime$allocate.bump");
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_top")), RDX);
+    // The memory may have held objects before the last collection
+    asm.emit_mov(RCX, RAX);
+    asm.emit_label("runtime$allocate.zero");
+    asm.emit_mov(qword(RCX), 0);
+    asm.emit_add(RCX, 8);
+    asm.emit_cmp(RCX, RDX);
+    asm.emit_jcc(Condition::B, "runtime$allocate.zero");
+    asm.emit_mov(qword(RAX + CLASS_POINTER_OFFSET), RDI);
+    asm.emit_ret();
+
+    asm.emit_label("runtime$allocate.collect");
+    asm.emit_cmp(qword(Memory::symbol("runtime$heap_end")), 0);
+    asm.emit_jcc(Condition::E, "runtime$allocate.map");
+    asm.emit_push(RDI);
+    asm.emit_push(RSI);
+    asm.emit_push(RCX);
+    asm.emit_mov(RDI, RCX);
+    asm.emit_mov(RSI, RBP);
+    asm.emit_call("runtime$collect");
+    asm.emit_pop(RCX);
+    asm.emit_pop(RSI);
+    asm.emit_pop(RDI);
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$heap_top")));
+    asm.emit_mov(RDX, RAX);
+    asm.emit_add(RDX, RSI);
+    asm.emit_cmp(RDX, qword(Memory::symbol("runtime$heap_end")));
+    asm.emit_jcc(Condition::A, "runtime$allocate.out_of_memory");
+    asm.emit_jmp("runtime$allocate.bump");
+
+    // Both halves are mapped at once, the kernel only backs the pages that get used
+    asm.emit_label("runtime$allocate.map");
+    asm.emit_push(RDI);
+    asm.emit_push(RSI);
+    asm.emit_push(RCX);
+    // mmap(NULL, heap size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)
+    asm.emit_mov(RAX, abi.mmap_syscall());
+    asm.emit_xor(EDI, EDI);
+    asm.emit_mov(RSI, 2 * semispace_size);
+    asm.emit_mov(RDX, 3);
+    asm.emit_mov(R10, abi.map_private_anonymous());
+    asm.emit_mov(R8, -1);
+    asm.emit_xor(R9D, R9D);
+    asm.emit_syscall();
+    // Linux returns -errno, which is above every address it maps
+    asm.emit_cmp(RAX, -4096);
+    asm.emit_jcc(Condition::A, "runtime$allocate.out_of_memory");
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_start")), RAX);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_top")), RAX);
+    asm.emit_mov(RSI, semispace_size);
+    asm.emit_add(RAX, RSI);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_end")), RAX);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_spare")), RAX);
+    asm.emit_pop(RCX);
+    asm.emit_pop(RSI);
+    asm.emit_pop(RDI);
+    asm.emit_jmp("runtime$allocate");
+
+    // Thrown from where the allocation was asked for
+    asm.emit_label("runtime$allocate.out_of_memory");
+    asm.emit_mov(RDI, "runtime$out_of_memory_error");
+    asm.emit_mov(RSI, RCX);
+    asm.emit_mov(RDX, RBP);
+    asm.emit_jmp("runtime$unwind");
+}
+
+// Puts the size of the object at `object`, whose class is at `class`, in `size`, the
+// elements included for arrays
+pub(crate) fn emit_object_size(asm: &mut Assembly, object: Register, class: Register, size: Register) {
+    asm.emit_mov(size, qword(class + ELEMENT_SIZE_OFFSET));
+    asm.emit_imul(size, qword(object + ARRAY_LENGTH_OFFSET));
+    asm.emit_add(size, qword(class + INSTANCE_SIZE_OFFSET));
+    asm.emit_add(size, 7);
+    asm.emit_and(size, -8);
+}
+
+// Keeps the object in `register` where the collector updates it, until it is popped
+pub(crate) fn emit_push_handle(asm: &mut Assembly, register: Register) {
+    asm.emit_mov(R8, qword(Memory::symbol("runtime$handle_count")));
+    asm.emit_mov(R9, "runtime$handles");
+    asm.emit_mov(qword(R9 + R8 * 8), register);
+    asm.emit_add(qword(Memory::symbol("runtime$handle_count")), 1);
+}
+
+// Loads the object pushed last into `register`
+pub(crate) fn emit_top_handle(asm: &mut Assembly, register: Register) {
+    asm.emit_mov(R8, qword(Memory::symbol("runtime$handle_count")));
+    asm.emit_mov(R9, "runtime$handles");
+    asm.emit_mov(register, qword(R9 + R8 * 8 - 8));
+}
+
+pub(crate) fn emit_pop_handle(asm: &mut Assembly) {
+    asm.emit_sub(qword(Memory::symbol("runtime$handle_count")), 1);
+}
+
+/// `runtime$new_array` allocates an array of the class in rdi with the length in rsi and
+/// returns it in rax. `runtime$allocate_array` does the same without checking the length,
+/// taking the return address into compiled code in rcx.
+fn emit_new_array(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let new_array = abi.symbol("runtime$new_array");
+    asm.emit_global(&new_array);
+    asm.emit_function_start(&new_array);
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::S, "runtime$new_array.negative");
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_jmp("runtime$allocate_array");
+    asm.emit_label("runtime$new_array.negative");
+    asm.emit_mov(RDI, RSI);
+    asm.emit_jmp("runtime$throw_negative_array_size");
+
+    asm.emit_label("runtime$allocate_array");
+    asm.emit_push(RSI);
+    asm.emit_mov(RAX, qword(RDI + ELEMENT_SIZE_OFFSET));
+    asm.emit_imul(RAX, RSI);
+    asm.emit_add(RAX, qword(RDI + INSTANCE_SIZE_OFFSET));
+    asm.emit_add(RAX, 7);
+    asm.emit_and(RAX, -8);
+    asm.emit_mov(RSI, RAX);
+    asm.emit_call("runtime$allocate");
+    asm.emit_pop(RDX);
+    asm.emit_mov(qword(RAX + ARRAY_LENGTH_OFFSET), RDX);
+    asm.emit_ret();
+}
+
+/// `runtime$new_multi_array` allocates an array of the class in rdi with as many
+/// dimensions as rsi says, the outer ones filled with arrays of the inner ones. The
+/// lengths are in the operand stack slots from the one rdx points to down, the outermost
+/// first. No array is allocated unless every length is valid.
+fn emit_new_multi_array(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let new_multi_array = abi.symbol("runtime$new_multi_array");
+    asm.emit_global(&new_multi_array);
+    asm.emit_function_start(&new_multi_array);
+    asm.emit_mov(R8, RDX);
+    asm.emit_mov(RCX, RSI);
+    asm.emit_label("runtime$new_multi_array.check");
+    asm.emit_movsxd(RAX, dword(R8));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::S, "runtime$new_multi_array.negative");
+    asm.emit_sub(R8, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$new_multi_array.check");
+    asm.emit_mov(RCX, qword(RSP));
+    asm.emit_jmp("runtime$multi_array");
+    asm.emit_label("runtime$new_multi_array.negative");
+    asm.emit_mov(RDI, RAX);
+    asm.emit_jmp("runtime$throw_negative_array_size");
+
+    // rbx holds the class, r12 the dimensions left, r13 the length's slot, r14 the return
+    // address into compiled code and r15 the index of the next element
+    asm.emit_label("runtime$multi_array");
+    asm.emit_push(RBX);
+    asm.emit_push(R12);
+    asm.emit_push(R13);
+    asm.emit_push(R14);
+    asm.emit_push(R15);
+    asm.emit_mov(RBX, RDI);
+    asm.emit_mov(R12, RSI);
+    asm.emit_mov(R13, RDX);
+    asm.emit_mov(R14, RCX);
+    asm.emit_movsxd(RSI, dword(R13));
+    asm.emit_call("runtime$allocate_array");
+    asm.emit_cmp(R12, 1);
+    asm.emit_jcc(Condition::E, "runtime$multi_array.done");
+    emit_push_handle(asm, RAX);
+    asm.emit_xor(R15D, R15D);
+    asm.emit_label("runtime$multi_array.element");
+    emit_top_handle(asm, RAX);
+    asm.emit_cmp(R15, qword(RAX + ARRAY_LENGTH_OFFSET));
+    asm.emit_jcc(Condition::Ge, "runtime$multi_array.filled");
+    asm.emit_mov(RDI, qword(RBX + ELEMENT_CLASS_OFFSET));
+    asm.emit_mov(RSI, R12);
+    asm.emit_sub(RSI, 1);
+    asm.emit_mov(RDX, R13);
+    asm.emit_sub(RDX, 8);
+    asm.emit_mov(RCX, R14);
+    asm.emit_call("runtime$multi_array");
+    asm.emit_mov(RDX, RAX);
+    emit_top_handle(asm, RAX);
+    asm.emit_mov(qword(RAX + R15 * 8 + ARRAY_DATA_OFFSET), RDX);
+    asm.emit_add(R15, 1);
+    asm.emit_jmp("runtime$multi_array.element");
+    asm.emit_label("runtime$multi_array.filled");
+    emit_top_handle(asm, RAX);
+    emit_pop_handle(asm);
+    asm.emit_label("runtime$multi_array.done");
+    asm.emit_pop(R15);
+    asm.emit_pop(R14);
+    asm.emit_pop(R13);
+    asm.emit_pop(R12);
+    asm.emit_pop(RBX);
+    asm.emit_ret();
+}
+
+/// `runtime$main_args` returns main's String[] argument, made from the rdi command line
+/// arguments whose addresses are at rsi, argc and argv the way C's main gets them. Their
+/// bytes are decoded as UTF-8, the way the JDK's launcher does on Linux, into Latin-1
+/// strings when all the characters fit and UTF-16 ones otherwise.
+///
+/// `runtime$decode_utf8` decodes the character at rsi into eax and moves rsi past it.
+/// A byte that doesn't start a character, or a sequence cut short, overlong, a surrogate
+/// or past U+10FFFF, becomes U+FFFD and rsi moves to the first byte that doesn't belong
+/// to it. A NUL always ends a sequence. It changes only rax, rcx, rdx, rsi and r8.
+fn emit_main_args(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let main_args = abi.symbol("runtime$main_args");
+    asm.emit_global(&main_args);
+    asm.emit_function_start(&main_args);
+    asm.emit_push(RBX);
+    asm.emit_push(R12);
+    asm.emit_push(R13);
+    asm.emit_push(R14);
+    asm.emit_push(R15);
+    // r12 holds the number of arguments and r13 their addresses, without the program's name
+    asm.emit_mov(R12, RDI);
+    asm.emit_sub(R12, 1);
+    asm.emit_mov(R13, RSI);
+    asm.emit_add(R13, 8);
+    // No compiled code is running yet, so the collector has no frames to walk
+    asm.emit_mov(RDI, abi.symbol(&mangle_class("[Ljava/lang/String;")));
+    asm.emit_mov(RSI, R12);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_call("runtime$allocate_array");
+    emit_push_handle(asm, RAX);
+
+    // r15 counts the arguments, rbx points to the current one, r14 is how many UTF-16
+    // characters it has and r9 their coder
+    asm.emit_xor(R15D, R15D);
+    asm.emit_label("runtime$main_args.argument");
+    asm.emit_cmp(R15, R12);
+    asm.emit_jcc(Condition::Ge, "runtime$main_args.done");
+    asm.emit_mov(RBX, qword(R13 + R15 * 8));
+    asm.emit_mov(RSI, RBX);
+    asm.emit_xor(R14D, R14D);
+    asm.emit_xor(R9D, R9D);
+    asm.emit_label("runtime$main_args.count");
+    asm.emit_cmp(byte(RSI), 0);
+    asm.emit_jcc(Condition::E, "runtime$main_args.string");
+    asm.emit_call("runtime$decode_utf8");
+    asm.emit_add(R14, 1);
+    asm.emit_cmp(EAX, 0xff);
+    asm.emit_jcc(Condition::Be, "runtime$main_args.count");
+    asm.emit_mov(R9D, UTF16);
+    // Characters past the BMP take a surrogate pair
+    asm.emit_cmp(EAX, 0x10000);
+    asm.emit_jcc(Condition::B, "runtime$main_args.count");
+    asm.emit_add(R14, 1);
+    asm.emit_jmp("runtime$main_args.count");
+
+    asm.emit_label("runtime$main_args.string");
+    asm.emit_mov(RDI, R14);
+    asm.emit_mov(RSI, R9);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_call("runtime$new_string");
+    emit_top_handle(asm, RDX);
+    asm.emit_mov(qword(RDX + R15 * 8 + ARRAY_DATA_OFFSET), RAX);
+
+    // Nothing is allocated while the characters are stored, rdi holds the string's
+    // array and r10 the index of the next character
+    asm.emit_movzx(R9D, byte(RAX + STRING_CODER_OFFSET));
+    asm.emit_mov(RDI, qword(RAX + STRING_VALUE_OFFSET));
+    asm.emit_xor(R10D, R10D);
+    asm.emit_mov(RSI, RBX);
+    asm.emit_label("runtime$main_args.store");
+    asm.emit_cmp(byte(RSI), 0);
+    asm.emit_jcc(Condition::E, "runtime$main_args.next");
+    asm.emit_call("runtime$decode_utf8");
+    asm.emit_test(R9, R9);
+    asm.emit_jcc(Condition::Ne, "runtime$main_args.utf16");
+    asm.emit_mov(byte(RDI + R10 + ARRAY_DATA_OFFSET), AL);
+    asm.emit_add(R10, 1);
+    asm.emit_jmp("runtime$main_args.store");
+    asm.emit_label("runtime$main_args.utf16");
+    asm.emit_cmp(EAX, 0x10000);
+    asm.emit_jcc(Condition::B, "runtime$main_args.bmp");
+    // 0xd800 + (c - 0x10000) / 0x400 and 0xdc00 + (c - 0x10000) % 0x400
+    asm.emit_sub(EAX, 0x10000);
+    asm.emit_mov(ECX, EAX);
+    asm.emit_shr(ECX, 10);
+    asm.emit_add(ECX, 0xd800);
+    asm.emit_mov(word(RDI + R10 * 2 + ARRAY_DATA_OFFSET), CX);
+    asm.emit_add(R10, 1);
+    asm.emit_and(EAX, 0x3ff);
+    asm.emit_add(EAX, 0xdc00);
+    asm.emit_label("runtime$main_args.bmp");
+    asm.emit_mov(word(RDI + R10 * 2 + ARRAY_DATA_OFFSET), AX);
+    asm.emit_add(R10, 1);
+    asm.emit_jmp("runtime$main_args.store");
+
+    asm.emit_label("runtime$main_args.next");
+    asm.emit_add(R15, 1);
+    asm.emit_jmp("runtime$main_args.argument");
+
+    asm.emit_label("runtime$main_args.done");
+    emit_top_handle(asm, RAX);
+    emit_pop_handle(asm);
+    asm.emit_pop(R15);
+    asm.emit_pop(R14);
+    asm.emit_pop(R13);
+    asm.emit_pop(R12);
+    asm.emit_pop(RBX);
+    asm.emit_ret();
+
+    // The lead byte gives the number of continuation bytes in rdx and the smallest
+    // character that needs them in r8
+    asm.emit_label("runtime$decode_utf8");
+    asm.emit_movzx(EAX, byte(RSI));
+    asm.emit_add(RSI, 1);
+    asm.emit_cmp(EAX, 0x80);
+    asm.emit_jcc(Condition::B, "runtime$decode_utf8.done");
+    asm.emit_cmp(EAX, 0xc2);
+    asm.emit_jcc(Condition::B, "runtime$decode_utf8.malformed");
+    asm.emit_cmp(EAX, 0xf5);
+    asm.emit_jcc(Condition::Ae, "runtime$decode_utf8.malformed");
+    asm.emit_cmp(EAX, 0xe0);
+    asm.emit_jcc(Condition::Ae, "runtime$decode_utf8.three_bytes");
+    asm.emit_and(EAX, 0x1f);
+    asm.emit_mov(EDX, 1);
+    asm.emit_mov(R8D, 0x80);
+    asm.emit_jmp("runtime$decode_utf8.continuation");
+    asm.emit_label("runtime$decode_utf8.three_bytes");
+    asm.emit_cmp(EAX, 0xf0);
+    asm.emit_jcc(Condition::Ae, "runtime$decode_utf8.four_bytes");
+    asm.emit_and(EAX, 0x0f);
+    asm.emit_mov(EDX, 2);
+    asm.emit_mov(R8D, 0x800);
+    asm.emit_jmp("runtime$decode_utf8.continuation");
+    asm.emit_label("runtime$decode_utf8.four_bytes");
+    asm.emit_and(EAX, 0x07);
+    asm.emit_mov(EDX, 3);
+    asm.emit_mov(R8D, 0x10000);
+    asm.emit_label("runtime$decode_utf8.continuation");
+    asm.emit_movzx(ECX, byte(RSI));
+    asm.emit_cmp(ECX, 0x80);
+    asm.emit_jcc(Condition::B, "runtime$decode_utf8.malformed");
+    asm.emit_cmp(ECX, 0xbf);
+    asm.emit_jcc(Condition::A, "runtime$decode_utf8.malformed");
+    asm.emit_shl(EAX, 6);
+    asm.emit_and(ECX, 0x3f);
+    asm.emit_or(EAX, ECX);
+    asm.emit_add(RSI, 1);
+    asm.emit_sub(EDX, 1);
+    asm.emit_jcc(Condition::Ne, "runtime$decode_utf8.continuation");
+    asm.emit_cmp(RAX, R8);
+    asm.emit_jcc(Condition::B, "runtime$decode_utf8.malformed");
+    asm.emit_cmp(EAX, 0x10ffff);
+    asm.emit_jcc(Condition::A, "runtime$decode_utf8.malformed");
+    asm.emit_cmp(EAX, 0xd800);
+    asm.emit_jcc(Condition::B, "runtime$decode_utf8.done");
+    asm.emit_cmp(EAX, 0xdfff);
+    asm.emit_jcc(Condition::A, "runtime$decode_utf8.done");
+    asm.emit_label("runtime$decode_utf8.malformed");
+    asm.emit_mov(EAX, 0xfffd);
+    asm.emit_label("runtime$decode_utf8.done");
+    asm.emit_ret();
+}
+
+/// `runtime$check_array_store` makes sure the object in rsi can be stored in the array of
+/// references in rdi and `runtime$check_cast` that the object in rdi, unless it is null,
+/// is an instance of the class in rsi. `runtime$instance_of` sets rax to 1 when it is
+/// and not null, to 0 otherwise.
+///
+/// `runtime$is_assignable` sets rax to 1 when instances of the class in rdi can be used
+/// as the class in rsi and to 0 otherwise, changing only rcx besides. Classes are looked
+/// up in the display, interfaces in the list of interfaces, and an array of references
+/// can be used as another when its elements can, see `layout`.
+fn emit_type_checks(asm: &mut Assembly, abi: &dyn OsAbi) {
+    let check_array_store = abi.symbol("runtime$check_array_store");
+    asm.emit_global(&check_array_store);
+    asm.emit_function_start(&check_array_store);
+    asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_mov(RDI, qword(RSI + CLASS_POINTER_OFFSET));
+    asm.emit_mov(RSI, qword(RAX + ELEMENT_CLASS_OFFSET));
+    asm.emit_mov(R8, RDI);
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$check_array_store.fail");
+    asm.emit_ret();
+    asm.emit_label("runtime$check_array_store.fail");
+    asm.emit_mov(RDI, R8);
+    asm.emit_jmp("runtime$throw_array_store");
+
+    let check_cast = abi.symbol("runtime$check_cast");
+    asm.emit_global(&check_cast);
+    asm.emit_function_start(&check_cast);
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, "runtime$check_cast.done");
+    asm.emit_mov(RDI, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_mov(R8, RDI);
+    asm.emit_mov(R9, RSI);
+    asm.emit_call("runtime$is_assignable");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$check_cast.fail");
+    asm.emit_label("runtime$check_cast.done");
+    asm.emit_ret();
+    asm.emit_label("runtime$check_cast.fail");
+    asm.emit_mov(RDI, R8);
+    asm.emit_mov(RSI, R9);
+    asm.emit_jmp("runtime$throw_class_cast");
+
+    let instance_of = abi.symbol("runtime$instance_of");
+    asm.emit_global(&instance_of);
+    asm.emit_function_start(&instance_of);
+    asm.emit_xor(EAX, EAX);
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, "runtime$instance_of.null");
+    asm.emit_mov(RDI, qword(RDI + CLASS_POINTER_OFFSET));
+    asm.emit_jmp("runtime$is_assignable");
+    asm.emit_label("runtime$instance_of.null");
+    asm.emit_ret();
+
+    asm.emit_label("runtime$is_assignable");
+    asm.emit_cmp(RDI, RSI);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.yes");
+    asm.emit_test(qword(RSI + ACCESS_FLAGS_OFFSET), ACC_INTERFACE);
+    asm.emit_jcc(Condition::Ne, "runtime$is_assignable.interface");
+    asm.emit_cmp(qword(RSI + ELEMENT_SIZE_OFFSET), 0);
+    asm.emit_jcc(Condition::Ne, "runtime$is_assignable.array");
+    // A superclass is as deep in the hierarchy as it is in the display
+    asm.emit_mov(RAX, qword(RSI + DEPTH_OFFSET));
+    asm.emit_cmp(RAX, qword(RDI + DEPTH_OFFSET));
+    asm.emit_jcc(Condition::A, "runtime$is_assignable.no");
+    asm.emit_mov(RCX, qword(RDI + DISPLAY_OFFSET));
+    asm.emit_cmp(qword(RCX + RAX * 8), RSI);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.yes");
+    asm.emit_jmp("runtime$is_assignable.no");
+
+    asm.emit_label("runtime$is_assignable.interface");
+    asm.emit_mov(RCX, qword(RDI + INTERFACES_OFFSET));
+    asm.emit_mov(RAX, qword(RCX));
+    asm.emit_label("runtime$is_assignable.next_interface");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.no");
+    asm.emit_cmp(qword(RCX + RAX * 8), RSI);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.yes");
+    asm.emit_sub(RAX, 1);
+    asm.emit_jmp("runtime$is_assignable.next_interface");
+
+    // Arrays of primitives are only the same array class, arrays of references compare
+    // their elements
+    asm.emit_label("runtime$is_assignable.array");
+    asm.emit_mov(RDI, qword(RDI + ELEMENT_CLASS_OFFSET));
+    asm.emit_mov(RSI, qword(RSI + ELEMENT_CLASS_OFFSET));
+    asm.emit_test(RDI, RDI);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.no");
+    asm.emit_test(RSI, RSI);
+    asm.emit_jcc(Condition::E, "runtime$is_assignable.no");
+    asm.emit_jmp("runtime$is_assignable");
+
+    asm.emit_label("runtime$is_assignable.no");
+    asm.emit_xor(EAX, EAX);
+    asm.emit_ret();
+    asm.emit_label("runtime$is_assignable.yes");
+    asm.emit_mov(EAX, 1);
+    asm.emit_ret();
+}
+
+/// `runtime$collect` is a copying collector. It copies the objects reachable from the
+/// stack frames of compiled methods to the spare half of the heap and allocates from
+/// there afterwards. rdi holds the return address into the innermost compiled method and
+/// rsi its rbp.
+///
+/// The stack maps the compiler emits say which slots of a frame hold references at each
+/// call. The frames are walked up to the first return address without a stack map, the
+/// one into the entry point. The objects copied so far are scanned in order for the
+/// references in their fields, which get copied after them, until none are left. The
+/// objects the runtime's routines hold and the static fields are roots too.
+///
+/// A copied object's class pointer is replaced with its new address plus one, metadata
+/// and objects are 8 byte aligned so the low bit tells the two apart.
+fn emit_collect(asm: &mut Assembly, abi: &dyn OsAbi, semispace_size: usize) {
+    asm.emit_label("runtime$collect");
+    asm.emit_push(RBX);
+    asm.emit_push(R12);
+    asm.emit_push(R13);
+    asm.emit_push(R14);
+    asm.emit_push(R15);
+    // r12 and r13 bound the half being collected, r14 is where the next copy goes and
+    // r15 the next copied object to scan
+    asm.emit_mov(R12, qword(Memory::symbol("runtime$heap_start")));
+    asm.emit_mov(R13, semispace_size);
+    asm.emit_add(R13, R12);
+    asm.emit_mov(R14, qword(Memory::symbol("runtime$heap_spare")));
+    asm.emit_mov(R15, R14);
+
+    // Look the return address in rdi up, rbx walks the slots of its stack map and rcx
+    // counts them down
+    asm.emit_label("runtime$collect.frame");
+    asm.emit_call("runtime$find_stack_map");
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::E, "runtime$collect.handles");
+    asm.emit_mov(RBX, RAX);
+    asm.emit_mov(RCX, qword(RBX + 8));
+    asm.emit_add(RBX, 16);
+    asm.emit_label("runtime$collect.root");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$collect.caller");
+    asm.emit_mov(RDX, RSI);
+    asm.emit_sub(RDX, qword(RBX));
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_add(RBX, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$collect.root");
+
+    // On to the frame of the method that called this one
+    asm.emit_label("runtime$collect.caller");
+    asm.emit_mov(RDI, qword(RSI + 8));
+    asm.emit_mov(RSI, qword(RSI));
+    asm.emit_jmp("runtime$collect.frame");
+
+    // The objects the runtime's routines hold
+    asm.emit_label("runtime$collect.handles");
+    asm.emit_mov(RCX, qword(Memory::symbol("runtime$handle_count")));
+    asm.emit_mov(RBX, "runtime$handles");
+    asm.emit_label("runtime$collect.handle");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$collect.statics");
+    asm.emit_mov(RDX, RBX);
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_add(RBX, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$collect.handle");
+
+    // The static fields holding references, from the compiler's table of their addresses,
+    // and the pool of interned strings
+    asm.emit_label("runtime$collect.statics");
+    asm.emit_mov(RDX, "runtime$intern_table");
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_mov(RBX, abi.symbol("runtime$static_roots"));
+    asm.emit_mov(RCX, qword(RBX));
+    asm.emit_add(RBX, 8);
+    asm.emit_label("runtime$collect.static");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$collect.scan");
+    asm.emit_mov(RDX, qword(RBX));
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_add(RBX, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$collect.static");
+
+    // The fields of the object at r15, rbx walks its reference offsets
+    asm.emit_label("runtime$collect.scan");
+    asm.emit_cmp(R15, R14);
+    asm.emit_jcc(Condition::Ae, "runtime$collect.done");
+    asm.emit_mov(RAX, qword(R15 + CLASS_POINTER_OFFSET));
+    asm.emit_mov(RBX, qword(RAX + REFERENCE_FIELDS_OFFSET));
+    asm.emit_mov(RCX, qword(RBX));
+    asm.emit_add(RBX, 8);
+    asm.emit_label("runtime$collect.field");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$collect.next");
+    asm.emit_mov(RDX, R15);
+    asm.emit_add(RDX, qword(RBX));
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_add(RBX, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$collect.field");
+    // And the elements of an array of references
+    asm.emit_label("runtime$collect.next");
+    asm.emit_mov(RAX, qword(R15 + CLASS_POINTER_OFFSET));
+    asm.emit_cmp(qword(RAX + ELEMENT_CLASS_OFFSET), 0);
+    asm.emit_jcc(Condition::E, "runtime$collect.scanned");
+    asm.emit_mov(RCX, qword(R15 + ARRAY_LENGTH_OFFSET));
+    asm.emit_mov(RBX, R15);
+    asm.emit_add(RBX, ARRAY_DATA_OFFSET);
+    asm.emit_label("runtime$collect.element");
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::E, "runtime$collect.scanned");
+    asm.emit_mov(RDX, RBX);
+    asm.emit_call("runtime$collect.forward");
+    asm.emit_add(RBX, 8);
+    asm.emit_sub(RCX, 1);
+    asm.emit_jmp("runtime$collect.element");
+    asm.emit_label("runtime$collect.scanned");
+    asm.emit_mov(RAX, qword(R15 + CLASS_POINTER_OFFSET));
+    emit_object_size(asm, R15, RAX, R9);
+    asm.emit_add(R15, R9);
+    asm.emit_jmp("runtime$collect.scan");
+
+    // The halves swap and allocation goes on after the copies
+    asm.emit_label("runtime$collect.done");
+    asm.emit_mov(RAX, qword(Memory::symbol("runtime$heap_spare")));
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_start")), RAX);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_spare")), R12);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_top")), R14);
+    asm.emit_mov(RDX, semispace_size);
+    asm.emit_add(RAX, RDX);
+    asm.emit_mov(qword(Memory::symbol("runtime$heap_end")), RAX);
+    asm.emit_pop(R15);
+    asm.emit_pop(R14);
+    asm.emit_pop(R13);
+    asm.emit_pop(R12);
+    asm.emit_pop(RBX);
+    asm.emit_ret();
+
+    // Points the reference at the address in rdx to the copy of its object, copying the
+    // object first unless that was done already. Null and references outside the half
+    // being collected are left alone.
+    asm.emit_label("runtime$collect.forward");
+    asm.emit_mov(RAX, qword(RDX));
+    asm.emit_cmp(RAX, R12);
+    asm.emit_jcc(Condition::B, "runtime$collect.forward_done");
+    asm.emit_cmp(RAX, R13);
+    asm.emit_jcc(Condition::Ae, "runtime$collect.forward_done");
+    asm.emit_mov(R8, qword(RAX + CLASS_POINTER_OFFSET));
+    asm.emit_test(R8, 1);
+    asm.emit_jcc(Condition::E, "runtime$collect.copy");
+    asm.emit_sub(R8, 1);
+    asm.emit_mov(qword(RDX), R8);
+    asm.emit_ret();
+    asm.emit_label("runtime$collect.copy");
+    emit_object_size(asm, RAX, R8, R9);
+    asm.emit_xor(R10D, R10D);
+    asm.emit_label("runtime$collect.copy_word");
+    asm.emit_mov(R11, qword(RAX + R10));
+    asm.emit_mov(qword(R14 + R10), R11);
+    asm.emit_add(R10, 8);
+    asm.emit_cmp(R10, R9);
+    asm.emit_jcc(Condition::B, "runtime$collect.copy_word");
+    asm.emit_mov(qword(RDX), R14);
+    asm.emit_mov(R8, R14);
+    asm.emit_or(R8, 1);
+    asm.emit_mov(qword(RAX + CLASS_POINTER_OFFSET), R8);
+    asm.emit_add(R14, R9);
+    asm.emit_label("runtime$collect.forward_done");
+    asm.emit_ret();
+}
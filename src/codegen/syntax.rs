@@ -0,0 +1,265 @@
+use crate::codegen::inst::{Memory, Operand, Register, RegisterKind, SseOp, X86Inst};
+
+// Prints instructions as assembler source, in NASM's Intel syntax for nasm and yasm or
+// in AT&T syntax for the GNU assembler.
+//
+// https://sourceware.org/binutils/docs/as/i386_002dVariations.html
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Nasm,
+    Att,
+}
+
+pub const DEFAULT_SYNTAX: &str = "nasm";
+
+pub fn syntax_by_name(name: &str) -> Result<Syntax, String> {
+    match name {
+        "nasm" | "intel" => Ok(Syntax::Nasm),
+        "att" | "gas" => Ok(Syntax::Att),
+        _ => Err(format!("Unknown syntax: {}, expected nasm or att", name)),
+    }
+}
+
+impl Syntax {
+    pub fn print(self, code: &[X86Inst]) -> String {
+        // NASM addresses a bare symbol absolutely unless told otherwise, the encoder and
+        // the AT&T printer make it RIP-relative
+        let prelude = match self {
+            Syntax::Nasm => "default rel\n",
+            Syntax::Att => "",
+        };
+        let lines = code.iter().map(|inst| match self {
+            Syntax::Nasm => nasm(inst),
+            Syntax::Att => att(inst),
+        });
+        lines.fold(prelude.to_string(), |text, line| text + &line + "\n")
+    }
+}
+
+// Data is always a list of numbers, so no string ever needs quoting or escaping
+fn byte_list(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| byte.to_string()).collect();
+    bytes.join(", ")
+}
+
+fn nasm_size(size: u8) -> &'static str {
+    match size {
+        1 => "byte ",
+        2 => "word ",
+        4 => "dword ",
+        8 => "qword ",
+        _ => "",
+    }
+}
+
+// `name + 8` or `name - 8`
+fn with_offset(name: &str, offset: i64) -> String {
+    match offset {
+        0 => name.to_string(),
+        offset if offset < 0 => format!("{} - {}", name, offset.unsigned_abs()),
+        offset => format!("{} + {}", name, offset),
+    }
+}
+
+fn nasm_memory(memory: &Memory) -> String {
+    let mut terms = Vec::new();
+    if let Some(base) = memory.base {
+        terms.push(base.name());
+    }
+    if let Some(symbol) = &memory.symbol {
+        terms.push(symbol.clone());
+    }
+    if let Some((index, scale)) = memory.index {
+        terms.push(format!("{}*{}", index.name(), scale));
+    }
+    let address = match terms.is_empty() {
+        true => memory.displacement.to_string(),
+        false => with_offset(&terms.join(" + "), memory.displacement),
+    };
+    format!("{}[{}]", nasm_size(memory.size.unwrap_or(0)), address)
+}
+
+fn nasm_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(register) => register.name(),
+        Operand::Memory(memory) => nasm_memory(memory),
+        Operand::Immediate(value) => value.to_string(),
+        Operand::Symbol(symbol, offset) => with_offset(symbol, *offset),
+    }
+}
+
+pub fn nasm(inst: &X86Inst) -> String {
+    match inst {
+        X86Inst::Section(name) => format!("section {}", name),
+        X86Inst::Global(name) => format!("global {}", name),
+        X86Inst::Extern(name) => format!("extern {}", name),
+        X86Inst::Label(name) => format!("{}:", name),
+        X86Inst::Align(alignment) => format!("align {}", alignment),
+        X86Inst::Db(bytes) => format!("db {}", byte_list(bytes)),
+        X86Inst::Dq(value) => format!("dq {}", nasm_operand(value)),
+        _ => {
+            let mnemonic = inst.mnemonic().unwrap_or_default();
+            let operands: Vec<String> = inst.operands().iter().map(nasm_operand).collect();
+            match operands.is_empty() {
+                true => mnemonic,
+                false => format!("{} {}", mnemonic, operands.join(", ")),
+            }
+        },
+    }
+}
+
+fn att_register(register: &Register) -> String {
+    match register.kind {
+        RegisterKind::X87 => format!("%st({})", register.number),
+        _ => format!("%{}", register.name()),
+    }
+}
+
+fn att_memory(memory: &Memory) -> String {
+    let displacement = match &memory.symbol {
+        Some(symbol) => match memory.displacement {
+            0 => symbol.clone(),
+            offset => format!("{}{:+}", symbol, offset),
+        },
+        None if memory.displacement != 0 || (memory.base.is_none() && memory.index.is_none()) => {
+            memory.displacement.to_string()
+        },
+        None => String::new(),
+    };
+
+    match (memory.base, memory.index, &memory.symbol) {
+        // RIP-relative, like the encoder and NASM under `default rel`
+        (None, None, Some(_)) => format!("{}(%rip)", displacement),
+        (None, None, None) => displacement,
+        (base, index, _) => {
+            let base = base.map(|base| att_register(&base)).unwrap_or_default();
+            match index {
+                Some((index, scale)) => format!("{}({},{},{})", displacement, base, att_register(&index), scale),
+                None => format!("{}({})", displacement, base),
+            }
+        },
+    }
+}
+
+fn att_operand(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(register) => att_register(register),
+        Operand::Memory(memory) => att_memory(memory),
+        Operand::Immediate(value) => format!("${}", value),
+        Operand::Symbol(symbol, 0) => format!("${}", symbol),
+        Operand::Symbol(symbol, offset) => format!("${}{:+}", symbol, offset),
+    }
+}
+
+// Jump and call targets are written without the `$` and indirect ones with a `*`
+fn att_target(operand: &Operand) -> String {
+    match operand {
+        Operand::Symbol(symbol, 0) => symbol.clone(),
+        Operand::Symbol(symbol, offset) => format!("{}{:+}", symbol, offset),
+        operand => format!("*{}", att_operand(operand)),
+    }
+}
+
+fn att_suffix(size: Option<u8>) -> &'static str {
+    match size {
+        Some(1) => "b",
+        Some(2) => "w",
+        Some(4) => "l",
+        Some(8) => "q",
+        _ => "",
+    }
+}
+
+// AT&T lists the source first
+fn att_instruction(mnemonic: &str, operands: &[&Operand]) -> String {
+    let operands: Vec<String> = operands.iter().rev().map(|operand| att_operand(operand)).collect();
+    match operands.is_empty() {
+        true => mnemonic.to_string(),
+        false => format!("{} {}", mnemonic, operands.join(", ")),
+    }
+}
+
+// Integer instructions carry the operand size in the mnemonic
+fn att_sized(mnemonic: &str, operands: &[&Operand]) -> String {
+    let size = operands.iter().find_map(|operand| operand.size());
+    att_instruction(&format!("{}{}", mnemonic, att_suffix(size)), operands)
+}
+
+pub fn att(inst: &X86Inst) -> String {
+    match inst {
+        X86Inst::Section(name) => format!(".section {}", name),
+        X86Inst::Global(name) => format!(".globl {}", name),
+        X86Inst::Extern(name) => format!(".extern {}", name),
+        X86Inst::Label(name) => format!("{}:", name),
+        X86Inst::Align(alignment) => format!(".balign {}", alignment),
+        X86Inst::Db(bytes) => format!(".byte {}", byte_list(bytes)),
+        X86Inst::Dq(Operand::Immediate(value)) => format!(".quad {}", value),
+        X86Inst::Dq(value) => format!(".quad {}", att_target(value)),
+
+        // Only movabs takes a 64-bit immediate or address
+        X86Inst::Mov(dest @ Operand::Register(_), src @ Operand::Symbol(..)) => att_instruction("movabsq", &[dest, src]),
+        X86Inst::Mov(dest @ Operand::Register(register), src @ Operand::Immediate(value))
+            if register.size == 8 && i32::try_from(*value).is_err() =>
+        {
+            att_instruction("movabsq", &[dest, src])
+        },
+        X86Inst::Movsx(dest, src) | X86Inst::Movzx(dest, src) | X86Inst::Movsxd(dest, src) => {
+            let extension = if matches!(inst, X86Inst::Movzx(..)) { "movz" } else { "movs" };
+            let mnemonic = format!("{}{}{}", extension, att_suffix(src.size()), att_suffix(dest.size()));
+            att_instruction(&mnemonic, &[dest, src])
+        },
+        X86Inst::Mov(dest, src)
+        | X86Inst::Arithmetic(_, dest, src)
+        | X86Inst::Test(dest, src)
+        | X86Inst::Imul(dest, src)
+        | X86Inst::Btc(dest, src) => att_sized(&inst.mnemonic().unwrap_or_default(), &[dest, src]),
+        // The count is always a byte, the suffix comes from the destination
+        X86Inst::Shift(op, dest, count) => {
+            att_instruction(&format!("{}{}", op.mnemonic(), att_suffix(dest.size())), &[dest, count])
+        },
+        X86Inst::Push(operand) | X86Inst::Pop(operand) | X86Inst::Neg(operand) | X86Inst::Idiv(operand) => {
+            att_sized(&inst.mnemonic().unwrap_or_default(), &[operand])
+        },
+        X86Inst::Cdq => "cltd".to_string(),
+        X86Inst::Cqo => "cqto".to_string(),
+        X86Inst::Setcc(_, dest) | X86Inst::Fnstsw(dest) => att_instruction(&inst.mnemonic().unwrap_or_default(), &[dest]),
+        X86Inst::Jmp(target) => format!("jmp {}", att_target(target)),
+        X86Inst::Jcc(condition, label) => format!("j{} {}", condition.suffix(), label),
+        X86Inst::Call(target) => format!("call {}", att_target(target)),
+        // The integer operand of a conversion from an integer decides between 32 and 64 bits
+        X86Inst::Sse(op @ (SseOp::Cvtsi2ss | SseOp::Cvtsi2sd), dest, src) => {
+            att_instruction(&format!("{}{}", op.mnemonic(), att_suffix(src.size())), &[dest, src])
+        },
+        X86Inst::Sse(op, dest, src) => att_instruction(op.mnemonic(), &[dest, src]),
+        X86Inst::Fld(operand @ Operand::Memory(memory))
+        | X86Inst::Fstp(operand @ Operand::Memory(memory))
+        | X86Inst::Fmul(operand @ Operand::Memory(memory))
+        | X86Inst::Fdiv(operand @ Operand::Memory(memory)) => {
+            let suffix = if memory.size == Some(4) { "s" } else { "l" };
+            att_instruction(&format!("{}{}", inst.mnemonic().unwrap_or_default(), suffix), &[operand])
+        },
+        // Integers in memory are l for 32 and ll for 64 bits
+        X86Inst::Fistp(operand @ Operand::Memory(memory)) => {
+            let suffix = if memory.size == Some(4) { "l" } else { "ll" };
+            att_instruction(&format!("fistp{}", suffix), &[operand])
+        },
+        X86Inst::Fld(operand) | X86Inst::Fstp(operand) | X86Inst::Fmul(operand) | X86Inst::Fdiv(operand) | X86Inst::Fistp(operand) => {
+            att_instruction(&inst.mnemonic().unwrap_or_default(), &[operand])
+        },
+        X86Inst::Fld1
+        | X86Inst::Fyl2x
+        | X86Inst::F2xm1
+        | X86Inst::Faddp
+        | X86Inst::Fscale
+        | X86Inst::Fprem
+        | X86Inst::Fldln2
+        | X86Inst::Fldl2e
+        | X86Inst::Fldpi
+        | X86Inst::Fsin
+        | X86Inst::Fcos
+        | X86Inst::Leave
+        | X86Inst::Ret
+        | X86Inst::Syscall => inst.mnemonic().unwrap_or_default(),
+    }
+}
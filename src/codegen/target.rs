@@ -0,0 +1,211 @@
+// The operating system conventions the generated assembly follows. The instruction set is
+// always x86_64, what changes between targets is how the program talks to the kernel
+// and how the object file names things.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Text,
+    Data,
+    ReadOnlyData,
+}
+
+/// How a target delivers signals, for runtimes that handle SIGSEGV themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalAbi {
+    pub sigaction_syscall: u64,
+    pub sigreturn_syscall: u64,
+    pub sigsegv: u64,
+    /// The sa_flags that have the kernel pass a ucontext and return through sa_restorer.
+    pub flags: u64,
+    /// Where the saved rip and rsp are in the ucontext.
+    pub rip_offset: usize,
+    pub rsp_offset: usize,
+}
+
+/// Where the entry point finds the command line arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryArguments {
+    /// argc at the top of the stack the kernel hands over, followed by argv.
+    Stack,
+    /// argc in rdi and argv in rsi, the entry point being called like C's main.
+    Registers,
+}
+
+pub trait OsAbi {
+    fn name(&self) -> &'static str;
+
+    /// The symbol the linker starts the program at.
+    fn entry_symbol(&self) -> &'static str;
+
+    fn entry_arguments(&self) -> EntryArguments;
+
+    fn exit_syscall(&self) -> u64;
+
+    fn write_syscall(&self) -> u64;
+
+    fn mmap_syscall(&self) -> u64;
+
+    /// Fills a timeval with the seconds and microseconds since the epoch.
+    fn gettimeofday_syscall(&self) -> u64;
+
+    /// The mmap flags for private memory not backed by a file.
+    fn map_private_anonymous(&self) -> u64;
+
+    /// The object file format passed to `nasm -f`.
+    fn object_format(&self) -> &'static str;
+
+    /// Extra flags when linking through a C compiler driver instead of `ld`.
+    fn c_driver_flags(&self) -> &'static [&'static str];
+
+    /// The section name given to NASM's `section` directive.
+    fn section_name(&self, section: Section) -> &'static str;
+
+    /// The name a global symbol has in the object file.
+    fn symbol(&self, name: &str) -> String;
+
+    /// How to catch SIGSEGV, if the runtime knows how to on this target.
+    fn signal_abi(&self) -> Option<SignalAbi>;
+}
+
+/// Linux on x86_64, linked statically with `ld` and entered at `_start`.
+#[derive(Debug, Default)]
+pub struct Linux;
+
+impl OsAbi for Linux {
+    fn name(&self) -> &'static str {
+        "linux"
+    }
+
+    fn entry_symbol(&self) -> &'static str {
+        "_start"
+    }
+
+    fn entry_arguments(&self) -> EntryArguments {
+        EntryArguments::Stack
+    }
+
+    fn exit_syscall(&self) -> u64 {
+        60
+    }
+
+    fn write_syscall(&self) -> u64 {
+        1
+    }
+
+    fn mmap_syscall(&self) -> u64 {
+        9
+    }
+
+    fn gettimeofday_syscall(&self) -> u64 {
+        96
+    }
+
+    // MAP_PRIVATE | MAP_ANONYMOUS
+    fn map_private_anonymous(&self) -> u64 {
+        0x22
+    }
+
+    fn object_format(&self) -> &'static str {
+        "elf64"
+    }
+
+    // Our _start replaces the C runtime's
+    fn c_driver_flags(&self) -> &'static [&'static str] {
+        &["-nostdlib", "-static"]
+    }
+
+    fn section_name(&self, section: Section) -> &'static str {
+        match section {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::ReadOnlyData => ".rodata",
+        }
+    }
+
+    fn symbol(&self, name: &str) -> String {
+        name.to_string()
+    }
+
+    // rt_sigaction and rt_sigreturn, SA_SIGINFO | SA_RESTORER, and the offsets of
+    // uc_mcontext's rsp and rip
+    fn signal_abi(&self) -> Option<SignalAbi> {
+        Some(SignalAbi { sigaction_syscall: 13, sigreturn_syscall: 15, sigsegv: 11, flags: 0x0400_0004, rip_offset: 168, rsp_offset: 160 })
+    }
+}
+
+/// macOS, where BSD syscalls are numbered from 0x2000000 and C symbols get a leading
+/// underscore. The entry point is `_main`, called by dyld.
+#[derive(Debug, Default)]
+pub struct MacOs;
+
+impl OsAbi for MacOs {
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    fn entry_symbol(&self) -> &'static str {
+        "_main"
+    }
+
+    fn entry_arguments(&self) -> EntryArguments {
+        EntryArguments::Registers
+    }
+
+    fn exit_syscall(&self) -> u64 {
+        0x2000001
+    }
+
+    fn write_syscall(&self) -> u64 {
+        0x2000004
+    }
+
+    fn mmap_syscall(&self) -> u64 {
+        0x20000c5
+    }
+
+    fn gettimeofday_syscall(&self) -> u64 {
+        0x2000074
+    }
+
+    // MAP_PRIVATE | MAP_ANON
+    fn map_private_anonymous(&self) -> u64 {
+        0x1002
+    }
+
+    fn object_format(&self) -> &'static str {
+        "macho64"
+    }
+
+    // Static executables are not supported, dyld calls _main like a C program's
+    fn c_driver_flags(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn section_name(&self, section: Section) -> &'static str {
+        match section {
+            Section::Text => "__TEXT,__text",
+            Section::Data => "__DATA,__data",
+            Section::ReadOnlyData => "__TEXT,__const",
+        }
+    }
+
+    fn symbol(&self, name: &str) -> String {
+        format!("_{}", name)
+    }
+
+    // Signals reach a process through libc's trampoline, which a static runtime cannot
+    // rely on
+    fn signal_abi(&self) -> Option<SignalAbi> {
+        None
+    }
+}
+
+pub const DEFAULT_TARGET: &str = "linux";
+
+pub fn target_by_name(name: &str) -> Result<Box<dyn OsAbi>, String> {
+    match name {
+        "linux" => Ok(Box::new(Linux)),
+        "macos" => Ok(Box::new(MacOs)),
+        _ => Err(format!("Unknown target: {} (expected linux or macos)", name)),
+    }
+}
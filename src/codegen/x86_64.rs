@@ -1,92 +1,2310 @@
-use crate::{bytecode::attribute::CodeInstruction, codegen::Assembly};
+use std::collections::{BTreeMap, BTreeSet};
 
+use crate::bytecode::attribute::{CodeAttribute, CodeInstruction};
+use crate::bytecode::classpath::ClassPath;
+use crate::bytecode::constantpool::{ConstantPool, ConstantPoolEntry, MemberRef};
+use crate::bytecode::descriptor::{parse_field_descriptor, parse_method_descriptor, FieldType, MethodDescriptor};
+use crate::bytecode::method::Method;
+use crate::bytecode::lambda::{is_lambda_call_site, lambda_class_name, LAMBDA_FACTORY};
+use crate::bytecode::{CallSite, ParsedBytecode, ACC_ABSTRACT, ACC_ENUM, ACC_FINAL, ACC_INTERFACE, ACC_NATIVE, ACC_PRIVATE, ACC_STATIC, ACC_SYNCHRONIZED, ACC_SYNTHETIC};
+use crate::codegen::inst::{
+    byte, dword, qword, word, Condition, Memory, Operand, Register, SseOp, X86Inst, AH, AL, AX, CL, DL, DX, EAX, ECX, EDI, EDX, ESI, R12, R8, R9, RAX, RBP, RBX, RCX, RDI, RDX,
+    RSI, RSP, ST0, XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7,
+};
+use crate::codegen::layout::{
+    class_layout, has_metadata, is_runtime_class, java_name, stub_layout, vtable, ClassLayout, FieldLayout, VirtualMethod, ARRAY_DATA_OFFSET, ARRAY_LENGTH_OFFSET, CLASS_CLASS, CLASS_POINTER_OFFSET,
+    ERROR_CLASS, EXCEPTION_IN_INITIALIZER_ERROR_CLASS, LATIN1, OBJECT_CLASS, STRING_BUILDER_CLASS, STRING_CLASS, UTF16, VTABLE_OFFSET,
+};
+use crate::codegen::library::{all_library_methods, find_library_method, LibraryMethod};
+use crate::codegen::mangle::{mangle_class, mangle_class_state, mangle_initializer, mangle_method, mangle_static_field};
+use crate::codegen::target::{EntryArguments, OsAbi, Section};
+use crate::codegen::Assembly;
+use crate::ir;
+
+/// How the compiled code finds out that a reference it uses is null.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NullChecks {
+    /// A compare and branch before every use.
+    #[default]
+    Explicit,
+    /// A load from the reference, the runtime's SIGSEGV handler throws when it faults.
+    Signal,
+}
+
+pub const DEFAULT_NULL_CHECKS: &str = "explicit";
+
+pub fn null_checks_by_name(name: &str) -> Result<NullChecks, String> {
+    match name {
+        "explicit" => Ok(NullChecks::Explicit),
+        "signal" => Ok(NullChecks::Signal),
+        _ => Err(format!("Unknown null checks: {}, expected explicit or signal", name)),
+    }
+}
+
+/// The constants the methods of a class refer to. They go to .rodata after the code,
+/// except for the string literals, which are objects and have a header that is written.
 #[derive(Debug, Default)]
 pub struct DataSection {
-    offset: usize,
-    elements: Vec<String>,
+    // The string literals loaded by ldc and the String constants of static fields, as
+    // (label, UTF-16 code units). Each text has one object, so equal literals are the
+    // same String.
+    strings: Vec<(String, Vec<u16>)>,
+    // The long and double constants loaded by ldc2_w, as (label, bits)
+    quads: Vec<(String, u64)>,
+    // The classes whose Class object the code loads or locks, the label of each being
+    // numbered by its index
+    class_objects: Vec<String>,
+    // The jump tables of tableswitches and dense lookupswitches, as (label, target labels)
+    jump_tables: Vec<(String, Vec<String>)>,
+    // The return address of every call and the offsets below rbp of the frame slots
+    // holding references while it is made, as (label, offsets)
+    stack_maps: Vec<(String, Vec<usize>)>,
+    // The name and descriptor of each interface method selector, indexed by selector
+    selectors: Vec<(String, String)>,
+    // The array classes the code creates and those of their elements, which need
+    // metadata unless the runtime has it
+    array_classes: Vec<String>,
+    // The classes other than arrays that checkcast and instanceof test against, which
+    // need a stub when they have no class file
+    checked_classes: Vec<String>,
+    // The ranges of code exception handlers protect, in the order of the methods'
+    // exception tables
+    exception_ranges: Vec<ExceptionRange>,
+    null_checks: NullChecks,
+    // The loads that fault on null and where the code goes on after them, as (fault,
+    // resume) labels
+    null_check_sites: Vec<(String, String)>,
+    // The resume labels of the instruction being compiled, which get its stack map
+    unmapped_null_checks: Vec<String>,
+}
+
+/// A range of a method's code and where the exceptions thrown in it go when they are
+/// instances of `catch_class`, or of any class when it is None.
+#[derive(Debug)]
+struct ExceptionRange {
+    start: String,
+    end: String,
+    handler: String,
+    catch_class: Option<String>,
+}
+
+impl DataSection {
+    /// Adds a string literal and returns the label of its String, identical literals
+    /// share one.
+    pub fn add_string(&mut self, text: &[u16]) -> String {
+        if let Some((label, _)) = self.strings.iter().find(|(_, t)| t == text) {
+            return label.clone();
+        }
+
+        let label = format!("data_section_string_{}", self.strings.len());
+        self.strings.push((label.clone(), text.to_vec()));
+        label
+    }
+
+    /// Adds a 64-bit constant and returns its label, identical constants share one.
+    pub fn add_quad(&mut self, bits: u64) -> String {
+        if let Some((label, _)) = self.quads.iter().find(|(_, b)| *b == bits) {
+            return label.clone();
+        }
+
+        let label = format!("data_section_quad_{}", self.quads.len());
+        self.quads.push((label.clone(), bits));
+        label
+    }
+
+    /// Adds the Class object of `class_name` and returns its label.
+    pub fn add_class_object(&mut self, class_name: &str) -> String {
+        let index = match self.class_objects.iter().position(|c| c == class_name) {
+            Some(index) => index,
+            None => {
+                self.class_objects.push(class_name.to_string());
+                self.class_objects.len() - 1
+            },
+        };
+        format!("data_section_class_{}", index)
+    }
+
+    /// Returns the selector of the interface methods named `name` with `descriptor`.
+    pub fn add_selector(&mut self, name: &str, descriptor: &str) -> usize {
+        if let Some(selector) = self.selectors.iter().position(|(n, d)| n == name && d == descriptor) {
+            return selector;
+        }
+
+        self.selectors.push((name.to_string(), descriptor.to_string()));
+        self.selectors.len() - 1
+    }
+
+    /// Records that the code creates arrays of class `class_name`, like `[[I`.
+    pub fn add_array_class(&mut self, class_name: &str) {
+        let mut class_name = class_name;
+        while class_name.starts_with('[') {
+            if !self.array_classes.iter().any(|c| c == class_name) {
+                self.array_classes.push(class_name.to_string());
+            }
+            class_name = &class_name[1..];
+        }
+    }
+
+    /// Records that the code tests objects against `class_name`.
+    pub fn add_checked_class(&mut self, class_name: &str) {
+        if class_name.starts_with('[') {
+            self.add_array_class(class_name);
+        } else if !self.checked_classes.iter().any(|c| c == class_name) {
+            self.checked_classes.push(class_name.to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty() && self.quads.is_empty() && self.jump_tables.is_empty()
+    }
+}
+
+/// Where the local variables and the operand stack of a method live. Every JVM slot is
+/// eight bytes below rbp, the locals first and then the operand stack. Longs and doubles
+/// are kept whole in the first of their two slots. One more slot past the deepest the
+/// stack gets holds an instruction's own reference, like a string concatenation's builder.
+/// A synchronized method has a last slot for the object whose monitor it holds.
+#[derive(Debug)]
+pub struct StackFrame {
+    max_locals: usize,
+    max_stack: usize,
+    synchronized: bool,
+}
+
+impl StackFrame {
+    pub fn new(code_attribute: &CodeAttribute, synchronized: bool) -> Self {
+        Self {
+            max_locals: code_attribute.max_locals as usize,
+            max_stack: code_attribute.max_stack as usize,
+            synchronized,
+        }
+    }
+
+    /// The size of the frame below rbp, keeping rsp 16 byte aligned.
+    pub fn size(&self) -> usize {
+        ((self.max_locals + self.max_stack + 1 + self.synchronized as usize) * 8 + 15) & !15
+    }
+
+    /// The slot holding the object a synchronized method locks.
+    pub fn monitor(&self) -> Memory {
+        RBP - self.monitor_offset()
+    }
+
+    fn monitor_offset(&self) -> usize {
+        8 * (self.max_locals + self.max_stack + 2)
+    }
+
+    pub fn local(&self, index: usize) -> Memory {
+        RBP - 8 * (index + 1)
+    }
+
+    /// The operand stack slot `depth` slots from the bottom of the stack.
+    pub fn stack(&self, depth: usize) -> Memory {
+        RBP - 8 * (self.max_locals + depth + 1)
+    }
+
+    /// The offsets below rbp of the slots `state` says hold references.
+    pub fn reference_offsets(&self, state: &ir::Frame) -> Vec<usize> {
+        let locals = state
+            .locals
+            .iter()
+            .enumerate()
+            .filter(|(_, ty)| **ty == Some(ir::Type::Reference))
+            .map(|(index, _)| 8 * (index + 1));
+
+        let mut depth = 0;
+        let mut stack = Vec::new();
+        for ty in &state.stack {
+            if *ty == ir::Type::Reference {
+                stack.push(8 * (self.max_locals + depth + 1));
+            }
+            depth += if ty.is_wide() { 2 } else { 1 };
+        }
+
+        let monitor = self.synchronized.then(|| self.monitor_offset());
+        locals.chain(stack).chain(monitor).collect()
+    }
+}
+
+/// The label of the instruction at `pc` in the method compiled to `symbol`, every
+/// reachable instruction gets one so branches can jump to it.
+fn pc_label(symbol: &str, pc: u32) -> String {
+    format!("{}.pc{}", symbol, pc)
 }
 
-pub fn codegen(parsed_bytecode: &crate::bytecode::ParsedBytecode) -> Result<(), String> {
+/// Compiles the classes of a program into assembly.
+pub fn codegen(classes: &ClassPath, abi: &dyn OsAbi, null_checks: NullChecks) -> Result<Assembly, String> {
     let mut asm = Assembly::new();
 
-    let mut ds = DataSection::default();
+    let mut ds = DataSection { null_checks, ..DataSection::default() };
+
+    let main_class = classes.main_class();
+    let class_name = main_class.class_name()?;
+    let main = main_class
+        .find_method("main", "([Ljava/lang/String;)V")?
+        .ok_or_else(|| format!("{} has no main method", class_name))?;
+    if main.access_flags & ACC_STATIC == 0 {
+        return Err(format!("{}.main is not static", class_name));
+    }
+
+    asm.emit_section(abi.section_name(Section::Text));
+    let initializer = needs_initialization(classes, &class_name)?.then(|| abi.symbol(&mangle_initializer(&class_name)));
+    emit_entry(&mut asm, abi, initializer.as_deref(), &abi.symbol(&mangle_method(&class_name, "main", "([Ljava/lang/String;)V")));
+
+    for class in classes.classes.values() {
+        for method in &class.methods {
+            // Abstract and native methods have no code
+            if method.access_flags & (ACC_ABSTRACT | ACC_NATIVE) != 0 {
+                continue;
+            }
+            // Only deserialization calls it, which the runtime doesn't support
+            if method.access_flags & ACC_SYNTHETIC != 0 && class.method_name(method)? == "$deserializeLambda$" {
+                continue;
+            }
+
+            emit_method(&mut asm, &mut ds, abi, classes, class, method)?;
+        }
+    }
+    for class_name in classes.classes.keys() {
+        if needs_initialization(classes, class_name)? {
+            emit_initializer(&mut asm, &mut ds, abi, classes, class_name)?;
+        }
+    }
+    let static_roots = emit_static_storage(&mut asm, &mut ds, abi, classes)?;
+
+    asm.emit_section(abi.section_name(Section::ReadOnlyData));
+    asm.emit_align(8);
+    // The classes without class files that the metadata refers to get stubs
+    let mut stubs = ds.checked_classes.clone();
+    for class_name in classes.classes.keys() {
+        let layout = class_layout(classes, class_name)?;
+        stubs.extend(layout.interfaces.iter().cloned());
+        emit_class(&mut asm, abi, &layout, &vtable(classes, class_name)?, &ds.selectors);
+    }
+    for class_name in ds.array_classes.iter().filter(|c| !is_runtime_class(c)) {
+        let layout = class_layout(classes, class_name)?;
+        stubs.extend(layout.element_class.iter().cloned());
+        emit_class(&mut asm, abi, &layout, &vtable(classes, class_name)?, &[]);
+    }
+    stubs.retain(|class_name| !has_metadata(classes, class_name));
+    stubs.sort();
+    stubs.dedup();
+    let object_vtable = vtable(classes, OBJECT_CLASS)?;
+    for class_name in &stubs {
+        emit_class(&mut asm, abi, &stub_layout(class_name), &object_vtable, &[]);
+    }
+    emit_stack_maps(&mut asm, abi, &ds.stack_maps);
+    emit_exception_table(&mut asm, abi, &ds.exception_ranges);
+    emit_null_check_table(&mut asm, abi, &ds.null_check_sites);
+    emit_static_roots(&mut asm, abi, &static_roots);
+    emit_string_literals(&mut asm, abi, &ds.strings);
+    emit_class_objects(&mut asm, abi, classes, &ds.class_objects)?;
+
+    if !ds.is_empty() {
+        emit_data_section(&mut asm, abi, ds);
+    }
+    // The runtime's routines, metadata and library methods the program refers to
+    asm.declare_externs();
+
+    Ok(asm)
+}
+
+/// Emits a class's metadata, see `layout`.
+pub fn emit_class(asm: &mut Assembly, abi: &dyn OsAbi, layout: &ClassLayout, vtable: &[VirtualMethod], selectors: &[(String, String)]) {
+    let symbol = abi.symbol(&mangle_class(&layout.class_name));
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    match &layout.super_class {
+        Some(super_class) => asm.emit_dq(abi.symbol(&mangle_class(super_class))),
+        None => asm.emit_dq(0),
+    }
+    asm.emit_dq(layout.size);
+    let references = format!("{}.references", symbol);
+    asm.emit_dq(&references);
+    let itable = format!("{}.itable", symbol);
+    asm.emit_dq(&itable);
+    asm.emit_dq(layout.element_size);
+    match &layout.element_class {
+        Some(element_class) => asm.emit_dq(abi.symbol(&mangle_class(element_class))),
+        None => asm.emit_dq(0),
+    }
+    asm.emit_dq(layout.access_flags);
+    let name = format!("{}.name", symbol);
+    asm.emit_dq(&name);
+    let interfaces = format!("{}.interfaces", symbol);
+    asm.emit_dq(&interfaces);
+    asm.emit_dq(layout.display.len() - 1);
+    let display = format!("{}.display", symbol);
+    asm.emit_dq(&display);
+    let implementation = |method: &VirtualMethod| {
+        let class_name = method.implementation.as_ref()?;
+        Some(abi.symbol(&mangle_method(class_name, &method.name, &method.descriptor)))
+    };
+    for method in vtable {
+        match implementation(method) {
+            Some(implementation) => asm.emit_dq(&implementation),
+            None => asm.emit_dq(0),
+        }
+    }
+
+    let offsets = layout.reference_offsets();
+    asm.emit_label(&references);
+    asm.emit_dq(offsets.len());
+    for offset in offsets {
+        asm.emit_dq(offset);
+    }
+
+    // Only the selectors some interface call uses, for the methods the class implements
+    let entries: Vec<(usize, String)> = selectors
+        .iter()
+        .enumerate()
+        .filter_map(|(selector, (name, descriptor))| {
+            let method = vtable.iter().find(|method| &method.name == name && &method.descriptor == descriptor)?;
+            Some((selector, implementation(method)?))
+        })
+        .collect();
+    asm.emit_label(&itable);
+    asm.emit_dq(entries.len());
+    for (selector, method) in entries {
+        asm.emit_dq(selector);
+        asm.emit_dq(&method);
+    }
+
+    asm.emit_label(&interfaces);
+    asm.emit_dq(layout.interfaces.len());
+    for interface in &layout.interfaces {
+        asm.emit_dq(abi.symbol(&mangle_class(interface)));
+    }
+
+    asm.emit_label(&display);
+    for class_name in &layout.display {
+        asm.emit_dq(abi.symbol(&mangle_class(class_name)));
+    }
+
+    let java_name = java_name(&layout.class_name);
+    asm.emit_label(&name);
+    asm.emit_dq(java_name.len());
+    asm.emit_db_bytes(java_name.as_bytes());
+    // The next class's metadata is read in qwords
+    asm.emit_align(8);
+}
+
+/// Emits the table the collector finds the references on the stack with. It walks the
+/// frames through their saved rbp and looks up each return address:
+///
+/// dq <number of calls>
+/// dq <return address>, <number of slots>, <offset below rbp of each slot>...
+fn emit_stack_maps(asm: &mut Assembly, abi: &dyn OsAbi, stack_maps: &[(String, Vec<usize>)]) {
+    let symbol = abi.symbol("runtime$stack_maps");
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    asm.emit_dq(stack_maps.len());
+    for (label, offsets) in stack_maps {
+        asm.emit_dq(label);
+        asm.emit_dq(offsets.len());
+        for offset in offsets {
+            asm.emit_dq(*offset);
+        }
+    }
+}
+
+/// Emits the table the runtime's SIGSEGV handler finds null checks in, see `runtime`.
+fn emit_null_check_table(asm: &mut Assembly, abi: &dyn OsAbi, null_check_sites: &[(String, String)]) {
+    let symbol = abi.symbol("runtime$null_checks");
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    asm.emit_dq(null_check_sites.len());
+    for (fault, resume) in null_check_sites {
+        asm.emit_dq(fault);
+        asm.emit_dq(resume);
+    }
+}
+
+/// Emits the table `runtime$throw` finds exception handlers in, see `runtime`.
+fn emit_exception_table(asm: &mut Assembly, abi: &dyn OsAbi, exception_ranges: &[ExceptionRange]) {
+    let symbol = abi.symbol("runtime$exception_table");
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    asm.emit_dq(exception_ranges.len());
+    for range in exception_ranges {
+        asm.emit_dq(&range.start);
+        asm.emit_dq(&range.end);
+        asm.emit_dq(&range.handler);
+        match &range.catch_class {
+            Some(catch_class) => asm.emit_dq(abi.symbol(&mangle_class(catch_class))),
+            None => asm.emit_dq(0),
+        }
+    }
+}
+
+/// Emits the table of the static fields holding references, which the collector treats
+/// as roots:
+///
+/// dq <number of fields>
+/// dq <address of the field>...
+fn emit_static_roots(asm: &mut Assembly, abi: &dyn OsAbi, static_roots: &[String]) {
+    let symbol = abi.symbol("runtime$static_roots");
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    asm.emit_dq(static_roots.len());
+    for root in static_roots {
+        asm.emit_dq(root);
+    }
+}
+
+/// Emits the table of the String objects of the literals that String.intern starts its
+/// pool with, their count and then their addresses.
+fn emit_string_literals(asm: &mut Assembly, abi: &dyn OsAbi, strings: &[(String, Vec<u16>)]) {
+    let symbol = abi.symbol("runtime$string_literals");
+    asm.emit_global(&symbol);
+    asm.emit_label(&symbol);
+    asm.emit_dq(strings.len());
+    for (label, _) in strings {
+        asm.emit_dq(label);
+    }
+}
+
+/// Emits the Class objects the code refers to, see `layout`. Their headers are written
+/// when they are locked, so they go to the data section.
+fn emit_class_objects(asm: &mut Assembly, abi: &dyn OsAbi, classes: &ClassPath, class_names: &[String]) -> Result<(), String> {
+    asm.emit_section(abi.section_name(Section::Data));
+    for (index, class_name) in class_names.iter().enumerate() {
+        if !has_metadata(classes, class_name) {
+            return Err(format!("Can't load the Class of {}, its class file was not found", class_name));
+        }
+
+        // javac keeps an enum's constants in $VALUES, which values() copies
+        let mut enum_constants = Operand::from(0);
+        if let Some(class) = classes.find(class_name)
+            && class.access_flags & ACC_ENUM != 0
+            && class.find_field("$VALUES", &format!("[L{};", class_name))?.is_some()
+        {
+            enum_constants = abi.symbol(&mangle_static_field(class_name, "$VALUES")).into();
+        }
+        let initializer = match needs_initialization(classes, class_name)? {
+            true => abi.symbol(&mangle_initializer(class_name)).into(),
+            false => Operand::from(0),
+        };
+        // What getCanonicalName gives member classes
+        let canonical_name = java_name(class_name).replace('$', ".");
+
+        let label = format!("data_section_class_{}", index);
+        asm.emit_align(8);
+        asm.emit_label(&label);
+        asm.emit_dq(abi.symbol(&mangle_class(CLASS_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(abi.symbol(&mangle_class(class_name)));
+        asm.emit_dq(enum_constants);
+        asm.emit_dq(initializer);
+        asm.emit_dq(format!("{}.name", label));
+        asm.emit_label(&format!("{}.name", label));
+        asm.emit_dq(canonical_name.len());
+        asm.emit_db_bytes(canonical_name.as_bytes());
+    }
+    Ok(())
+}
+
+/// Emits the static fields of the classes, a qword each whatever its type, and the
+/// initialization state of the classes that have an initializer. A field starts out with
+/// its ConstantValue, or zero.
+/// Returns the symbols of the fields holding references.
+fn emit_static_storage(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, classes: &ClassPath) -> Result<Vec<String>, String> {
+    asm.emit_section(abi.section_name(Section::Data));
+    asm.emit_align(8);
+    let mut static_roots = Vec::new();
+    for (class_name, class) in &classes.classes {
+        if needs_initialization(classes, class_name)? {
+            asm.emit_label(&abi.symbol(&mangle_class_state(class_name)));
+            asm.emit_dq(UNINITIALIZED);
+        }
 
-    for method in &parsed_bytecode.methods {
-        let name = parsed_bytecode
-            .constant_pool
-            .find_utf8_constant_pool_entry(method.name_index)?;
-        if name.bytes != "main" {
+        for field in class.fields.iter().filter(|field| field.access_flags & ACC_STATIC != 0) {
+            let symbol = abi.symbol(&mangle_static_field(class_name, &class.field_name(field)?));
+            let value = match class.constant_value(field)? {
+                Some(index) => static_constant(ds, class, index)?,
+                None => Operand::from(0),
+            };
+            asm.emit_label(&symbol);
+            asm.emit_dq(value);
+            if parse_field_descriptor(&class.field_descriptor(field)?)?.is_reference() {
+                static_roots.push(symbol);
+            }
+        }
+    }
+
+    Ok(static_roots)
+}
+
+// The initial value of a static field with a ConstantValue, ints are zero extended
+fn static_constant(ds: &mut DataSection, class: &ParsedBytecode, index: u16) -> Result<Operand, String> {
+    Ok(match class.constant_pool.get(index)? {
+        ConstantPoolEntry::Integer(entry) => (entry.value() as u32).into(),
+        ConstantPoolEntry::Long(entry) => entry.value().into(),
+        ConstantPoolEntry::Float(entry) => entry.value().to_bits().into(),
+        ConstantPoolEntry::Double(entry) => entry.value().to_bits().into(),
+        ConstantPoolEntry::String(entry) => ds.add_string(&class.constant_pool.find_utf8_constant_pool_entry(entry.string_index)?.units).into(),
+        entry => return Err(format!("Unsupported ConstantValue: {:?}", entry)),
+    })
+}
+
+fn emit_data_section(asm: &mut Assembly, abi: &dyn OsAbi, ds: DataSection) {
+    asm.emit_section(abi.section_name(Section::ReadOnlyData));
+
+    asm.emit_align(8);
+    for (label, bits) in ds.quads {
+        asm.emit_label(&label);
+        asm.emit_dq(bits);
+    }
+    for (label, targets) in ds.jump_tables {
+        asm.emit_label(&label);
+        for target in targets {
+            asm.emit_dq(&target);
+        }
+    }
+
+    // Each literal is a String and its byte array, Latin-1 when all its characters are
+    // and UTF-16 otherwise. Like the runtime's OutOfMemoryError they are outside the heap.
+    asm.emit_section(abi.section_name(Section::Data));
+    for (label, chars) in ds.strings {
+        let (coder, bytes): (u8, Vec<u8>) = match chars.iter().all(|&c| c <= 0xff) {
+            true => (LATIN1, chars.iter().map(|&c| c as u8).collect()),
+            false => (UTF16, chars.iter().flat_map(|c| c.to_le_bytes()).collect()),
+        };
+        asm.emit_align(8);
+        asm.emit_label(&label);
+        asm.emit_dq(abi.symbol(&mangle_class(STRING_CLASS)));
+        asm.emit_dq(0);
+        asm.emit_dq(format!("{}.value", label));
+        asm.emit_dq(coder);
+        asm.emit_label(&format!("{}.value", label));
+        asm.emit_dq(abi.symbol(&mangle_class("[B")));
+        asm.emit_dq(0);
+        asm.emit_dq(bytes.len());
+        // Written out as bytes, so quotes and non-ASCII characters need no escaping
+        if !bytes.is_empty() {
+            asm.emit_db_bytes(&bytes);
+        }
+    }
+}
+
+/// Compiles a method to a System V function named by `mangle_method`. Instance methods
+/// take `this` as their first argument.
+fn emit_method(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, classes: &ClassPath, parsed_bytecode: &ParsedBytecode, method: &Method) -> Result<(), String> {
+    let class_name = parsed_bytecode.class_name()?;
+    let name = parsed_bytecode.method_name(method)?;
+    let descriptor = parsed_bytecode.method_descriptor(method)?;
+    let symbol = &abi.symbol(&mangle_method(&class_name, &name, &descriptor));
+
+    let Some(code_attribute) = parsed_bytecode.code_attribute(method)? else {
+        return Err(format!("{}.{}{} has no Code attribute", class_name, name, descriptor));
+    };
+    let function = ir::build_function(parsed_bytecode, method)?
+        .ok_or_else(|| format!("{}.{}{} has no Code attribute", class_name, name, descriptor))?;
+    let synchronized = method.access_flags & ACC_SYNCHRONIZED != 0;
+    let frame = StackFrame::new(&code_attribute, synchronized);
+
+    asm.emit_function_start(symbol);
+    asm.emit_push(RBP);
+    asm.emit_mov(RBP, RSP);
+    asm.emit_sub(RSP, frame.size());
+    let is_static = method.access_flags & ACC_STATIC != 0;
+    emit_store_parameters(asm, &frame, &parameter_types(&parse_method_descriptor(&descriptor)?, !is_static));
+    if synchronized {
+        let monitor: Operand = if is_static { ds.add_class_object(&class_name).into() } else { qword(frame.local(0)).into() };
+        asm.emit_mov(RDI, monitor);
+        asm.emit_mov(qword(frame.monitor()), RDI);
+        asm.emit_call(abi.symbol("runtime$monitor_enter"));
+        let entered = format!("{}.entered", symbol);
+        asm.emit_label(&entered);
+        ds.stack_maps.push((entered, frame.reference_offsets(&function.frames[&0])));
+    }
+
+    let instructions = code_attribute.into_code_instructions_with_offsets()?;
+    let guards = initialization_guards(classes, parsed_bytecode, &function, &instructions)?;
+    for (pc, instruction) in instructions {
+        // Unreachable instructions have no frame and are left out, their labels may
+        // still bound an exception handler's range
+        let label = pc_label(symbol, pc);
+        asm.emit_label(&label);
+        let Some(state) = function.frames.get(&pc) else {
             continue;
+        };
+        let depth = state.stack_words();
+        let start = asm.code.len();
+        if let Some(target) = guards.get(&pc) {
+            emit_initialization_guard(asm, abi, &label, target);
         }
 
-        asm.emit_section_text();
-        asm.emit_global_main();
-        asm.emit_function_start("_main");
-
-        let descriptor = parsed_bytecode
-            .constant_pool
-            .find_utf8_constant_pool_entry(method.descriptor_index)?;
-
-        for attribute in &method.attributes {
-            let name = parsed_bytecode
-                .constant_pool
-                .find_utf8_constant_pool_entry(attribute.name_index)?;
-
-            if name.bytes == "Code" {
-                let code_attribute = attribute.into_code_attribute()?;
-                let code_instructions = code_attribute.into_code_instructions()?;
-
-                for instruction in code_instructions {
-                    match instruction {
-                        CodeInstruction::Ldc(index) => emit_ldc(&mut asm, index, parsed_bytecode, &mut ds)?,
-                        CodeInstruction::Aload0 => todo!("implement aload0"),
-                        CodeInstruction::InvokeVirtual(index) => emit_invoke_virtual(&mut asm, index, parsed_bytecode)?,
-                        CodeInstruction::InvokeSpecial(_) => todo!("implement invoke special"),
-                        CodeInstruction::GetStatic(_) => {},
-                        CodeInstruction::Return => emit_ret(&mut asm, parsed_bytecode)?,
-                    }
+        match instruction {
+            CodeInstruction::Nop => {},
+            CodeInstruction::AconstNull => asm.emit_mov(qword(frame.stack(depth)), 0),
+            CodeInstruction::Iconst(value) => emit_int_constant(asm, &frame, depth, value),
+            CodeInstruction::Lconst(value) => asm.emit_mov(qword(frame.stack(depth)), value),
+            CodeInstruction::Fconst(value) => emit_float_constant(asm, &frame, depth, value),
+            CodeInstruction::Dconst(value) => emit_double_constant(asm, &frame, depth, value),
+            CodeInstruction::Bipush(value) => emit_int_constant(asm, &frame, depth, value as i32),
+            CodeInstruction::Sipush(value) => emit_int_constant(asm, &frame, depth, value as i32),
+            CodeInstruction::Ldc(index) => emit_ldc(asm, index as u16, parsed_bytecode, ds, &frame, depth)?,
+            CodeInstruction::LdcW(index) | CodeInstruction::Ldc2W(index) => emit_ldc(asm, index, parsed_bytecode, ds, &frame, depth)?,
+            CodeInstruction::Iload(index)
+            | CodeInstruction::Lload(index)
+            | CodeInstruction::Fload(index)
+            | CodeInstruction::Dload(index)
+            | CodeInstruction::Aload(index) => emit_load(asm, &frame, depth, index as usize),
+            CodeInstruction::Iaload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Int),
+            CodeInstruction::Laload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Long),
+            CodeInstruction::Faload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Float),
+            CodeInstruction::Daload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Double),
+            CodeInstruction::Aaload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Reference),
+            CodeInstruction::Baload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Byte),
+            CodeInstruction::Caload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Char),
+            CodeInstruction::Saload => emit_array_load(asm, ds, abi, &frame, depth, &label, ir::ElementType::Short),
+            CodeInstruction::Istore(index) | CodeInstruction::Fstore(index) | CodeInstruction::Astore(index) => emit_store(asm, &frame, depth - 1, index as usize),
+            CodeInstruction::Lstore(index) | CodeInstruction::Dstore(index) => emit_store(asm, &frame, depth - 2, index as usize),
+            CodeInstruction::Iastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Int),
+            CodeInstruction::Lastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Long),
+            CodeInstruction::Fastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Float),
+            CodeInstruction::Dastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Double),
+            CodeInstruction::Aastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Reference),
+            CodeInstruction::Bastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Byte),
+            CodeInstruction::Castore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Char),
+            CodeInstruction::Sastore => emit_array_store(asm, ds, abi, &frame, depth, &label, ir::ElementType::Short),
+            CodeInstruction::Iinc(index, value) => emit_iinc(asm, &frame, index as usize, value),
+            CodeInstruction::Iadd => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_add(dest, src)),
+            CodeInstruction::Isub => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_sub(dest, src)),
+            CodeInstruction::Imul => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_imul(dest, src)),
+            CodeInstruction::Iand => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_and(dest, src)),
+            CodeInstruction::Ior => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_or(dest, src)),
+            CodeInstruction::Ixor => emit_int_binary(asm, &frame, depth, |asm, dest, src| asm.emit_xor(dest, src)),
+            CodeInstruction::Idiv => emit_int_division(asm, abi, &frame, depth, &label, false),
+            CodeInstruction::Irem => emit_int_division(asm, abi, &frame, depth, &label, true),
+            CodeInstruction::Ineg => asm.emit_neg(dword(frame.stack(depth - 1))),
+            CodeInstruction::Ishl => emit_int_shift(asm, &frame, depth, |asm, dest, count| asm.emit_shl(dest, count)),
+            CodeInstruction::Ishr => emit_int_shift(asm, &frame, depth, |asm, dest, count| asm.emit_sar(dest, count)),
+            CodeInstruction::Iushr => emit_int_shift(asm, &frame, depth, |asm, dest, count| asm.emit_shr(dest, count)),
+            CodeInstruction::Ladd => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_add(dest, src)),
+            CodeInstruction::Lsub => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_sub(dest, src)),
+            CodeInstruction::Lmul => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_imul(dest, src)),
+            CodeInstruction::Land => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_and(dest, src)),
+            CodeInstruction::Lor => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_or(dest, src)),
+            CodeInstruction::Lxor => emit_long_binary(asm, &frame, depth, |asm, dest, src| asm.emit_xor(dest, src)),
+            CodeInstruction::Ldiv => emit_long_division(asm, abi, &frame, depth, &label, false),
+            CodeInstruction::Lrem => emit_long_division(asm, abi, &frame, depth, &label, true),
+            CodeInstruction::Lneg => asm.emit_neg(qword(frame.stack(depth - 2))),
+            CodeInstruction::Lshl => emit_long_shift(asm, &frame, depth, |asm, dest, count| asm.emit_shl(dest, count)),
+            CodeInstruction::Lshr => emit_long_shift(asm, &frame, depth, |asm, dest, count| asm.emit_sar(dest, count)),
+            CodeInstruction::Lushr => emit_long_shift(asm, &frame, depth, |asm, dest, count| asm.emit_shr(dest, count)),
+            CodeInstruction::Fadd => emit_float_binary(asm, &frame, depth, SseOp::Addss, false),
+            CodeInstruction::Fsub => emit_float_binary(asm, &frame, depth, SseOp::Subss, false),
+            CodeInstruction::Fmul => emit_float_binary(asm, &frame, depth, SseOp::Mulss, false),
+            CodeInstruction::Fdiv => emit_float_binary(asm, &frame, depth, SseOp::Divss, false),
+            CodeInstruction::Dadd => emit_float_binary(asm, &frame, depth, SseOp::Addsd, true),
+            CodeInstruction::Dsub => emit_float_binary(asm, &frame, depth, SseOp::Subsd, true),
+            CodeInstruction::Dmul => emit_float_binary(asm, &frame, depth, SseOp::Mulsd, true),
+            CodeInstruction::Ddiv => emit_float_binary(asm, &frame, depth, SseOp::Divsd, true),
+            CodeInstruction::Frem => emit_float_remainder(asm, &frame, depth, &label, false),
+            CodeInstruction::Drem => emit_float_remainder(asm, &frame, depth, &label, true),
+            CodeInstruction::Fneg => asm.emit_xor(dword(frame.stack(depth - 1)), 0x80000000i64),
+            CodeInstruction::Dneg => asm.emit_btc(qword(frame.stack(depth - 2)), 63),
+            CodeInstruction::Lcmp => emit_lcmp(asm, &frame, depth),
+            CodeInstruction::Fcmpl => emit_float_compare(asm, &frame, depth, false, false),
+            CodeInstruction::Fcmpg => emit_float_compare(asm, &frame, depth, false, true),
+            CodeInstruction::Dcmpl => emit_float_compare(asm, &frame, depth, true, false),
+            CodeInstruction::Dcmpg => emit_float_compare(asm, &frame, depth, true, true),
+            CodeInstruction::I2l => emit_convert(asm, &frame, depth, &label, ir::Type::Int, ir::Type::Long),
+            CodeInstruction::I2f => emit_convert(asm, &frame, depth, &label, ir::Type::Int, ir::Type::Float),
+            CodeInstruction::I2d => emit_convert(asm, &frame, depth, &label, ir::Type::Int, ir::Type::Double),
+            CodeInstruction::L2i => emit_convert(asm, &frame, depth, &label, ir::Type::Long, ir::Type::Int),
+            CodeInstruction::L2f => emit_convert(asm, &frame, depth, &label, ir::Type::Long, ir::Type::Float),
+            CodeInstruction::L2d => emit_convert(asm, &frame, depth, &label, ir::Type::Long, ir::Type::Double),
+            CodeInstruction::F2i => emit_convert(asm, &frame, depth, &label, ir::Type::Float, ir::Type::Int),
+            CodeInstruction::F2l => emit_convert(asm, &frame, depth, &label, ir::Type::Float, ir::Type::Long),
+            CodeInstruction::F2d => emit_convert(asm, &frame, depth, &label, ir::Type::Float, ir::Type::Double),
+            CodeInstruction::D2i => emit_convert(asm, &frame, depth, &label, ir::Type::Double, ir::Type::Int),
+            CodeInstruction::D2l => emit_convert(asm, &frame, depth, &label, ir::Type::Double, ir::Type::Long),
+            CodeInstruction::D2f => emit_convert(asm, &frame, depth, &label, ir::Type::Double, ir::Type::Float),
+            CodeInstruction::I2b => emit_narrow(asm, &frame, depth, ir::ElementType::Byte),
+            CodeInstruction::I2c => emit_narrow(asm, &frame, depth, ir::ElementType::Char),
+            CodeInstruction::I2s => emit_narrow(asm, &frame, depth, ir::ElementType::Short),
+            CodeInstruction::Pop | CodeInstruction::Pop2 => {},
+            CodeInstruction::Dup => emit_dup(asm, &frame, depth, 1, 0),
+            CodeInstruction::DupX1 => emit_dup(asm, &frame, depth, 1, 1),
+            CodeInstruction::DupX2 => emit_dup(asm, &frame, depth, 1, 2),
+            CodeInstruction::Dup2 => emit_dup(asm, &frame, depth, 2, 0),
+            CodeInstruction::Dup2X1 => emit_dup(asm, &frame, depth, 2, 1),
+            CodeInstruction::Dup2X2 => emit_dup(asm, &frame, depth, 2, 2),
+            CodeInstruction::Swap => emit_swap(asm, &frame, depth),
+            CodeInstruction::Ifeq(target) => emit_if(asm, &frame, depth, Condition::E, &pc_label(symbol, target)),
+            CodeInstruction::Ifne(target) => emit_if(asm, &frame, depth, Condition::Ne, &pc_label(symbol, target)),
+            CodeInstruction::Iflt(target) => emit_if(asm, &frame, depth, Condition::L, &pc_label(symbol, target)),
+            CodeInstruction::Ifge(target) => emit_if(asm, &frame, depth, Condition::Ge, &pc_label(symbol, target)),
+            CodeInstruction::Ifgt(target) => emit_if(asm, &frame, depth, Condition::G, &pc_label(symbol, target)),
+            CodeInstruction::Ifle(target) => emit_if(asm, &frame, depth, Condition::Le, &pc_label(symbol, target)),
+            CodeInstruction::IfIcmpeq(target) => emit_if_cmp(asm, &frame, depth, Condition::E, &pc_label(symbol, target), false),
+            CodeInstruction::IfIcmpne(target) => emit_if_cmp(asm, &frame, depth, Condition::Ne, &pc_label(symbol, target), false),
+            CodeInstruction::IfIcmplt(target) => emit_if_cmp(asm, &frame, depth, Condition::L, &pc_label(symbol, target), false),
+            CodeInstruction::IfIcmpge(target) => emit_if_cmp(asm, &frame, depth, Condition::Ge, &pc_label(symbol, target), false),
+            CodeInstruction::IfIcmpgt(target) => emit_if_cmp(asm, &frame, depth, Condition::G, &pc_label(symbol, target), false),
+            CodeInstruction::IfIcmple(target) => emit_if_cmp(asm, &frame, depth, Condition::Le, &pc_label(symbol, target), false),
+            CodeInstruction::IfAcmpeq(target) => emit_if_cmp(asm, &frame, depth, Condition::E, &pc_label(symbol, target), true),
+            CodeInstruction::IfAcmpne(target) => emit_if_cmp(asm, &frame, depth, Condition::Ne, &pc_label(symbol, target), true),
+            CodeInstruction::IfNull(target) => emit_if_null(asm, &frame, depth, Condition::E, &pc_label(symbol, target)),
+            CodeInstruction::IfNonNull(target) => emit_if_null(asm, &frame, depth, Condition::Ne, &pc_label(symbol, target)),
+            CodeInstruction::Goto(target) => asm.emit_jmp(pc_label(symbol, target)),
+            CodeInstruction::TableSwitch { default, low, high, offsets } => {
+                let table = format!("{}.table", label);
+                emit_table_switch(asm, &frame, depth, &table, low, high, &pc_label(symbol, default));
+                ds.jump_tables.push((table, offsets.iter().map(|target| pc_label(symbol, *target)).collect()));
+            },
+            CodeInstruction::LookupSwitch { default, pairs } => {
+                let cases: Vec<(i32, String)> = pairs.iter().map(|(key, target)| (*key, pc_label(symbol, *target))).collect();
+                emit_lookup_switch(asm, ds, &frame, depth, &label, &cases, &pc_label(symbol, default));
+            },
+            CodeInstruction::New(index) => emit_new(asm, abi, &frame, depth, classes, &parsed_bytecode.constant_pool.find_class_name(index)?)?,
+            CodeInstruction::NewArray(atype) => emit_new_array(asm, ds, abi, &frame, depth, &primitive_array_class(atype)?),
+            CodeInstruction::ANewArray(index) => {
+                let element = parsed_bytecode.constant_pool.find_class_name(index)?;
+                // Array classes are named by their descriptor, other classes by their name
+                let class_name = if element.starts_with('[') { format!("[{}", element) } else { format!("[L{};", element) };
+                emit_new_array(asm, ds, abi, &frame, depth, &class_name);
+            },
+            CodeInstruction::MultiANewArray(index, dimensions) => {
+                let class_name = parsed_bytecode.constant_pool.find_class_name(index)?;
+                emit_new_multi_array(asm, ds, abi, &frame, depth, &class_name, dimensions as usize);
+            },
+            CodeInstruction::ArrayLength => {
+                asm.emit_mov(RAX, qword(frame.stack(depth - 1)));
+                emit_null_check(asm, ds, abi, &label, RAX);
+                asm.emit_mov(EAX, dword(RAX + ARRAY_LENGTH_OFFSET));
+                asm.emit_mov(qword(frame.stack(depth - 1)), RAX);
+            },
+            CodeInstruction::GetField(index) => {
+                let field = resolve_field(classes, &parsed_bytecode.constant_pool.find_member_ref(index)?)?;
+                emit_get_field(asm, ds, abi, &frame, depth, &label, &field);
+            },
+            CodeInstruction::PutField(index) => {
+                let field = resolve_field(classes, &parsed_bytecode.constant_pool.find_member_ref(index)?)?;
+                emit_put_field(asm, ds, abi, &frame, depth, &label, &field);
+            },
+            CodeInstruction::InvokeVirtual(index) => {
+                let method = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                emit_receiver_null_check(asm, ds, abi, &frame, depth, &label, &method)?;
+                emit_invoke_virtual(asm, abi, &frame, depth, classes, &method)?;
+            },
+            CodeInstruction::InvokeSpecial(index) => {
+                let method = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                // The receiver of a constructor was just created
+                if method.name != "<init>" {
+                    emit_receiver_null_check(asm, ds, abi, &frame, depth, &label, &method)?;
                 }
+                emit_invoke_special(asm, abi, &frame, depth, classes, &method)?;
+            },
+            CodeInstruction::InvokeInterface(index, _) => {
+                let method = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                emit_receiver_null_check(asm, ds, abi, &frame, depth, &label, &method)?;
+                emit_invoke_interface(asm, ds, abi, &frame, depth, classes, &method)?;
+            },
+            CodeInstruction::CheckCast(index) => {
+                let class_name = parsed_bytecode.constant_pool.find_class_name(index)?;
+                emit_check_cast(asm, ds, abi, &frame, depth, &class_name);
+            },
+            CodeInstruction::InstanceOf(index) => {
+                let class_name = parsed_bytecode.constant_pool.find_class_name(index)?;
+                emit_instance_of(asm, ds, abi, &frame, depth, &class_name);
+            },
+            CodeInstruction::GetStatic(index) => {
+                let field = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                match runtime_stream(&field) {
+                    Some(stream) => {
+                        asm.emit_mov(RAX, abi.symbol(stream));
+                        asm.emit_mov(qword(frame.stack(depth)), RAX);
+                    },
+                    None => {
+                        let declaring_class = resolve_static_field(classes, &field)?;
+                        emit_get_static(asm, abi, &frame, depth, &declaring_class, &field)?;
+                    },
+                }
+            },
+            CodeInstruction::PutStatic(index) => {
+                let field = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                let declaring_class = resolve_static_field(classes, &field)?;
+                emit_put_static(asm, abi, &frame, depth, &declaring_class, &field)?;
+            },
+            CodeInstruction::InvokeStatic(index) => {
+                let method = parsed_bytecode.constant_pool.find_member_ref(index)?;
+                let symbol = method_symbol(abi, classes, &method)?;
+                emit_invoke(asm, &frame, depth, &method, false, Dispatch::Direct(symbol))?;
+            },
+            CodeInstruction::InvokeDynamic(index) => {
+                let call_site = parsed_bytecode.call_site(index)?;
+                if is_lambda_call_site(&call_site) {
+                    let factory = MemberRef { class_name: lambda_class_name(&class_name, index), name: LAMBDA_FACTORY.to_string(), descriptor: call_site.descriptor };
+                    let symbol = method_symbol(abi, classes, &factory)?;
+                    emit_invoke(asm, &frame, depth, &factory, false, Dispatch::Direct(symbol))?;
+                    emit_safepoints(asm, ds, start, &label, &frame, state);
+                    continue;
+                }
+                emit_string_concat(asm, ds, abi, &frame, depth, classes, &string_concat(&parsed_bytecode.constant_pool, &call_site)?)?;
+                let mut scratch = state.clone();
+                scratch.stack.push(ir::Type::Reference);
+                emit_safepoints(asm, ds, start, &label, &frame, &scratch);
+                continue;
+            },
+            CodeInstruction::Ireturn => emit_return(asm, abi, &frame, depth, Some(ir::Type::Int)),
+            CodeInstruction::Lreturn => emit_return(asm, abi, &frame, depth, Some(ir::Type::Long)),
+            CodeInstruction::Freturn => emit_return(asm, abi, &frame, depth, Some(ir::Type::Float)),
+            CodeInstruction::Dreturn => emit_return(asm, abi, &frame, depth, Some(ir::Type::Double)),
+            CodeInstruction::Areturn => emit_return(asm, abi, &frame, depth, Some(ir::Type::Reference)),
+            CodeInstruction::Return => emit_return(asm, abi, &frame, depth, None),
+            CodeInstruction::MonitorEnter => emit_monitor(asm, ds, abi, &frame, depth, &label, "runtime$monitor_enter"),
+            CodeInstruction::MonitorExit => emit_monitor(asm, ds, abi, &frame, depth, &label, "runtime$monitor_exit"),
+            CodeInstruction::AThrow => {
+                asm.emit_mov(RDI, qword(frame.stack(depth - 1)));
+                asm.emit_call(abi.symbol("runtime$throw"));
+            },
+            instruction => return Err(format!("Unsupported instruction at {}: {:?}", pc, instruction)),
+        }
+
+        emit_safepoints(asm, ds, start, &label, &frame, state);
+    }
+    asm.emit_label(&pc_label(symbol, code_attribute.code_length));
+
+    for (index, entry) in code_attribute.exception_table.iter().enumerate() {
+        // Nothing in the range can throw when the handler is unreachable
+        if !function.frames.contains_key(&(entry.handler_pc as u32)) {
+            continue;
+        }
+
+        let catch_class = match entry.catch_type {
+            0 => None,
+            catch_type => Some(parsed_bytecode.constant_pool.find_class_name(catch_type)?),
+        };
+        if let Some(catch_class) = &catch_class
+            && classes.find(catch_class).is_none()
+            && !is_runtime_class(catch_class)
+        {
+            return Err(format!("Can't catch {}, its class file was not found", catch_class));
+        }
+
+        let handler = format!("{}.handler{}", symbol, index);
+        emit_handler_entry(asm, &frame, &handler, &pc_label(symbol, entry.handler_pc as u32));
+        ds.exception_ranges.push(ExceptionRange {
+            start: pc_label(symbol, entry.start_pc as u32),
+            end: pc_label(symbol, entry.end_pc as u32),
+            handler,
+            catch_class,
+        });
+    }
+    if synchronized {
+        emit_synchronized_handler(asm, ds, abi, &frame, symbol, code_attribute.code_length);
+    }
+
+    Ok(())
+}
+
+/// Catches whatever leaves a synchronized method by a throw, after the method's own
+/// handlers had their turn, to let go of its monitor and throw it on.
+fn emit_synchronized_handler(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, symbol: &str, code_length: u32) {
+    let label = |name: &str| format!("{}.synchronized.{}", symbol, name);
+    let references = frame.reference_offsets(&ir::Frame { locals: Vec::new(), stack: vec![ir::Type::Reference] });
+
+    asm.emit_label(&label("handler"));
+    asm.emit_mov(RSP, RBP);
+    asm.emit_sub(RSP, frame.size());
+    asm.emit_mov(qword(frame.stack(0)), RAX);
+    asm.emit_mov(RDI, qword(frame.monitor()));
+    asm.emit_call(abi.symbol("runtime$monitor_exit"));
+    asm.emit_label(&label("exited"));
+    ds.stack_maps.push((label("exited"), references.clone()));
+    asm.emit_mov(RDI, qword(frame.stack(0)));
+    asm.emit_call(abi.symbol("runtime$throw"));
+    asm.emit_label(&label("thrown"));
+    ds.stack_maps.push((label("thrown"), references));
+
+    ds.exception_ranges.push(ExceptionRange {
+        start: pc_label(symbol, 0),
+        end: pc_label(symbol, code_length),
+        handler: label("handler"),
+        catch_class: None,
+    });
+}
+
+/// Enters or exits the monitor of the object on top of the stack, `routine` being the
+/// runtime's routine doing it.
+fn emit_monitor(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, routine: &str) {
+    asm.emit_mov(RDI, qword(frame.stack(depth - 1)));
+    emit_null_check(asm, ds, abi, label, RDI);
+    asm.emit_call(abi.symbol(routine));
+}
+
+/// Where `runtime$throw` enters an exception handler, with the frame in rbp and the
+/// exception in rax. The handler's code expects the exception alone on the operand stack.
+fn emit_handler_entry(asm: &mut Assembly, frame: &StackFrame, label: &str, handler: &str) {
+    asm.emit_label(label);
+    asm.emit_mov(RSP, RBP);
+    asm.emit_sub(RSP, frame.size());
+    asm.emit_mov(qword(frame.stack(0)), RAX);
+    asm.emit_jmp(handler);
+}
+
+/// Throws a NullPointerException when the reference in `register` is null. With signal
+/// null checks a load from it faults instead, and the runtime makes it look like it
+/// called `runtime$throw_null_pointer_exception` from its resume label.
+fn emit_null_check(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, label: &str, register: Register) {
+    match ds.null_checks {
+        NullChecks::Explicit => {
+            let not_null = format!("{}.not_null", label);
+            asm.emit_test(register, register);
+            asm.emit_jcc(Condition::Ne, &not_null);
+            asm.emit_call(abi.symbol("runtime$throw_null_pointer_exception"));
+            asm.emit_label(&not_null);
+        },
+        NullChecks::Signal => {
+            let fault = format!("{}.null_check", label);
+            let resume = format!("{}.null_check.resume", label);
+            asm.emit_label(&fault);
+            asm.emit_cmp(byte(register), 0);
+            asm.emit_label(&resume);
+            ds.null_check_sites.push((fault, resume.clone()));
+            ds.unmapped_null_checks.push(resume);
+        },
+    }
+}
+
+/// Checks the receiver of a call to `method` before its arguments are loaded.
+fn emit_receiver_null_check(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, method: &MemberRef) -> Result<(), String> {
+    let descriptor = parse_method_descriptor(&method.descriptor)?;
+    asm.emit_mov(RAX, qword(frame.stack(depth - descriptor.parameter_slots() - 1)));
+    emit_null_check(asm, ds, abi, label, RAX);
+    Ok(())
+}
+
+/// Every call is a place the collector may run, from an allocation in it or in a method
+/// it calls. The addresses the calls emitted since `start` return to are labelled and
+/// mapped to the slots holding references, the ones before the instruction.
+fn emit_safepoints(asm: &mut Assembly, ds: &mut DataSection, start: usize, label: &str, frame: &StackFrame, state: &ir::Frame) {
+    // A faulting null check returns to its resume label from a call to the runtime
+    for resume in std::mem::take(&mut ds.unmapped_null_checks) {
+        ds.stack_maps.push((resume, frame.reference_offsets(state)));
+    }
+
+    let calls: Vec<usize> = (start..asm.code.len()).filter(|&index| matches!(asm.code[index], X86Inst::Call(_))).collect();
+    for (number, index) in calls.into_iter().enumerate().rev() {
+        let return_label = format!("{}.return{}", label, number);
+        asm.code.insert(index + 1, X86Inst::Label(return_label.clone()));
+        ds.stack_maps.push((return_label, frame.reference_offsets(state)));
+    }
+}
+
+fn emit_ldc(asm: &mut Assembly, index: u16, parsed_bytecode: &ParsedBytecode, ds: &mut DataSection, frame: &StackFrame, depth: usize) -> Result<(), String> {
+    let str = match parsed_bytecode.constant_pool.get(index)? {
+        ConstantPoolEntry::Integer(entry) => {
+            emit_int_constant(asm, frame, depth, entry.value());
+            return Ok(());
+        },
+        ConstantPoolEntry::Float(entry) => {
+            emit_float_constant(asm, frame, depth, entry.value());
+            return Ok(());
+        },
+        ConstantPoolEntry::Long(entry) => {
+            let label = ds.add_quad(entry.value() as u64);
+            asm.emit_mov(RAX, qword(Memory::symbol(&label)));
+            asm.emit_mov(qword(frame.stack(depth)), RAX);
+            return Ok(());
+        },
+        ConstantPoolEntry::Double(entry) => {
+            let label = ds.add_quad(entry.value().to_bits());
+            asm.emit_mov(RAX, qword(Memory::symbol(&label)));
+            asm.emit_mov(qword(frame.stack(depth)), RAX);
+            return Ok(());
+        },
+        ConstantPoolEntry::ClassInfo(_) => {
+            let class_name = parsed_bytecode.constant_pool.find_class_name(index)?;
+            ds.add_array_class(&class_name);
+            asm.emit_mov(RAX, ds.add_class_object(&class_name));
+            asm.emit_mov(qword(frame.stack(depth)), RAX);
+            return Ok(());
+        },
+        ConstantPoolEntry::String(entry) => entry.clone(),
+        entry => return Err(format!("Unsupported constant for ldc: {:?}", entry)),
+    };
+
+    let value = parsed_bytecode.constant_pool.find_utf8_constant_pool_entry(str.string_index)?.units;
+    let label = ds.add_string(&value);
+    asm.emit_mov(RAX, &label);
+    asm.emit_mov(qword(frame.stack(depth)), RAX);
+    Ok(())
+}
+
+fn emit_int_constant(asm: &mut Assembly, frame: &StackFrame, depth: usize, value: i32) {
+    asm.emit_mov(dword(frame.stack(depth)), value);
+}
+
+fn emit_float_constant(asm: &mut Assembly, frame: &StackFrame, depth: usize, value: f32) {
+    asm.emit_mov(dword(frame.stack(depth)), value.to_bits());
+}
+
+fn emit_double_constant(asm: &mut Assembly, frame: &StackFrame, depth: usize, value: f64) {
+    asm.emit_mov(RAX, value.to_bits());
+    asm.emit_mov(qword(frame.stack(depth)), RAX);
+}
+
+/// Pushes a local variable of any type, whole slots are copied.
+fn emit_load(asm: &mut Assembly, frame: &StackFrame, depth: usize, index: usize) {
+    asm.emit_mov(RAX, qword(frame.local(index)));
+    asm.emit_mov(qword(frame.stack(depth)), RAX);
+}
+
+/// Stores the value in stack slot `slot` into a local variable.
+fn emit_store(asm: &mut Assembly, frame: &StackFrame, slot: usize, index: usize) {
+    asm.emit_mov(RAX, qword(frame.stack(slot)));
+    asm.emit_mov(qword(frame.local(index)), RAX);
+}
+
+fn emit_iinc(asm: &mut Assembly, frame: &StackFrame, index: usize, value: i16) {
+    asm.emit_add(dword(frame.local(index)), value);
+}
+
+/// Pops two ints and pushes `lhs op rhs`. x86 arithmetic wraps around on overflow, just
+/// like Java's.
+fn emit_int_binary(asm: &mut Assembly, frame: &StackFrame, depth: usize, op: fn(&mut Assembly, Operand, Operand)) {
+    asm.emit_mov(EAX, dword(frame.stack(depth - 2)));
+    op(asm, EAX.into(), dword(frame.stack(depth - 1)).into());
+    asm.emit_mov(dword(frame.stack(depth - 2)), EAX);
+}
+
+/// The shift count is masked to its low five bits by the CPU, as Java requires.
+fn emit_int_shift(asm: &mut Assembly, frame: &StackFrame, depth: usize, op: fn(&mut Assembly, Operand, Operand)) {
+    asm.emit_mov(ECX, dword(frame.stack(depth - 1)));
+    op(asm, dword(frame.stack(depth - 2)).into(), CL.into());
+}
+
+/// idiv faults on a zero divisor and on `Integer.MIN_VALUE / -1`. The first throws an
+/// ArithmeticException in Java and the second wraps around to `Integer.MIN_VALUE`, so
+/// both are checked before dividing.
+fn emit_int_division(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, remainder: bool) {
+    let not_zero = format!("{}.not_zero", label);
+    let divide = format!("{}.divide", label);
+    let done = format!("{}.done", label);
+
+    asm.emit_mov(EAX, dword(frame.stack(depth - 2)));
+    asm.emit_mov(ECX, dword(frame.stack(depth - 1)));
+    asm.emit_test(ECX, ECX);
+    asm.emit_jcc(Condition::Ne, &not_zero);
+    asm.emit_call(abi.symbol("runtime$throw_arithmetic_exception"));
+    asm.emit_label(&not_zero);
+
+    asm.emit_cmp(ECX, -1);
+    asm.emit_jcc(Condition::Ne, &divide);
+    if remainder {
+        asm.emit_xor(EDX, EDX);
+    } else {
+        asm.emit_neg(EAX);
+    }
+    asm.emit_jmp(&done);
+
+    asm.emit_label(&divide);
+    asm.emit_cdq();
+    asm.emit_idiv(ECX);
+
+    asm.emit_label(&done);
+    asm.emit_mov(dword(frame.stack(depth - 2)), if remainder { EDX } else { EAX });
+}
+
+fn emit_long_binary(asm: &mut Assembly, frame: &StackFrame, depth: usize, op: fn(&mut Assembly, Operand, Operand)) {
+    asm.emit_mov(RAX, qword(frame.stack(depth - 4)));
+    op(asm, RAX.into(), qword(frame.stack(depth - 2)).into());
+    asm.emit_mov(qword(frame.stack(depth - 4)), RAX);
+}
+
+/// The shift count is an int and is masked to its low six bits by the CPU.
+fn emit_long_shift(asm: &mut Assembly, frame: &StackFrame, depth: usize, op: fn(&mut Assembly, Operand, Operand)) {
+    asm.emit_mov(ECX, dword(frame.stack(depth - 1)));
+    op(asm, qword(frame.stack(depth - 3)).into(), CL.into());
+}
+
+/// Same as `emit_int_division` with 64-bit operands.
+fn emit_long_division(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, remainder: bool) {
+    let not_zero = format!("{}.not_zero", label);
+    let divide = format!("{}.divide", label);
+    let done = format!("{}.done", label);
+
+    asm.emit_mov(RAX, qword(frame.stack(depth - 4)));
+    asm.emit_mov(RCX, qword(frame.stack(depth - 2)));
+    asm.emit_test(RCX, RCX);
+    asm.emit_jcc(Condition::Ne, &not_zero);
+    asm.emit_call(abi.symbol("runtime$throw_arithmetic_exception"));
+    asm.emit_label(&not_zero);
+
+    asm.emit_cmp(RCX, -1);
+    asm.emit_jcc(Condition::Ne, &divide);
+    if remainder {
+        asm.emit_xor(EDX, EDX);
+    } else {
+        asm.emit_neg(RAX);
+    }
+    asm.emit_jmp(&done);
+
+    asm.emit_label(&divide);
+    asm.emit_cqo();
+    asm.emit_idiv(RCX);
+
+    asm.emit_label(&done);
+    asm.emit_mov(qword(frame.stack(depth - 4)), if remainder { RDX } else { RAX });
+}
+
+/// Pushes 1, 0 or -1 when the first long is greater, equal or less than the second.
+fn emit_lcmp(asm: &mut Assembly, frame: &StackFrame, depth: usize) {
+    asm.emit_xor(ECX, ECX);
+    asm.emit_xor(EDX, EDX);
+    asm.emit_mov(RAX, qword(frame.stack(depth - 4)));
+    asm.emit_cmp(RAX, qword(frame.stack(depth - 2)));
+    asm.emit_setcc(Condition::G, CL);
+    asm.emit_setcc(Condition::L, DL);
+    asm.emit_sub(ECX, EDX);
+    asm.emit_mov(dword(frame.stack(depth - 4)), ECX);
+}
+
+/// Returns the operand size and scalar move for floats or, when `wide`, doubles.
+fn float_operands(wide: bool) -> (fn(Memory) -> Memory, SseOp, usize) {
+    if wide { (qword, SseOp::Movsd, 2) } else { (dword, SseOp::Movss, 1) }
+}
+
+/// `op` is the scalar SSE2 instruction, its IEEE 754 semantics are the ones Java uses.
+fn emit_float_binary(asm: &mut Assembly, frame: &StackFrame, depth: usize, op: SseOp, wide: bool) {
+    let (size, mov, words) = float_operands(wide);
+    let lhs = size(frame.stack(depth - 2 * words));
+    let rhs = size(frame.stack(depth - words));
+
+    asm.emit_sse(mov, XMM0, lhs.clone());
+    asm.emit_sse(op, XMM0, rhs);
+    asm.emit_sse(mov, lhs, XMM0);
+}
+
+/// Java's floating point remainder truncates like C's fmod, which SSE has no instruction
+/// for. The x87 fprem does, but has to be repeated until it reports a complete reduction.
+fn emit_float_remainder(asm: &mut Assembly, frame: &StackFrame, depth: usize, label: &str, wide: bool) {
+    let (size, _, words) = float_operands(wide);
+    let lhs = size(frame.stack(depth - 2 * words));
+    let rhs = size(frame.stack(depth - words));
+    let reduce = format!("{}.reduce", label);
+
+    asm.emit_fld(rhs);
+    asm.emit_fld(lhs.clone());
+    asm.emit_label(&reduce);
+    asm.emit_fprem();
+    asm.emit_fnstsw(AX);
+    // C2 is set while the reduction is incomplete
+    asm.emit_test(AH, 4);
+    asm.emit_jcc(Condition::Ne, &reduce);
+    asm.emit_fstp(lhs);
+    asm.emit_fstp(ST0);
+}
+
+/// Pushes 1, 0 or -1 like lcmp. If either operand is NaN the comparison is unordered,
+/// which ucomiss reports as below, so fcmpl gets its -1 for free. fcmpg computes the
+/// negated comparison of the swapped operands instead, turning unordered into 1.
+fn emit_float_compare(asm: &mut Assembly, frame: &StackFrame, depth: usize, wide: bool, nan_is_greater: bool) {
+    let (size, mov, words) = float_operands(wide);
+    let ucomis = if wide { SseOp::Ucomisd } else { SseOp::Ucomiss };
+    let lhs = size(frame.stack(depth - 2 * words));
+    let rhs = size(frame.stack(depth - words));
+    let (first, second, above, below) = if nan_is_greater { (rhs, lhs, CL, AL) } else { (lhs, rhs, AL, CL) };
+
+    asm.emit_xor(EAX, EAX);
+    asm.emit_xor(ECX, ECX);
+    asm.emit_sse(mov, XMM0, first);
+    asm.emit_sse(ucomis, XMM0, second);
+    asm.emit_setcc(Condition::A, above);
+    asm.emit_setcc(Condition::B, below);
+    asm.emit_sub(EAX, ECX);
+    asm.emit_mov(dword(frame.stack(depth - 2 * words)), EAX);
+}
+
+/// Converts the value on top of the stack in place.
+fn emit_convert(asm: &mut Assembly, frame: &StackFrame, depth: usize, label: &str, from: ir::Type, to: ir::Type) {
+    let slot = frame.stack(depth - if from.is_wide() { 2 } else { 1 });
+
+    match (from, to) {
+        (ir::Type::Int, ir::Type::Long) => {
+            asm.emit_movsxd(RAX, dword(slot.clone()));
+            asm.emit_mov(qword(slot), RAX);
+        },
+        // The int is the low half of the long, which is already where it belongs
+        (ir::Type::Long, ir::Type::Int) => {},
+        (ir::Type::Int | ir::Type::Long, ir::Type::Float | ir::Type::Double) => {
+            let source = if from == ir::Type::Long { qword(slot.clone()) } else { dword(slot.clone()) };
+            let (size, mov, _) = float_operands(to == ir::Type::Double);
+            asm.emit_sse(if to == ir::Type::Double { SseOp::Cvtsi2sd } else { SseOp::Cvtsi2ss }, XMM0, source);
+            asm.emit_sse(mov, size(slot), XMM0);
+        },
+        (ir::Type::Float, ir::Type::Double) => {
+            asm.emit_sse(SseOp::Cvtss2sd, XMM0, dword(slot.clone()));
+            asm.emit_sse(SseOp::Movsd, qword(slot), XMM0);
+        },
+        (ir::Type::Double, ir::Type::Float) => {
+            asm.emit_sse(SseOp::Cvtsd2ss, XMM0, qword(slot.clone()));
+            asm.emit_sse(SseOp::Movss, dword(slot), XMM0);
+        },
+        (ir::Type::Float | ir::Type::Double, ir::Type::Int | ir::Type::Long) => {
+            emit_float_to_integer(asm, slot, label, from == ir::Type::Double, to == ir::Type::Long);
+        },
+        _ => unreachable!("no conversion from {:?} to {:?}", from, to),
+    }
+}
+
+/// Java saturates: NaN becomes 0 and values out of range the closest of MIN_VALUE and
+/// MAX_VALUE. cvttss2si produces MIN_VALUE for all of those, so when it does the
+/// source is inspected to pick the right result.
+fn emit_float_to_integer(asm: &mut Assembly, slot: Memory, label: &str, from_double: bool, to_long: bool) {
+    let (size, mov, _) = float_operands(from_double);
+    let (convert, ucomis) = if from_double { (SseOp::Cvttsd2si, SseOp::Ucomisd) } else { (SseOp::Cvttss2si, SseOp::Ucomiss) };
+    let (result, scratch, min, max) = if to_long {
+        (RAX, RCX, i64::MIN, i64::MAX)
+    } else {
+        (EAX, ECX, i32::MIN as u32 as i64, i32::MAX as i64)
+    };
+    let nan = format!("{}.nan", label);
+    let done = format!("{}.done", label);
+
+    asm.emit_sse(mov, XMM0, size(slot.clone()));
+    asm.emit_sse(convert, result, XMM0);
+    asm.emit_mov(scratch, min);
+    asm.emit_cmp(result, scratch);
+    asm.emit_jcc(Condition::Ne, &done);
+    asm.emit_sse(ucomis, XMM0, XMM0);
+    asm.emit_jcc(Condition::P, &nan);
+    asm.emit_sse(SseOp::Xorps, XMM1, XMM1);
+    asm.emit_sse(ucomis, XMM0, XMM1);
+    asm.emit_jcc(Condition::B, &done);
+    asm.emit_mov(result, max);
+    asm.emit_jmp(&done);
+    asm.emit_label(&nan);
+    asm.emit_xor(EAX, EAX);
+    asm.emit_label(&done);
+    asm.emit_mov(if to_long { qword(slot) } else { dword(slot) }, result);
+}
+
+/// i2b, i2c and i2s truncate the int and sign or zero extend it back.
+fn emit_narrow(asm: &mut Assembly, frame: &StackFrame, depth: usize, to: ir::ElementType) {
+    let slot = frame.stack(depth - 1);
+    match to {
+        ir::ElementType::Byte => asm.emit_movsx(EAX, byte(slot.clone())),
+        ir::ElementType::Char => asm.emit_movzx(EAX, word(slot.clone())),
+        _ => asm.emit_movsx(EAX, word(slot.clone())),
+    }
+    asm.emit_mov(dword(slot), EAX);
+}
+
+/// ifeq to ifle compare the int on top of the stack with zero.
+fn emit_if(asm: &mut Assembly, frame: &StackFrame, depth: usize, condition: Condition, target: &str) {
+    asm.emit_cmp(dword(frame.stack(depth - 1)), 0);
+    asm.emit_jcc(condition, target);
+}
+
+/// if_icmp and, when `reference`, if_acmp compare the two values on top of the stack.
+fn emit_if_cmp(asm: &mut Assembly, frame: &StackFrame, depth: usize, condition: Condition, target: &str, reference: bool) {
+    let (size, register): (fn(Memory) -> Memory, Register) = if reference { (qword, RAX) } else { (dword, EAX) };
+
+    asm.emit_mov(register, size(frame.stack(depth - 2)));
+    asm.emit_cmp(register, size(frame.stack(depth - 1)));
+    asm.emit_jcc(condition, target);
+}
+
+fn emit_if_null(asm: &mut Assembly, frame: &StackFrame, depth: usize, condition: Condition, target: &str) {
+    asm.emit_cmp(qword(frame.stack(depth - 1)), 0);
+    asm.emit_jcc(condition, target);
+}
+
+/// Indexes a table of case addresses with `key - low`. Keys outside of [low, high] wrap
+/// around to large unsigned values, so a single comparison sends them to the default.
+fn emit_table_switch(asm: &mut Assembly, frame: &StackFrame, depth: usize, table: &str, low: i32, high: i32, default: &str) {
+    asm.emit_movsxd(RAX, dword(frame.stack(depth - 1)));
+    asm.emit_sub(RAX, low);
+    asm.emit_cmp(RAX, high as i64 - low as i64);
+    asm.emit_jcc(Condition::A, default);
+    asm.emit_jmp(qword(Memory::symbol(table) + RAX * 8));
+}
+
+// A lookupswitch whose keys take up at least this share of the range from the smallest
+// to the largest is as dense as a tableswitch
+const LOOKUP_SWITCH_MIN_DENSITY: f64 = 0.5;
+
+// A sparser search compares the key against each case in turn when this many are left
+const LOOKUP_SWITCH_LINEAR_CASES: usize = 4;
+
+/// Dense lookupswitches get a jump table like a tableswitch, with the keys between the
+/// cases going to the default. The cases are sorted by key, so sparse ones are binary
+/// searched.
+fn emit_lookup_switch(asm: &mut Assembly, ds: &mut DataSection, frame: &StackFrame, depth: usize, label: &str, cases: &[(i32, String)], default: &str) {
+    if let (Some((low, _)), Some((high, _))) = (cases.first(), cases.last()) {
+        let range = *high as i64 - *low as i64 + 1;
+        // One or two compares beat loading from a table
+        if cases.len() > 2 && cases.len() as f64 >= range as f64 * LOOKUP_SWITCH_MIN_DENSITY {
+            let table = format!("{}.table", label);
+            emit_table_switch(asm, frame, depth, &table, *low, *high, default);
+            let mut targets = vec![default.to_string(); range as usize];
+            for (key, target) in cases {
+                targets[(*key as i64 - *low as i64) as usize] = target.clone();
             }
+            ds.jump_tables.push((table, targets));
+            return;
+        }
+    }
+
+    asm.emit_mov(EAX, dword(frame.stack(depth - 1)));
+    emit_lookup_switch_cases(asm, label, cases, 0, default);
+}
+
+// `first` is the index of cases[0] among all the cases of the switch
+fn emit_lookup_switch_cases(asm: &mut Assembly, label: &str, cases: &[(i32, String)], first: usize, default: &str) {
+    if cases.len() <= LOOKUP_SWITCH_LINEAR_CASES {
+        for (key, target) in cases {
+            asm.emit_cmp(EAX, *key);
+            asm.emit_jcc(Condition::E, target);
         }
+        asm.emit_jmp(default);
+        return;
     }
 
-    // Emit data section
+    let middle = cases.len() / 2;
+    let (key, target) = &cases[middle];
+    let below = format!("{}.below{}", label, first + middle);
 
-    asm.emit_section_data();
-    asm.emit_global_data_section_elements();
-    asm.emit_data_section_elements();
+    asm.emit_cmp(EAX, *key);
+    asm.emit_jcc(Condition::E, target);
+    asm.emit_jcc(Condition::L, &below);
+    emit_lookup_switch_cases(asm, label, &cases[middle + 1..], first + middle + 1, default);
+    asm.emit_label(&below);
+    emit_lookup_switch_cases(asm, label, &cases[..middle], first, default);
+}
+
+/// The dup family copies the top `top` slots below the next `below` slots. Whole slots
+/// are moved so it works for every type.
+fn emit_dup(asm: &mut Assembly, frame: &StackFrame, depth: usize, top: usize, below: usize) {
+    const REGISTERS: [Register; 4] = [RAX, RCX, RDX, RSI];
 
-    for element in ds.elements {
-        asm.emit_db(&element);
+    let base = depth - top - below;
+    let moved = top + below;
+    for (i, register) in REGISTERS.iter().take(moved).enumerate() {
+        asm.emit_mov(*register, qword(frame.stack(base + i)));
     }
 
-    println!("{}", asm.code.join(""));
+    let order = (below..moved).chain(0..moved);
+    for (slot, register) in order.enumerate() {
+        asm.emit_mov(qword(frame.stack(base + slot)), REGISTERS[register]);
+    }
+}
+
+fn emit_swap(asm: &mut Assembly, frame: &StackFrame, depth: usize) {
+    asm.emit_mov(RAX, qword(frame.stack(depth - 2)));
+    asm.emit_mov(RCX, qword(frame.stack(depth - 1)));
+    asm.emit_mov(qword(frame.stack(depth - 2)), RCX);
+    asm.emit_mov(qword(frame.stack(depth - 1)), RAX);
+}
+
+/// Calls through the receiver's vtable unless only one method can be called, one that
+/// is private or final or declared in a final class.
+fn emit_invoke_virtual(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, classes: &ClassPath, method: &MemberRef) -> Result<(), String> {
+    // The library's methods are neither private nor final
+    let final_method = match classes.resolve_method(&method.class_name, &method.name, &method.descriptor)? {
+        Some((_, resolved)) => resolved.access_flags & (ACC_PRIVATE | ACC_FINAL) != 0,
+        None => !library_method(classes, method)?.is_virtual(),
+    };
+    if final_method || class_layout(classes, &method.class_name)?.access_flags & ACC_FINAL != 0 {
+        let symbol = method_symbol(abi, classes, method)?;
+        return emit_invoke(asm, frame, depth, method, true, Dispatch::Direct(symbol));
+    }
+
+    let index = vtable(classes, &method.class_name)?
+        .iter()
+        .position(|slot| slot.name == method.name && slot.descriptor == method.descriptor)
+        .ok_or_else(|| format!("{}.{}{} is not virtual", method.class_name, method.name, method.descriptor))?;
+    emit_invoke(asm, frame, depth, method, true, Dispatch::Virtual(index))
+}
+
+/// Constructors, private methods and super calls, which all call the method the
+/// reference resolves to.
+fn emit_invoke_special(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, classes: &ClassPath, method: &MemberRef) -> Result<(), String> {
+    // Object's constructor does nothing
+    if method.class_name == OBJECT_CLASS && method.name == "<init>" {
+        return Ok(());
+    }
+
+    let symbol = method_symbol(abi, classes, method)?;
+    emit_invoke(asm, frame, depth, method, true, Dispatch::Direct(symbol))
+}
+
+/// Calls the method the receiver's class has for the name and descriptor, found through
+/// its itable. Private interface methods are called directly.
+fn emit_invoke_interface(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, classes: &ClassPath, method: &MemberRef) -> Result<(), String> {
+    if let Some((_, resolved)) = classes.resolve_method(&method.class_name, &method.name, &method.descriptor)?
+        && resolved.access_flags & ACC_PRIVATE != 0
+    {
+        let symbol = method_symbol(abi, classes, method)?;
+        return emit_invoke(asm, frame, depth, method, true, Dispatch::Direct(symbol));
+    }
+
+    // The JDK's default methods, like Function.andThen, are not in the library
+    if !is_implemented(classes, &method.name, &method.descriptor)? {
+        return Err(format!("{}.{}{} is not supported by the runtime", method.class_name, method.name, method.descriptor));
+    }
+
+    let selector = ds.add_selector(&method.name, &method.descriptor);
+    emit_invoke(asm, frame, depth, method, true, Dispatch::Interface(selector, abi.symbol("runtime$interface_method")))
+}
+
+/// Whether a class of the program or of the library has an instance method with a body
+/// for the name and descriptor, one an interface call could reach.
+fn is_implemented(classes: &ClassPath, name: &str, descriptor: &str) -> Result<bool, String> {
+    for class in classes.classes.values() {
+        for method in class.methods.iter().filter(|method| method.access_flags & (ACC_ABSTRACT | ACC_STATIC) == 0) {
+            if class.method_name(method)? == name && class.method_descriptor(method)? == descriptor {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(all_library_methods().any(|method| method.is_virtual() && method.name == name && method.descriptor == descriptor))
+}
+
+/// Throws a ClassCastException unless the object on top of the stack is null or an
+/// instance of `class_name`.
+fn emit_check_cast(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str) {
+    ds.add_checked_class(class_name);
+
+    asm.emit_mov(RDI, qword(frame.stack(depth - 1)));
+    asm.emit_mov(RSI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_call(abi.symbol("runtime$check_cast"));
+}
+
+/// Replaces the object on top of the stack with 1 when it is an instance of
+/// `class_name` and 0 when it is not or is null.
+fn emit_instance_of(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str) {
+    ds.add_checked_class(class_name);
+
+    asm.emit_mov(RDI, qword(frame.stack(depth - 1)));
+    asm.emit_mov(RSI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_call(abi.symbol("runtime$instance_of"));
+    asm.emit_mov(qword(frame.stack(depth - 1)), RAX);
+}
 
+/// Allocates an instance of `class_name` and pushes it. Its fields are zero until a
+/// constructor is called on it.
+fn emit_new(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, classes: &ClassPath, class_name: &str) -> Result<(), String> {
+    if classes.find(class_name).is_none() && !is_runtime_class(class_name) {
+        return Err(format!("Can't create a {}, its class file was not found", class_name));
+    }
+
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_call(abi.symbol("runtime$new"));
+    asm.emit_mov(qword(frame.stack(depth)), RAX);
     Ok(())
 }
 
-fn emit_ldc(asm: &mut Assembly, index: u8, parsed_bytecode: &crate::bytecode::ParsedBytecode, ds: &mut DataSection) -> Result<(), String> {
-    asm.emit_mov("rsi", &format!("qword [data_section_elements + {}]", ds.offset));
-    ds.offset += 8;
+// The class of the arrays newarray creates for each of its type codes
+fn primitive_array_class(atype: u8) -> Result<String, String> {
+    let class_name = match atype {
+        4 => "[Z",
+        5 => "[C",
+        6 => "[F",
+        7 => "[D",
+        8 => "[B",
+        9 => "[S",
+        10 => "[I",
+        11 => "[J",
+        _ => return Err(format!("Invalid newarray type {}", atype)),
+    };
+    Ok(class_name.to_string())
+}
+
+/// Replaces the length on top of the stack with a new array of class `class_name`.
+fn emit_new_array(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str) {
+    ds.add_array_class(class_name);
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_movsxd(RSI, dword(frame.stack(depth - 1)));
+    asm.emit_call(abi.symbol("runtime$new_array"));
+    asm.emit_mov(qword(frame.stack(depth - 1)), RAX);
+}
+
+/// Replaces the `dimensions` lengths on top of the stack with a new array of class
+/// `class_name`, the runtime reads the lengths from their slots.
+fn emit_new_multi_array(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str, dimensions: usize) {
+    ds.add_array_class(class_name);
+    let first = depth - dimensions;
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_mov(RSI, dimensions);
+    asm.emit_mov(RDX, RBP);
+    asm.emit_sub(RDX, 8 * (frame.max_locals + first + 1));
+    asm.emit_call(abi.symbol("runtime$new_multi_array"));
+    asm.emit_mov(qword(frame.stack(first)), RAX);
+}
+
+// The element at the index in rcx of the array in rax
+fn element_address(element: ir::ElementType) -> Memory {
+    let (size, scale): (fn(Memory) -> Memory, u8) = match element {
+        ir::ElementType::Boolean | ir::ElementType::Byte => (byte, 1),
+        ir::ElementType::Char | ir::ElementType::Short => (word, 2),
+        ir::ElementType::Int | ir::ElementType::Float => (dword, 4),
+        _ => (qword, 8),
+    };
+    size(RAX + RCX * scale + ARRAY_DATA_OFFSET)
+}
+
+// Loads the array at `array` into rax and the index at `index` into rcx, throwing an
+// ArrayIndexOutOfBoundsException unless the index is below the length. Compared unsigned
+// a negative index is too large as well.
+fn emit_bounds_check(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, label: &str, array: Memory, index: Memory) {
+    let in_bounds = format!("{}.in_bounds", label);
+    let length = dword(RAX + ARRAY_LENGTH_OFFSET);
+    asm.emit_mov(RAX, qword(array));
+    emit_null_check(asm, ds, abi, label, RAX);
+    asm.emit_mov(ECX, dword(index));
+    asm.emit_cmp(ECX, length.clone());
+    asm.emit_jcc(Condition::B, &in_bounds);
+    asm.emit_mov(EDI, ECX);
+    asm.emit_mov(ESI, length);
+    asm.emit_call(abi.symbol("runtime$throw_array_index_out_of_bounds"));
+    asm.emit_label(&in_bounds);
+}
+
+/// Replaces an array and an index on top of the stack with the element at the index,
+/// extended to an int the way the JVM does when it is narrower.
+fn emit_array_load(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, element: ir::ElementType) {
+    emit_bounds_check(asm, ds, abi, label, frame.stack(depth - 2), frame.stack(depth - 1));
+    let address = element_address(element);
+    match element {
+        ir::ElementType::Boolean | ir::ElementType::Char => asm.emit_movzx(EAX, address),
+        ir::ElementType::Byte | ir::ElementType::Short => asm.emit_movsx(EAX, address),
+        ir::ElementType::Int | ir::ElementType::Float => asm.emit_mov(EAX, address),
+        _ => asm.emit_mov(RAX, address),
+    }
+    asm.emit_mov(qword(frame.stack(depth - 2)), RAX);
+}
+
+/// Pops a value, an index and an array and stores the value in the array. A reference
+/// has to be an instance of the array's element class, or null.
+fn emit_array_store(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, element: ir::ElementType) {
+    let width = if element.value_type().is_wide() { 2 } else { 1 };
+    let value = depth - width;
+    emit_bounds_check(asm, ds, abi, label, frame.stack(depth - width - 2), frame.stack(depth - width - 1));
+    asm.emit_mov(RDX, qword(frame.stack(value)));
+    if element == ir::ElementType::Reference {
+        let checked = format!("{}.checked", label);
+        asm.emit_test(RDX, RDX);
+        asm.emit_jcc(Condition::E, &checked);
+        asm.emit_mov(RDI, RAX);
+        asm.emit_mov(RSI, RDX);
+        asm.emit_call(abi.symbol("runtime$check_array_store"));
+        // The call doesn't keep them
+        asm.emit_mov(RAX, qword(frame.stack(depth - 3)));
+        asm.emit_mov(ECX, dword(frame.stack(depth - 2)));
+        asm.emit_mov(RDX, qword(frame.stack(value)));
+        asm.emit_label(&checked);
+    }
+    let address = element_address(element);
+    match element {
+        ir::ElementType::Boolean | ir::ElementType::Byte => asm.emit_mov(address, DL),
+        ir::ElementType::Char | ir::ElementType::Short => asm.emit_mov(address, DX),
+        ir::ElementType::Int | ir::ElementType::Float => asm.emit_mov(address, EDX),
+        _ => asm.emit_mov(address, RDX),
+    }
+}
+
+fn resolve_field(classes: &ClassPath, field: &MemberRef) -> Result<FieldLayout, String> {
+    let layout = class_layout(classes, &field.class_name)?;
+    layout
+        .field(&field.name, &field.descriptor)
+        .cloned()
+        .ok_or_else(|| format!("{} has no field {} {}", field.class_name, field.name, field.descriptor))
+}
+
+// The size of the memory operands a field of type `field_type` is accessed with
+fn field_size(field_type: &FieldType) -> fn(Memory) -> Memory {
+    match field_type {
+        FieldType::Boolean | FieldType::Byte => byte,
+        FieldType::Char | FieldType::Short => word,
+        FieldType::Int | FieldType::Float => dword,
+        _ => qword,
+    }
+}
+
+// The field at `offset` in the object in `register`
+fn field_address(field_type: &FieldType, register: Register, offset: usize) -> Memory {
+    field_size(field_type)(register + offset)
+}
+
+// Loads a field into rax, fields narrower than an int are extended the way the JVM does
+fn emit_load_field(asm: &mut Assembly, field_type: &FieldType, address: Memory) {
+    match field_type {
+        FieldType::Boolean | FieldType::Char => asm.emit_movzx(EAX, address),
+        FieldType::Byte | FieldType::Short => asm.emit_movsx(EAX, address),
+        FieldType::Int | FieldType::Float => asm.emit_mov(EAX, address),
+        _ => asm.emit_mov(RAX, address),
+    }
+}
+
+// Stores rax in a field
+fn emit_store_field(asm: &mut Assembly, field_type: &FieldType, address: Memory) {
+    match field_type {
+        // Only the lowest bit of a boolean is kept
+        FieldType::Boolean => {
+            asm.emit_and(EAX, 1);
+            asm.emit_mov(address, AL);
+        },
+        FieldType::Byte => asm.emit_mov(address, AL),
+        FieldType::Char | FieldType::Short => asm.emit_mov(address, AX),
+        FieldType::Int | FieldType::Float => asm.emit_mov(address, EAX),
+        _ => asm.emit_mov(address, RAX),
+    }
+}
+
+/// Replaces the object on top of the stack with the value of one of its fields.
+fn emit_get_field(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, field: &FieldLayout) {
+    asm.emit_mov(RAX, qword(frame.stack(depth - 1)));
+    emit_null_check(asm, ds, abi, label, RAX);
+    emit_load_field(asm, &field.field_type, field_address(&field.field_type, RAX, field.offset));
+    asm.emit_mov(qword(frame.stack(depth - 1)), RAX);
+}
+
+/// Pops a value and an object and stores the value in one of the object's fields.
+fn emit_put_field(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, label: &str, field: &FieldLayout) {
+    let width = if field.field_type.is_wide() { 2 } else { 1 };
+    asm.emit_mov(RCX, qword(frame.stack(depth - width - 1)));
+    emit_null_check(asm, ds, abi, label, RCX);
+    // The check's call doesn't keep rax
+    asm.emit_mov(RAX, qword(frame.stack(depth - width)));
+    emit_store_field(asm, &field.field_type, field_address(&field.field_type, RCX, field.offset));
+}
+
+// The stream of the runtime System.out or System.err is
+fn runtime_stream(field: &MemberRef) -> Option<&'static str> {
+    match (field.class_name.as_str(), field.name.as_str()) {
+        ("java/lang/System", "out") => Some("runtime$system_out"),
+        ("java/lang/System", "err") => Some("runtime$system_err"),
+        _ => None,
+    }
+}
+
+/// The class declaring the static field a reference resolves to. The JDK's classes
+/// have no static fields besides the streams of System.
+fn resolve_static_field(classes: &ClassPath, field: &MemberRef) -> Result<String, String> {
+    match classes.resolve_field(&field.class_name, &field.name, &field.descriptor)? {
+        Some((class, resolved)) if resolved.access_flags & ACC_STATIC != 0 => class.class_name(),
+        Some(_) => Err(format!("{}.{} is not static", field.class_name, field.name)),
+        None => Err(format!("{}.{} is not supported by the runtime", field.class_name, field.name)),
+    }
+}
+
+// The slot of a static field of `class_name`
+fn static_address(abi: &dyn OsAbi, class_name: &str, field: &MemberRef) -> Result<(FieldType, Memory), String> {
+    let field_type = parse_field_descriptor(&field.descriptor)?;
+    let address = field_size(&field_type)(Memory::symbol(&abi.symbol(&mangle_static_field(class_name, &field.name))));
+    Ok((field_type, address))
+}
 
-    let str = parsed_bytecode.constant_pool.find_string_constant_pool_entry(index.into())?;
-    ds.elements.push(parsed_bytecode.constant_pool.find_utf8_constant_pool_entry(str.string_index.into())?.bytes.clone());
+/// Pushes a static field declared by `class_name`.
+fn emit_get_static(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str, field: &MemberRef) -> Result<(), String> {
+    let (field_type, address) = static_address(abi, class_name, field)?;
+    emit_load_field(asm, &field_type, address);
+    asm.emit_mov(qword(frame.stack(depth)), RAX);
+    Ok(())
+}
 
-    
-    asm.emit_mov("rdx", &parsed_bytecode.constant_pool.find_utf8_constant_pool_entry(str.string_index.into())?.bytes.len().to_string());
+/// Pops a value and stores it in a static field declared by `class_name`.
+fn emit_put_static(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, class_name: &str, field: &MemberRef) -> Result<(), String> {
+    let (field_type, address) = static_address(abi, class_name, field)?;
+    let width = if field_type.is_wide() { 2 } else { 1 };
+    asm.emit_mov(RAX, qword(frame.stack(depth - width)));
+    emit_store_field(asm, &field_type, address);
     Ok(())
 }
 
-fn emit_invoke_virtual(asm: &mut Assembly, index: u16, parsed_bytecode: &crate::bytecode::ParsedBytecode) -> Result<(), String> {
-    asm.emit_call("runtime$println");
+// The states of a class's initialization
+const UNINITIALIZED: u8 = 0;
+const IN_PROGRESS: u8 = 1;
+const INITIALIZED: u8 = 2;
+const ERRONEOUS: u8 = 3;
+
+/// Whether initializing a class runs any code: its static initializer, or the
+/// initialization of a class it depends on.
+fn needs_initialization(classes: &ClassPath, class_name: &str) -> Result<bool, String> {
+    let Some(class) = classes.find(class_name) else {
+        return Ok(false);
+    };
+    Ok(class.find_method("<clinit>", "()V")?.is_some() || !initialization_dependencies(classes, class_name)?.is_empty())
+}
+
+/// The classes initialized before a class is, in the order JVMS 5.5 gives: the
+/// superclass, then the superinterfaces that declare a method with a body, each after
+/// the interfaces it extends. Interfaces depend on none. Only the classes that need
+/// initialization are listed.
+fn initialization_dependencies(classes: &ClassPath, class_name: &str) -> Result<Vec<String>, String> {
+    let Some(class) = classes.find(class_name) else {
+        return Ok(Vec::new());
+    };
+    if class.access_flags & ACC_INTERFACE != 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut candidates: Vec<String> = class.super_class_name()?.into_iter().collect();
+    let mut pending: Vec<(String, bool)> = class.interface_names()?.into_iter().rev().map(|interface| (interface, false)).collect();
+    while let Some((interface, visited)) = pending.pop() {
+        let Some(class) = classes.find(&interface) else {
+            continue;
+        };
+        if !visited {
+            pending.push((interface, true));
+            pending.extend(class.interface_names()?.into_iter().rev().map(|interface| (interface, false)));
+            continue;
+        }
+        let has_body = class.methods.iter().any(|method| method.access_flags & (ACC_ABSTRACT | ACC_STATIC) == 0);
+        if has_body && !candidates.contains(&interface) {
+            candidates.push(interface);
+        }
+    }
 
+    let mut dependencies = Vec::new();
+    for candidate in candidates {
+        if needs_initialization(classes, &candidate)? {
+            dependencies.push(candidate);
+        }
+    }
+    Ok(dependencies)
+}
+
+/// A class and the classes initialized before it, directly or not.
+fn initialized_by(classes: &ClassPath, class_name: &str) -> Result<Vec<String>, String> {
+    let mut initialized = vec![class_name.to_string()];
+    let mut next = 0;
+    while next < initialized.len() {
+        for dependency in initialization_dependencies(classes, &initialized[next])? {
+            if !initialized.contains(&dependency) {
+                initialized.push(dependency);
+            }
+        }
+        next += 1;
+    }
+    Ok(initialized)
+}
+
+// The class an instruction initializes before it uses it, if any. The JDK's classes are
+// the runtime's and need none.
+fn initialization_target(classes: &ClassPath, constant_pool: &ConstantPool, instruction: &CodeInstruction) -> Result<Option<String>, String> {
+    match instruction {
+        CodeInstruction::New(index) => Ok(Some(constant_pool.find_class_name(*index)?)),
+        CodeInstruction::GetStatic(index) | CodeInstruction::PutStatic(index) => {
+            let field = constant_pool.find_member_ref(*index)?;
+            classes.resolve_field(&field.class_name, &field.name, &field.descriptor)?.map(|(class, _)| class.class_name()).transpose()
+        },
+        CodeInstruction::InvokeStatic(index) => {
+            let method = constant_pool.find_member_ref(*index)?;
+            classes.resolve_method(&method.class_name, &method.name, &method.descriptor)?.map(|(class, _)| class.class_name()).transpose()
+        },
+        _ => Ok(None),
+    }
+}
+
+/// The instructions of a method that have to initialize a class first, by pc. There is
+/// no check where initializing the class runs no code, where it has started whenever
+/// the method runs, or where every path to the instruction initialized it already.
+fn initialization_guards(classes: &ClassPath, parsed_bytecode: &ParsedBytecode, function: &ir::Function, instructions: &[(u32, CodeInstruction)]) -> Result<BTreeMap<u32, String>, String> {
+    // Code of a class runs once its initialization has started, and the main class is
+    // initialized before main is called
+    let mut initialized = initialized_by(classes, &function.class_name)?;
+    initialized.extend(initialized_by(classes, &classes.main)?);
+    let mut guards = BTreeMap::new();
+    // What passing each check initializes
+    let mut initializes = BTreeMap::new();
+    for (pc, instruction) in instructions.iter().filter(|(pc, _)| function.frames.contains_key(pc)) {
+        if let Some(target) = initialization_target(classes, &parsed_bytecode.constant_pool, instruction)?
+            && needs_initialization(classes, &target)?
+            && !initialized.contains(&target)
+        {
+            initializes.insert(target.clone(), initialized_by(classes, &target)?);
+            guards.insert(*pc, target);
+        }
+    }
+
+    // The blocks are contiguous runs of instructions, found by their first pc
+    let mut starts: Vec<(u32, usize)> = function.blocks.iter().enumerate().filter_map(|(index, block)| Some((block.start_pc?, index))).collect();
+    starts.sort();
+    let block_of = |pc: u32| starts[starts.partition_point(|(start, _)| *start <= pc) - 1].1;
+    let mut checked = vec![BTreeSet::new(); function.blocks.len()];
+    for (pc, target) in &guards {
+        checked[block_of(*pc)].extend(initializes[target].iter().cloned());
+    }
+
+    // The classes checked on every path into each block, None until a path is found. An
+    // exception can leave a block before its checks, so its handlers only get what it
+    // starts with.
+    let mut entry: Vec<Option<BTreeSet<String>>> = vec![None; function.blocks.len()];
+    entry[0] = Some(BTreeSet::new());
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &function.blocks {
+            let Some(start) = entry[block.id.0 as usize].clone() else {
+                continue;
+            };
+            let end: BTreeSet<String> = start.union(&checked[block.id.0 as usize]).cloned().collect();
+            let edges = block.terminator.successors().into_iter().map(|successor| (successor, &end));
+            for (successor, classes) in edges.chain(block.handlers.iter().map(|edge| (edge.handler, &start))) {
+                let merged = match &entry[successor.0 as usize] {
+                    Some(known) => known.intersection(classes).cloned().collect(),
+                    None => classes.clone(),
+                };
+                if entry[successor.0 as usize].as_ref() != Some(&merged) {
+                    entry[successor.0 as usize] = Some(merged);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut current = None;
+    let mut checked = BTreeSet::new();
+    guards.retain(|pc, target| {
+        let block = block_of(*pc);
+        if current != Some(block) {
+            current = Some(block);
+            checked = entry[block].clone().unwrap_or_default();
+        }
+        let needed = !checked.contains(target);
+        checked.extend(initializes[target].iter().cloned());
+        needed
+    });
+    Ok(guards)
+}
+
+/// Initializes `target` unless that has been done.
+fn emit_initialization_guard(asm: &mut Assembly, abi: &dyn OsAbi, label: &str, target: &str) {
+    let initialized = format!("{}.initialized", label);
+    asm.emit_cmp(qword(Memory::symbol(&abi.symbol(&mangle_class_state(target)))), INITIALIZED);
+    asm.emit_jcc(Condition::E, &initialized);
+    asm.emit_call(abi.symbol(&mangle_initializer(target)));
+    asm.emit_label(&initialized);
+}
+
+/// Emits the function initializing a class, which runs the initializers of the classes
+/// it depends on and then its static initializer. The class's state says whether that
+/// has been done: once it has started the function returns at once, as initialization
+/// already in progress is this thread's own, unless it failed and the class is unusable.
+///
+/// An exception thrown while initializing leaves the class erroneous and is thrown on,
+/// wrapped in an ExceptionInInitializerError unless it is an Error.
+fn emit_initializer(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, classes: &ClassPath, class_name: &str) -> Result<(), String> {
+    let symbol = abi.symbol(&mangle_initializer(class_name));
+    let state = qword(Memory::symbol(&abi.symbol(&mangle_class_state(class_name))));
+    let label = |name: &str| format!("{}.{}", symbol, name);
+
+    let mut calls: Vec<String> = initialization_dependencies(classes, class_name)?
+        .iter()
+        .map(|dependency| abi.symbol(&mangle_initializer(dependency)))
+        .collect();
+    if let Some(class) = classes.find(class_name)
+        && class.find_method("<clinit>", "()V")?.is_some()
+    {
+        calls.push(abi.symbol(&mangle_method(class_name, "<clinit>", "()V")));
+    }
+
+    asm.emit_function_start(&symbol);
+    asm.emit_cmp(state.clone(), UNINITIALIZED);
+    asm.emit_jcc(Condition::Ne, &label("started"));
+    // The exception is kept in the one slot of the frame while the handler allocates
+    asm.emit_push(RBP);
+    asm.emit_mov(RBP, RSP);
+    asm.emit_sub(RSP, 16);
+    asm.emit_mov(state.clone(), IN_PROGRESS);
+    asm.emit_label(&label("start"));
+    for (number, call) in calls.iter().enumerate() {
+        asm.emit_call(call);
+        let return_label = label(&format!("return{}", number));
+        asm.emit_label(&return_label);
+        ds.stack_maps.push((return_label, Vec::new()));
+    }
+    asm.emit_label(&label("end"));
+    asm.emit_mov(state.clone(), INITIALIZED);
+    asm.emit_leave();
+    asm.emit_ret();
+
+    asm.emit_label(&label("started"));
+    asm.emit_cmp(state.clone(), ERRONEOUS);
+    asm.emit_jcc(Condition::E, &label("erroneous"));
+    asm.emit_ret();
+    asm.emit_label(&label("erroneous"));
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(class_name)));
+    asm.emit_jmp(abi.symbol("runtime$throw_no_class_def_found_error"));
+
+    asm.emit_label(&label("handler"));
+    asm.emit_mov(RSP, RBP);
+    asm.emit_sub(RSP, 16);
+    asm.emit_mov(qword(RBP - 8), RAX);
+    asm.emit_mov(state, ERRONEOUS);
+    asm.emit_mov(RDI, RAX);
+    asm.emit_mov(RSI, abi.symbol(&mangle_class(ERROR_CLASS)));
+    asm.emit_call(abi.symbol("runtime$instance_of"));
+    let is_error = label("is_error");
+    asm.emit_label(&is_error);
+    ds.stack_maps.push((is_error.clone(), vec![8]));
+    asm.emit_test(RAX, RAX);
+    asm.emit_jcc(Condition::Ne, &label("throw"));
+    // Its constructor would leave everything zero
+    asm.emit_mov(RDI, abi.symbol(&mangle_class(EXCEPTION_IN_INITIALIZER_ERROR_CLASS)));
+    asm.emit_call(abi.symbol("runtime$new"));
+    let wrapped = label("wrapped");
+    asm.emit_label(&wrapped);
+    ds.stack_maps.push((wrapped, vec![8]));
+    asm.emit_mov(qword(RBP - 8), RAX);
+    asm.emit_label(&label("throw"));
+    asm.emit_mov(RDI, qword(RBP - 8));
+    asm.emit_call(abi.symbol("runtime$throw"));
+    let thrown = label("thrown");
+    asm.emit_label(&thrown);
+    ds.stack_maps.push((thrown, vec![8]));
+
+    ds.exception_ranges.push(ExceptionRange { start: label("start"), end: label("end"), handler: label("handler"), catch_class: None });
     Ok(())
 }
 
-fn emit_ret(asm: &mut Assembly, parsed_bytecode: &crate::bytecode::ParsedBytecode) -> Result<(), String> {
-    asm.emit_mov("rax", "0x2000001");
-    asm.emit_mov("rdi", "0");
+/// The process entry point. It initializes the main class, calls its main method and
+/// exits with status 0 once main returns.
+fn emit_entry(asm: &mut Assembly, abi: &dyn OsAbi, initializer: Option<&str>, main: &str) {
+    asm.emit_global(abi.entry_symbol());
+    asm.emit_function_start(abi.entry_symbol());
+    // rbx holds argc and r12 argv until main's String[] is made from them
+    match abi.entry_arguments() {
+        EntryArguments::Stack => {
+            asm.emit_mov(RBX, qword(RSP));
+            asm.emit_mov(R12, RSP);
+            asm.emit_add(R12, 8);
+        },
+        EntryArguments::Registers => {
+            asm.emit_mov(RBX, RDI);
+            asm.emit_mov(R12, RSI);
+        },
+    }
+    // Whoever jumps here, main has to be called with a 16 byte aligned stack
+    asm.emit_and(RSP, -16);
+    asm.emit_call(abi.symbol("runtime$initialize"));
+    // Compiled code doesn't keep rbx or r12
+    if let Some(initializer) = initializer {
+        asm.emit_push(RBX);
+        asm.emit_push(R12);
+        asm.emit_call(initializer);
+        asm.emit_pop(R12);
+        asm.emit_pop(RBX);
+    }
+    asm.emit_mov(RDI, RBX);
+    asm.emit_mov(RSI, R12);
+    asm.emit_call(abi.symbol("runtime$main_args"));
+    asm.emit_mov(RDI, RAX);
+    asm.emit_call(main);
+
+    asm.emit_mov(RAX, abi.exit_syscall());
+    asm.emit_mov(RDI, 0);
     asm.emit_syscall();
+}
+
+// The System V AMD64 argument registers
+const INTEGER_ARGUMENT_REGISTERS: [Register; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+const SSE_ARGUMENT_REGISTERS: [Register; 8] = [XMM0, XMM1, XMM2, XMM3, XMM4, XMM5, XMM6, XMM7];
+
+/// Where a parameter is passed, the ints, longs and references go in the integer
+/// registers and the floats and doubles in the SSE registers. Once a kind of register
+/// runs out the remaining parameters of that kind are passed on the stack.
+enum ArgumentLocation {
+    Register(Register),
+    Stack(usize),
+}
+
+/// The types of the arguments a method takes, `this` first for instance methods.
+fn parameter_types(descriptor: &MethodDescriptor, receiver: bool) -> Vec<ir::Type> {
+    let this = receiver.then_some(ir::Type::Reference);
+    this.into_iter().chain(descriptor.parameters.iter().map(ir::Type::from_field_type)).collect()
+}
+
+/// Assigns a location to each parameter, along with the JVM slot it takes among the
+/// parameters. Stack arguments are numbered in the order they are laid out in memory.
+fn argument_locations(parameters: &[ir::Type]) -> Vec<(ir::Type, usize, ArgumentLocation)> {
+    let mut integers = INTEGER_ARGUMENT_REGISTERS.iter();
+    let mut sses = SSE_ARGUMENT_REGISTERS.iter();
+    let mut stack = 0;
+    let mut slot = 0;
+
+    let mut locations = Vec::with_capacity(parameters.len());
+    for &ty in parameters {
+        let register = match ty {
+            ir::Type::Float | ir::Type::Double => sses.next(),
+            _ => integers.next(),
+        };
+        let location = match register {
+            Some(&register) => ArgumentLocation::Register(register),
+            None => {
+                stack += 1;
+                ArgumentLocation::Stack(stack - 1)
+            },
+        };
+
+        locations.push((ty, slot, location));
+        slot += if ty.is_wide() { 2 } else { 1 };
+    }
+
+    locations
+}
+
+/// Loads a value into a register of the kind it is passed in. Ints are moved as whole
+/// slots like the other integer types, the upper half is never looked at.
+fn emit_load_register(asm: &mut Assembly, ty: ir::Type, register: Register, address: Memory) {
+    match ty {
+        ir::Type::Float => asm.emit_sse(SseOp::Movss, register, dword(address)),
+        ir::Type::Double => asm.emit_sse(SseOp::Movsd, register, qword(address)),
+        _ => asm.emit_mov(register, qword(address)),
+    }
+}
+
+fn emit_store_register(asm: &mut Assembly, ty: ir::Type, address: Memory, register: Register) {
+    match ty {
+        ir::Type::Float => asm.emit_sse(SseOp::Movss, dword(address), register),
+        ir::Type::Double => asm.emit_sse(SseOp::Movsd, qword(address), register),
+        _ => asm.emit_mov(qword(address), register),
+    }
+}
+
+fn return_register(ty: ir::Type) -> Register {
+    match ty {
+        ir::Type::Float | ir::Type::Double => XMM0,
+        _ => RAX,
+    }
+}
+
+/// Copies the incoming arguments into the parameter local variables.
+fn emit_store_parameters(asm: &mut Assembly, frame: &StackFrame, parameters: &[ir::Type]) {
+    for (ty, slot, location) in argument_locations(parameters) {
+        let local = frame.local(slot);
+        match location {
+            ArgumentLocation::Register(register) => emit_store_register(asm, ty, local, register),
+            // Above the saved rbp and the return address
+            ArgumentLocation::Stack(index) => {
+                asm.emit_mov(RAX, qword(RBP + 16 + 8 * index));
+                asm.emit_mov(qword(local), RAX);
+            },
+        }
+    }
+}
+
+/// How a call finds the method it runs.
+enum Dispatch {
+    // The method known when compiling, by symbol
+    Direct(String),
+    // The method at an index of the receiver's vtable
+    Virtual(usize),
+    // The method for a selector in the receiver's itable, looked up by the runtime
+    // routine with the symbol
+    Interface(usize, String),
+}
+
+/// The symbol of the method a reference resolves to. Methods of the JDK's classes are
+/// the runtime library's and left to the linker.
+fn method_symbol(abi: &dyn OsAbi, classes: &ClassPath, method: &MemberRef) -> Result<String, String> {
+    match classes.resolve_method(&method.class_name, &method.name, &method.descriptor)? {
+        Some((class, _)) => Ok(abi.symbol(&mangle_method(&class.class_name()?, &method.name, &method.descriptor))),
+        None => {
+            let library_method = library_method(classes, method)?;
+            Ok(abi.symbol(&mangle_method(library_method.class_name, &method.name, &method.descriptor)))
+        },
+    }
+}
+
+/// The library method a reference resolves to when neither its class nor a superclass
+/// we compile has it, found from the first superclass that is the JDK's.
+fn library_method(classes: &ClassPath, method: &MemberRef) -> Result<LibraryMethod, String> {
+    let mut class_name = method.class_name.clone();
+    while let Some(class) = classes.find(&class_name) {
+        class_name = class.super_class_name()?.unwrap_or_else(|| OBJECT_CLASS.to_string());
+    }
+    find_library_method(&class_name, &method.name, &method.descriptor)
+        .ok_or_else(|| format!("{}.{}{} is not supported by the runtime", method.class_name, method.name, method.descriptor))
+}
+
+/// Calls a method with the arguments, and the receiver unless it is static, on top of
+/// the operand stack and pushes what it returns. Every value lives in the frame, so no
+/// registers have to be saved.
+fn emit_invoke(asm: &mut Assembly, frame: &StackFrame, depth: usize, method: &MemberRef, receiver: bool, dispatch: Dispatch) -> Result<(), String> {
+    let descriptor = parse_method_descriptor(&method.descriptor)?;
+    let parameters = parameter_types(&descriptor, receiver);
+    let base = depth - descriptor.parameter_slots() - receiver as usize;
+    let locations = argument_locations(&parameters);
+
+    let stack_arguments: Vec<usize> = locations
+        .iter()
+        .filter(|(_, _, location)| matches!(location, ArgumentLocation::Stack(_)))
+        .map(|(_, slot, _)| base + slot)
+        .collect();
+    // rsp has to stay 16 byte aligned at the call
+    let padding = if stack_arguments.len() % 2 == 1 { 8 } else { 0 };
+    if padding != 0 {
+        asm.emit_sub(RSP, padding);
+    }
+    for slot in stack_arguments.iter().rev() {
+        asm.emit_push(qword(frame.stack(*slot)));
+    }
+
+    for (ty, slot, location) in &locations {
+        if let ArgumentLocation::Register(register) = location {
+            emit_load_register(asm, *ty, *register, frame.stack(base + slot));
+        }
+    }
+
+    // The receiver is in rdi once the arguments are
+    match dispatch {
+        Dispatch::Direct(symbol) => asm.emit_call(&symbol),
+        Dispatch::Virtual(index) => {
+            asm.emit_mov(RAX, qword(RDI + CLASS_POINTER_OFFSET));
+            asm.emit_call(qword(RAX + VTABLE_OFFSET + 8 * index));
+        },
+        Dispatch::Interface(selector, lookup) => {
+            asm.emit_mov(RAX, selector);
+            asm.emit_call(&lookup);
+            asm.emit_call(RAX);
+        },
+    }
+
+    let cleanup = 8 * stack_arguments.len() + padding;
+    if cleanup != 0 {
+        asm.emit_add(RSP, cleanup);
+    }
+
+    if let Some(return_type) = &descriptor.return_type {
+        let ty = ir::Type::from_field_type(return_type);
+        emit_store_register(asm, ty, frame.stack(base), return_register(ty));
+    }
+
+    Ok(())
+}
+
+/// A piece of a string concatenation, text with the recipe's constants folded in or the
+/// index of an argument.
+enum ConcatPiece {
+    Text(Vec<u16>),
+    Argument(usize),
+}
+
+type StringConcat = (Vec<ConcatPiece>, MethodDescriptor);
+
+/// Resolves an invokedynamic call site that isn't a lambda's. Only string concatenation
+/// is supported, the StringConcatFactory.makeConcatWithConstants bootstrap method, whose
+/// recipe has a `\u{1}` for each argument and a `\u{2}` for each constant after it.
+fn string_concat(constant_pool: &ConstantPool, call_site: &CallSite) -> Result<StringConcat, String> {
+    let bootstrap = &call_site.bootstrap_method;
+    if bootstrap.class_name != "java/lang/invoke/StringConcatFactory" || bootstrap.name != "makeConcatWithConstants" {
+        return Err(format!("invokedynamic with bootstrap method {}.{} is not supported", bootstrap.class_name, bootstrap.name));
+    }
+
+    let string_constant = |index: &u16| -> Result<Vec<u16>, String> {
+        let entry = constant_pool.find_string_constant_pool_entry(*index)?;
+        Ok(constant_pool.find_utf8_constant_pool_entry(entry.string_index)?.units)
+    };
+    let mut arguments = call_site.bootstrap_arguments.iter();
+    let recipe = string_constant(arguments.next().ok_or("makeConcatWithConstants without a recipe")?)?;
+
+    let mut pieces = Vec::new();
+    let mut text = Vec::new();
+    let mut argument = 0;
+    for &unit in &recipe {
+        match unit {
+            1 => {
+                if !text.is_empty() {
+                    pieces.push(ConcatPiece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(ConcatPiece::Argument(argument));
+                argument += 1;
+            },
+            2 => text.extend(string_constant(arguments.next().ok_or("makeConcatWithConstants recipe has too few constants")?)?),
+            unit => text.push(unit),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(ConcatPiece::Text(text));
+    }
+
+    let descriptor = parse_method_descriptor(&call_site.descriptor)?;
+    if argument != descriptor.parameters.len() {
+        let recipe = String::from_utf16_lossy(&recipe);
+        return Err(format!("makeConcatWithConstants recipe {:?} doesn't match {} arguments", recipe, descriptor.parameters.len()));
+    }
+    Ok((pieces, descriptor))
+}
+
+/// Concatenates the arguments on top of the operand stack the way the recipe says with a
+/// StringBuilder, kept in the slot above them, and pushes the string.
+fn emit_string_concat(asm: &mut Assembly, ds: &mut DataSection, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, classes: &ClassPath, concat: &StringConcat) -> Result<(), String> {
+    let (pieces, descriptor) = concat;
+    let base = depth - descriptor.parameter_slots();
+    let slots: Vec<usize> = descriptor
+        .parameters
+        .iter()
+        .scan(base, |slot, parameter| {
+            let current = *slot;
+            *slot += if parameter.is_wide() { 2 } else { 1 };
+            Some(current)
+        })
+        .collect();
+
+    // The builder's slot is mapped as a reference while the instruction runs
+    let builder = depth;
+    asm.emit_mov(qword(frame.stack(builder)), 0);
+    emit_new(asm, abi, frame, depth, classes, STRING_BUILDER_CLASS)?;
+    asm.emit_mov(RDI, qword(frame.stack(builder)));
+    emit_builder_call(asm, abi, classes, "<init>", "()V")?;
+
+    for piece in pieces {
+        let parameter = match piece {
+            ConcatPiece::Text(text) => {
+                asm.emit_mov(RSI, ds.add_string(text));
+                "Ljava/lang/String;".to_string()
+            },
+            ConcatPiece::Argument(index) => {
+                let parameter = &descriptor.parameters[*index];
+                let ty = ir::Type::from_field_type(parameter);
+                let register = if matches!(ty, ir::Type::Float | ir::Type::Double) { XMM0 } else { RSI };
+                emit_load_register(asm, ty, register, frame.stack(slots[*index]));
+                match parameter {
+                    FieldType::Byte | FieldType::Short => "I".to_string(),
+                    FieldType::Object(class_name) if class_name == STRING_CLASS => parameter.to_string(),
+                    FieldType::Object(_) | FieldType::Array(_) => "Ljava/lang/Object;".to_string(),
+                    _ => parameter.to_string(),
+                }
+            },
+        };
+        asm.emit_mov(RDI, qword(frame.stack(builder)));
+        emit_builder_call(asm, abi, classes, "append", &format!("({})Ljava/lang/StringBuilder;", parameter))?;
+    }
+
+    asm.emit_mov(RDI, qword(frame.stack(builder)));
+    emit_builder_call(asm, abi, classes, "toString", "()Ljava/lang/String;")?;
+    asm.emit_mov(qword(frame.stack(base)), RAX);
+    Ok(())
+}
 
+/// Calls a StringBuilder method of the runtime library with its arguments in registers.
+fn emit_builder_call(asm: &mut Assembly, abi: &dyn OsAbi, classes: &ClassPath, name: &str, descriptor: &str) -> Result<(), String> {
+    let method = MemberRef { class_name: STRING_BUILDER_CLASS.to_string(), name: name.to_string(), descriptor: descriptor.to_string() };
+    let symbol = method_symbol(abi, classes, &method)?;
+    asm.emit_call(&symbol);
     Ok(())
 }
+
+/// Leaves the return value in rax or xmm0 and returns to the caller.
+fn emit_return(asm: &mut Assembly, abi: &dyn OsAbi, frame: &StackFrame, depth: usize, ty: Option<ir::Type>) {
+    if frame.synchronized {
+        asm.emit_mov(RDI, qword(frame.monitor()));
+        asm.emit_call(abi.symbol("runtime$monitor_exit"));
+    }
+    if let Some(ty) = ty {
+        let slot = depth - if ty.is_wide() { 2 } else { 1 };
+        emit_load_register(asm, ty, return_register(ty), frame.stack(slot));
+    }
+
+    asm.emit_leave();
+    asm.emit_ret();
+}
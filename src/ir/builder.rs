@@ -0,0 +1,1268 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::bytecode::attribute::CodeInstruction;
+use crate::bytecode::constantpool::ConstantPoolEntry;
+use crate::bytecode::descriptor::{parse_field_descriptor, parse_method_descriptor};
+use crate::bytecode::method::Method;
+use crate::bytecode::{ParsedBytecode, ACC_STATIC};
+use crate::ir::{
+    BinaryOp, Block, BlockId, CompareOp, Condition, Constant, ElementType, ExceptionEdge, Frame, Function, Inst,
+    InstKind, InvokeKind, Phi, Terminator, Type, ValueId,
+};
+
+/// The abstract JVM frame: the SSA value held by every local variable and operand stack
+/// slot. A wide value takes a single stack entry and leaves its second local slot empty.
+#[derive(Debug, Clone)]
+struct FrameState {
+    locals: Vec<Option<ValueId>>,
+    stack: Vec<ValueId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Local(usize),
+    Stack(usize),
+}
+
+impl FrameState {
+    fn get(&self, slot: Slot) -> Option<ValueId> {
+        match slot {
+            Slot::Local(index) => self.locals[index],
+            Slot::Stack(index) => self.stack.get(index).copied(),
+        }
+    }
+}
+
+// The block a phi belongs to and its incoming values, None where a predecessor has no
+// value in the phi's slot
+type PendingPhis = HashMap<ValueId, (BlockId, Vec<(BlockId, Option<ValueId>)>)>;
+
+/// A basic block of bytecode, `first..last` indexes into the decoded instructions.
+struct BytecodeBlock {
+    start_pc: u32,
+    first: usize,
+    last: usize,
+    successors: Vec<u32>,
+    handlers: Vec<(Option<String>, u32)>,
+}
+
+pub fn build_class(class: &ParsedBytecode) -> Result<Vec<Function>, String> {
+    let mut functions = Vec::new();
+    for method in &class.methods {
+        if let Some(function) = build_function(class, method)? {
+            functions.push(function);
+        }
+    }
+
+    Ok(functions)
+}
+
+/// Builds the SSA form of a method by abstract interpretation of its operand stack and
+/// local variables. Returns None for methods without code.
+pub fn build_function(class: &ParsedBytecode, method: &Method) -> Result<Option<Function>, String> {
+    let code = match class.code_attribute(method)? {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+
+    let name = class.method_name(method)?;
+    let descriptor = class.method_descriptor(method)?;
+    let instructions = code.into_code_instructions_with_offsets()?;
+    let blocks = split_blocks(class, &instructions, &code.exception_table, code.code_length)?;
+
+    let mut builder = Builder {
+        class,
+        value_types: Vec::new(),
+        insts: Vec::new(),
+        state: FrameState { locals: vec![None; code.max_locals as usize], stack: Vec::new() },
+        pc: 0,
+        frames: BTreeMap::new(),
+    };
+
+    // The arguments are the initial values of the first local variables
+    let is_static = method.access_flags & ACC_STATIC != 0;
+    let method_descriptor = parse_method_descriptor(&descriptor)?;
+    let mut params = Vec::new();
+    let mut slot = 0;
+    if !is_static {
+        let this = builder.new_value(Type::Reference);
+        params.push(this);
+        builder.store_local(0, this)?;
+        slot += 1;
+    }
+    for parameter in &method_descriptor.parameters {
+        let ty = Type::from_field_type(parameter);
+        let value = builder.new_value(ty);
+        params.push(value);
+        builder.store_local(slot, value)?;
+        slot += if ty.is_wide() { 2 } else { 1 };
+    }
+    let entry_state = builder.state.clone();
+
+    // Only blocks reachable from the entry are translated, in reverse postorder so that
+    // every block but a loop header is visited after all of its predecessors
+    let index_of_pc: HashMap<u32, usize> = blocks.iter().enumerate().map(|(i, b)| (b.start_pc, i)).collect();
+    let order = reverse_postorder(&blocks, &index_of_pc);
+    let reachable: HashSet<usize> = order.iter().copied().collect();
+
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); blocks.len()];
+    let mut handler_blocks = HashSet::new();
+    for &index in &order {
+        for successor in &blocks[index].successors {
+            predecessors[index_of_pc[successor]].push(index);
+        }
+        for (_, handler_pc) in &blocks[index].handlers {
+            predecessors[index_of_pc[handler_pc]].push(index);
+            handler_blocks.insert(index_of_pc[handler_pc]);
+        }
+    }
+
+    // A branch back to the first instruction needs somewhere to merge with the arguments
+    let synthetic_entry = !predecessors[0].is_empty();
+    let first_id = if synthetic_entry { 1 } else { 0 };
+    let mut block_ids = HashMap::new();
+    for (index, block) in blocks.iter().enumerate() {
+        if reachable.contains(&index) {
+            block_ids.insert(block.start_pc, BlockId(first_id + block_ids.len() as u32));
+        }
+    }
+
+    let mut ir_blocks: Vec<Option<Block>> = vec![None; block_ids.len()];
+    let mut exit_states: HashMap<usize, FrameState> = HashMap::new();
+    let mut phi_slots: HashMap<usize, Vec<(Slot, ValueId)>> = HashMap::new();
+
+    for &index in &order {
+        let block = &blocks[index];
+        let is_handler = handler_blocks.contains(&index);
+        let preds = &predecessors[index];
+
+        let entry = if index == 0 && !synthetic_entry {
+            entry_state.clone()
+        } else if preds.len() == 1 && !is_handler && exit_states.contains_key(&preds[0]) {
+            exit_states[&preds[0]].clone()
+        } else {
+            let mut incoming: Vec<&FrameState> = preds.iter().filter_map(|p| exit_states.get(p)).collect();
+            if index == 0 {
+                incoming.push(&entry_state);
+            }
+            let all_known = incoming.len() == preds.len() + if index == 0 { 1 } else { 0 };
+            let (state, slots) = builder.merge_states(&incoming, all_known, is_handler, block.start_pc)?;
+            phi_slots.insert(index, slots);
+            state
+        };
+
+        builder.state = entry;
+        builder.insts = Vec::new();
+        if is_handler {
+            if !predecessors[index].iter().all(|p| blocks[*p].handlers.iter().any(|(_, h)| *h == block.start_pc)) {
+                return Err(format!("Exception handler at {} is also reached by normal control flow", block.start_pc));
+            }
+            builder.pc = block.start_pc;
+            let exception = builder.emit(InstKind::CaughtException, Some(Type::Reference)).unwrap();
+            builder.state.stack = vec![exception];
+        }
+
+        for (pc, instruction) in &instructions[block.first..block.last - 1] {
+            builder.pc = *pc;
+            builder.frames.insert(*pc, builder.state.clone());
+            builder.interpret(instruction)?;
+        }
+        let (last_pc, last_instruction) = &instructions[block.last - 1];
+        builder.pc = *last_pc;
+        builder.frames.insert(*last_pc, builder.state.clone());
+        let next_block = instructions.get(block.last).and_then(|(pc, _)| block_ids.get(pc).copied());
+        let terminator = builder.terminate(last_instruction, next_block, &block_ids)?;
+
+        let handlers = block
+            .handlers
+            .iter()
+            .map(|(catch_type, handler_pc)| ExceptionEdge { catch_type: catch_type.clone(), handler: block_ids[handler_pc] })
+            .collect();
+
+        let id = block_ids[&block.start_pc];
+        ir_blocks[(id.0 - first_id) as usize] = Some(Block {
+            id,
+            start_pc: Some(block.start_pc),
+            phis: Vec::new(),
+            insts: std::mem::take(&mut builder.insts),
+            terminator,
+            handlers,
+        });
+        exit_states.insert(index, builder.state.clone());
+    }
+
+    let mut ir_blocks: Vec<Block> = ir_blocks.into_iter().map(|b| b.unwrap()).collect();
+    if synthetic_entry {
+        ir_blocks.insert(0, Block {
+            id: BlockId(0),
+            start_pc: None,
+            phis: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Goto(block_ids[&0]),
+            handlers: Vec::new(),
+        });
+    }
+
+    // Now that every predecessor has been translated the phis can be filled in
+    let mut phis: PendingPhis = HashMap::new();
+    for (&index, slots) in &phi_slots {
+        let id = block_ids[&blocks[index].start_pc];
+        let mut incoming_states: Vec<(BlockId, &FrameState)> = predecessors[index]
+            .iter()
+            .map(|p| (block_ids[&blocks[*p].start_pc], &exit_states[p]))
+            .collect();
+        if index == 0 {
+            incoming_states.insert(0, (BlockId(0), &entry_state));
+        }
+
+        for (slot, dest) in slots {
+            let incoming = incoming_states.iter().map(|(pred, state)| (*pred, state.get(*slot))).collect();
+            phis.insert(*dest, (id, incoming));
+        }
+    }
+
+    let mut function = Function {
+        class_name: class.class_name()?,
+        name,
+        descriptor,
+        is_static,
+        params,
+        blocks: ir_blocks,
+        value_types: builder.value_types,
+        frames: BTreeMap::new(),
+    };
+    let invalid = resolve_phis(&mut function, phis)?;
+
+    // Values that were dropped along with their phi leave the slot dead
+    let slot_type = |value: ValueId| if invalid.contains(&value) { None } else { Some(function.value_type(value)) };
+    let frames = builder
+        .frames
+        .iter()
+        .map(|(pc, state)| {
+            let locals = state.locals.iter().map(|value| value.and_then(slot_type)).collect();
+            let stack = state.stack.iter().map(|value| function.value_type(*value)).collect();
+            (*pc, Frame { locals, stack })
+        })
+        .collect();
+    function.frames = frames;
+    renumber_values(&mut function);
+
+    Ok(Some(function))
+}
+
+struct Builder<'a> {
+    class: &'a ParsedBytecode,
+    value_types: Vec<Type>,
+    insts: Vec<Inst>,
+    state: FrameState,
+    pc: u32,
+    // The state before every translated instruction
+    frames: BTreeMap<u32, FrameState>,
+}
+
+impl Builder<'_> {
+    fn new_value(&mut self, ty: Type) -> ValueId {
+        self.value_types.push(ty);
+        ValueId(self.value_types.len() as u32 - 1)
+    }
+
+    fn value_type(&self, value: ValueId) -> Type {
+        self.value_types[value.0 as usize]
+    }
+
+    fn emit(&mut self, kind: InstKind, ty: Option<Type>) -> Option<ValueId> {
+        let dest = ty.map(|ty| self.new_value(ty));
+        self.insts.push(Inst { pc: self.pc, dest, kind });
+        dest
+    }
+
+    /// Emits an instruction producing a value and pushes it onto the operand stack.
+    fn emit_push(&mut self, kind: InstKind, ty: Type) {
+        let value = self.emit(kind, Some(ty)).unwrap();
+        self.state.stack.push(value);
+    }
+
+    fn constant(&mut self, constant: Constant) -> ValueId {
+        let ty = constant.value_type();
+        self.emit(InstKind::Const(constant), Some(ty)).unwrap()
+    }
+
+    fn pop(&mut self) -> Result<ValueId, String> {
+        self.state.stack.pop().ok_or_else(|| format!("Operand stack underflow at {}", self.pc))
+    }
+
+    fn pop_n(&mut self, n: usize) -> Result<Vec<ValueId>, String> {
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            values.push(self.pop()?);
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    /// Pops values off the operand stack until exactly `words` slots have been popped.
+    fn pop_words(&mut self, words: usize) -> Result<Vec<ValueId>, String> {
+        let mut values = Vec::new();
+        let mut popped = 0;
+        while popped < words {
+            let value = self.pop()?;
+            popped += if self.value_type(value).is_wide() { 2 } else { 1 };
+            values.push(value);
+        }
+        if popped != words {
+            return Err(format!("Operation splits a long or double on the operand stack at {}", self.pc));
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    /// Implements the dup family: the top `top` words are copied below the next `below` words.
+    fn dup_words(&mut self, top: usize, below: usize) -> Result<(), String> {
+        let top = self.pop_words(top)?;
+        let below = self.pop_words(below)?;
+        self.state.stack.extend(&top);
+        self.state.stack.extend(&below);
+        self.state.stack.extend(&top);
+        Ok(())
+    }
+
+    fn load_local(&mut self, index: u16, ty: Type) -> Result<(), String> {
+        let value = self
+            .state
+            .locals
+            .get(index as usize)
+            .copied()
+            .flatten()
+            .ok_or_else(|| format!("Load from uninitialized local {} at {}", index, self.pc))?;
+        if self.value_type(value) != ty {
+            return Err(format!("Local {} does not hold a {:?} at {}", index, ty, self.pc));
+        }
+        self.state.stack.push(value);
+        Ok(())
+    }
+
+    fn store_local(&mut self, index: usize, value: ValueId) -> Result<(), String> {
+        let wide = self.value_type(value).is_wide();
+        if index + if wide { 2 } else { 1 } > self.state.locals.len() {
+            return Err(format!("Store to local {} out of bounds at {}", index, self.pc));
+        }
+
+        // Overwriting the second half of a wide value invalidates it
+        if index > 0
+            && let Some(previous) = self.state.locals[index - 1]
+            && self.value_type(previous).is_wide()
+        {
+            self.state.locals[index - 1] = None;
+        }
+        self.state.locals[index] = Some(value);
+        if wide {
+            self.state.locals[index + 1] = None;
+        }
+        Ok(())
+    }
+
+    fn store(&mut self, index: u16) -> Result<(), String> {
+        let value = self.pop()?;
+        self.store_local(index as usize, value)
+    }
+
+    fn binary(&mut self, op: BinaryOp, ty: Type) -> Result<(), String> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.emit_push(InstKind::Binary { op, ty, lhs, rhs }, ty);
+        Ok(())
+    }
+
+    fn negate(&mut self, ty: Type) -> Result<(), String> {
+        let value = self.pop()?;
+        self.emit_push(InstKind::Negate { ty, value }, ty);
+        Ok(())
+    }
+
+    fn convert(&mut self, from: Type, to: Type) -> Result<(), String> {
+        let value = self.pop()?;
+        self.emit_push(InstKind::Convert { from, to, value }, to);
+        Ok(())
+    }
+
+    fn narrow(&mut self, to: ElementType) -> Result<(), String> {
+        let value = self.pop()?;
+        self.emit_push(InstKind::Narrow { to, value }, Type::Int);
+        Ok(())
+    }
+
+    fn compare(&mut self, op: CompareOp, ty: Type) -> Result<(), String> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.emit_push(InstKind::Compare { op, ty, lhs, rhs }, Type::Int);
+        Ok(())
+    }
+
+    fn array_load(&mut self, element: ElementType) -> Result<(), String> {
+        let index = self.pop()?;
+        let array = self.pop()?;
+        self.emit_push(InstKind::ArrayLoad { element, array, index }, element.value_type());
+        Ok(())
+    }
+
+    fn array_store(&mut self, element: ElementType) -> Result<(), String> {
+        let value = self.pop()?;
+        let index = self.pop()?;
+        let array = self.pop()?;
+        self.emit(InstKind::ArrayStore { element, array, index, value }, None);
+        Ok(())
+    }
+
+    fn load_constant(&mut self, index: u16) -> Result<(), String> {
+        let constant_pool = &self.class.constant_pool;
+        let constant = match constant_pool.get(index)? {
+            ConstantPoolEntry::Integer(entry) => Constant::Int(entry.value()),
+            ConstantPoolEntry::Float(entry) => Constant::Float(entry.value()),
+            ConstantPoolEntry::Long(entry) => Constant::Long(entry.value()),
+            ConstantPoolEntry::Double(entry) => Constant::Double(entry.value()),
+            ConstantPoolEntry::String(entry) => {
+                Constant::String(constant_pool.find_utf8_constant_pool_entry(entry.string_index)?.bytes)
+            },
+            ConstantPoolEntry::ClassInfo(entry) => {
+                Constant::Class(constant_pool.find_utf8_constant_pool_entry(entry.name_index)?.bytes)
+            },
+            entry => return Err(format!("Unsupported constant for ldc at {}: {:?}", self.pc, entry)),
+        };
+
+        let value = self.constant(constant);
+        self.state.stack.push(value);
+        Ok(())
+    }
+
+    fn invoke(&mut self, kind: InvokeKind, index: u16) -> Result<(), String> {
+        let method = self.class.constant_pool.find_member_ref(index)?;
+        let descriptor = parse_method_descriptor(&method.descriptor)?;
+        let receiver = if kind == InvokeKind::Static { 0 } else { 1 };
+        let args = self.pop_n(descriptor.parameters.len() + receiver)?;
+        let ty = descriptor.return_type.as_ref().map(Type::from_field_type);
+
+        if let Some(value) = self.emit(InstKind::Invoke { kind, method, args }, ty) {
+            self.state.stack.push(value);
+        }
+        Ok(())
+    }
+
+    fn invoke_dynamic(&mut self, index: u16) -> Result<(), String> {
+        let constant_pool = &self.class.constant_pool;
+        let entry = constant_pool.find_invoke_dynamic_constant_pool_entry(index)?;
+        let (name, descriptor) = constant_pool.find_name_and_type(entry.name_and_type_index)?;
+        let method_descriptor = parse_method_descriptor(&descriptor)?;
+        let args = self.pop_n(method_descriptor.parameters.len())?;
+        let ty = method_descriptor.return_type.as_ref().map(Type::from_field_type);
+
+        let kind = InstKind::InvokeDynamic { bootstrap_method: entry.bootstrap_method_attr_index, name, descriptor, args };
+        if let Some(value) = self.emit(kind, ty) {
+            self.state.stack.push(value);
+        }
+        Ok(())
+    }
+
+    fn field_type(&self, index: u16) -> Result<(crate::bytecode::constantpool::MemberRef, Type), String> {
+        let field = self.class.constant_pool.find_member_ref(index)?;
+        let ty = Type::from_field_type(&parse_field_descriptor(&field.descriptor)?);
+        Ok((field, ty))
+    }
+
+    /// Translates an instruction that doesn't end its basic block.
+    fn interpret(&mut self, instruction: &CodeInstruction) -> Result<(), String> {
+        match instruction {
+            CodeInstruction::Nop => {},
+            CodeInstruction::AconstNull => {
+                let value = self.constant(Constant::Null);
+                self.state.stack.push(value);
+            },
+            CodeInstruction::Iconst(value) => self.emit_push(InstKind::Const(Constant::Int(*value)), Type::Int),
+            CodeInstruction::Lconst(value) => self.emit_push(InstKind::Const(Constant::Long(*value)), Type::Long),
+            CodeInstruction::Fconst(value) => self.emit_push(InstKind::Const(Constant::Float(*value)), Type::Float),
+            CodeInstruction::Dconst(value) => self.emit_push(InstKind::Const(Constant::Double(*value)), Type::Double),
+            CodeInstruction::Bipush(value) => self.emit_push(InstKind::Const(Constant::Int(*value as i32)), Type::Int),
+            CodeInstruction::Sipush(value) => self.emit_push(InstKind::Const(Constant::Int(*value as i32)), Type::Int),
+            CodeInstruction::Ldc(index) => self.load_constant(*index as u16)?,
+            CodeInstruction::LdcW(index) | CodeInstruction::Ldc2W(index) => self.load_constant(*index)?,
+            CodeInstruction::Iload(index) => self.load_local(*index, Type::Int)?,
+            CodeInstruction::Lload(index) => self.load_local(*index, Type::Long)?,
+            CodeInstruction::Fload(index) => self.load_local(*index, Type::Float)?,
+            CodeInstruction::Dload(index) => self.load_local(*index, Type::Double)?,
+            CodeInstruction::Aload(index) => self.load_local(*index, Type::Reference)?,
+            CodeInstruction::Iaload => self.array_load(ElementType::Int)?,
+            CodeInstruction::Laload => self.array_load(ElementType::Long)?,
+            CodeInstruction::Faload => self.array_load(ElementType::Float)?,
+            CodeInstruction::Daload => self.array_load(ElementType::Double)?,
+            CodeInstruction::Aaload => self.array_load(ElementType::Reference)?,
+            // baload is shared by byte and boolean arrays
+            CodeInstruction::Baload => self.array_load(ElementType::Byte)?,
+            CodeInstruction::Caload => self.array_load(ElementType::Char)?,
+            CodeInstruction::Saload => self.array_load(ElementType::Short)?,
+            CodeInstruction::Istore(index)
+            | CodeInstruction::Lstore(index)
+            | CodeInstruction::Fstore(index)
+            | CodeInstruction::Dstore(index)
+            | CodeInstruction::Astore(index) => self.store(*index)?,
+            CodeInstruction::Iastore => self.array_store(ElementType::Int)?,
+            CodeInstruction::Lastore => self.array_store(ElementType::Long)?,
+            CodeInstruction::Fastore => self.array_store(ElementType::Float)?,
+            CodeInstruction::Dastore => self.array_store(ElementType::Double)?,
+            CodeInstruction::Aastore => self.array_store(ElementType::Reference)?,
+            CodeInstruction::Bastore => self.array_store(ElementType::Byte)?,
+            CodeInstruction::Castore => self.array_store(ElementType::Char)?,
+            CodeInstruction::Sastore => self.array_store(ElementType::Short)?,
+            CodeInstruction::Pop => {
+                self.pop_words(1)?;
+            },
+            CodeInstruction::Pop2 => {
+                self.pop_words(2)?;
+            },
+            CodeInstruction::Dup => self.dup_words(1, 0)?,
+            CodeInstruction::DupX1 => self.dup_words(1, 1)?,
+            CodeInstruction::DupX2 => self.dup_words(1, 2)?,
+            CodeInstruction::Dup2 => self.dup_words(2, 0)?,
+            CodeInstruction::Dup2X1 => self.dup_words(2, 1)?,
+            CodeInstruction::Dup2X2 => self.dup_words(2, 2)?,
+            CodeInstruction::Swap => {
+                let values = self.pop_words(2)?;
+                if values.len() != 2 {
+                    return Err(format!("swap of a long or double at {}", self.pc));
+                }
+                self.state.stack.push(values[1]);
+                self.state.stack.push(values[0]);
+            },
+            CodeInstruction::Iadd => self.binary(BinaryOp::Add, Type::Int)?,
+            CodeInstruction::Ladd => self.binary(BinaryOp::Add, Type::Long)?,
+            CodeInstruction::Fadd => self.binary(BinaryOp::Add, Type::Float)?,
+            CodeInstruction::Dadd => self.binary(BinaryOp::Add, Type::Double)?,
+            CodeInstruction::Isub => self.binary(BinaryOp::Sub, Type::Int)?,
+            CodeInstruction::Lsub => self.binary(BinaryOp::Sub, Type::Long)?,
+            CodeInstruction::Fsub => self.binary(BinaryOp::Sub, Type::Float)?,
+            CodeInstruction::Dsub => self.binary(BinaryOp::Sub, Type::Double)?,
+            CodeInstruction::Imul => self.binary(BinaryOp::Mul, Type::Int)?,
+            CodeInstruction::Lmul => self.binary(BinaryOp::Mul, Type::Long)?,
+            CodeInstruction::Fmul => self.binary(BinaryOp::Mul, Type::Float)?,
+            CodeInstruction::Dmul => self.binary(BinaryOp::Mul, Type::Double)?,
+            CodeInstruction::Idiv => self.binary(BinaryOp::Div, Type::Int)?,
+            CodeInstruction::Ldiv => self.binary(BinaryOp::Div, Type::Long)?,
+            CodeInstruction::Fdiv => self.binary(BinaryOp::Div, Type::Float)?,
+            CodeInstruction::Ddiv => self.binary(BinaryOp::Div, Type::Double)?,
+            CodeInstruction::Irem => self.binary(BinaryOp::Rem, Type::Int)?,
+            CodeInstruction::Lrem => self.binary(BinaryOp::Rem, Type::Long)?,
+            CodeInstruction::Frem => self.binary(BinaryOp::Rem, Type::Float)?,
+            CodeInstruction::Drem => self.binary(BinaryOp::Rem, Type::Double)?,
+            CodeInstruction::Ineg => self.negate(Type::Int)?,
+            CodeInstruction::Lneg => self.negate(Type::Long)?,
+            CodeInstruction::Fneg => self.negate(Type::Float)?,
+            CodeInstruction::Dneg => self.negate(Type::Double)?,
+            CodeInstruction::Ishl => self.binary(BinaryOp::Shl, Type::Int)?,
+            CodeInstruction::Lshl => self.binary(BinaryOp::Shl, Type::Long)?,
+            CodeInstruction::Ishr => self.binary(BinaryOp::Shr, Type::Int)?,
+            CodeInstruction::Lshr => self.binary(BinaryOp::Shr, Type::Long)?,
+            CodeInstruction::Iushr => self.binary(BinaryOp::Ushr, Type::Int)?,
+            CodeInstruction::Lushr => self.binary(BinaryOp::Ushr, Type::Long)?,
+            CodeInstruction::Iand => self.binary(BinaryOp::And, Type::Int)?,
+            CodeInstruction::Land => self.binary(BinaryOp::And, Type::Long)?,
+            CodeInstruction::Ior => self.binary(BinaryOp::Or, Type::Int)?,
+            CodeInstruction::Lor => self.binary(BinaryOp::Or, Type::Long)?,
+            CodeInstruction::Ixor => self.binary(BinaryOp::Xor, Type::Int)?,
+            CodeInstruction::Lxor => self.binary(BinaryOp::Xor, Type::Long)?,
+            CodeInstruction::Iinc(index, constant) => {
+                self.load_local(*index, Type::Int)?;
+                let lhs = self.pop()?;
+                let rhs = self.constant(Constant::Int(*constant as i32));
+                let value = self.emit(InstKind::Binary { op: BinaryOp::Add, ty: Type::Int, lhs, rhs }, Some(Type::Int)).unwrap();
+                self.store_local(*index as usize, value)?;
+            },
+            CodeInstruction::I2l => self.convert(Type::Int, Type::Long)?,
+            CodeInstruction::I2f => self.convert(Type::Int, Type::Float)?,
+            CodeInstruction::I2d => self.convert(Type::Int, Type::Double)?,
+            CodeInstruction::L2i => self.convert(Type::Long, Type::Int)?,
+            CodeInstruction::L2f => self.convert(Type::Long, Type::Float)?,
+            CodeInstruction::L2d => self.convert(Type::Long, Type::Double)?,
+            CodeInstruction::F2i => self.convert(Type::Float, Type::Int)?,
+            CodeInstruction::F2l => self.convert(Type::Float, Type::Long)?,
+            CodeInstruction::F2d => self.convert(Type::Float, Type::Double)?,
+            CodeInstruction::D2i => self.convert(Type::Double, Type::Int)?,
+            CodeInstruction::D2l => self.convert(Type::Double, Type::Long)?,
+            CodeInstruction::D2f => self.convert(Type::Double, Type::Float)?,
+            CodeInstruction::I2b => self.narrow(ElementType::Byte)?,
+            CodeInstruction::I2c => self.narrow(ElementType::Char)?,
+            CodeInstruction::I2s => self.narrow(ElementType::Short)?,
+            CodeInstruction::Lcmp => self.compare(CompareOp::Cmp, Type::Long)?,
+            CodeInstruction::Fcmpl => self.compare(CompareOp::Cmpl, Type::Float)?,
+            CodeInstruction::Fcmpg => self.compare(CompareOp::Cmpg, Type::Float)?,
+            CodeInstruction::Dcmpl => self.compare(CompareOp::Cmpl, Type::Double)?,
+            CodeInstruction::Dcmpg => self.compare(CompareOp::Cmpg, Type::Double)?,
+            CodeInstruction::GetStatic(index) => {
+                let (field, ty) = self.field_type(*index)?;
+                self.emit_push(InstKind::GetStatic { field }, ty);
+            },
+            CodeInstruction::PutStatic(index) => {
+                let (field, _) = self.field_type(*index)?;
+                let value = self.pop()?;
+                self.emit(InstKind::PutStatic { field, value }, None);
+            },
+            CodeInstruction::GetField(index) => {
+                let (field, ty) = self.field_type(*index)?;
+                let object = self.pop()?;
+                self.emit_push(InstKind::GetField { field, object }, ty);
+            },
+            CodeInstruction::PutField(index) => {
+                let (field, _) = self.field_type(*index)?;
+                let value = self.pop()?;
+                let object = self.pop()?;
+                self.emit(InstKind::PutField { field, object, value }, None);
+            },
+            CodeInstruction::InvokeVirtual(index) => self.invoke(InvokeKind::Virtual, *index)?,
+            CodeInstruction::InvokeSpecial(index) => self.invoke(InvokeKind::Special, *index)?,
+            CodeInstruction::InvokeStatic(index) => self.invoke(InvokeKind::Static, *index)?,
+            CodeInstruction::InvokeInterface(index, _) => self.invoke(InvokeKind::Interface, *index)?,
+            CodeInstruction::InvokeDynamic(index) => self.invoke_dynamic(*index)?,
+            CodeInstruction::New(index) => {
+                let class_name = self.class.constant_pool.find_class_name(*index)?;
+                self.emit_push(InstKind::New { class_name }, Type::Reference);
+            },
+            CodeInstruction::NewArray(atype) => {
+                let component = match atype {
+                    4 => "Z",
+                    5 => "C",
+                    6 => "F",
+                    7 => "D",
+                    8 => "B",
+                    9 => "S",
+                    10 => "I",
+                    11 => "J",
+                    _ => return Err(format!("Invalid newarray type {} at {}", atype, self.pc)),
+                };
+                let length = self.pop()?;
+                self.emit_push(InstKind::NewArray { component: component.to_string(), length }, Type::Reference);
+            },
+            CodeInstruction::ANewArray(index) => {
+                let class_name = self.class.constant_pool.find_class_name(*index)?;
+                let component = if class_name.starts_with('[') { class_name } else { format!("L{};", class_name) };
+                let length = self.pop()?;
+                self.emit_push(InstKind::NewArray { component, length }, Type::Reference);
+            },
+            CodeInstruction::MultiANewArray(index, dimensions) => {
+                let descriptor = self.class.constant_pool.find_class_name(*index)?;
+                let dimensions = self.pop_n(*dimensions as usize)?;
+                self.emit_push(InstKind::MultiNewArray { descriptor, dimensions }, Type::Reference);
+            },
+            CodeInstruction::ArrayLength => {
+                let array = self.pop()?;
+                self.emit_push(InstKind::ArrayLength { array }, Type::Int);
+            },
+            CodeInstruction::CheckCast(index) => {
+                let class_name = self.class.constant_pool.find_class_name(*index)?;
+                let object = self.pop()?;
+                self.emit_push(InstKind::CheckCast { class_name, object }, Type::Reference);
+            },
+            CodeInstruction::InstanceOf(index) => {
+                let class_name = self.class.constant_pool.find_class_name(*index)?;
+                let object = self.pop()?;
+                self.emit_push(InstKind::InstanceOf { class_name, object }, Type::Int);
+            },
+            CodeInstruction::MonitorEnter => {
+                let object = self.pop()?;
+                self.emit(InstKind::MonitorEnter { object }, None);
+            },
+            CodeInstruction::MonitorExit => {
+                let object = self.pop()?;
+                self.emit(InstKind::MonitorExit { object }, None);
+            },
+            CodeInstruction::Jsr(_) | CodeInstruction::Ret(_) => {
+                return Err(format!("jsr and ret are not supported (at {})", self.pc));
+            },
+            instruction => {
+                return Err(format!("Unexpected control flow instruction {:?} at {}", instruction, self.pc));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Translates the last instruction of a block into its terminator. Instructions that
+    /// don't transfer control fall through to `next_block`.
+    fn terminate(&mut self, instruction: &CodeInstruction, next_block: Option<BlockId>, block_ids: &HashMap<u32, BlockId>) -> Result<Terminator, String> {
+        let pc = self.pc;
+        let target = |offset: &u32| -> Result<BlockId, String> {
+            block_ids.get(offset).copied().ok_or_else(|| format!("Branch to invalid offset {} at {}", offset, pc))
+        };
+        let next = || next_block.ok_or_else(|| format!("Execution falls off the end of the code at {}", pc));
+
+        let (cond, ty, target_offset, against) = match instruction {
+            CodeInstruction::Ifeq(t) => (Condition::Eq, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::Ifne(t) => (Condition::Ne, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::Iflt(t) => (Condition::Lt, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::Ifge(t) => (Condition::Ge, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::Ifgt(t) => (Condition::Gt, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::Ifle(t) => (Condition::Le, Type::Int, t, Some(Constant::Int(0))),
+            CodeInstruction::IfNull(t) => (Condition::Eq, Type::Reference, t, Some(Constant::Null)),
+            CodeInstruction::IfNonNull(t) => (Condition::Ne, Type::Reference, t, Some(Constant::Null)),
+            CodeInstruction::IfIcmpeq(t) => (Condition::Eq, Type::Int, t, None),
+            CodeInstruction::IfIcmpne(t) => (Condition::Ne, Type::Int, t, None),
+            CodeInstruction::IfIcmplt(t) => (Condition::Lt, Type::Int, t, None),
+            CodeInstruction::IfIcmpge(t) => (Condition::Ge, Type::Int, t, None),
+            CodeInstruction::IfIcmpgt(t) => (Condition::Gt, Type::Int, t, None),
+            CodeInstruction::IfIcmple(t) => (Condition::Le, Type::Int, t, None),
+            CodeInstruction::IfAcmpeq(t) => (Condition::Eq, Type::Reference, t, None),
+            CodeInstruction::IfAcmpne(t) => (Condition::Ne, Type::Reference, t, None),
+            CodeInstruction::Goto(t) => return Ok(Terminator::Goto(target(t)?)),
+            CodeInstruction::TableSwitch { default, low, offsets, .. } => {
+                let value = self.pop()?;
+                let mut cases = Vec::with_capacity(offsets.len());
+                for (i, offset) in offsets.iter().enumerate() {
+                    cases.push((low.wrapping_add(i as i32), target(offset)?));
+                }
+                return Ok(Terminator::Switch { value, cases, default: target(default)? });
+            },
+            CodeInstruction::LookupSwitch { default, pairs } => {
+                let value = self.pop()?;
+                let mut cases = Vec::with_capacity(pairs.len());
+                for (key, offset) in pairs {
+                    cases.push((*key, target(offset)?));
+                }
+                return Ok(Terminator::Switch { value, cases, default: target(default)? });
+            },
+            CodeInstruction::Ireturn
+            | CodeInstruction::Lreturn
+            | CodeInstruction::Freturn
+            | CodeInstruction::Dreturn
+            | CodeInstruction::Areturn => return Ok(Terminator::Return(Some(self.pop()?))),
+            CodeInstruction::Return => return Ok(Terminator::Return(None)),
+            CodeInstruction::AThrow => return Ok(Terminator::Throw(self.pop()?)),
+            instruction => {
+                self.interpret(instruction)?;
+                return Ok(Terminator::Goto(next()?));
+            },
+        };
+
+        let rhs = match against {
+            Some(constant) => self.constant(constant),
+            None => self.pop()?,
+        };
+        let lhs = self.pop()?;
+        Ok(Terminator::If { cond, ty, lhs, rhs, then_block: target(target_offset)?, else_block: next()? })
+    }
+
+    /// Computes the entry state of a block with several predecessors. Slots that hold the
+    /// same value in every predecessor keep it, the others get a phi. Slots whose types
+    /// disagree are dead and left empty.
+    fn merge_states(&mut self, incoming: &[&FrameState], all_known: bool, is_handler: bool, pc: u32) -> Result<(FrameState, Vec<(Slot, ValueId)>), String> {
+        let first = incoming.first().ok_or_else(|| format!("Block at {} has no translated predecessor", pc))?;
+        let mut state = FrameState { locals: vec![None; first.locals.len()], stack: Vec::new() };
+        let mut slots = Vec::new();
+
+        let mut merge = |builder: &mut Self, slot: Slot| -> Option<ValueId> {
+            let values: Vec<Option<ValueId>> = incoming.iter().map(|s| s.get(slot)).collect();
+            let first = values[0]?;
+            let ty = builder.value_type(first);
+            if values.iter().any(|v| v.is_none_or(|v| builder.value_type(v) != ty)) {
+                return None;
+            }
+            if all_known && values.iter().all(|v| *v == Some(first)) {
+                return Some(first);
+            }
+            let phi = builder.new_value(ty);
+            slots.push((slot, phi));
+            Some(phi)
+        };
+
+        for index in 0..state.locals.len() {
+            state.locals[index] = merge(self, Slot::Local(index));
+        }
+        if !is_handler {
+            if incoming.iter().any(|s| s.stack.len() != first.stack.len()) {
+                return Err(format!("Operand stack depth differs between predecessors of {}", pc));
+            }
+            for index in 0..first.stack.len() {
+                let value = merge(self, Slot::Stack(index))
+                    .ok_or_else(|| format!("Operand stack types differ between predecessors of {}", pc))?;
+                state.stack.push(value);
+            }
+        }
+
+        Ok((state, slots))
+    }
+}
+
+fn branch_targets(instruction: &CodeInstruction) -> Vec<u32> {
+    match instruction {
+        CodeInstruction::Ifeq(t)
+        | CodeInstruction::Ifne(t)
+        | CodeInstruction::Iflt(t)
+        | CodeInstruction::Ifge(t)
+        | CodeInstruction::Ifgt(t)
+        | CodeInstruction::Ifle(t)
+        | CodeInstruction::IfIcmpeq(t)
+        | CodeInstruction::IfIcmpne(t)
+        | CodeInstruction::IfIcmplt(t)
+        | CodeInstruction::IfIcmpge(t)
+        | CodeInstruction::IfIcmpgt(t)
+        | CodeInstruction::IfIcmple(t)
+        | CodeInstruction::IfAcmpeq(t)
+        | CodeInstruction::IfAcmpne(t)
+        | CodeInstruction::IfNull(t)
+        | CodeInstruction::IfNonNull(t)
+        | CodeInstruction::Goto(t) => vec![*t],
+        CodeInstruction::TableSwitch { default, offsets, .. } => {
+            let mut targets = offsets.clone();
+            targets.push(*default);
+            targets
+        },
+        CodeInstruction::LookupSwitch { default, pairs } => {
+            let mut targets: Vec<u32> = pairs.iter().map(|(_, t)| *t).collect();
+            targets.push(*default);
+            targets
+        },
+        _ => vec![],
+    }
+}
+
+fn is_conditional_branch(instruction: &CodeInstruction) -> bool {
+    !matches!(instruction, CodeInstruction::Goto(_) | CodeInstruction::TableSwitch { .. } | CodeInstruction::LookupSwitch { .. })
+        && !branch_targets(instruction).is_empty()
+}
+
+fn ends_block(instruction: &CodeInstruction) -> bool {
+    !branch_targets(instruction).is_empty()
+        || matches!(
+            instruction,
+            CodeInstruction::Ireturn
+                | CodeInstruction::Lreturn
+                | CodeInstruction::Freturn
+                | CodeInstruction::Dreturn
+                | CodeInstruction::Areturn
+                | CodeInstruction::Return
+                | CodeInstruction::AThrow
+                | CodeInstruction::Jsr(_)
+                | CodeInstruction::Ret(_)
+        )
+}
+
+/// Whether an instruction can transfer control to an exception handler.
+fn can_throw(instruction: &CodeInstruction) -> bool {
+    matches!(
+        instruction,
+        CodeInstruction::Idiv
+            | CodeInstruction::Ldiv
+            | CodeInstruction::Irem
+            | CodeInstruction::Lrem
+            | CodeInstruction::Iaload
+            | CodeInstruction::Laload
+            | CodeInstruction::Faload
+            | CodeInstruction::Daload
+            | CodeInstruction::Aaload
+            | CodeInstruction::Baload
+            | CodeInstruction::Caload
+            | CodeInstruction::Saload
+            | CodeInstruction::Iastore
+            | CodeInstruction::Lastore
+            | CodeInstruction::Fastore
+            | CodeInstruction::Dastore
+            | CodeInstruction::Aastore
+            | CodeInstruction::Bastore
+            | CodeInstruction::Castore
+            | CodeInstruction::Sastore
+            | CodeInstruction::ArrayLength
+            | CodeInstruction::AThrow
+            | CodeInstruction::GetStatic(_)
+            | CodeInstruction::PutStatic(_)
+            | CodeInstruction::GetField(_)
+            | CodeInstruction::PutField(_)
+            | CodeInstruction::InvokeVirtual(_)
+            | CodeInstruction::InvokeSpecial(_)
+            | CodeInstruction::InvokeStatic(_)
+            | CodeInstruction::InvokeInterface(_, _)
+            | CodeInstruction::InvokeDynamic(_)
+            | CodeInstruction::New(_)
+            | CodeInstruction::NewArray(_)
+            | CodeInstruction::ANewArray(_)
+            | CodeInstruction::MultiANewArray(_, _)
+            | CodeInstruction::CheckCast(_)
+            | CodeInstruction::MonitorEnter
+            | CodeInstruction::MonitorExit
+    )
+}
+
+/// Splits the code into basic blocks. Inside a protected range every instruction that
+/// can throw ends its block, so the locals at the end of the block are the ones its
+/// exception handlers see.
+fn split_blocks(class: &ParsedBytecode, instructions: &[(u32, CodeInstruction)], exception_table: &[crate::bytecode::attribute::ExceptionTableEntry], code_length: u32) -> Result<Vec<BytecodeBlock>, String> {
+    let is_protected = |pc: u32| exception_table.iter().any(|e| (e.start_pc as u32) <= pc && pc < e.end_pc as u32);
+
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for entry in exception_table {
+        leaders.insert(entry.start_pc as u32);
+        if (entry.end_pc as u32) < code_length {
+            leaders.insert(entry.end_pc as u32);
+        }
+        leaders.insert(entry.handler_pc as u32);
+    }
+    for (i, (pc, instruction)) in instructions.iter().enumerate() {
+        leaders.extend(branch_targets(instruction));
+        if (ends_block(instruction) || (can_throw(instruction) && is_protected(*pc)))
+            && let Some((next_pc, _)) = instructions.get(i + 1)
+        {
+            leaders.insert(*next_pc);
+        }
+    }
+
+    let index_of_pc: HashMap<u32, usize> = instructions.iter().enumerate().map(|(i, (pc, _))| (*pc, i)).collect();
+    if let Some(leader) = leaders.iter().find(|pc| !index_of_pc.contains_key(pc)) {
+        return Err(format!("Branch or exception handler at {} is not on an instruction boundary", leader));
+    }
+
+    let leaders: Vec<u32> = leaders.into_iter().collect();
+    let mut blocks = Vec::with_capacity(leaders.len());
+    for (i, start_pc) in leaders.iter().enumerate() {
+        let first = index_of_pc[start_pc];
+        let last = leaders.get(i + 1).map(|pc| index_of_pc[pc]).unwrap_or(instructions.len());
+        let (_, last_instruction) = &instructions[last - 1];
+
+        let mut successors = branch_targets(last_instruction);
+        if !ends_block(last_instruction) || is_conditional_branch(last_instruction) {
+            match instructions.get(last) {
+                Some((next_pc, _)) => successors.push(*next_pc),
+                None => return Err(format!("Execution falls off the end of the code at {}", start_pc)),
+            }
+        }
+
+        let mut handlers = Vec::new();
+        if instructions[first..last].iter().any(|(_, instruction)| can_throw(instruction)) {
+            for entry in exception_table {
+                if (entry.start_pc as u32) <= *start_pc && *start_pc < entry.end_pc as u32 {
+                    let catch_type = match entry.catch_type {
+                        0 => None,
+                        index => Some(class.constant_pool.find_class_name(index)?),
+                    };
+                    handlers.push((catch_type, entry.handler_pc as u32));
+                }
+            }
+        }
+
+        blocks.push(BytecodeBlock { start_pc: *start_pc, first, last, successors, handlers });
+    }
+
+    Ok(blocks)
+}
+
+fn reverse_postorder(blocks: &[BytecodeBlock], index_of_pc: &HashMap<u32, usize>) -> Vec<usize> {
+    let mut visited = vec![false; blocks.len()];
+    let mut postorder = Vec::with_capacity(blocks.len());
+    // (block, whether its successors have been pushed)
+    let mut stack = vec![(0, false)];
+
+    while let Some((index, expanded)) = stack.pop() {
+        if expanded {
+            postorder.push(index);
+            continue;
+        }
+        if visited[index] {
+            continue;
+        }
+        visited[index] = true;
+        stack.push((index, true));
+
+        let block = &blocks[index];
+        let successors = block.successors.iter().chain(block.handlers.iter().map(|(_, pc)| pc));
+        for successor in successors.rev() {
+            let successor = index_of_pc[successor];
+            if !visited[successor] {
+                stack.push((successor, false));
+            }
+        }
+    }
+
+    postorder.reverse();
+    postorder
+}
+
+/// Fills in the phis of the function. Phis that merge mismatched or missing values are
+/// dropped, which is only valid when nothing uses them, and phis that merge a single
+/// value are replaced by it. Returns the dropped phis.
+fn resolve_phis(function: &mut Function, mut phis: PendingPhis) -> Result<HashSet<ValueId>, String> {
+    let mut invalid: HashSet<ValueId> = HashSet::new();
+    loop {
+        let mut changed = false;
+        for (dest, (_, incoming)) in &phis {
+            if invalid.contains(dest) {
+                continue;
+            }
+            let ty = function.value_type(*dest);
+            let is_invalid = incoming.iter().any(|(_, value)| match value {
+                None => true,
+                Some(value) => function.value_type(*value) != ty || invalid.contains(value),
+            });
+            if is_invalid {
+                invalid.insert(*dest);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            if inst.kind.operands().iter().any(|v| invalid.contains(v)) {
+                return Err(format!("Value used at {} has conflicting types on incoming paths", inst.pc));
+            }
+        }
+        if block.terminator.operands_mut().iter().any(|v| invalid.contains(v)) {
+            return Err(format!("Value used at the end of block {} has conflicting types on incoming paths", block.id.0));
+        }
+    }
+    phis.retain(|dest, _| !invalid.contains(dest));
+
+    // Replace phis that only merge one value (besides themselves) until none are left
+    let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+    let resolve = |replacements: &HashMap<ValueId, ValueId>, mut value: ValueId| {
+        while let Some(next) = replacements.get(&value) {
+            value = *next;
+        }
+        value
+    };
+    loop {
+        let mut changed = false;
+        let mut dests: Vec<ValueId> = phis.keys().copied().collect();
+        dests.sort();
+        for dest in dests {
+            let (_, incoming) = &phis[&dest];
+            let mut unique = incoming
+                .iter()
+                .map(|(_, value)| resolve(&replacements, value.unwrap()))
+                .filter(|value| *value != dest)
+                .collect::<Vec<_>>();
+            unique.dedup();
+            if unique.len() <= 1 || unique.iter().all(|v| *v == unique[0]) {
+                if let Some(value) = unique.first() {
+                    replacements.insert(dest, *value);
+                }
+                phis.remove(&dest);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (dest, (block, incoming)) in phis {
+        let incoming = incoming.into_iter().map(|(pred, value)| (pred, resolve(&replacements, value.unwrap()))).collect();
+        function.blocks[block.0 as usize].phis.push(Phi { dest, incoming });
+    }
+    for block in &mut function.blocks {
+        block.phis.sort_by_key(|phi| phi.dest);
+        for inst in &mut block.insts {
+            for operand in inst.kind.operands_mut() {
+                *operand = resolve(&replacements, *operand);
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            *operand = resolve(&replacements, *operand);
+        }
+    }
+
+    Ok(invalid)
+}
+
+/// Renumbers the values densely in definition order, dropping the ones left unused by
+/// phi resolution.
+fn renumber_values(function: &mut Function) {
+    let mut numbers: HashMap<ValueId, ValueId> = HashMap::new();
+    let mut value_types = Vec::new();
+    let mut define = |value: &mut ValueId, numbers: &mut HashMap<ValueId, ValueId>| {
+        let number = ValueId(value_types.len() as u32);
+        value_types.push(function.value_types[value.0 as usize]);
+        numbers.insert(*value, number);
+        *value = number;
+    };
+
+    for param in &mut function.params {
+        define(param, &mut numbers);
+    }
+    for block in &mut function.blocks {
+        for phi in &mut block.phis {
+            define(&mut phi.dest, &mut numbers);
+        }
+        for inst in &mut block.insts {
+            if let Some(dest) = &mut inst.dest {
+                define(dest, &mut numbers);
+            }
+        }
+    }
+
+    for block in &mut function.blocks {
+        for phi in &mut block.phis {
+            for (_, value) in &mut phi.incoming {
+                *value = numbers[value];
+            }
+        }
+        for inst in &mut block.insts {
+            for operand in inst.kind.operands_mut() {
+                *operand = numbers[operand];
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            *operand = numbers[operand];
+        }
+    }
+    function.value_types = value_types;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::attribute::{
+        ASTORE_0, D2L, DLOAD_0, GOTO, IADD, ICONST_M1, IDIV, IF_ICMPGE, IF_ICMPLE, IINC, ILOAD_0, IRETURN, ISTORE_0, LADD, LLOAD, LLOAD_0, LRETURN, LSTORE, POP, RETURN, WIDE,
+    };
+    use crate::bytecode::writer::{ClassWriter, Code};
+    use crate::bytecode::{parse_bytecode, ACC_PUBLIC};
+
+    const ICONST_0: u8 = ICONST_M1 + 1;
+
+    fn code(max_stack: u16, max_locals: u16, bytes: &[u8]) -> Code {
+        Code { bytes: bytes.to_vec(), max_stack, max_locals, ..Code::default() }
+    }
+
+    // Builds the IR of a static method with `descriptor` and `code` and dumps it. `writer`
+    // holds the constants the code refers to.
+    fn build(mut writer: ClassWriter, descriptor: &str, code: Code) -> Result<String, String> {
+        writer.method(ACC_PUBLIC | ACC_STATIC, "test", descriptor, &code);
+        let class = parse_bytecode(&writer.finish(ACC_PUBLIC, "Test", "java/lang/Object", &[]))?;
+        let function = build_function(&class, &class.methods[0])?.expect("The method has code");
+        Ok(function.to_string())
+    }
+
+    #[test]
+    fn loops_have_phis_at_the_header() {
+        // int s = 0; for (int i = 0; i < n; i++) s += i; return s;
+        let code = code(2, 3, &[
+            ICONST_0, ISTORE_0 + 1, ICONST_0, ISTORE_0 + 2,
+            ILOAD_0 + 2, ILOAD_0, IF_ICMPGE, 0, 13,
+            ILOAD_0 + 1, ILOAD_0 + 2, IADD, ISTORE_0 + 1, IINC, 2, 1, GOTO, 0xff, 0xf4,
+            ILOAD_0 + 1, IRETURN,
+        ]);
+        assert_eq!(
+            build(ClassWriter::new(), "(I)I", code).unwrap(),
+            "\
+function Test.test(I)I static (v0: int) {
+bb0: ; pc 0
+    v1: int = const 0
+    v2: int = const 0
+    goto bb1
+bb1: ; pc 4
+    v3: int = phi [bb0: v1], [bb2: v5]
+    v4: int = phi [bb0: v2], [bb2: v7]
+    if.int ge v4, v0 then bb3 else bb2
+bb2: ; pc 9
+    v5: int = add.int v3, v4
+    v6: int = const 1
+    v7: int = add.int v4, v6
+    goto bb1
+bb3: ; pc 19
+    return v3
+}
+"
+        );
+    }
+
+    #[test]
+    fn if_else_merges_with_a_phi() {
+        // int m; if (a > b) m = a; else m = b; return m;
+        let code = code(2, 3, &[
+            ILOAD_0, ILOAD_0 + 1, IF_ICMPLE, 0, 8,
+            ILOAD_0, ISTORE_0 + 2, GOTO, 0, 5,
+            ILOAD_0 + 1, ISTORE_0 + 2,
+            ILOAD_0 + 2, IRETURN,
+        ]);
+        assert_eq!(
+            build(ClassWriter::new(), "(II)I", code).unwrap(),
+            "\
+function Test.test(II)I static (v0: int, v1: int) {
+bb0: ; pc 0
+    if.int le v0, v1 then bb2 else bb1
+bb1: ; pc 5
+    goto bb3
+bb2: ; pc 10
+    goto bb3
+bb3: ; pc 12
+    v2: int = phi [bb1: v0], [bb2: v1]
+    return v2
+}
+"
+        );
+    }
+
+    #[test]
+    fn handlers_start_with_the_caught_exception() {
+        // int r = 0; try { r = a / b; } catch (ArithmeticException e) { r = -1; } return r;
+        let mut writer = ClassWriter::new();
+        let arithmetic_exception = writer.class("java/lang/ArithmeticException");
+        let mut code = code(2, 4, &[
+            ICONST_0, ISTORE_0 + 2,
+            ILOAD_0, ILOAD_0 + 1, IDIV, ISTORE_0 + 2, GOTO, 0, 6,
+            ASTORE_0 + 3, ICONST_M1, ISTORE_0 + 2,
+            ILOAD_0 + 2, IRETURN,
+        ]);
+        code.exception_table.push([2, 6, 9, arithmetic_exception]);
+        assert_eq!(
+            build(writer, "(II)I", code).unwrap(),
+            "\
+function Test.test(II)I static (v0: int, v1: int) {
+bb0: ; pc 0
+    v2: int = const 0
+    goto bb1
+bb1: ; pc 2
+    v3: int = div.int v0, v1
+    goto bb2
+    catch java/lang/ArithmeticException -> bb4
+bb2: ; pc 5
+    goto bb3
+bb3: ; pc 6
+    goto bb5
+bb4: ; pc 9
+    v4: ref = caughtexception
+    v5: int = const -1
+    goto bb5
+bb5: ; pc 12
+    v6: int = phi [bb4: v5], [bb3: v3]
+    return v6
+}
+"
+        );
+    }
+
+    #[test]
+    fn wide_values_take_one_value_for_two_local_slots() {
+        // long x = a + (long) d; return x; with x in local 300, past what one byte indexes
+        let code = code(4, 302, &[
+            LLOAD_0, DLOAD_0 + 2, D2L, LADD,
+            WIDE, LSTORE, 1, 44,
+            WIDE, LLOAD, 1, 44, LRETURN,
+        ]);
+        assert_eq!(
+            build(ClassWriter::new(), "(JD)J", code).unwrap(),
+            "\
+function Test.test(JD)J static (v0: long, v1: double) {
+bb0: ; pc 0
+    v2: long = convert.double.long v1
+    v3: long = add.long v0, v2
+    return v3
+}
+"
+        );
+    }
+
+    #[test]
+    fn handlers_reached_by_normal_control_flow_are_rejected() {
+        // The handler at 4 catches what the idiv throws and also follows the unprotected pop
+        let mut code = code(2, 0, &[ICONST_0, ICONST_0, IDIV, POP, POP, RETURN]);
+        code.exception_table.push([0, 3, 4, 0]);
+        assert_eq!(build(ClassWriter::new(), "()V", code), Err("Exception handler at 4 is also reached by normal control flow".to_string()));
+    }
+}
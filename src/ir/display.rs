@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::ir::{
+    BinaryOp, Block, BlockId, CompareOp, Condition, Constant, ElementType, Function, Inst, InstKind, InvokeKind,
+    Terminator, Type, ValueId,
+};
+
+// The textual form looks like this:
+//
+// function HelloWorld.main([Ljava/lang/String;)V static (v0: ref) {
+// bb0: ; pc 0
+//     v1: ref = getstatic java/lang/System.out:Ljava/io/PrintStream;
+//     v2: ref = const "Hello, World!"
+//     invoke.virtual java/io/PrintStream.println:(Ljava/lang/String;)V (v1, v2)
+//     return
+// }
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Long => "long",
+            Type::Float => "float",
+            Type::Double => "double",
+            Type::Reference => "ref",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for ElementType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ElementType::Boolean => "boolean",
+            ElementType::Byte => "byte",
+            ElementType::Char => "char",
+            ElementType::Short => "short",
+            ElementType::Int => "int",
+            ElementType::Long => "long",
+            ElementType::Float => "float",
+            ElementType::Double => "double",
+            ElementType::Reference => "ref",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Null => write!(f, "null"),
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Long(value) => write!(f, "{}L", value),
+            Constant::Float(value) => write!(f, "{:?}f", value),
+            Constant::Double(value) => write!(f, "{:?}d", value),
+            Constant::String(value) => write!(f, "{:?}", value),
+            Constant::Class(name) => write!(f, "class {}", name),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Rem => "rem",
+            BinaryOp::Shl => "shl",
+            BinaryOp::Shr => "shr",
+            BinaryOp::Ushr => "ushr",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompareOp::Cmp => "cmp",
+            CompareOp::Cmpl => "cmpl",
+            CompareOp::Cmpg => "cmpg",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Condition::Eq => "eq",
+            Condition::Ne => "ne",
+            Condition::Lt => "lt",
+            Condition::Ge => "ge",
+            Condition::Gt => "gt",
+            Condition::Le => "le",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for InvokeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InvokeKind::Static => "static",
+            InvokeKind::Virtual => "virtual",
+            InvokeKind::Special => "special",
+            InvokeKind::Interface => "interface",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn write_values(f: &mut fmt::Formatter<'_>, values: &[ValueId]) -> fmt::Result {
+    write!(f, "(")?;
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for InstKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstKind::Const(constant) => write!(f, "const {}", constant),
+            InstKind::Binary { op, ty, lhs, rhs } => write!(f, "{}.{} {}, {}", op, ty, lhs, rhs),
+            InstKind::Negate { ty, value } => write!(f, "neg.{} {}", ty, value),
+            InstKind::Convert { from, to, value } => write!(f, "convert.{}.{} {}", from, to, value),
+            InstKind::Narrow { to, value } => write!(f, "narrow.{} {}", to, value),
+            InstKind::Compare { op, ty, lhs, rhs } => write!(f, "{}.{} {}, {}", op, ty, lhs, rhs),
+            InstKind::Invoke { kind, method, args } => {
+                write!(f, "invoke.{} {}.{}:{} ", kind, method.class_name, method.name, method.descriptor)?;
+                write_values(f, args)
+            },
+            InstKind::InvokeDynamic { bootstrap_method, name, descriptor, args } => {
+                write!(f, "invokedynamic #{} {}:{} ", bootstrap_method, name, descriptor)?;
+                write_values(f, args)
+            },
+            InstKind::GetField { field, object } => {
+                write!(f, "getfield {}.{}:{} {}", field.class_name, field.name, field.descriptor, object)
+            },
+            InstKind::PutField { field, object, value } => {
+                write!(f, "putfield {}.{}:{} {}, {}", field.class_name, field.name, field.descriptor, object, value)
+            },
+            InstKind::GetStatic { field } => write!(f, "getstatic {}.{}:{}", field.class_name, field.name, field.descriptor),
+            InstKind::PutStatic { field, value } => {
+                write!(f, "putstatic {}.{}:{} {}", field.class_name, field.name, field.descriptor, value)
+            },
+            InstKind::New { class_name } => write!(f, "new {}", class_name),
+            InstKind::NewArray { component, length } => write!(f, "newarray {} {}", component, length),
+            InstKind::MultiNewArray { descriptor, dimensions } => {
+                write!(f, "multinewarray {} ", descriptor)?;
+                write_values(f, dimensions)
+            },
+            InstKind::ArrayLength { array } => write!(f, "arraylength {}", array),
+            InstKind::ArrayLoad { element, array, index } => write!(f, "arrayload.{} {}, {}", element, array, index),
+            InstKind::ArrayStore { element, array, index, value } => {
+                write!(f, "arraystore.{} {}, {}, {}", element, array, index, value)
+            },
+            InstKind::CheckCast { class_name, object } => write!(f, "checkcast {} {}", class_name, object),
+            InstKind::InstanceOf { class_name, object } => write!(f, "instanceof {} {}", class_name, object),
+            InstKind::MonitorEnter { object } => write!(f, "monitorenter {}", object),
+            InstKind::MonitorExit { object } => write!(f, "monitorexit {}", object),
+            InstKind::CaughtException => write!(f, "caughtexception"),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Goto(target) => write!(f, "goto {}", target),
+            Terminator::If { cond, ty, lhs, rhs, then_block, else_block } => {
+                write!(f, "if.{} {} {}, {} then {} else {}", ty, cond, lhs, rhs, then_block, else_block)
+            },
+            Terminator::Switch { value, cases, default } => {
+                write!(f, "switch {} [", value)?;
+                for (i, (key, target)) in cases.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, target)?;
+                }
+                write!(f, "] default {}", default)
+            },
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Throw(value) => write!(f, "throw {}", value),
+        }
+    }
+}
+
+fn write_inst(f: &mut fmt::Formatter<'_>, function: &Function, inst: &Inst) -> fmt::Result {
+    match inst.dest {
+        Some(dest) => writeln!(f, "    {}: {} = {}", dest, function.value_type(dest), inst.kind),
+        None => writeln!(f, "    {}", inst.kind),
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, function: &Function, block: &Block) -> fmt::Result {
+    match block.start_pc {
+        Some(pc) => writeln!(f, "{}: ; pc {}", block.id, pc)?,
+        None => writeln!(f, "{}:", block.id)?,
+    }
+
+    for phi in &block.phis {
+        write!(f, "    {}: {} = phi", phi.dest, function.value_type(phi.dest))?;
+        for (i, (pred, value)) in phi.incoming.iter().enumerate() {
+            write!(f, "{} [{}: {}]", if i > 0 { "," } else { "" }, pred, value)?;
+        }
+        writeln!(f)?;
+    }
+    for inst in &block.insts {
+        write_inst(f, function, inst)?;
+    }
+    writeln!(f, "    {}", block.terminator)?;
+    for edge in &block.handlers {
+        writeln!(f, "    catch {} -> {}", edge.catch_type.as_deref().unwrap_or("any"), edge.handler)?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "function {}.{}{}", self.class_name, self.name, self.descriptor)?;
+        if self.is_static {
+            write!(f, " static")?;
+        }
+        write!(f, " (")?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", param, self.value_type(*param))?;
+        }
+        writeln!(f, ") {{")?;
+
+        for block in &self.blocks {
+            write_block(f, self, block)?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
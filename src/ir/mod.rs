@@ -0,0 +1,305 @@
+pub mod builder;
+mod display;
+
+use std::collections::BTreeMap;
+
+use crate::bytecode::constantpool::MemberRef;
+use crate::bytecode::descriptor::FieldType;
+
+pub use builder::{build_class, build_function};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+/// The computational types of the JVM. Booleans, bytes, chars and shorts are all ints
+/// once they are on the operand stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+
+impl Type {
+    pub fn from_field_type(field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int => Type::Int,
+            FieldType::Long => Type::Long,
+            FieldType::Float => Type::Float,
+            FieldType::Double => Type::Double,
+            FieldType::Object(_) | FieldType::Array(_) => Type::Reference,
+        }
+    }
+
+    /// Longs and doubles take up two local variable and operand stack slots.
+    pub fn is_wide(self) -> bool {
+        matches!(self, Type::Long | Type::Double)
+    }
+}
+
+/// The element types of primitive and reference arrays, also used for the narrowing
+/// conversions `i2b`, `i2c` and `i2s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    Boolean,
+    Byte,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+
+impl ElementType {
+    pub fn value_type(self) -> Type {
+        match self {
+            ElementType::Boolean | ElementType::Byte | ElementType::Char | ElementType::Short | ElementType::Int => Type::Int,
+            ElementType::Long => Type::Long,
+            ElementType::Float => Type::Float,
+            ElementType::Double => Type::Double,
+            ElementType::Reference => Type::Reference,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Null,
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    // A java.lang.Class literal, holds the binary name of the class
+    Class(String),
+}
+
+impl Constant {
+    pub fn value_type(&self) -> Type {
+        match self {
+            Constant::Int(_) => Type::Int,
+            Constant::Long(_) => Type::Long,
+            Constant::Float(_) => Type::Float,
+            Constant::Double(_) => Type::Double,
+            Constant::Null | Constant::String(_) | Constant::Class(_) => Type::Reference,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    Ushr,
+    And,
+    Or,
+    Xor,
+}
+
+/// `lcmp` is `Cmp`, the floating point compares differ in the result they produce when
+/// either operand is NaN: -1 for `Cmpl` and 1 for `Cmpg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Cmp,
+    Cmpl,
+    Cmpg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvokeKind {
+    Static,
+    Virtual,
+    Special,
+    Interface,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    Const(Constant),
+    Binary { op: BinaryOp, ty: Type, lhs: ValueId, rhs: ValueId },
+    Negate { ty: Type, value: ValueId },
+    Convert { from: Type, to: Type, value: ValueId },
+    // Truncates an int to a byte, char or short and extends it back
+    Narrow { to: ElementType, value: ValueId },
+    Compare { op: CompareOp, ty: Type, lhs: ValueId, rhs: ValueId },
+    Invoke { kind: InvokeKind, method: MemberRef, args: Vec<ValueId> },
+    InvokeDynamic { bootstrap_method: u16, name: String, descriptor: String, args: Vec<ValueId> },
+    GetField { field: MemberRef, object: ValueId },
+    PutField { field: MemberRef, object: ValueId, value: ValueId },
+    GetStatic { field: MemberRef },
+    PutStatic { field: MemberRef, value: ValueId },
+    New { class_name: String },
+    // `component` is the field descriptor of the elements, e.g. `I` or `Ljava/lang/String;`
+    NewArray { component: String, length: ValueId },
+    // `descriptor` is the type of the whole array, e.g. `[[I`
+    MultiNewArray { descriptor: String, dimensions: Vec<ValueId> },
+    ArrayLength { array: ValueId },
+    ArrayLoad { element: ElementType, array: ValueId, index: ValueId },
+    ArrayStore { element: ElementType, array: ValueId, index: ValueId, value: ValueId },
+    CheckCast { class_name: String, object: ValueId },
+    InstanceOf { class_name: String, object: ValueId },
+    MonitorEnter { object: ValueId },
+    MonitorExit { object: ValueId },
+    // The exception being handled, always the first instruction of a handler block
+    CaughtException,
+}
+
+impl InstKind {
+    pub fn operands(&self) -> Vec<ValueId> {
+        let mut kind = self.clone();
+        kind.operands_mut().into_iter().map(|v| *v).collect()
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            InstKind::Const(_) | InstKind::GetStatic { .. } | InstKind::New { .. } | InstKind::CaughtException => vec![],
+            InstKind::Binary { lhs, rhs, .. } | InstKind::Compare { lhs, rhs, .. } => vec![lhs, rhs],
+            InstKind::Negate { value, .. }
+            | InstKind::Convert { value, .. }
+            | InstKind::Narrow { value, .. }
+            | InstKind::PutStatic { value, .. } => vec![value],
+            InstKind::Invoke { args, .. } | InstKind::InvokeDynamic { args, .. } => args.iter_mut().collect(),
+            InstKind::GetField { object, .. }
+            | InstKind::CheckCast { object, .. }
+            | InstKind::InstanceOf { object, .. }
+            | InstKind::MonitorEnter { object }
+            | InstKind::MonitorExit { object } => vec![object],
+            InstKind::PutField { object, value, .. } => vec![object, value],
+            InstKind::NewArray { length, .. } => vec![length],
+            InstKind::MultiNewArray { dimensions, .. } => dimensions.iter_mut().collect(),
+            InstKind::ArrayLength { array } => vec![array],
+            InstKind::ArrayLoad { array, index, .. } => vec![array, index],
+            InstKind::ArrayStore { array, index, value, .. } => vec![array, index, value],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inst {
+    // The offset of the bytecode instruction this was built from
+    pub pc: u32,
+    pub dest: Option<ValueId>,
+    pub kind: InstKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    pub dest: ValueId,
+    pub incoming: Vec<(BlockId, ValueId)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Goto(BlockId),
+    If { cond: Condition, ty: Type, lhs: ValueId, rhs: ValueId, then_block: BlockId, else_block: BlockId },
+    Switch { value: ValueId, cases: Vec<(i32, BlockId)>, default: BlockId },
+    Return(Option<ValueId>),
+    Throw(ValueId),
+}
+
+impl Terminator {
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Goto(_) | Terminator::Return(None) => vec![],
+            Terminator::If { lhs, rhs, .. } => vec![lhs, rhs],
+            Terminator::Switch { value, .. } | Terminator::Return(Some(value)) | Terminator::Throw(value) => vec![value],
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::If { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Switch { cases, default, .. } => {
+                let mut successors: Vec<BlockId> = cases.iter().map(|(_, target)| *target).collect();
+                successors.push(*default);
+                successors
+            },
+            Terminator::Return(_) | Terminator::Throw(_) => vec![],
+        }
+    }
+}
+
+/// An edge to an exception handler. Edges are listed in exception table order, so the
+/// first one whose catch type matches the thrown exception is taken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExceptionEdge {
+    // None catches everything, javac uses it for finally blocks
+    pub catch_type: Option<String>,
+    pub handler: BlockId,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: BlockId,
+    // The bytecode offset the block starts at, None for a synthetic entry block
+    pub start_pc: Option<u32>,
+    pub phis: Vec<Phi>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+    pub handlers: Vec<ExceptionEdge>,
+}
+
+/// The types held by the local variables and the operand stack before a bytecode
+/// instruction, like a verifier stack map frame. A wide value takes a single stack entry
+/// and its second local slot is None, as are dead and uninitialized locals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub locals: Vec<Option<Type>>,
+    pub stack: Vec<Type>,
+}
+
+impl Frame {
+    /// The depth of the operand stack counted in JVM slots.
+    pub fn stack_words(&self) -> usize {
+        self.stack.iter().map(|ty| if ty.is_wide() { 2 } else { 1 }).sum()
+    }
+}
+
+/// A method in SSA form. Blocks are indexed by their id and the entry block is always
+/// the first one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub class_name: String,
+    pub name: String,
+    pub descriptor: String,
+    pub is_static: bool,
+    pub params: Vec<ValueId>,
+    pub blocks: Vec<Block>,
+    // Indexed by ValueId
+    pub value_types: Vec<Type>,
+    // Keyed by the offset of every reachable bytecode instruction
+    pub frames: BTreeMap<u32, Frame>,
+}
+
+impl Function {
+    pub fn value_type(&self, value: ValueId) -> Type {
+        self.value_types[value.0 as usize]
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+}
@@ -1,22 +1,141 @@
 use std::env;
+use std::path::Path;
 
+pub mod build;
 pub mod bytecode;
 pub mod codegen;
+pub mod ir;
+
+// Removes `flag` and the value after it from the arguments
+fn take_option(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    let Some(position) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    if position + 1 >= args.len() {
+        return Err(format!("Missing value for {}", flag));
+    }
+
+    let value = args.remove(position + 1);
+    args.remove(position);
+    Ok(Some(value))
+}
+
+fn run_build(mut args: Vec<String>, abi: &dyn codegen::target::OsAbi, null_checks: codegen::x86_64::NullChecks) -> Result<(), String> {
+    let mut toolchain = build::Toolchain::default();
+    if let Some(assembler) = take_option(&mut args, "--assembler")? {
+        toolchain.assembler = assembler;
+    }
+    if let Some(linker) = take_option(&mut args, "--linker")? {
+        toolchain.linker = linker;
+    }
+    let heap_size = match take_option(&mut args, "--heap-size")? {
+        Some(heap_size) => build::parse_heap_size(&heap_size)?,
+        None => codegen::runtime::DEFAULT_HEAP_SIZE,
+    };
+    let output = take_option(&mut args, "-o")?;
+
+    let [class_path] = args.as_slice() else {
+        return Err("Usage: npjava build <file.class> [-o <output>] [--assembler <builtin|nasm|as>] [--linker <builtin|ld|cc>] [--heap-size <bytes[k|m|g]>]".to_string());
+    };
+    // Foo.class builds ./Foo by default
+    let output = output.unwrap_or_else(|| {
+        Path::new(class_path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "a.out".to_string())
+    });
+
+    build::build(class_path, &output, &toolchain, abi, heap_size, null_checks)
+}
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let target = match take_option(&mut args, "--target") {
+        Ok(target) => target.unwrap_or_else(|| codegen::target::DEFAULT_TARGET.to_string()),
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let abi = match codegen::target::target_by_name(&target) {
+        Ok(abi) => abi,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+
+    // Printed assembly is NASM unless asked for AT&T
+    let syntax = match take_option(&mut args, "--syntax") {
+        Ok(syntax) => syntax.unwrap_or_else(|| codegen::syntax::DEFAULT_SYNTAX.to_string()),
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let syntax = match codegen::syntax::syntax_by_name(&syntax) {
+        Ok(syntax) => syntax,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+
+    // Null references are compared against unless the runtime is to catch the fault
+    let null_checks = match take_option(&mut args, "--null-checks") {
+        Ok(null_checks) => null_checks.unwrap_or_else(|| codegen::x86_64::DEFAULT_NULL_CHECKS.to_string()),
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let null_checks = match codegen::x86_64::null_checks_by_name(&null_checks) {
+        Ok(null_checks) => null_checks,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+
     if args.is_empty() {
         println!("No arguments provided");
         return;
     }
 
-    let parsed_bytecode = bytecode::from_file(&args[0]);
-    match parsed_bytecode {
+    if args[0] == "build" {
+        if let Err(e) = run_build(args.split_off(1), abi.as_ref(), null_checks) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args[0] == "ir" {
+        if args.len() < 2 {
+            println!("Usage: npjava ir <file.class>");
+            return;
+        }
+
+        match bytecode::from_file(&args[1]).and_then(|parsed_bytecode| ir::build_class(&parsed_bytecode)) {
+            Err(e) => println!("Error: {}", e),
+            Ok(functions) => {
+                for function in functions {
+                    println!("{}", function);
+                }
+            }
+        }
+        return;
+    }
+
+    // Without a subcommand the assembly is printed
+    let classes = bytecode::classpath::ClassPath::load(&args[0]);
+    match classes {
         Err(e) => println!("Error: {}", e),
-        Ok(parsed_bytecode) => {
+        Ok(classes) => {
 
-            match codegen::x86_64::codegen(&parsed_bytecode) {
-                Ok(_) => println!("Codegen successful"),
+            match codegen::x86_64::codegen(&classes, abi.as_ref(), null_checks) {
+                Ok(asm) => print!("{}", syntax.print(&asm.code)),
                 Err(e) => println!("Error: {}", e),
             }
         }
@@ -0,0 +1,1031 @@
+// Builds Java programs with `npjava build` and runs them. javac compiles them first, so
+// the tests are skipped where there is no JDK, and so are the ones linking with ld where
+// there is no ld.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn has_tool(name: &str) -> bool {
+    Command::new(name).arg("--version").output().is_ok_and(|output| output.status.success())
+}
+
+// A directory of its own for each test, as they run in parallel
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("npjava-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn javac(dir: &Path, source: &Path) {
+    let output = Command::new("javac").args(["-encoding", "UTF-8", "-d"]).arg(dir).arg(source).output().unwrap();
+    assert!(output.status.success(), "javac failed: {}", String::from_utf8_lossy(&output.stderr));
+}
+
+// Compiles `source`, the text of the class `class_name` and the classes it declares
+fn compile(dir: &Path, class_name: &str, source: &str) -> PathBuf {
+    let path = dir.join(format!("{}.java", class_name));
+    fs::write(&path, source).unwrap();
+    javac(dir, &path);
+    dir.join(format!("{}.class", class_name))
+}
+
+fn example(dir: &Path, class_name: &str) -> PathBuf {
+    javac(dir, &Path::new(env!("CARGO_MANIFEST_DIR")).join("examples").join(format!("{}.java", class_name)));
+    dir.join(format!("{}.class", class_name))
+}
+
+// Builds the class and runs it, returning its exit code and what it printed
+fn build_and_run(class: &Path, args: &[&str]) -> (i32, String) {
+    build_and_run_with_arguments(class, args, &[])
+}
+
+// Like `build_and_run`, passing the program `arguments`
+fn build_and_run_with_arguments(class: &Path, args: &[&str], arguments: &[&OsStr]) -> (i32, String) {
+    let executable = class.with_extension("");
+    let output = Command::new(env!("CARGO_BIN_EXE_npjava"))
+        .arg("build")
+        .arg(class)
+        .arg("-o")
+        .arg(&executable)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "npjava build failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let output = Command::new(&executable).args(arguments).output().unwrap();
+    (output.status.code().unwrap_or(-1), String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[test]
+fn hello_world_links_with_ld() {
+    if !has_tool("javac") || !has_tool("ld") {
+        return;
+    }
+
+    let dir = test_dir("hello_world_links_with_ld");
+    let class = example(&dir, "HelloWorld");
+    assert_eq!(build_and_run(&class, &["--assembler", "builtin", "--linker", "ld"]), (0, "Hello, World!\n".to_string()));
+}
+
+// The vtables and itables of the program's classes hold the library's methods they inherit
+#[test]
+fn inherited_library_methods_link_with_ld() {
+    if !has_tool("javac") || !has_tool("ld") {
+        return;
+    }
+
+    let dir = test_dir("inherited_library_methods_link_with_ld");
+    let class = compile(
+        &dir,
+        "Inherited",
+        r#"
+        public class Inherited {
+            interface Named { String getMessage(); }
+            static class Failure extends RuntimeException implements Named {
+                Failure(String message) { super(message); }
+            }
+            static class Point {
+                public String toString() { return "Point"; }
+            }
+            public static void main(String[] args) {
+                Object point = new Point();
+                System.out.println(point.equals(point) + " " + (point.hashCode() == point.hashCode()) + " " + point);
+                Named named = new Failure("broken");
+                System.out.println(named.getMessage() + " " + named);
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &["--assembler", "builtin", "--linker", "ld"]),
+        (0, "true true Point\nbroken Inherited$Failure: broken\n".to_string())
+    );
+}
+
+#[test]
+fn synchronized_code_declares_the_monitor_routines() {
+    if !has_tool("javac") || !has_tool("ld") {
+        return;
+    }
+
+    let dir = test_dir("synchronized_code_declares_the_monitor_routines");
+    let class = compile(
+        &dir,
+        "Counter",
+        r#"
+        public class Counter {
+            int count;
+            synchronized void add(int n) { count += n; }
+            public static void main(String[] args) {
+                Counter counter = new Counter();
+                for (int i = 0; i < 10; i++) {
+                    synchronized (counter) {
+                        counter.add(i);
+                    }
+                }
+                System.out.println(counter.count);
+            }
+        }
+        "#,
+    );
+    assert_eq!(build_and_run(&class, &["--assembler", "builtin", "--linker", "ld"]), (0, "45\n".to_string()));
+
+    let source = fs::read_to_string(class.with_extension("S")).unwrap();
+    assert!(source.contains("extern runtime$monitor_enter\n") && source.contains("extern runtime$monitor_exit\n"));
+}
+
+// Allocates far more than the heap holds, so it only prints ok if the collector works
+#[test]
+fn allocation_runs_in_a_small_heap() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("allocation_runs_in_a_small_heap");
+    let class = example(&dir, "Allocation");
+    assert_eq!(build_and_run(&class, &["--heap-size", "1m"]), (0, "ok\n".to_string()));
+}
+
+#[test]
+fn arrays_are_cloneable_and_serializable() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("arrays_are_cloneable_and_serializable");
+    let class = compile(
+        &dir,
+        "Arrays",
+        r#"
+        import java.io.Serializable;
+        public class Arrays {
+            public static void main(String[] args) {
+                Object[] objects = { new int[0], new String[0], new Object(), "text" };
+                for (Object o : objects) {
+                    System.out.println((o instanceof Cloneable) + " " + (o instanceof Serializable));
+                }
+                Cloneable cloneable = (Cloneable) objects[1];
+                Serializable[] serializables = new int[1][];
+                System.out.println(cloneable == objects[1] && serializables.length == 1);
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (0, "true true\ntrue true\nfalse false\nfalse true\ntrue\n".to_string())
+    );
+}
+
+// javac gives classes with serializable lambdas a $deserializeLambda$ method, which only
+// deserialization calls
+#[test]
+fn serializable_lambdas_compile() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("serializable_lambdas_compile");
+    let class = compile(
+        &dir,
+        "Lambdas",
+        r#"
+        import java.io.Serializable;
+        import java.util.function.IntUnaryOperator;
+        public class Lambdas {
+            public static void main(String[] args) {
+                int step = 2;
+                Runnable run = (Runnable & Serializable) () -> System.out.println("run");
+                IntUnaryOperator add = (IntUnaryOperator & Serializable) x -> x + step;
+                run.run();
+                System.out.println(add.applyAsInt(40) + " " + (add instanceof Serializable));
+            }
+        }
+        "#,
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, "run\n42 true\n".to_string()));
+}
+
+// An enum switch indexes javac's $SwitchMap$ array with the constant's ordinal
+#[test]
+fn enum_switches_run() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("enum_switches_run");
+    let class = compile(
+        &dir,
+        "Seasons",
+        r#"
+        public class Seasons {
+            enum Season { WINTER, SPRING, SUMMER, AUTUMN }
+            static String describe(Season season) {
+                switch (season) {
+                    case WINTER: return "cold";
+                    case SUMMER: return "hot";
+                    default: return "mild";
+                }
+            }
+            public static void main(String[] args) {
+                for (Season season : Season.values()) {
+                    System.out.println(season.ordinal() + " " + season.name() + " " + describe(season));
+                }
+                System.out.println(Season.valueOf("AUTUMN") == Season.AUTUMN);
+                try {
+                    Season.valueOf("MONSOON");
+                } catch (IllegalArgumentException e) {
+                    System.out.println(e.getMessage());
+                }
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (
+            0,
+            "0 WINTER cold\n1 SPRING mild\n2 SUMMER hot\n3 AUTUMN mild\ntrue\nNo enum constant Seasons.Season.MONSOON\n".to_string()
+        )
+    );
+}
+
+// Classes without class files get stub metadata, which only the program's classes
+// implementing them are instances of
+#[test]
+fn casts_to_classes_without_class_files_are_checked() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("casts_to_classes_without_class_files_are_checked");
+    let class = compile(
+        &dir,
+        "Casts",
+        r#"
+        import java.io.Closeable;
+        import java.util.List;
+        public class Casts {
+            static class Resource implements Closeable {
+                public void close() {}
+            }
+            public static void main(String[] args) {
+                try {
+                    List<?> list = (List<?>) (Object) "x";
+                    System.out.println("no cce " + list);
+                } catch (ClassCastException e) {
+                    System.out.println("cce");
+                }
+                Object task = (Runnable) () -> {};
+                Object resource = new Resource();
+                Object number = 42;
+                System.out.println((task instanceof Runnable) + " " + (task instanceof List));
+                System.out.println((resource instanceof Closeable) + " " + (resource instanceof AutoCloseable) + " " + (resource instanceof Runnable));
+                System.out.println(("x" instanceof Comparable) + " " + ("x" instanceof CharSequence) + " " + (number instanceof Number));
+                try {
+                    Object[] lists = new List<?>[1];
+                    lists[0] = "x";
+                } catch (ArrayStoreException e) {
+                    System.out.println("ase");
+                }
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (0, "cce\ntrue false\ntrue true false\ntrue true true\nase\n".to_string())
+    );
+}
+
+// Literals keep their UTF-16 code units, unpaired surrogates included
+#[test]
+fn string_literals_keep_unpaired_surrogates() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("string_literals_keep_unpaired_surrogates");
+    let class = compile(
+        &dir,
+        "Surrogates",
+        r#"
+        public class Surrogates {
+            static final String PAIR = "\uD83D\uDE00";
+            public static void main(String[] args) {
+                String lone = "a\uD800b";
+                System.out.println(lone.length() + " " + (int) lone.charAt(1) + " " + (int) "\uDC00".charAt(0));
+                System.out.println(PAIR.length() + " " + (int) PAIR.charAt(0) + " " + (int) PAIR.charAt(1));
+                System.out.println((lone == "a\uD800b") + " " + lone.equals("a\uFFFDb") + " " + ("x" + lone).length());
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (0, "3 55296 56320\n2 55357 56832\ntrue false 4\n".to_string())
+    );
+}
+
+// Null references are caught by compares in one mode and by the SIGSEGV handler, which
+// reads the faulting rip out of the signal's ucontext, in the other
+#[test]
+fn implicit_exceptions_are_thrown_with_either_null_checks() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("implicit_exceptions_are_thrown_with_either_null_checks");
+    let class = compile(
+        &dir,
+        "NullChecks",
+        r#"
+        public class NullChecks {
+            static class Point {
+                int x;
+                int get() { return x; }
+                final int getFinal() { return x; }
+            }
+            static Point point;
+            static int[] numbers;
+            static Object lock;
+            static RuntimeException failure;
+
+            interface Run { void run(); }
+
+            static void check(String name, Run run) {
+                try {
+                    run.run();
+                    System.out.println(name + " ran");
+                } catch (NullPointerException e) {
+                    System.out.println(name + " npe");
+                } catch (ArithmeticException e) {
+                    System.out.println(name + " " + e.getMessage());
+                } catch (ClassCastException e) {
+                    System.out.println(name + " cce");
+                }
+            }
+
+            public static void main(String[] args) {
+                check("virtual", () -> point.get());
+                check("final", () -> point.getFinal());
+                check("getfield", () -> System.out.println(point.x));
+                check("putfield", () -> point.x = 1);
+                check("iaload", () -> System.out.println(numbers[0]));
+                check("iastore", () -> numbers[0] = 1);
+                check("arraylength", () -> System.out.println(numbers.length));
+                check("monitorenter", () -> { synchronized (lock) { System.out.println("locked"); } });
+                check("throw", () -> { throw failure; });
+                check("idiv", () -> System.out.println(1 / identity(0)));
+                check("lrem", () -> System.out.println(1L % identity(0)));
+                check("checkcast", () -> System.out.println((String) (Object) Integer.valueOf(1)));
+                System.out.println("done");
+            }
+
+            static int identity(int n) { return n; }
+        }
+        "#,
+    );
+    let expected = "\
+virtual npe
+final npe
+getfield npe
+putfield npe
+iaload npe
+iastore npe
+arraylength npe
+monitorenter npe
+throw npe
+idiv / by zero
+lrem / by zero
+checkcast cce
+done
+";
+    for null_checks in ["explicit", "signal"] {
+        assert_eq!(build_and_run(&class, &["--null-checks", null_checks]), (0, expected.to_string()), "--null-checks {}", null_checks);
+    }
+}
+
+// Arguments are decoded as UTF-8 like the JDK's launcher does, so printing them gives
+// back their bytes, and malformed bytes become U+FFFD
+#[test]
+fn main_arguments_are_decoded_as_utf8() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("main_arguments_are_decoded_as_utf8");
+    let class = compile(
+        &dir,
+        "Echo",
+        r#"
+        public class Echo {
+            public static void main(String[] args) {
+                for (String arg : args) {
+                    System.out.println(arg + " " + arg.length() + " " + (int) arg.charAt(arg.length() - 1) + " " + arg.equals("héllo"));
+                }
+            }
+        }
+        "#,
+    );
+    let arguments = ["héllo", "ωmega", "a😀", "plain"].map(OsStr::new);
+    let malformed = OsStr::from_bytes(b"a\xe2\x82");
+    assert_eq!(
+        build_and_run_with_arguments(&class, &[], &[&arguments[..], &[malformed]].concat()),
+        (0, "héllo 5 111 true\nωmega 5 97 false\na😀 3 56832 false\nplain 5 110 false\na\u{fffd} 2 65533 false\n".to_string())
+    );
+}
+
+// Equal literals are one String wherever they are loaded, and String.intern finds them
+#[test]
+fn string_literals_are_interned() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("string_literals_are_interned");
+    let class = compile(
+        &dir,
+        "Interned",
+        r#"
+        public class Interned {
+            static class Other {
+                static final String NAME = "shared";
+                static String literal() {
+                    return "shared";
+                }
+            }
+            public static void main(String[] args) {
+                String shared = "shared";
+                String built = new StringBuilder("x").toString();
+                String joined = args.length + "b";
+                System.out.println(("a" + "b" == "ab") + " " + (shared == Other.literal()) + " " + (shared == Other.NAME));
+                System.out.println((built == "x") + " " + (built.intern() == "x") + " " + (new StringBuilder("x").toString().intern() == "x"));
+                System.out.println((joined == "0b") + " " + (joined.intern() == "0b") + " " + (joined.intern() == joined.intern()));
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (0, "true true true\nfalse true true\nfalse true true\n".to_string())
+    );
+}
+
+// A covariant override and the bridge method javac adds for it differ only in their
+// return type, and so do the bridges of generic interfaces
+#[test]
+fn covariant_overrides_and_bridge_methods_build() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("covariant_overrides_and_bridge_methods_build");
+    let class = compile(
+        &dir,
+        "Cov",
+        r#"
+        import java.util.Iterator;
+        public class Cov {
+            static class A {
+                A self() { return this; }
+                Object value() { return "A"; }
+                public String toString() { return "A"; }
+            }
+            static class B extends A {
+                @Override B self() { return this; }
+                @Override String value() { return "B"; }
+                public String toString() { return "B"; }
+            }
+            interface Source<T> { T get(); }
+            static class Counter implements Source<Integer> {
+                int n;
+                public Integer get() { return n++; }
+            }
+            public static void main(String[] args) {
+                A a = new B();
+                B b = new B();
+                System.out.println(a.self() + " " + b.self() + " " + a.value() + " " + b.value().length());
+                Source<Integer> source = new Counter();
+                int sum = source.get() + source.get() + new Counter().get();
+                System.out.println(sum);
+                Iterator<Integer> it = new Iterator<Integer>() {
+                    int i = 0;
+                    public boolean hasNext() { return i < 3; }
+                    public Integer next() { return i++; }
+                };
+                while (it.hasNext()) {
+                    System.out.println(it.next());
+                }
+            }
+        }
+        "#,
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, "B B B 1\n1\n0\n1\n2\n".to_string()));
+}
+
+// The JDK's default methods are not in the library, so calling one fails the build
+// instead of throwing AbstractMethodError when the program runs
+#[test]
+fn library_default_methods_are_rejected() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("library_default_methods_are_rejected");
+    let class = compile(
+        &dir,
+        "Compose",
+        r#"
+        import java.util.function.Function;
+        public class Compose {
+            public static void main(String[] args) {
+                Function<Integer, Integer> inc = x -> x + 1;
+                System.out.println(inc.andThen(x -> x * 2).apply(3));
+            }
+        }
+        "#,
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_npjava")).arg("build").arg(&class).arg("-o").arg(class.with_extension("")).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("java/util/function/Function.andThen(Ljava/util/function/Function;)Ljava/util/function/Function; is not supported by the runtime"));
+}
+
+// Virtual calls go through the vtable and interface calls through the itable, reaching
+// overrides, abstract methods implemented further down and default methods. Final and
+// private methods and the methods of final classes are called directly.
+#[test]
+fn virtual_and_interface_calls_dispatch() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("virtual_and_interface_calls_dispatch");
+    let class = compile(
+        &dir,
+        "Dispatch",
+        r#"
+        public class Dispatch {
+            interface Shape {
+                double area();
+                default String describe() {
+                    return name() + " " + (int) area();
+                }
+                String name();
+            }
+            interface Named {
+                default String name() {
+                    return "named";
+                }
+            }
+            static abstract class Base implements Shape {
+                public String name() {
+                    return "base";
+                }
+                public abstract double area();
+                final String tag() {
+                    return "tag " + secret();
+                }
+                private String secret() {
+                    return "base secret";
+                }
+            }
+            static class Square extends Base {
+                double side;
+                Square(double side) {
+                    this.side = side;
+                }
+                public double area() {
+                    return side * side;
+                }
+                public String name() {
+                    return "square";
+                }
+                private String secret() {
+                    return "square secret";
+                }
+            }
+            static class Big extends Square {
+                Big() {
+                    super(10);
+                }
+                public String describe() {
+                    return "big " + super.describe();
+                }
+            }
+            static final class Circle extends Base implements Named {
+                public double area() {
+                    return 3 * 2 * 2;
+                }
+                public String name() {
+                    return Named.super.name() + " circle";
+                }
+            }
+            public static void main(String[] args) {
+                Shape[] shapes = { new Square(3), new Big(), new Circle() };
+                for (Shape shape : shapes) {
+                    Base base = (Base) shape;
+                    System.out.println(shape.describe() + " / " + base.name() + " / " + base.tag());
+                }
+                Circle circle = new Circle();
+                System.out.println(circle.name() + " " + circle.area());
+                Object object = new Big();
+                System.out.println(object.toString().startsWith("Dispatch$Big@") + " " + object.equals(object));
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (
+            0,
+            "square 9 / square / tag base secret\nbig square 100 / square / tag base secret\nnamed circle 12 / named circle / tag base secret\nnamed circle 12.0\ntrue true\n"
+                .to_string()
+        )
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_npjava")).arg(&class).output().unwrap();
+    let assembly = String::from_utf8_lossy(&output.stdout);
+    for method in ["Base_tag", "Base_secret", "Circle_name", "Circle_area"] {
+        assert!(assembly.contains(&format!("call Java_Dispatch_00024{}__", method)), "{} is not called directly", method);
+    }
+}
+
+// Classes are initialized the way JVMS 5.5 says: on first active use, superclass first,
+// not for reading a constant or making an array. A circular initialization sees the
+// class it started from uninitialized, and a failed one throws ExceptionInInitializerError
+// once and NoClassDefFoundError after.
+#[test]
+fn classes_are_initialized_in_order() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("classes_are_initialized_in_order");
+    let class = compile(
+        &dir,
+        "Init",
+        r#"
+        public class Init {
+            static StringBuilder log = new StringBuilder();
+            static class Parent {
+                static int parent = mark("Parent");
+            }
+            static class Child extends Parent {
+                static int child = mark("Child");
+                static final int CONSTANT = 7;
+                static void touch() {}
+            }
+            static class Lazy {
+                static int value = mark("Lazy");
+            }
+            static class A {
+                static int a = mark("A") + B.b;
+            }
+            static class B {
+                static int b = mark("B") + A.a + 10;
+            }
+            static class Broken {
+                static int value = fail();
+                static int fail() {
+                    mark("Broken");
+                    throw new IllegalStateException("boom");
+                }
+            }
+            static int mark(String name) {
+                log.append(name).append(' ');
+                return 1;
+            }
+            public static void main(String[] args) {
+                System.out.println(Child.CONSTANT + " " + log);
+                Child.touch();
+                System.out.println(log);
+                Lazy[] none = new Lazy[2];
+                System.out.println(none.length + " " + log);
+                System.out.println(Lazy.value + " " + log);
+                System.out.println(A.a + " " + B.b + " " + log);
+                try {
+                    System.out.println(Broken.value);
+                } catch (ExceptionInInitializerError e) {
+                    System.out.println("eiie");
+                }
+                try {
+                    System.out.println(Broken.value);
+                } catch (NoClassDefFoundError e) {
+                    System.out.println("ncdfe");
+                }
+                System.out.println(log);
+            }
+        }
+        "#,
+    );
+    assert_eq!(
+        build_and_run(&class, &[]),
+        (0, "7 \nParent Child \n2 Parent Child \n1 Parent Child Lazy \n12 11 Parent Child Lazy A B \neiie\nncdfe\nParent Child Lazy A B Broken \n".to_string())
+    );
+}
+
+// The edge cases of compares, conversions and remainders, with the results the JVM
+// gives: NaN compares false whichever of fcmpl and fcmpg is used, conversions to int and
+// long saturate and take NaN to 0, narrowing keeps the low bits and MIN_VALUE / -1 wraps
+#[test]
+fn floating_point_edge_cases_match_the_jvm() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("floating_point_edge_cases_match_the_jvm");
+    let class = compile(
+        &dir,
+        "Floats",
+        r#"
+        public class Floats {
+            static float f(float x) {
+                return x;
+            }
+            static double d(double x) {
+                return x;
+            }
+            public static void main(String[] args) {
+                float fnan = f(0f) / f(0f);
+                double dnan = d(0) / d(0);
+                System.out.println((fnan < 1f) + " " + (fnan > 1f) + " " + (fnan == fnan) + " " + (fnan != fnan) + " " + (fnan <= 1f) + " " + (fnan >= 1f));
+                System.out.println((dnan < 1) + " " + (dnan > 1) + " " + (dnan == dnan) + " " + (dnan != dnan) + " " + (dnan <= 1) + " " + (dnan >= 1));
+                System.out.println((f(1f) < f(2f)) + " " + (d(-0.0) == d(0.0)));
+                System.out.println((int) dnan + " " + (long) fnan + " " + (int) d(1e20) + " " + (int) d(-1e20) + " " + (long) f(1e20f) + " " + (long) f(-1e20f));
+                System.out.println((int) f(1e20f) + " " + (long) d(-1e20) + " " + (int) d(-2.9) + " " + (long) f(2.9f) + " " + (int) (char) d(70000.5));
+                int wide = 0x12345f80 + args.length;
+                System.out.println((byte) wide + " " + (int) (char) (wide | 0xffff) + " " + (short) wide + " " + (byte) -129 + " " + (short) (wide + 0x8000));
+                int min = Integer.MIN_VALUE + args.length;
+                long lmin = Long.MIN_VALUE + args.length;
+                System.out.println(min / -1 + " " + min % -1 + " " + lmin / -1 + " " + lmin % -1);
+                System.out.println(d(5.5) % d(2) + " " + d(-5.5) % d(2) + " " + f(7.5f) % f(-2f) + " " + d(1) % d(0) + " " + f(3f) % f(0f) + " " + d(1e300) % d(3));
+                System.out.println((float) d(1e40) + " " + (double) f(1.1f) + " " + (float) Long.MAX_VALUE + " " + (double) (lmin + 1) + " " + (long) d(9.223372036854776E18));
+            }
+        }
+        "#,
+    );
+    let expected = concat!(
+        "false false false true false false\n",
+        "false false false true false false\n",
+        "true true\n",
+        "0 0 2147483647 -2147483648 9223372036854775807 -9223372036854775808\n",
+        "2147483647 -9223372036854775808 -2 2 4464\n",
+        "-128 65535 24448 127 -8320\n",
+        "-2147483648 0 -9223372036854775808 0\n",
+        "1.5 -1.5 1.5 NaN NaN 0.0\n",
+        "Infinity 1.100000023841858 9.223372E18 -9.223372036854776E18 9223372036854775807\n",
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, expected.to_string()));
+}
+
+// Int arithmetic wraps around, division truncates toward zero, shift counts are taken
+// mod 32, and iinc, wide iinc included, updates locals in place
+#[test]
+fn int_arithmetic_matches_the_jvm() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("int_arithmetic_matches_the_jvm");
+    let class = compile(
+        &dir,
+        "Ints",
+        r#"
+        public class Ints {
+            public static void main(String[] args) {
+                int a = 7 + args.length;
+                int b = -3 + args.length;
+                int big = Integer.MAX_VALUE - args.length;
+                System.out.println((a + b) + " " + (a - b) + " " + (a * b) + " " + (a / b) + " " + (a % b) + " " + (-a / 2) + " " + (-a % 2));
+                System.out.println((big + 1) + " " + (big * 2) + " " + (-(big + 1)) + " " + (big * big));
+                System.out.println((a << 30) + " " + (b >> 1) + " " + (b >>> 28) + " " + (a << 33) + " " + (b >> 65) + " " + (1 << -1));
+                System.out.println((a & b) + " " + (a | b) + " " + (a ^ b) + " " + (~a) + " " + (-b));
+                int i = a;
+                i += 1000;
+                i -= 1;
+                i *= -2;
+                i++;
+                i--;
+                i--;
+                int x = 0, y = 1, z = 2, w = 3, v = 4, u = 5;
+                x = y++ + ++z;
+                System.out.println(i + " " + x + " " + y + " " + z + " " + (w + v + u));
+                int c = 100000 + args.length;
+                System.out.println(c * c + " " + c * c / c + " " + 0x7fff_ffff / -1 + " " + -2147483647 / 2);
+            }
+        }
+        "#,
+    );
+    let expected = concat!(
+        "4 10 -21 -2 1 -3 -1\n",
+        "-2147483648 -2 -2147483648 1\n",
+        "-1073741824 -2 15 14 -2 -2147483648\n",
+        "5 -1 -6 -8 3\n",
+        "-2013 4 2 3 12\n",
+        "1410065408 14100 -2147483647 -1073741823\n",
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, expected.to_string()));
+}
+
+// Conditional branches on ints, longs, doubles and references, short-circuit operators
+// and loops with labelled break and continue
+#[test]
+fn branches_and_loops_match_the_jvm() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("branches_and_loops_match_the_jvm");
+    let class = compile(
+        &dir,
+        "Loops",
+        r#"
+        public class Loops {
+            static int collatz(int n) {
+                int steps = 0;
+                while (n != 1) {
+                    n = n % 2 == 0 ? n / 2 : 3 * n + 1;
+                    steps++;
+                }
+                return steps;
+            }
+            static String sign(long value, double other, Object object) {
+                String s = value < 0 ? "neg" : value == 0 ? "zero" : "pos";
+                if (other >= 0.5 && object != null) {
+                    s += "+";
+                } else if (object == null || other < -1) {
+                    s += "?";
+                }
+                return s;
+            }
+            public static void main(String[] args) {
+                int sum = 0;
+                for (int i = 0; i < 10; i++) {
+                    if (i == 3) {
+                        continue;
+                    }
+                    if (i > 7) {
+                        break;
+                    }
+                    sum += i;
+                }
+                int count = 0;
+                outer:
+                for (int i = 0; i < 5; i++) {
+                    for (int j = 0; j < 5; j++) {
+                        if (j > i) {
+                            continue outer;
+                        }
+                        if (i * j > 6) {
+                            break outer;
+                        }
+                        count++;
+                    }
+                }
+                int k = 10;
+                do {
+                    k -= 3;
+                } while (k > 0);
+                System.out.println(sum + " " + count + " " + k + " " + collatz(27));
+                System.out.println(sign(-5, 1, args) + " " + sign(0, 0.5, null) + " " + sign(7, -2, "x") + " " + sign(Long.MIN_VALUE, 0, "x"));
+                boolean flag = args.length == 0;
+                String object = flag ? "y" : null;
+                System.out.println((flag && object != null) + " " + (!flag || object == null) + " " + (object == "y") + " " + (args != null));
+            }
+        }
+        "#,
+    );
+    let expected = concat!(
+        "25 9 -2 111\n",
+        "neg+ zero? pos? neg\n",
+        "true false true true\n",
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, expected.to_string()));
+}
+
+// Concatenation of every type of argument, null and objects with a toString, and of
+// constants holding the recipe's \u0001 and \u0002 tags, which javac passes as constant
+// arguments
+#[test]
+fn string_concatenation_matches_the_jvm() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("string_concatenation_matches_the_jvm");
+    let class = compile(
+        &dir,
+        "Concat",
+        r#"
+        public class Concat {
+            static class Point {
+                int x, y;
+                Point(int x, int y) {
+                    this.x = x;
+                    this.y = y;
+                }
+                public String toString() {
+                    return "(" + x + ", " + y + ")";
+                }
+            }
+            public static void main(String[] args) {
+                int i = -42;
+                long l = 1L << 40;
+                char c = 'é';
+                boolean z = true;
+                byte b = -7;
+                short s = 300;
+                float f = 1.5f;
+                double d = -0.25;
+                String n = null;
+                Object o = new Point(1, 2);
+                System.out.println("i=" + i + " l=" + l + " c=" + c + " z=" + z + " b=" + b + " s=" + s);
+                System.out.println(f + "|" + d + "|" + n + "|" + o + "|" + args.length + "|" + (i + l));
+                String marker = "one\u0001two\u0002three";
+                String joined = marker + i + "\u0001";
+                System.out.println(joined.length() + " " + (int) joined.charAt(3) + " " + (int) joined.charAt(7) + " " + (int) joined.charAt(joined.length() - 1));
+                String acc = "";
+                for (int k = 0; k < 5; k++) {
+                    acc += k + ",";
+                }
+                System.out.println(acc + 'x' + 1 + 2 + (1 + 2) + "☃" + c);
+            }
+        }
+        "#,
+    );
+    let expected = concat!(
+        "i=-42 l=1099511627776 c=é z=true b=-7 s=300\n",
+        "1.5|-0.25|null|(1, 2)|0|1099511627734\n",
+        "17 1 2 1\n",
+        "0,1,2,3,4,x123☃é\n",
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, expected.to_string()));
+}
+
+// Boolean and Float box like the JDK's, printf takes them for %b and %f, and Math's
+// exponential, logarithm, sine and cosine agree with the JVM's below 2^30
+#[test]
+fn boxes_printf_and_math_functions_match_the_jvm() {
+    if !has_tool("javac") {
+        return;
+    }
+
+    let dir = test_dir("boxes_printf_and_math_functions_match_the_jvm");
+    let class = compile(
+        &dir,
+        "Library",
+        r#"
+        public class Library {
+            public static void main(String[] args) {
+                System.out.printf("%b %b %b %b%n", true, false, null, "x");
+                System.out.printf("[%7b] [%-7b] [%.2b]%n", true, false, true);
+                System.out.printf("%f %.2f %10.3f %f%n", 1.5f, 1.1f, -2.25f, 3.0);
+
+                Boolean t = true;
+                Boolean f = Boolean.valueOf(false);
+                System.out.println(t + " " + f + " " + (t == Boolean.valueOf(true)) + " " + t.equals(f) + " " + t.hashCode() + " " + f.hashCode());
+                Float x = 1.5f;
+                Float nan = Float.NaN;
+                System.out.println(x + " " + x.doubleValue() + " " + x.hashCode() + " " + nan.equals(Float.NaN) + " " + x.equals(1.5) + " " + Float.valueOf(0.0f).equals(-0.0f));
+                Object o = x;
+                System.out.println((o instanceof Number) + " " + (o instanceof Comparable));
+
+                double[] xs = {0.0, -0.0, 0.5, -1.0, 2.0, Math.PI, Math.PI / 2, 10.0, 12345.678, 1e9, -745.0, 710.0, 1e-10, Double.NaN, Double.POSITIVE_INFINITY, Double.NEGATIVE_INFINITY};
+                for (double v : xs) {
+                    System.out.println(Math.sin(v) + " " + Math.cos(v) + " " + Math.exp(v) + " " + Math.log(v));
+                }
+            }
+        }
+        "#,
+    );
+    let expected = concat!(
+        "true false false true\n",
+        "[   true] [false  ] [tr]\n",
+        "1.500000 1.10     -2.250 3.000000\n",
+        "true false true false 1231 1237\n",
+        "1.5 1.5 1069547520 true false false\n",
+        "true true\n",
+        "0.0 1.0 1.0 -Infinity\n",
+        "-0.0 1.0 1.0 -Infinity\n",
+        "0.479425538604203 0.8775825618903728 1.6487212707001282 -0.6931471805599453\n",
+        "-0.8414709848078965 0.5403023058681398 0.36787944117144233 NaN\n",
+        "0.9092974268256817 -0.4161468365471424 7.38905609893065 0.6931471805599453\n",
+        "1.2246467991473532E-16 -1.0 23.140692632779267 1.1447298858494002\n",
+        "1.0 6.123233995736766E-17 4.810477380965351 0.4515827052894548\n",
+        "-0.5440211108893698 -0.8390715290764524 22026.465794806718 2.302585092994046\n",
+        "-0.7040813137533816 0.7101193587160628 Infinity 9.421061321291832\n",
+        "0.5458434494486996 0.8378871813639024 Infinity 20.72326583694641\n",
+        "0.42823715115227945 -0.9036663888697973 4.9E-324 NaN\n",
+        "6.0288706691585265E-5 0.999999998182636 Infinity 6.565264970035361\n",
+        "1.0E-10 1.0 1.0000000001 -23.025850929940457\n",
+        "NaN NaN NaN NaN\n",
+        "NaN NaN Infinity Infinity\n",
+        "NaN NaN 0.0 NaN\n",
+    );
+    assert_eq!(build_and_run(&class, &[]), (0, expected.to_string()));
+}